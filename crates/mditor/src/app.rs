@@ -1,23 +1,71 @@
-use crate::custom_widget::editor_canvas::EditorCanvas;
-use crate::model::{editor_message::EditorMessage, error::Error};
+use crate::custom_widget::editor_canvas::{self, EditorCanvas};
+use crate::custom_widget::glyph_metrics::GlyphMetrics;
+use crate::model::wrap::{self, wrap_rows};
+use crate::model::{
+    autosave::{Autosave, newer_recovery, recovery_path},
+    caret_style::CaretStyle,
+    color_scheme::ColorScheme,
+    editor_message::{EditorMessage, UnsavedChoice},
+    error::Error,
+    find::Find,
+    gutter_style::GutterStyle,
+    line_change::{LineChange, line_changes},
+    markdown::{self, Block},
+    recent::{self, MAX_RECENT, RecentFile, push_recent},
+    scroll,
+    snippet::Snippets,
+    tab_style::TabStyle,
+};
 use iced::border::Radius;
 use iced::keyboard::Key;
 use iced::keyboard::key::Named;
 use iced::widget::{
-    button, canvas, column, container, horizontal_rule, horizontal_space, row, rule, scrollable,
-    text, text_input,
+    button, canvas, column, container, horizontal_rule, horizontal_space, pick_list, row, rule,
+    scrollable, text, text_input, vertical_rule,
 };
 use iced::{
-    Border, Center, Element, Event, Font, Shadow, Subscription, Task, Theme, event, window,
+    Border, Center, Element, Event, Font, Shadow, Subscription, Task, Theme, clipboard, event,
+    time, window,
 };
 use iced::{Length, highlighter};
-use std::path::PathBuf;
-use text_buffer::{TextBuffer, TextBufferBuilder};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use text_buffer::{
+    Eol, EolMode, FileEncoding, LineBreak, LinePrefix, SearchOptions, TextBuffer,
+    TextBufferBuilder, TextStats, column as text_column, detect_eol, diff_lines,
+    encoding_rs::UTF_8, leading_whitespace, line_break, save_atomic, save_atomic_with, step_word,
+    toggle_prefix,
+};
 use unicode_segmentation::UnicodeSegmentation;
 
-// TODO: implement size and spacing settings
-const FONT_SIZE: f32 = 14.0;
-const LINE_SPACING: f32 = 1.4;
+// Editor text size and line height as a multiple of it, until changed or zoomed
+const DEFAULT_FONT_SIZE: f32 = 14.0;
+const DEFAULT_LINE_SPACING: f32 = 1.4;
+const FONT_SIZE_RANGE: std::ops::RangeInclusive<f32> = 6.0..=72.0;
+const LINE_SPACING_RANGE: std::ops::RangeInclusive<f32> = 1.0..=3.0;
+// Line spacings the status bar cycles through
+const LINE_SPACING_STEPS: [f32; 5] = [1.0, 1.2, 1.4, 1.6, 2.0];
+// Points added or removed by one zoom step
+const ZOOM_STEP: f32 = 1.0;
+// Column soft wrap breaks at when enabled
+const WRAP_COLUMN: usize = 80;
+// Cells between tab stops
+const TAB_WIDTH: usize = 4;
+// Highlighter language for new files and files without an extension
+const DEFAULT_SYNTAX: &str = "md";
+// Cells kept clear between the caret and the edge when scrolling sideways to follow it
+const SCROLL_MARGIN_CELLS: usize = 4;
+// Rows kept clear above or below the caret when scrolling to follow it
+const SCROLL_MARGIN_ROWS: usize = 2;
+// How long the caret stays on, and then off, when blinking
+const CARET_BLINK: Duration = Duration::from_millis(500);
+// Pause in editing after which the Markdown preview catches up
+const PREVIEW_DELAY: Duration = Duration::from_millis(200);
+// Pause in editing after which the gutter change markers catch up
+const LINE_CHANGES_DELAY: Duration = Duration::from_millis(300);
 
 // 0-based
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -26,6 +74,17 @@ struct Caret {
     col: usize,
 }
 
+// The document as a save wrote it: recorded as the saved state once the write succeeds,
+// whatever was typed while it ran
+#[derive(Debug, Clone)]
+struct SavedText {
+    version: u64,
+    len: usize,
+    hash: u64,
+    eol: Eol,
+    lines: Rc<Vec<String>>,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Selection {
     anchor: Caret,
@@ -36,130 +95,347 @@ pub struct App {
     file: Option<PathBuf>,
     buffer: TextBuffer,
     theme: highlighter::Theme,
+    syntax: String, // file extension or language name picking the highlighter grammar
+    colors: ColorScheme,
+    gutter: GutterStyle,
+    caret_style: CaretStyle,
+    font_size: f32,
+    line_spacing: f32,
     is_loading: bool,
     is_dirty: bool,
+    error: Option<String>, // why the last open or save failed, for the status bar
+    pending: Option<PendingAction>, // waiting on the unsaved changes prompt or its save
+    recent: Vec<PathBuf>,  // most recently opened or saved first
+    saved_state: (usize, u64), // (length, content hash) of the text last opened or saved
+    saved_version: u64,    // buffer version known to hold the saved text
+    eol: Eol,              // what Enter inserts and every line break is saved as
+    encoding: FileEncoding, // what the file was read as and is written back as
+    saved_eol: Eol,
+    saved_lines: Rc<Vec<String>>,
+    saving: Option<SavedText>, // what the save in flight is writing
+    line_changes: Vec<Option<LineChange>>, // per line, against `saved_lines`
+    line_changes_due: Option<u64>, // `render_version` of the last edit not yet diffed
     active: bool,
     line: usize,
     col: usize,
-    preferred_col: Option<usize>, // preserve horizontal position when moving up/down
+    preferred_col: Option<usize>, // preserve horizontal (display) position when moving up/down
+    wrap_column: Option<usize>,
+    tab_width: Option<usize>, // None draws and hit-tests a tab as a single cell
+    tab: TabStyle,            // what the Tab key inserts
+    auto_indent: bool,        // Enter keeps the indentation and continues Markdown lists
+    highlight_brackets: bool, // mark the bracket at the caret and its partner
+    show_trailing_whitespace: bool,
+    show_whitespace: bool, // marks on spaces, tabs and line ends
+    snippets: Snippets,
+    stats: TextStats, // kept up to date from the buffer's change events
+    show_stats: bool,
+    read_only: bool, // edits are refused; moving, selecting and copying still work
+    autosave: Autosave,
+    edits_since_autosave: usize, // since the recovery file was written or the file saved
+    preview: Option<Vec<Block>>, // the rendered Markdown pane, when open
+    preview_due: Option<u64>,    // `render_version` of the last edit the preview lags behind
+    bracket_pair: Option<(Caret, Option<Caret>)>, // bracket at/before the caret and its match, if any
+    bracket_checked: Option<(usize, usize, u64)>, // caret and `render_version` `bracket_pair` is for
     selection: Option<Selection>,
+    find: Option<Find>,        // the find bar, when open
+    find_focused: bool,        // keys go to the find bar instead of the document
+    goto_line: Option<String>, // what is typed into the go-to-line bar, when open
+    typing: bool,              // an undo group of typed characters is open
+    scroll_x: f32,             // how far long lines are scrolled to the left
+    scroll_y: f32,
+    viewport_width: f32,
+    viewport_height: f32, // 0 until the editor scrollable reports its size
     render_version: u64,
+    caret_visible: bool, // the blink's phase; the canvas skips the caret while off
+    caret_moved_at: Instant, // last edit or caret move, after which the caret stays on a blink
     input_value: String,
     input_id: text_input::Id,
+    find_input_id: text_input::Id,
+    goto_input_id: text_input::Id,
+    scroll_id: scrollable::Id,
 }
 
 impl App {
     pub fn new() -> (Self, Task<EditorMessage>) {
-        let app = Self {
+        let mut app = Self {
             file: None,
             buffer: TextBufferBuilder::new().finish(),
             theme: highlighter::Theme::SolarizedDark,
+            syntax: DEFAULT_SYNTAX.to_string(),
+            colors: ColorScheme::default(),
+            gutter: GutterStyle::default(),
+            caret_style: CaretStyle::default(),
+            font_size: DEFAULT_FONT_SIZE,
+            line_spacing: DEFAULT_LINE_SPACING,
             is_loading: false,
             is_dirty: false,
+            error: None,
+            pending: None,
+            recent: Vec::new(),
+            saved_state: (0, 0),
+            saved_version: 0,
+            eol: Eol::default(),
+            encoding: FileEncoding::default(),
+            saved_eol: Eol::default(),
+            saved_lines: Rc::default(),
+            saving: None,
+            line_changes: Vec::new(),
+            line_changes_due: None,
             active: false,
             line: 0,
             col: 0,
             preferred_col: None,
+            wrap_column: None,
+            tab_width: Some(TAB_WIDTH),
+            tab: TabStyle::default(),
+            auto_indent: true,
+            highlight_brackets: true,
+            show_trailing_whitespace: true,
+            show_whitespace: false,
+            snippets: Snippets::builtin(),
+            stats: TextStats::default(),
+            show_stats: false,
+            read_only: false,
+            autosave: Autosave::default(),
+            edits_since_autosave: 0,
+            preview: None,
+            preview_due: None,
+            bracket_pair: None,
+            bracket_checked: None,
             selection: None,
+            find: None,
+            find_focused: false,
+            goto_line: None,
+            typing: false,
+            scroll_x: 0.0,
+            scroll_y: 0.0,
+            viewport_width: 0.0,
+            viewport_height: 0.0,
             render_version: 0,
+            caret_visible: true,
+            caret_moved_at: Instant::now(),
             input_value: String::new(),
             input_id: text_input::Id::unique(),
+            find_input_id: text_input::Id::unique(),
+            goto_input_id: text_input::Id::unique(),
+            scroll_id: scrollable::Id::unique(),
         };
-        let task = text_input::focus(app.input_id.clone());
+        app.mark_saved();
+        app.reset_stats();
+        let task = Task::batch([
+            text_input::focus(app.input_id.clone()),
+            Task::perform(load_recent(), EditorMessage::RecentLoaded),
+        ]);
         (app, task)
     }
 
     pub fn update(&mut self, message: EditorMessage) -> Task<EditorMessage> {
+        // Consecutive typed characters undo together; anything else ends the run
+        // The view scrolls along as text is typed; that does not end the typing run
+        let typing = match &message {
+            EditorMessage::Insert(text) => text.chars().count() == 1,
+            EditorMessage::Scrolled(_) | EditorMessage::CaretBlink(_) => self.typing,
+            _ => false,
+        };
+        if typing != self.typing {
+            if typing {
+                self.buffer.begin_group();
+            } else {
+                self.buffer.end_group();
+            }
+            self.typing = typing;
+        }
+
+        let caret = (self.line, self.col);
+        let mut task = self.handle(message);
+        self.refresh_bracket_pair();
+        let mut edited = false;
+        for change in self.buffer.take_changes() {
+            self.stats.apply(&change);
+            self.edits_since_autosave += 1;
+            edited = true;
+        }
+        if edited {
+            self.refresh_matches();
+            // Diffing against the saved lines reads the whole document, so it waits for a pause
+            let version = self.render_version;
+            self.line_changes_due = Some(version);
+            task = Task::batch([
+                task,
+                Task::perform(delay(LINE_CHANGES_DELAY), move |_| {
+                    EditorMessage::LineChangesDue(version)
+                }),
+            ]);
+            if self.is_dirty && self.autosave.due(self.edits_since_autosave, false) {
+                self.write_recovery();
+            }
+            if self.preview.is_some() {
+                // Only the timer of the latest edit refreshes the preview
+                let version = self.render_version;
+                self.preview_due = Some(version);
+                task = Task::batch([
+                    task,
+                    Task::perform(delay(PREVIEW_DELAY), move |_| {
+                        EditorMessage::PreviewDue(version)
+                    }),
+                ]);
+            }
+        }
+        self.reveal_caret_x();
+        if edited || caret != (self.line, self.col) {
+            // The caret shows as soon as it moves, for a whole blink
+            self.caret_visible = true;
+            self.caret_moved_at = Instant::now();
+            task = Task::batch([task, self.reveal_caret_y()]);
+        }
+        task
+    }
+
+    fn handle(&mut self, message: EditorMessage) -> Task<EditorMessage> {
+        if self.read_only && message.edits_text() {
+            return Task::none();
+        }
         match message {
-            EditorMessage::NewFile => {
-                if !self.is_loading {
-                    self.file = None;
-                    self.buffer = TextBufferBuilder::new().finish();
-                    self.is_dirty = false;
-                    self.render_version = self.render_version.wrapping_add(1);
+            EditorMessage::NewFile => self.leave_file(PendingAction::New),
+            EditorMessage::OpenFile => self.leave_file(PendingAction::Open),
+            EditorMessage::FileDropped(path) | EditorMessage::OpenRecent(path) => {
+                self.leave_file(PendingAction::OpenPath(path))
+            }
+            EditorMessage::RecentLoaded(stored) => {
+                // Files opened before the list was read stay in front
+                let opened = std::mem::replace(&mut self.recent, stored);
+                for path in opened.into_iter().rev() {
+                    push_recent(&mut self.recent, path, MAX_RECENT);
                 }
                 Task::none()
             }
-
-            EditorMessage::OpenFile => {
-                if self.is_loading {
-                    Task::none()
-                } else {
-                    self.is_loading = true;
-                    Task::perform(open(), EditorMessage::FileOpened)
+            EditorMessage::Scrolled(viewport) => {
+                self.scroll_y = viewport.absolute_offset().y;
+                self.viewport_width = viewport.bounds().width;
+                self.viewport_height = viewport.bounds().height;
+                self.render_version = self.render_version.wrapping_add(1);
+                Task::none()
+            }
+            EditorMessage::UnsavedChangesAnswered(choice) => {
+                let Some(action) = self.pending.clone() else {
+                    return Task::none();
+                };
+                match choice {
+                    UnsavedChoice::Save => self.save(save_target(self.file.as_ref())),
+                    UnsavedChoice::Discard => {
+                        remove_recovery(self.file.as_ref());
+                        self.pending = None;
+                        self.run(action)
+                    }
+                    UnsavedChoice::Cancel => {
+                        self.pending = None;
+                        Task::none()
+                    }
                 }
             }
             EditorMessage::FileOpened(result) => {
                 self.is_loading = false;
-                self.is_dirty = false;
-                if let Ok((path, chunks)) = result {
-                    self.file = Some(path);
+                if let Err(error @ Error::IoError(_)) = &result {
+                    self.error = Some(format!("Could not open file: {error}"));
+                }
+                if let Ok((path, chunks, encoding)) = result {
+                    self.syntax = syntax_token(&path);
+                    let recovery = newer_recovery(&path);
+                    self.file = Some(path.clone());
+                    self.encoding = encoding;
+                    self.error = None;
 
-                    let mut builder = TextBufferBuilder::new();
+                    // The first chunk is plenty to tell which line break the file uses
+                    self.eol = detect_eol(chunks.first().map_or("", String::as_str));
+                    let size = chunks.iter().map(String::len).sum();
+                    let mut builder = TextBufferBuilder::with_capacity(size);
                     for s in chunks {
                         builder.accept_chunk(&s);
                     }
                     self.buffer = builder.finish();
                     self.input_value.clear();
                     self.set_cursor(0, 0);
-                    self.is_dirty = false;
+                    self.mark_saved();
+                    self.reset_stats();
+                    self.refresh_preview();
                     self.render_version = self.render_version.wrapping_add(1);
+                    let remembered = self.remember_recent();
+                    if let Some(recovery) = recovery {
+                        let offer = Task::future(offer_recovery(path, recovery)).and_then(
+                            |(path, text)| Task::done(EditorMessage::Recovered { path, text }),
+                        );
+                        return Task::batch([remembered, offer]);
+                    }
+                    return remembered;
                 }
                 Task::none()
             }
-            EditorMessage::SaveFile => {
-                if self.is_loading {
-                    Task::none()
-                } else if let Some(path) = self.file.clone() {
-                    self.is_loading = true;
-                    let content = self.buffer.get_text();
-                    Task::perform(save_to_path(path, content), EditorMessage::FileSaved)
-                } else {
-                    self.is_loading = true;
-                    let content = self.buffer.get_text();
-                    Task::perform(save_as(content), EditorMessage::FileSaved)
+            EditorMessage::Recovered { path, text } => {
+                // Another file was opened while the question was up
+                if self.file.as_ref() != Some(&path) {
+                    return Task::none();
                 }
+                self.buffer.set_text(&text);
+                self.set_cursor(self.line, self.col);
+                self.selection = None;
+                self.input_value.clear();
+                self.refresh_dirty();
+                self.render_version = self.render_version.wrapping_add(1);
+                text_input::focus(self.input_id.clone())
             }
-            EditorMessage::SaveAs => {
-                if self.is_loading {
-                    Task::none()
-                } else {
-                    self.is_loading = true;
-                    let content = self.buffer.get_text();
-                    Task::perform(save_as(content), EditorMessage::FileSaved)
+            EditorMessage::CaretBlink(now) => {
+                // Only the caret layer of the canvas is redrawn, so no new render version
+                if now.saturating_duration_since(self.caret_moved_at) >= CARET_BLINK {
+                    self.caret_visible = !self.caret_visible;
                 }
+                Task::none()
+            }
+            EditorMessage::AutosaveTick => {
+                if self.is_dirty && self.autosave.due(self.edits_since_autosave, true) {
+                    self.write_recovery();
+                }
+                Task::none()
             }
+            EditorMessage::ToggleAutosave => {
+                self.autosave.enabled = !self.autosave.enabled;
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::SaveFile => self.save(save_target(self.file.as_ref())),
+            EditorMessage::SaveAs => self.save(SaveTarget::Prompt),
             EditorMessage::FileSaved(result) => {
                 self.is_loading = false;
+                let saved = self.saving.take();
                 match result {
                     Ok(maybe_path) => {
-                        self.is_dirty = false;
+                        if let Some(saved) = saved {
+                            self.mark_saved_as(saved);
+                        }
+                        self.error = None;
+                        remove_recovery(self.file.as_ref());
                         if let Some(path) = maybe_path {
+                            remove_recovery(Some(&path));
                             self.file = Some(path);
                         }
+                        let remembered = self.remember_recent();
+                        // Saved from the unsaved changes prompt: carry on
+                        if let Some(action) = self.pending.take() {
+                            return Task::batch([remembered, self.run(action)]);
+                        }
+                        return remembered;
                     }
-                    Err(_) => {
-                        // TODO: Show error message in status bar
-                    }
+                    // Cancelled: nothing was written, nothing changes
+                    Err(Error::DialogClosed) => {}
+                    Err(error) => self.error = Some(format!("Could not save file: {error}")),
                 }
+                self.pending = None;
                 Task::none()
             }
-            EditorMessage::ActivateEditor => {
-                self.active = true;
-                text_input::focus(self.input_id.clone())
-            }
-            EditorMessage::DeactivateEditor => {
-                self.active = false;
-                Task::none()
-            }
-            EditorMessage::SetCursor { line, column } => {
-                self.set_cursor(line, column);
-                self.selection = None;
-                self.preferred_col = Some(self.col);
-                text_input::focus(self.input_id.clone())
-            }
             EditorMessage::Insert(to_insert) => {
-                self.insert(to_insert.as_str());
+                // The hidden input reports its own (empty) contents on Backspace, Delete
+                // and cut; those have handlers of their own
+                if !to_insert.is_empty() {
+                    self.insert(to_insert.as_str());
+                }
                 text_input::focus(self.input_id.clone())
             }
             EditorMessage::Backspace => {
@@ -170,6 +446,35 @@ impl App {
                 self.enter();
                 text_input::focus(self.input_id.clone())
             }
+            EditorMessage::Tab => {
+                if self.spans_lines() {
+                    self.indent_selection(1);
+                } else {
+                    self.tab();
+                }
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::Dedent => {
+                if self.spans_lines() {
+                    self.indent_selection(-1);
+                } else {
+                    self.dedent();
+                }
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ToggleAutoIndent => {
+                self.auto_indent = !self.auto_indent;
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::SetLineEnding(eol) => {
+                self.eol = eol;
+                self.refresh_dirty();
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ToggleTabStyle => {
+                self.tab = self.tab.toggled(TAB_WIDTH);
+                text_input::focus(self.input_id.clone())
+            }
             EditorMessage::MoveLeft => {
                 self.cursor_left();
                 text_input::focus(self.input_id.clone())
@@ -186,15 +491,66 @@ impl App {
                 self.cursor_down();
                 text_input::focus(self.input_id.clone())
             }
+            EditorMessage::MoveHome => {
+                self.cursor_home();
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::MoveEnd => {
+                self.cursor_end();
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::MoveWordLeft => {
+                self.word_step(false, false);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::MoveWordRight => {
+                self.word_step(true, false);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ExtendWordLeft => {
+                self.word_step(false, true);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ExtendWordRight => {
+                self.word_step(true, true);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::PageUp => {
+                self.page(false);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::PageDown => {
+                self.page(true);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::MoveDocumentStart => {
+                self.set_cursor(0, 0);
+                self.preferred_col = Some(self.display_col());
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::MoveDocumentEnd => {
+                self.set_cursor(usize::MAX, usize::MAX);
+                self.preferred_col = Some(self.display_col());
+                text_input::focus(self.input_id.clone())
+            }
             EditorMessage::DeleteForward => {
                 self.delete_forward();
                 text_input::focus(self.input_id.clone())
             }
+            EditorMessage::DeleteToLineEnd => {
+                self.delete_to_line_boundary(true);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::DeleteToLineStart => {
+                self.delete_to_line_boundary(false);
+                text_input::focus(self.input_id.clone())
+            }
             EditorMessage::SelectAll => {
                 self.select_all();
                 text_input::focus(self.input_id.clone())
             }
             EditorMessage::BeginSelection { line, column } => {
+                self.find_focused = false;
                 self.begin_selection(line, column);
                 text_input::focus(self.input_id.clone())
             }
@@ -203,6 +559,254 @@ impl App {
                 text_input::focus(self.input_id.clone())
             }
             EditorMessage::EndSelection => Task::none(),
+            EditorMessage::SelectWord { line, column } => {
+                self.find_focused = false;
+                self.select_word(line, column);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::SelectLine { line } => {
+                self.find_focused = false;
+                self.select_line(line);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ToggleWrap => {
+                self.wrap_column = match self.wrap_column {
+                    Some(_) => None,
+                    None => Some(WRAP_COLUMN),
+                };
+                self.preferred_col = Some(self.display_col());
+                self.render_version = self.render_version.wrapping_add(1);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ToggleGutterAlign => {
+                self.gutter.align = self.gutter.align.flipped();
+                self.render_version = self.render_version.wrapping_add(1);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::CycleCaretStyle => {
+                self.caret_style = self.caret_style.cycled();
+                self.render_version = self.render_version.wrapping_add(1);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::SetFontSize(size) => {
+                self.font_size = size.clamp(*FONT_SIZE_RANGE.start(), *FONT_SIZE_RANGE.end());
+                self.render_version = self.render_version.wrapping_add(1);
+                Task::none()
+            }
+            EditorMessage::SetLineSpacing(spacing) => {
+                self.line_spacing =
+                    spacing.clamp(*LINE_SPACING_RANGE.start(), *LINE_SPACING_RANGE.end());
+                self.render_version = self.render_version.wrapping_add(1);
+                Task::none()
+            }
+            EditorMessage::ZoomIn => {
+                self.handle(EditorMessage::SetFontSize(self.font_size + ZOOM_STEP))
+            }
+            EditorMessage::ZoomOut => {
+                self.handle(EditorMessage::SetFontSize(self.font_size - ZOOM_STEP))
+            }
+            EditorMessage::ZoomReset => self.handle(EditorMessage::SetFontSize(DEFAULT_FONT_SIZE)),
+            EditorMessage::ToggleStats => {
+                self.show_stats = !self.show_stats;
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ToggleReadOnly => {
+                self.read_only = !self.read_only;
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::TogglePreview => {
+                self.preview = match self.preview {
+                    Some(_) => None,
+                    None => Some(Vec::new()),
+                };
+                self.refresh_preview();
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::PreviewDue(version) => {
+                // A later edit has a timer of its own
+                if self.preview_due == Some(version) {
+                    self.refresh_preview();
+                }
+                Task::none()
+            }
+            EditorMessage::LineChangesDue(version) => {
+                if self.line_changes_due == Some(version) {
+                    self.refresh_line_changes();
+                }
+                Task::none()
+            }
+            EditorMessage::ToggleTrailingWhitespace => {
+                self.show_trailing_whitespace = !self.show_trailing_whitespace;
+                self.render_version = self.render_version.wrapping_add(1);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ToggleWhitespace => {
+                self.show_whitespace = !self.show_whitespace;
+                self.render_version = self.render_version.wrapping_add(1);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ToggleBracketHighlight => {
+                self.highlight_brackets = !self.highlight_brackets;
+                self.render_version = self.render_version.wrapping_add(1);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ToggleWord { step } => {
+                self.toggle_word_at_caret(step);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ToggleBold => {
+                self.toggle_mark("**");
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ToggleItalic => {
+                self.toggle_mark("*");
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ToggleCode => {
+                self.toggle_mark("`");
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ToggleBulletList => {
+                self.toggle_line_prefix(LinePrefix::Bullet);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ToggleNumberedList => {
+                self.toggle_line_prefix(LinePrefix::Numbered);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::SetHeadingLevel(level) => {
+                self.toggle_line_prefix(LinePrefix::Heading(level));
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::DuplicateLines => {
+                self.duplicate_lines();
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::MoveLinesUp => {
+                self.move_lines(false);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::MoveLinesDown => {
+                self.move_lines(true);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::JoinLines => {
+                let lines = self.selected_lines();
+                if let Some(caret) = self.buffer.join_lines(lines.start() + 1..lines.end() + 2) {
+                    self.replaced_text_at(caret);
+                }
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::Copy => match self.selected_text() {
+                Some(text) => clipboard::write(text),
+                None => Task::none(),
+            },
+            EditorMessage::Cut => match self.selected_text() {
+                Some(text) => {
+                    self.backspace();
+                    clipboard::write(text)
+                }
+                None => Task::none(),
+            },
+            EditorMessage::Paste => {
+                clipboard::read().and_then(|text| Task::done(EditorMessage::Pasted(text)))
+            }
+            EditorMessage::Pasted(text) => {
+                self.type_text(&text);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::ToggleFind => {
+                if self.find.take().is_some() {
+                    self.find_focused = false;
+                    return text_input::focus(self.input_id.clone());
+                }
+                // Start from the selected text when it is on one line
+                let query = self
+                    .selected_text()
+                    .filter(|text| !text.contains(['\n', '\r']))
+                    .unwrap_or_default();
+                self.find = Some(Find {
+                    query,
+                    ..Find::default()
+                });
+                self.refresh_matches();
+                self.find_focused = true;
+                text_input::focus(self.find_input_id.clone())
+            }
+            EditorMessage::FindQueryChanged(query) => {
+                if let Some(find) = &mut self.find {
+                    find.query = query;
+                }
+                self.refresh_matches();
+                self.find_focused = true;
+                Task::none()
+            }
+            EditorMessage::ReplacementChanged(replacement) => {
+                if let Some(find) = &mut self.find {
+                    find.replacement = replacement;
+                }
+                self.find_focused = true;
+                Task::none()
+            }
+            EditorMessage::FindNext => {
+                self.find_step(true);
+                self.focus_find()
+            }
+            EditorMessage::FindPrev => {
+                self.find_step(false);
+                self.focus_find()
+            }
+            EditorMessage::Replace => {
+                self.replace_current();
+                self.focus_find()
+            }
+            EditorMessage::ReplaceAll => {
+                self.replace_all();
+                self.focus_find()
+            }
+            EditorMessage::GotoLine => {
+                if self.goto_line.take().is_some() {
+                    return text_input::focus(self.input_id.clone());
+                }
+                self.goto_line = Some(String::new());
+                self.find_focused = false;
+                text_input::focus(self.goto_input_id.clone())
+            }
+            EditorMessage::GotoLineChanged(input) => {
+                if let Some(goto_line) = &mut self.goto_line {
+                    *goto_line = input;
+                }
+                Task::none()
+            }
+            EditorMessage::GotoLineSubmitted => {
+                let target = self
+                    .goto_line
+                    .as_deref()
+                    .and_then(|input| goto_target(input, self.buffer.get_line_count()));
+                // Anything but a number leaves the bar open to be corrected
+                let Some(line) = target else {
+                    return Task::none();
+                };
+                self.goto_line = None;
+                let caret = self.caret_at(self.buffer.get_offset_at(line + 1, 1));
+                self.set_cursor(caret.line, caret.col);
+                self.selection = None;
+                self.preferred_col = Some(self.display_col());
+                self.render_version = self.render_version.wrapping_add(1);
+                // Scrolled to even when the caret was already on that line
+                Task::batch([
+                    text_input::focus(self.input_id.clone()),
+                    self.reveal_caret_y(),
+                ])
+            }
+            EditorMessage::Undo => {
+                self.undo(false);
+                text_input::focus(self.input_id.clone())
+            }
+            EditorMessage::Redo => {
+                self.undo(true);
+                text_input::focus(self.input_id.clone())
+            }
             EditorMessage::ExtendLeft => {
                 self.extend_left();
                 text_input::focus(self.input_id.clone())
@@ -223,40 +827,77 @@ impl App {
     }
 
     pub fn view(&self) -> Element<'_, EditorMessage> {
+        let colors = self.colors;
         let controls = container(
             row![
                 action(text("New").size(12), Some(EditorMessage::NewFile)),
                 action(text("Open File...").size(12), Some(EditorMessage::OpenFile)),
                 action(text("Save File").size(12), Some(EditorMessage::SaveFile)),
                 action(text("Save As...").size(12), Some(EditorMessage::SaveAs)),
+                action(text("Preview").size(12), Some(EditorMessage::TogglePreview)),
             ]
+            .push_maybe(self.recent_menu())
             .align_y(Center)
             .height(Length::Fixed(20.0))
             .spacing(8),
         )
         .width(Length::Fill)
         .padding([2, 8])
-        .style(top_bar_bg);
+        .style(move |_| top_bar_bg(&colors));
 
-        let status = container(row![
-            text(if let Some(path) = &self.file {
-                let path = path.display().to_string();
-                if path.len() > 60 {
-                    format!("...{}", &path[path.len() - 40..])
-                } else {
-                    path
-                }
-            } else {
-                String::from("New file")
-            }),
-            horizontal_space(),
-            text(format!("{}:{}", self.line + 1, self.col + 1))
-        ])
+        let status = container(
+            row![
+                text(self.file_status()),
+                text(self.error.clone().unwrap_or_default()).color(colors.line_deleted),
+                horizontal_space(),
+                text(self.encoding.name()),
+                action(
+                    text(eol_label(self.eol)),
+                    Some(EditorMessage::SetLineEnding(next_eol(self.eol))),
+                ),
+                action(
+                    text(match self.tab {
+                        TabStyle::Spaces(n) => format!("Spaces: {n}"),
+                        TabStyle::Hard => String::from("Tabs"),
+                    }),
+                    Some(EditorMessage::ToggleTabStyle),
+                ),
+                action(
+                    text(format!("Spacing {:.1}", self.line_spacing)),
+                    Some(EditorMessage::SetLineSpacing(next_line_spacing(
+                        self.line_spacing
+                    ))),
+                ),
+                text(count_label(&self.selection_stats().unwrap_or(self.stats))),
+                text(format!("{}:{}", self.line + 1, self.col + 1))
+            ]
+            .push_maybe(
+                self.read_only
+                    .then(|| action(text("RO"), Some(EditorMessage::ToggleReadOnly))),
+            )
+            .spacing(16),
+        )
         .padding([2, 8])
         .width(Length::Fill)
-        .style(bottom_bar_bg);
+        .style(move |_| bottom_bar_bg(&colors));
+
+        let stats_panel = self.show_stats.then(|| {
+            container(text(stats_label(&self.stats, self.selection_stats())).size(12))
+                .padding([2, 8])
+                .width(Length::Fill)
+                .style(move |_| bottom_bar_bg(&colors))
+        });
 
-        let content_height = self.buffer.get_line_count() as f32 * FONT_SIZE * LINE_SPACING;
+        let display_rows = match self.wrap_column {
+            Some(width) => self
+                .buffer
+                .snapshot_lines()
+                .iter()
+                .map(|line| wrap_rows(line, width, self.tab_width).len())
+                .sum(),
+            None => self.buffer.get_line_count(),
+        };
+        let content_height = display_rows as f32 * self.line_height();
 
         let canvas = container(
             row![
@@ -265,12 +906,32 @@ impl App {
                         let editor = EditorCanvas::new(
                             &self.buffer,
                             Font::MONOSPACE,
-                            FONT_SIZE,
-                            LINE_SPACING,
+                            self.font_size,
+                            self.line_spacing,
                             self.line,
                             self.col,
+                            self.scroll_x,
                             self.render_version,
+                            self.caret_visible,
                         );
+                        let editor = editor
+                            .with_color_scheme(colors)
+                            .with_gutter_style(self.gutter)
+                            .with_caret_style(self.caret_style)
+                            .with_wrap(self.wrap_column)
+                            .with_tab_width(self.tab_width)
+                            .with_viewport(self.scroll_y, self.viewport_height)
+                            .with_line_changes(&self.line_changes)
+                            .with_trailing_whitespace(self.show_trailing_whitespace)
+                            .with_whitespace(self.show_whitespace)
+                            .with_highlighting(highlighter::Settings {
+                                theme: self.theme,
+                                token: self.syntax.clone(),
+                            })
+                            .with_bracket_pair(
+                                self.bracket_pair
+                                    .map(|(a, b)| ((a.line, a.col), b.map(|b| (b.line, b.col)))),
+                            );
                         let editor = if let Some(sel) = self.selection {
                             editor.with_selection(
                                 sel.anchor.line,
@@ -285,12 +946,17 @@ impl App {
                     }
                     .width(iced::Fill)
                     .height(Length::Fixed(content_height + 850.0)),
-                ),
+                )
+                .id(self.scroll_id.clone())
+                .on_scroll(EditorMessage::Scrolled),
                 // Hidden text_input to receive text runs & IME
                 container(
                     text_input("", &self.input_value)
                         .on_input(EditorMessage::Insert)
                         .on_submit(EditorMessage::Enter)
+                        // Pasting is left to `Paste`, which keeps the line breaks the
+                        // input would strip
+                        .on_paste(|_| EditorMessage::Insert(String::new()))
                         .id(self.input_id.clone())
                         .size(1)
                         .padding(0)
@@ -300,17 +966,92 @@ impl App {
             ]
             .height(iced::Fill),
         )
-        .style(editor_bg)
+        .style(move |_| editor_bg(&colors))
         .height(iced::Fill);
 
-        column![
-            controls,
-            horizontal_rule(1).style(black_rule),
-            canvas,
-            horizontal_rule(1).style(black_rule),
-            status,
-        ]
-        .into()
+        // The editor and its preview side by side, each half the width
+        let panes = row![canvas].push_maybe(self.preview.as_ref().map(|blocks| {
+            row![
+                vertical_rule(1).style(black_rule),
+                container(scrollable(markdown::view(blocks, &colors)))
+                    .width(iced::Fill)
+                    .height(iced::Fill)
+                    .style(move |_| editor_bg(&colors)),
+            ]
+        }));
+
+        let find_bar = self.find.as_ref().map(|find| {
+            container(
+                row![
+                    text_input("Find", &find.query)
+                        .id(self.find_input_id.clone())
+                        .on_input(EditorMessage::FindQueryChanged)
+                        .on_submit(EditorMessage::FindNext)
+                        .size(12)
+                        .width(Length::Fixed(200.0)),
+                    text(find.label()).size(12).width(Length::Fixed(80.0)),
+                    action(text("Prev").size(12), Some(EditorMessage::FindPrev)),
+                    action(text("Next").size(12), Some(EditorMessage::FindNext)),
+                    text_input("Replace", &find.replacement)
+                        .on_input(EditorMessage::ReplacementChanged)
+                        .on_submit(EditorMessage::Replace)
+                        .size(12)
+                        .width(Length::Fixed(200.0)),
+                    action(text("Replace").size(12), Some(EditorMessage::Replace)),
+                    action(text("All").size(12), Some(EditorMessage::ReplaceAll)),
+                ]
+                .align_y(Center)
+                .spacing(8),
+            )
+            .width(Length::Fill)
+            .padding([2, 8])
+            .style(move |_| top_bar_bg(&colors))
+        });
+
+        let goto_bar = self.goto_line.as_ref().map(|input| {
+            container(
+                row![
+                    text_input(
+                        &format!("Go to line (1-{})", self.buffer.get_line_count()),
+                        input
+                    )
+                    .id(self.goto_input_id.clone())
+                    .on_input(EditorMessage::GotoLineChanged)
+                    .on_submit(EditorMessage::GotoLineSubmitted)
+                    .size(12)
+                    .width(Length::Fixed(200.0)),
+                    action(text("Go").size(12), Some(EditorMessage::GotoLineSubmitted)),
+                ]
+                .align_y(Center)
+                .spacing(8),
+            )
+            .width(Length::Fill)
+            .padding([2, 8])
+            .style(move |_| top_bar_bg(&colors))
+        });
+
+        column![controls, horizontal_rule(1).style(black_rule)]
+            .push_maybe(find_bar)
+            .push_maybe(goto_bar)
+            .push(panes)
+            .push(horizontal_rule(1).style(black_rule))
+            .push_maybe(stats_panel)
+            .push(status)
+            .into()
+    }
+
+    // Window title: the file name, marked while there are unsaved changes
+    pub fn title(&self) -> String {
+        let name = self
+            .file
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map_or_else(
+                || String::from("New file"),
+                |name| name.to_string_lossy().into_owned(),
+            );
+        let modified = if self.is_dirty { " (modified)" } else { "" };
+        format!("{name} — Mditor{modified}")
     }
 
     pub fn theme(&self) -> Theme {
@@ -322,77 +1063,724 @@ impl App {
     }
 
     pub fn subscription(&self) -> Subscription<EditorMessage> {
-        if self.active {
+        let events = if self.find_focused {
+            event::listen_with(map_find_event)
+        } else if self.goto_line.is_some() {
+            event::listen_with(map_goto_event)
+        } else if self.active {
             // Listen to all runtime events
             event::listen_with(map_runtime_event)
         } else {
             Subscription::none()
-        }
+        };
+        // Files can be dropped on the window before the editor has focus
+        let drops = event::listen_with(map_drop_event);
+        let autosave = if self.autosave.enabled && self.is_dirty && self.file.is_some() {
+            time::every(self.autosave.interval).map(|_| EditorMessage::AutosaveTick)
+        } else {
+            Subscription::none()
+        };
+        // One steady timer; a blink right after the caret moved keeps it on
+        let blink = if self.active {
+            time::every(CARET_BLINK).map(EditorMessage::CaretBlink)
+        } else {
+            Subscription::none()
+        };
+        Subscription::batch([events, drops, autosave, blink])
     }
 
-    fn set_cursor(&mut self, line: usize, column: usize) {
-        let last_line0 = self.buffer.get_line_count().saturating_sub(1);
-        self.line = line.min(last_line0);
-
-        let line_text = self.buffer.get_line_content(self.line + 1);
-        let max_col0 = grapheme_count(&line_text);
-        self.col = column.min(max_col0);
-
-        self.active = true;
-        self.render_version = self.render_version.wrapping_add(1);
+    // New or Open: ask first when there are unsaved changes
+    fn leave_file(&mut self, action: PendingAction) -> Task<EditorMessage> {
+        if self.is_loading || self.pending.is_some() {
+            return Task::none();
+        }
+        if self.is_dirty {
+            self.pending = Some(action);
+            return Task::perform(confirm_unsaved(), EditorMessage::UnsavedChangesAnswered);
+        }
+        self.run(action)
     }
 
-    fn insert(&mut self, to_insert: &str) {
-        self.input_value = to_insert.to_string();
+    fn run(&mut self, action: PendingAction) -> Task<EditorMessage> {
+        match action {
+            PendingAction::New => {
+                self.file = None;
+                self.syntax = DEFAULT_SYNTAX.to_string();
+                self.eol = Eol::default();
+                self.encoding = FileEncoding::default();
+                self.error = None;
+                self.buffer = TextBufferBuilder::new().finish();
+                self.input_value.clear();
+                self.set_cursor(0, 0);
+                self.selection = None;
+                self.mark_saved();
+                self.reset_stats();
+                self.refresh_preview();
+                self.render_version = self.render_version.wrapping_add(1);
+                Task::none()
+            }
+            PendingAction::Open => {
+                self.is_loading = true;
+                Task::perform(open(), EditorMessage::FileOpened)
+            }
+            PendingAction::OpenPath(path) => {
+                self.is_loading = true;
+                Task::perform(load(path), EditorMessage::FileOpened)
+            }
+        }
+    }
 
-        // If there is a selection, delete it first and move caret to start
-        if let Some((from, to)) = self.selection_range() {
-            self.delete_selection_range(from, to);
-            self.set_cursor(from.line, from.col);
-            self.selection = None;
+    fn save(&mut self, target: SaveTarget) -> Task<EditorMessage> {
+        if self.is_loading {
+            return Task::none();
+        }
+        self.is_loading = true;
+        self.saving = Some(self.current_text());
+        match target {
+            // UTF-8 goes from the pieces straight into the file
+            SaveTarget::Path(path) if self.encoding.encoding == UTF_8 => {
+                let result = self
+                    .write_utf8(&path)
+                    .map(|()| None)
+                    .map_err(|e| Error::IoError(e.kind()));
+                Task::done(EditorMessage::FileSaved(result))
+            }
+            SaveTarget::Path(path) => Task::perform(
+                save_to_path(path, self.saved_bytes()),
+                EditorMessage::FileSaved,
+            ),
+            SaveTarget::Prompt => Task::perform(
+                save_as(self.saved_bytes(), self.file.clone()),
+                EditorMessage::FileSaved,
+            ),
         }
+    }
 
-        let current_line = self.buffer.get_line_content(self.line + 1);
-        let byte_col0 = byte_col_for_grapheme_col(&current_line, self.col);
-        self.buffer
-            .insert_at(self.line + 1, byte_col0 + 1, to_insert);
+    // Stream the document into `path`, keeping the byte order mark it was opened with
+    fn write_utf8(&self, path: &Path) -> io::Result<()> {
+        save_atomic_with(path, |writer| {
+            writer.write_all(self.encoding.bom_bytes())?;
+            self.buffer
+                .save_to_writer(writer, EolMode::Normalize(self.eol))
+        })
+    }
 
-        if to_insert.contains('\n') {
-            let parts: Vec<&str> = to_insert.split('\n').collect();
-            self.line += parts.len() - 1;
-            self.col = parts.last().map(|s| grapheme_count(s)).unwrap_or(0);
+    fn refresh_bracket_pair(&mut self) {
+        // Blinks, ticks and scrolling leave the caret and the text as they were
+        if self.bracket_checked == Some((self.line, self.col, self.render_version)) {
+            return;
+        }
+        let pair = if self.highlight_brackets {
+            self.find_bracket_pair()
         } else {
-            self.col += grapheme_count(to_insert);
+            None
+        };
+        if pair != self.bracket_pair {
+            self.bracket_pair = pair;
+            self.render_version = self.render_version.wrapping_add(1);
         }
+        self.bracket_checked = Some((self.line, self.col, self.render_version));
+    }
 
+    // The bracket next to the caret and its partner; no partner for an unmatched bracket
+    fn find_bracket_pair(&self) -> Option<(Caret, Option<Caret>)> {
         let line_text = self.buffer.get_line_content(self.line + 1);
-        let max_col0 = grapheme_count(&line_text);
-        if self.col > max_col0 {
-            self.col = max_col0;
+        let col = bracket_near_caret(&line_text, self.col)?;
+        let byte_col0 = byte_col_for_grapheme_col(&line_text, col);
+        let offset = self.buffer.get_offset_at(self.line + 1, byte_col0 + 1);
+
+        let partner = self.buffer.matching_bracket(offset).map(|matched| {
+            let matched = self.buffer.get_position_at(matched);
+            let matched_text = self.buffer.get_line_content(matched.line());
+            Caret {
+                line: matched.line() - 1,
+                col: grapheme_count(&matched_text[..matched.column() - 1]),
+            }
+        });
+        Some((
+            Caret {
+                line: self.line,
+                col,
+            },
+            partner,
+        ))
+    }
+
+    // Parse the document again for the preview, if it is open
+    fn refresh_preview(&mut self) {
+        self.preview_due = None;
+        if let Some(blocks) = &mut self.preview {
+            *blocks = markdown::parse(&self.buffer.get_text());
         }
-        self.preferred_col = Some(self.col);
-        self.input_value.clear();
-        self.is_dirty = true;
-        self.selection = None;
-        self.render_version = self.render_version.wrapping_add(1);
     }
 
-    fn enter(&mut self) {
-        if let Some((from, to)) = self.selection_range() {
-            self.delete_selection_range(from, to);
-            self.set_cursor(from.line, from.col);
-            self.selection = None;
+    // Count a newly loaded buffer in full; edits then update the counts incrementally
+    fn reset_stats(&mut self) {
+        self.buffer.record_changes();
+        self.stats = self.buffer.stats();
+    }
+
+    // Counts of the selected text, if any
+    fn selection_stats(&self) -> Option<TextStats> {
+        let (from, to) = self.selection_range()?;
+        let text = self
+            .buffer
+            .get_text_in_range(self.offset_of(from), self.offset_of(to));
+        Some(TextStats::of(&text))
+    }
+
+    // The bytes of the saved file, every line break made `eol`, in the
+    // encoding it was opened with
+    fn saved_bytes(&self) -> Vec<u8> {
+        let mut text = Vec::with_capacity(self.buffer.get_length());
+        // Writing to memory cannot fail
+        let _ = self
+            .buffer
+            .save_to_writer(&mut text, EolMode::Normalize(self.eol));
+        if self.encoding == FileEncoding::default() {
+            return text;
+        }
+        // The buffer only ever holds UTF-8
+        match std::str::from_utf8(&text) {
+            Ok(text) => self.encoding.encode(text),
+            Err(_) => text,
+        }
+    }
+
+    // Drop-down of the recent files that still exist, if any
+    fn recent_menu(&self) -> Option<Element<'_, EditorMessage>> {
+        let entries: Vec<RecentFile> = self
+            .recent
+            .iter()
+            .filter(|path| path.exists())
+            .cloned()
+            .map(RecentFile)
+            .collect();
+        (!entries.is_empty()).then(|| {
+            pick_list(entries, None::<RecentFile>, |entry| {
+                EditorMessage::OpenRecent(entry.0)
+            })
+            .placeholder("Open Recent")
+            .text_size(12)
+            .padding([0, 4])
+            .into()
+        })
+    }
+
+    // File shown in the status bar, with a dot in front while there are unsaved changes
+    fn file_status(&self) -> String {
+        let label = file_label(self.file.as_ref());
+        if self.is_dirty {
+            format!("• {label}")
+        } else {
+            label
+        }
+    }
+
+    // Put the current file first in the recent files list and store the list
+    fn remember_recent(&mut self) -> Task<EditorMessage> {
+        let Some(path) = self.file.clone() else {
+            return Task::none();
+        };
+        push_recent(&mut self.recent, path, MAX_RECENT);
+        Task::future(store_recent(self.recent.clone())).discard()
+    }
+
+    // Copy the document to its recovery file, streamed a piece at a time
+    fn write_recovery(&mut self) {
+        let Some(path) = self.file.as_deref().and_then(recovery_path) else {
+            return;
+        };
+        self.edits_since_autosave = 0;
+        if let Err(error) = save_atomic_with(&path, |writer| self.buffer.write_to(writer)) {
+            self.error = Some(format!("Could not write recovery file: {error}"));
+        }
+    }
+
+    // The document as it is now, for a save to record once written
+    fn current_text(&self) -> SavedText {
+        SavedText {
+            version: self.buffer.version(),
+            len: self.buffer.get_length(),
+            hash: self.buffer.content_hash(),
+            eol: self.eol,
+            lines: self.buffer.snapshot_lines(),
+        }
+    }
+
+    // Record the current text as the saved state
+    fn mark_saved(&mut self) {
+        self.mark_saved_as(self.current_text());
+    }
+
+    // Record `saved` as the saved state. Edits made since it was taken stay dirty, and the
+    // gutter diffs them against it
+    fn mark_saved_as(&mut self, saved: SavedText) {
+        self.saved_state = (saved.len, saved.hash);
+        self.saved_version = saved.version;
+        self.saved_eol = saved.eol;
+        self.saved_lines = saved.lines;
+        self.edits_since_autosave = 0;
+        self.refresh_dirty();
+        self.refresh_line_changes();
+    }
+
+    // Dirty while the buffer is at another version than the saved text, which undo and redo
+    // return to. Text edited back by hand is only recognised by `refresh_line_changes`
+    fn refresh_dirty(&mut self) {
+        self.is_dirty = self.buffer.version() != self.saved_version || self.eol != self.saved_eol;
+    }
+
+    // Diff the document against the saved lines for the gutter markers, and clear dirty when
+    // the text turns out to match the saved text after all; the length check avoids hashing
+    fn refresh_line_changes(&mut self) {
+        self.line_changes_due = None;
+        let (saved_len, saved_hash) = self.saved_state;
+        let text_changed = self.buffer.version() != self.saved_version
+            && (self.buffer.get_length() != saved_len || self.buffer.content_hash() != saved_hash);
+        if !text_changed {
+            self.saved_version = self.buffer.version();
+            self.refresh_dirty();
+        }
+        self.line_changes = if text_changed {
+            let lines = self.buffer.snapshot_lines();
+            line_changes(&diff_lines(&self.saved_lines, &lines), lines.len())
+        } else {
+            Vec::new()
+        };
+    }
+
+    fn set_cursor(&mut self, line: usize, column: usize) {
+        let last_line0 = self.buffer.get_line_count().saturating_sub(1);
+        self.line = line.min(last_line0);
+
+        let line_text = self.buffer.get_line_content(self.line + 1);
+        let max_col0 = grapheme_count(&line_text);
+        self.col = column.min(max_col0);
+
+        self.active = true;
+        self.render_version = self.render_version.wrapping_add(1);
+    }
+
+    fn insert(&mut self, to_insert: &str) {
+        self.input_value = to_insert.to_string();
+        self.type_text(to_insert);
+    }
+
+    // Break the line. With auto-indent the new line starts with the indentation of the
+    // text before the caret, and in Markdown a list item continues with the next marker,
+    // while Enter on an empty item takes its marker off instead.
+    fn enter(&mut self) {
+        if !self.auto_indent || self.selection_range().is_some() {
+            self.type_text(self.eol.as_str());
+            return;
+        }
+        let line_text = self.buffer.get_line_content(self.line + 1);
+        let caret_b0 = byte_col_for_grapheme_col(&line_text, self.col);
+        let markdown = matches!(self.syntax.as_str(), "md" | "markdown");
+        match line_break(&line_text, caret_b0, markdown) {
+            LineBreak::Continue(indent) => {
+                self.type_text(&format!("{}{indent}", self.eol.as_str()))
+            }
+            LineBreak::EndList(marker) => {
+                let line_start = self.buffer.get_offset_at(self.line + 1, 1);
+                self.buffer.delete(line_start + marker.start, marker.len());
+                self.col = grapheme_count(&line_text[..marker.start]);
+                self.preferred_col = Some(self.display_col());
+                self.input_value.clear();
+                self.refresh_dirty();
+                self.render_version = self.render_version.wrapping_add(1);
+            }
+        }
+    }
+
+    // Replace the selection (or insert at the caret) with `text`, leaving the caret after it
+    fn type_text(&mut self, text: &str) {
+        let caret = Caret {
+            line: self.line,
+            col: self.col,
+        };
+        let (from, to) = self.selection_range().unwrap_or((caret, caret));
+        self.buffer
+            .type_over_selection(self.offset_of(from), self.offset_of(to), text);
+
+        let caret = caret_after_insert(from, text);
+        self.set_cursor(caret.line, caret.col);
+        self.selection = None;
+        self.preferred_col = Some(self.display_col());
+        self.input_value.clear();
+        self.refresh_dirty();
+        self.render_version = self.render_version.wrapping_add(1);
+    }
+
+    // Toggle the word under the caret (`true` to `false`) or step its trailing number,
+    // leaving the caret after the new word
+    fn toggle_word_at_caret(&mut self, step: i64) {
+        let offset = self.offset_of(Caret {
+            line: self.line,
+            col: self.col,
+        });
+        let Some(range) = self.buffer.word_range_at(offset) else {
+            return;
+        };
+        let Some(replacement) = self
+            .buffer
+            .word_at(offset)
+            .and_then(|word| step_word(&word, step))
+        else {
+            return;
+        };
+        self.buffer.replace(range.clone(), &replacement);
+        self.replaced_text_at(range.start + replacement.len());
+    }
+
+    // Wrap the selection in `mark`, or unwrap it if it already is, keeping the text selected.
+    // Without a selection the caret goes between a new pair, or an empty pair around it is
+    // removed
+    fn toggle_mark(&mut self, mark: &str) {
+        let caret = Caret {
+            line: self.line,
+            col: self.col,
+        };
+        let (from, to) = self.selection_range().unwrap_or((caret, caret));
+        let (start, end) = (self.offset_of(from), self.offset_of(to));
+        let before = self.buffer.get_line_content(from.line + 1);
+        let after = self.buffer.get_line_content(to.line + 1);
+        let toggle = mark_toggle(
+            &before[..byte_col_for_grapheme_col(&before, from.col)],
+            &self.buffer.get_text_in_range(start, end),
+            &after[byte_col_for_grapheme_col(&after, to.col)..],
+            mark,
+        );
+
+        let n = mark.len();
+        let (start, end) = match toggle {
+            MarkToggle::UnwrapInside => {
+                self.buffer.batch(|batch| {
+                    batch.delete(start..start + n);
+                    batch.delete(end - n..end);
+                });
+                (start, end - 2 * n)
+            }
+            MarkToggle::UnwrapOutside => {
+                self.buffer.batch(|batch| {
+                    batch.delete(start - n..start);
+                    batch.delete(end..end + n);
+                });
+                (start - n, end - n)
+            }
+            MarkToggle::Wrap => {
+                self.buffer.batch(|batch| {
+                    batch.insert(start, mark);
+                    batch.insert(end, mark);
+                });
+                (start + n, end + n)
+            }
+        };
+        self.replaced_text_at(end);
+        if start < end {
+            self.select_offsets(start, end);
+        }
+    }
+
+    // Give the caret line, or every line of the selection, a list marker or heading hashes,
+    // or take them off when all the lines have them
+    fn toggle_line_prefix(&mut self, prefix: LinePrefix) {
+        let lines = self.selected_lines();
+        let text = self
+            .buffer
+            .get_lines_range(lines.start() + 1, lines.end() + 2);
+        self.edit_line_starts(*lines.start(), &toggle_prefix(&text, prefix));
+    }
+
+    // Indent every line the selection touches by `amount` units of the tab style, or outdent
+    // them when it is negative
+    fn indent_selection(&mut self, amount: i32) {
+        let lines = self.selected_lines();
+        let text = self
+            .buffer
+            .get_lines_range(lines.start() + 1, lines.end() + 2);
+        let unit = match self.tab {
+            TabStyle::Hard => String::from("\t"),
+            TabStyle::Spaces(n) => " ".repeat(n),
+        };
+        let tab_width = self.tab.indent_width(self.tab_width.unwrap_or(TAB_WIDTH));
+        let edits = text_column::indent_edits(&text, &unit, amount, tab_width);
+        self.edit_line_starts(*lines.start(), &edits);
+    }
+
+    // Copy the caret line, or the selected lines, to just below, the caret and selection
+    // moving along onto the copy
+    fn duplicate_lines(&mut self) {
+        let lines = self.selected_lines();
+        self.buffer
+            .duplicate_lines(lines.start() + 1..lines.end() + 2, self.eol);
+        self.shift_lines(lines.count() as isize);
+    }
+
+    // Swap the caret line, or the selected lines, with the line above or below, unless they
+    // are already first or last
+    fn move_lines(&mut self, down: bool) {
+        let lines = self.selected_lines();
+        if self
+            .buffer
+            .move_lines(lines.start() + 1..lines.end() + 2, down)
+        {
+            self.shift_lines(if down { 1 } else { -1 });
+        }
+    }
+
+    // Move the caret and selection `by` lines after the lines under them moved as a whole,
+    // keeping their columns
+    fn shift_lines(&mut self, by: isize) {
+        let moved = |caret: Caret| Caret {
+            line: caret.line.saturating_add_signed(by),
+            col: caret.col,
+        };
+        let head = moved(Caret {
+            line: self.line,
+            col: self.col,
+        });
+        self.selection = self.selection.map(|sel| Selection {
+            anchor: moved(sel.anchor),
+            head,
+        });
+        self.set_cursor(head.line, head.col);
+        self.preferred_col = Some(self.display_col());
+        self.input_value.clear();
+        self.refresh_dirty();
+        self.render_version = self.render_version.wrapping_add(1);
+    }
+
+    // Whether the selection goes on past the end of a line
+    fn spans_lines(&self) -> bool {
+        self.selection_range()
+            .is_some_and(|(from, to)| from.line != to.line)
+    }
+
+    // 0-based lines of the selection, or the caret line. A selection ending at the start of a
+    // line leaves that line out
+    fn selected_lines(&self) -> std::ops::RangeInclusive<usize> {
+        let caret = Caret {
+            line: self.line,
+            col: self.col,
+        };
+        let (from, to) = self.selection_range().unwrap_or((caret, caret));
+        if to.col == 0 && to.line > from.line {
+            from.line..=to.line - 1
+        } else {
+            from.line..=to.line
         }
+    }
+
+    // Replace bytes at the start of the lines from `first` on, one edit per line as planned
+    // by `toggle_prefix` or `indent_edits`, as one undo step
+    fn edit_line_starts(&mut self, first: usize, edits: &[(std::ops::Range<usize>, String)]) {
+        let starts: Vec<usize> = (first..first + edits.len())
+            .map(|line| self.buffer.get_offset_at(line + 1, 1))
+            .collect();
+        self.buffer.batch(|batch| {
+            for (start, (range, text)) in starts.iter().zip(edits) {
+                batch.replace(start + range.start..start + range.end, text);
+            }
+        });
 
-        let current_line = self.buffer.get_line_content(self.line + 1);
-        let byte_col0 = byte_col_for_grapheme_col(&current_line, self.col);
-        self.buffer.insert_at(self.line + 1, byte_col0 + 1, "\n");
-        self.line += 1;
-        self.col = 0;
-        self.preferred_col = Some(self.col);
-        self.is_dirty = true;
+        // Carets keep their place in the text, and one at the start of its line stays there
+        // so whole lines stay selected. Line starts are ASCII, a grapheme per byte
+        let caret = Caret {
+            line: self.line,
+            col: self.col,
+        };
+        let moved = |caret: Caret| match edits.get(caret.line.wrapping_sub(first)) {
+            Some((range, text)) if caret.col > 0 && caret.col >= range.start => Caret {
+                line: caret.line,
+                col: caret.col.max(range.end) - range.len() + text.len(),
+            },
+            _ => caret,
+        };
+        let head = moved(caret);
+        self.selection = self.selection.map(|sel| Selection {
+            anchor: moved(sel.anchor),
+            head,
+        });
+        self.set_cursor(head.line, head.col);
+        self.preferred_col = Some(self.display_col());
+        self.input_value.clear();
+        self.refresh_dirty();
+        self.render_version = self.render_version.wrapping_add(1);
+    }
+
+    // Tab expands a snippet trigger right before the caret, and otherwise indents: a tab
+    // character, or spaces up to the next tab stop
+    fn tab(&mut self) {
+        let line_text = self.buffer.get_line_content(self.line + 1);
+        let caret_b0 = byte_col_for_grapheme_col(&line_text, self.col);
+        let expansion = match self.selection_range() {
+            None => self.snippets.expand(&line_text[..caret_b0]),
+            Some(_) => None,
+        };
+        let Some(expansion) = expansion else {
+            match self.tab {
+                TabStyle::Hard => self.type_text("\t"),
+                TabStyle::Spaces(n) => {
+                    let spaces = text_column::spaces_to_tab_stop(&line_text[..caret_b0], n);
+                    self.type_text(&" ".repeat(spaces));
+                }
+            }
+            return;
+        };
+
+        let caret = self.offset_of(Caret {
+            line: self.line,
+            col: self.col,
+        });
+        let start = caret - expansion.trigger_len;
+        self.buffer.replace(start..caret, &expansion.text);
+        self.replaced_text_at(start + expansion.caret);
+    }
+
+    // Remove one indentation unit from the start of the caret's line
+    fn dedent(&mut self) {
+        let line_text = self.buffer.get_line_content(self.line + 1);
+        let unit = self.tab.indent_width(self.tab_width.unwrap_or(TAB_WIDTH));
+        let removed = text_column::dedent_len(&line_text, unit);
+        if removed == 0 {
+            return;
+        }
+        let line_start = self.buffer.get_offset_at(self.line + 1, 1);
+        self.buffer.delete(line_start, removed);
+        // Indentation is ASCII, a grapheme per byte
+        self.col = self.col.saturating_sub(removed);
+        self.selection = None;
+        self.preferred_col = Some(self.display_col());
+        self.input_value.clear();
+        self.refresh_dirty();
         self.render_version = self.render_version.wrapping_add(1);
+    }
+
+    // Undo (or redo) one step, moving the caret to where the buffer says the step ended
+    fn undo(&mut self, redo: bool) {
+        let landed = if redo {
+            self.buffer.redo()
+        } else {
+            self.buffer.undo()
+        };
+        if let Some(landed) = landed {
+            self.replaced_text_at(landed.offset);
+        }
+    }
+
+    fn focus_find(&mut self) -> Task<EditorMessage> {
+        if self.find.is_none() {
+            return text_input::focus(self.input_id.clone());
+        }
+        self.find_focused = true;
+        text_input::focus(self.find_input_id.clone())
+    }
+
+    // Search the document again for the find bar's query
+    fn refresh_matches(&mut self) {
+        if let Some(find) = &mut self.find {
+            find.set_matches(self.buffer.find_all(&find.query, SearchOptions::default()));
+        }
+    }
+
+    // Select the next (or previous) match after (or before) the caret or selection
+    fn find_step(&mut self, forward: bool) {
+        let Some(find) = &self.find else {
+            return;
+        };
+        let caret = Caret {
+            line: self.line,
+            col: self.col,
+        };
+        let (from, to) = self.selection_range().unwrap_or((caret, caret));
+        let index = if forward {
+            find.next_from(self.offset_of(to))
+        } else {
+            find.prev_from(self.offset_of(from))
+        };
+        if let Some(index) = index {
+            self.select_match(index);
+        }
+    }
+
+    fn select_match(&mut self, index: usize) {
+        let Some(find) = &mut self.find else {
+            return;
+        };
+        find.current = Some(index);
+        let start = find.matches[index];
+        let end = start + find.query.len();
+        self.select_offsets(start, end);
+    }
+
+    // Replace the selected match, if a match is selected, and move on to the next one
+    fn replace_current(&mut self) {
+        let Some(find) = &self.find else {
+            return;
+        };
+        let current = find
+            .current
+            .map(|i| (find.matches[i], find.matches[i] + find.query.len()));
+        let replacement = find.replacement.clone();
+        let selected = self
+            .selection_range()
+            .map(|(from, to)| (self.offset_of(from), self.offset_of(to)));
+        if current.is_some() && selected == current {
+            self.type_text(&replacement);
+            self.refresh_matches();
+        }
+        self.find_step(true);
+    }
+
+    // Replace every match as one undo step, from the last so earlier offsets stay valid
+    fn replace_all(&mut self) {
+        let Some(find) = &self.find else {
+            return;
+        };
+        let Some(&first) = find.matches.first() else {
+            return;
+        };
+        let query_len = find.query.len();
+        let replacement = find.replacement.clone();
+        let matches = find.matches.clone();
+
+        self.buffer.batch(|batch| {
+            for &start in &matches {
+                batch.replace(start..start + query_len, &replacement);
+            }
+        });
+        self.replaced_text_at(first + replacement.len());
+        self.refresh_matches();
+    }
+
+    // Caret at byte `offset` of the document
+    fn caret_at(&self, offset: usize) -> Caret {
+        let position = self.buffer.get_position_at(offset);
+        let line_text = self.buffer.get_line_content(position.line());
+        Caret {
+            line: position.line() - 1,
+            col: grapheme_count(&line_text[..position.column() - 1]),
+        }
+    }
+
+    // After replacing text in place: caret to byte `offset`, selection gone, redraw
+    fn replaced_text_at(&mut self, offset: usize) {
+        let caret = self.caret_at(offset);
+        self.set_cursor(caret.line, caret.col);
+        self.selection = None;
+        self.preferred_col = Some(self.display_col());
         self.input_value.clear();
+        self.refresh_dirty();
+        self.render_version = self.render_version.wrapping_add(1);
+    }
+
+    // Byte offset of a caret in the document
+    fn offset_of(&self, caret: Caret) -> usize {
+        let line_text = self.buffer.get_line_content(caret.line + 1);
+        let byte_col0 = byte_col_for_grapheme_col(&line_text, caret.col);
+        self.buffer.get_offset_at(caret.line + 1, byte_col0 + 1)
     }
 
     fn backspace(&mut self) {
@@ -401,7 +1789,7 @@ impl App {
             self.delete_selection_range(from, to);
             self.set_cursor(from.line, from.col);
             self.selection = None;
-            self.is_dirty = true;
+            self.refresh_dirty();
             self.render_version = self.render_version.wrapping_add(1);
             self.input_value.clear();
             return;
@@ -425,7 +1813,7 @@ impl App {
             self.line -= 1;
             self.col = grapheme_count(&prev_text_before);
         }
-        self.is_dirty = true;
+        self.refresh_dirty();
         self.render_version = self.render_version.wrapping_add(1);
         self.input_value.clear();
     }
@@ -438,7 +1826,7 @@ impl App {
             let end_prev = grapheme_count(&self.buffer.get_line_content(prev_line + 1));
             self.set_cursor(prev_line, end_prev);
         }
-        self.preferred_col = Some(self.col);
+        self.preferred_col = Some(self.display_col());
     }
 
     fn cursor_right(&mut self) {
@@ -448,64 +1836,251 @@ impl App {
         } else if self.line + 1 < self.buffer.get_line_count() {
             self.set_cursor(self.line + 1, 0);
         }
-        self.preferred_col = Some(self.col);
+        self.preferred_col = Some(self.display_col());
+    }
+
+    // Move the caret to the next or previous word boundary, extending the selection to it
+    // when `extend`
+    fn word_step(&mut self, forward: bool, extend: bool) {
+        let offset = self.offset_of(Caret {
+            line: self.line,
+            col: self.col,
+        });
+        let target = if forward {
+            self.buffer.next_word_boundary(offset)
+        } else {
+            self.buffer.prev_word_boundary(offset)
+        };
+        let caret = self.caret_at(target);
+        if extend {
+            self.extend_selection_to(caret.line, caret.col);
+        } else {
+            self.set_cursor(caret.line, caret.col);
+            self.preferred_col = Some(self.display_col());
+        }
+    }
+
+    // Move the caret a screenful of rows down or up, keeping its column, and stopping at the
+    // first or last row of the document
+    fn page(&mut self, down: bool) {
+        let rows = (self.viewport_height / self.line_height()).floor() as usize;
+        for _ in 0..rows.max(1) {
+            let next = if down {
+                self.row_below_caret()
+            } else {
+                self.row_above_caret()
+            };
+            let Some((line, col)) = next else {
+                break;
+            };
+            self.set_cursor(line, col);
+        }
+    }
+
+    // Smart Home: to the first non-whitespace grapheme of the line, or to column 0 when
+    // already there
+    fn cursor_home(&mut self) {
+        let line_text = self.buffer.get_line_content(self.line + 1);
+        let indent = grapheme_count(leading_whitespace(&line_text));
+        let col = if self.col == indent { 0 } else { indent };
+        self.set_cursor(self.line, col);
+        self.preferred_col = Some(self.display_col());
+    }
+
+    fn cursor_end(&mut self) {
+        let end = grapheme_count(&self.buffer.get_line_content(self.line + 1));
+        self.set_cursor(self.line, end);
+        self.preferred_col = Some(self.display_col());
     }
 
     fn cursor_up(&mut self) {
-        if self.line == 0 {
-            return;
+        if let Some((line, col)) = self.row_above_caret() {
+            self.set_cursor(line, col);
         }
-        let desired = self.preferred_col.unwrap_or(self.col);
-        self.set_cursor(self.line.saturating_sub(1), desired);
     }
 
     fn cursor_down(&mut self) {
-        if self.line + 1 >= self.buffer.get_line_count() {
-            return;
+        if let Some((line, col)) = self.row_below_caret() {
+            self.set_cursor(line, col);
         }
-        let desired = self.preferred_col.unwrap_or(self.col);
-        self.set_cursor(self.line + 1, desired);
     }
 
-    fn selection_range(&self) -> Option<(Caret, Caret)> {
-        if let Some(sel) = self.selection {
-            if sel.anchor == sel.head {
-                None
-            } else if (sel.head.line, sel.head.col) < (sel.anchor.line, sel.anchor.col) {
-                Some((sel.head, sel.anchor))
-            } else {
-                Some((sel.anchor, sel.head))
+    // Scroll long lines sideways so the caret stays inside the text area; wrapped lines
+    // always fit
+    fn reveal_caret_x(&mut self) {
+        let scroll_x = match self.wrap_column {
+            Some(_) => 0.0,
+            None if self.viewport_width <= 0.0 => self.scroll_x,
+            None => {
+                let metrics = GlyphMetrics::shared(Font::MONOSPACE, self.font_size);
+                let cell = metrics.cell_width();
+                let line = self.buffer.get_line_content(self.line + 1);
+                let before = &line[..byte_col_for_grapheme_col(&line, self.col)];
+                let x = metrics.width(before, self.tab_width);
+                let gutter =
+                    editor_canvas::gutter_width(&self.gutter, self.buffer.get_line_count(), cell);
+                let margin = SCROLL_MARGIN_CELLS as f32 * cell;
+                scroll::reveal(
+                    self.scroll_x,
+                    x,
+                    x + cell,
+                    self.viewport_width - gutter,
+                    margin,
+                )
             }
-        } else {
-            None
+        };
+        if scroll_x != self.scroll_x {
+            self.scroll_x = scroll_x;
+            self.render_version = self.render_version.wrapping_add(1);
         }
     }
 
-    fn delete_selection_range(&mut self, from: Caret, to: Caret) {
-        if (from.line, from.col) == (to.line, to.col) {
-            return;
+    // Scroll the editor up or down to bring the caret's row into view
+    fn reveal_caret_y(&mut self) -> Task<EditorMessage> {
+        if self.viewport_height <= 0.0 {
+            return Task::none();
         }
+        let line_height = self.line_height();
+        let y = self.caret_row() as f32 * line_height;
+        let margin = SCROLL_MARGIN_ROWS as f32 * line_height;
+        let scroll_y = scroll::reveal(
+            self.scroll_y,
+            y,
+            y + line_height,
+            self.viewport_height,
+            margin,
+        );
+        if scroll_y == self.scroll_y {
+            return Task::none();
+        }
+        // Taken as scrolled already, so the moves that follow build on it
+        self.scroll_y = scroll_y;
+        scrollable::scroll_to(
+            self.scroll_id.clone(),
+            scrollable::AbsoluteOffset {
+                x: 0.0,
+                y: scroll_y,
+            },
+        )
+    }
 
-        let start_line1 = from.line + 1;
-        let start_line_text = self.buffer.get_line_content(start_line1);
-        let start_b0 = byte_col_for_grapheme_col(&start_line_text, from.col);
-        let start_off = self.buffer.get_offset_at(start_line1, start_b0 + 1);
+    fn line_height(&self) -> f32 {
+        self.font_size * self.line_spacing
+    }
 
-        let end_line1 = to.line + 1;
-        let end_line_text = self.buffer.get_line_content(end_line1);
-        let end_b0 = byte_col_for_grapheme_col(&end_line_text, to.col);
-        let end_off = self.buffer.get_offset_at(end_line1, end_b0 + 1);
+    // Display row of the caret from the top of the document
+    fn caret_row(&self) -> usize {
+        let Some(width) = self.wrap_column else {
+            return self.line;
+        };
+        let lines = self.buffer.snapshot_lines();
+        let above: usize = lines[..self.line]
+            .iter()
+            .map(|line| wrap_rows(line, width, self.tab_width).len())
+            .sum();
+        let rows = wrap_rows(&lines[self.line], width, self.tab_width);
+        above + wrap::row_of(&rows, self.col)
+    }
 
-        if end_off > start_off {
-            self.buffer.delete(start_off, end_off - start_off);
+    // Caret column as displayed: within its wrapped row when soft wrap is on
+    fn display_col(&self) -> usize {
+        match self.wrap_column {
+            Some(width) => {
+                let line = self.buffer.get_line_content(self.line + 1);
+                let rows = wrap_rows(&line, width, self.tab_width);
+                wrap::visual_position(&line, &rows, self.col, self.tab_width).1
+            }
+            None => self.col,
         }
+    }
 
-        // Move caret to start of selection and clear selection
+    // (line, col) one display row above the caret, None at the top of the document
+    fn row_above_caret(&self) -> Option<(usize, usize)> {
+        let desired = self.preferred_col.unwrap_or(self.display_col());
+        let Some(width) = self.wrap_column else {
+            return (self.line > 0).then(|| (self.line - 1, desired));
+        };
+
+        let line = self.buffer.get_line_content(self.line + 1);
+        let rows = wrap_rows(&line, width, self.tab_width);
+        if let Some(col) = wrap::row_above(&line, &rows, self.col, desired, self.tab_width) {
+            return Some((self.line, col));
+        }
+        if self.line == 0 {
+            return None;
+        }
+        let line = self.buffer.get_line_content(self.line);
+        let prev = wrap_rows(&line, width, self.tab_width);
+        Some((
+            self.line - 1,
+            wrap::column_at(&line, &prev, prev.len() - 1, desired, self.tab_width),
+        ))
+    }
+
+    // (line, col) one display row below the caret, None at the bottom of the document
+    fn row_below_caret(&self) -> Option<(usize, usize)> {
+        let desired = self.preferred_col.unwrap_or(self.display_col());
+        let has_next_line = self.line + 1 < self.buffer.get_line_count();
+        let Some(width) = self.wrap_column else {
+            return has_next_line.then(|| (self.line + 1, desired));
+        };
+
+        let line = self.buffer.get_line_content(self.line + 1);
+        let rows = wrap_rows(&line, width, self.tab_width);
+        if let Some(col) = wrap::row_below(&line, &rows, self.col, desired, self.tab_width) {
+            return Some((self.line, col));
+        }
+        if !has_next_line {
+            return None;
+        }
+        let line = self.buffer.get_line_content(self.line + 2);
+        let next = wrap_rows(&line, width, self.tab_width);
+        Some((
+            self.line + 1,
+            wrap::column_at(&line, &next, 0, desired, self.tab_width),
+        ))
+    }
+
+    fn selected_text(&self) -> Option<String> {
+        let (from, to) = self.selection_range()?;
+        Some(
+            self.buffer
+                .get_text_in_range(self.offset_of(from), self.offset_of(to)),
+        )
+    }
+
+    fn selection_range(&self) -> Option<(Caret, Caret)> {
+        if let Some(sel) = self.selection {
+            if sel.anchor == sel.head {
+                None
+            } else if (sel.head.line, sel.head.col) < (sel.anchor.line, sel.anchor.col) {
+                Some((sel.head, sel.anchor))
+            } else {
+                Some((sel.anchor, sel.head))
+            }
+        } else {
+            None
+        }
+    }
+
+    fn delete_selection_range(&mut self, from: Caret, to: Caret) {
+        if (from.line, from.col) == (to.line, to.col) {
+            return;
+        }
+
+        let start_off = self.offset_of(from);
+        let end_off = self.offset_of(to);
+
+        if end_off > start_off {
+            self.buffer.delete(start_off, end_off - start_off);
+        }
+
+        // Move caret to start of selection and clear selection
         self.line = from.line;
         self.col = from.col;
         self.selection = None;
-        self.is_dirty = true;
-        self.preferred_col = Some(self.col);
+        self.refresh_dirty();
+        self.preferred_col = Some(self.display_col());
         self.render_version = self.render_version.wrapping_add(1);
     }
 
@@ -521,6 +2096,39 @@ impl App {
         });
     }
 
+    // Select the word at a position, as on a double-click. Off a word only the caret moves.
+    fn select_word(&mut self, line: usize, column: usize) {
+        self.begin_selection(line, column);
+        let offset = self.offset_of(Caret {
+            line: self.line,
+            col: self.col,
+        });
+        if let Some(word) = self.buffer.word_range_at(offset) {
+            self.select_offsets(word.start, word.end);
+        }
+    }
+
+    // Select a whole line with its line break, as on a triple-click
+    fn select_line(&mut self, line: usize) {
+        let line = line.min(self.buffer.get_line_count().saturating_sub(1));
+        let start = self.buffer.get_offset_at(line + 1, 1);
+        let end = if line + 1 < self.buffer.get_line_count() {
+            self.buffer.get_offset_at(line + 2, 1)
+        } else {
+            self.buffer.get_length()
+        };
+        self.select_offsets(start, end);
+    }
+
+    // Select the bytes `start..end` with the caret at the end
+    fn select_offsets(&mut self, start: usize, end: usize) {
+        let anchor = self.caret_at(start);
+        let head = self.caret_at(end);
+        self.set_cursor(head.line, head.col);
+        self.selection = Some(Selection { anchor, head });
+        self.preferred_col = Some(self.display_col());
+    }
+
     fn extend_selection_to(&mut self, line: usize, column: usize) {
         let anchor = if let Some(sel) = self.selection {
             sel.anchor
@@ -538,7 +2146,7 @@ impl App {
                 col: self.col,
             },
         });
-        self.preferred_col = Some(self.col);
+        self.preferred_col = Some(self.display_col());
     }
 
     fn extend_left(&mut self) {
@@ -565,34 +2173,24 @@ impl App {
     }
 
     fn extend_up(&mut self) {
-        if self.line == 0 {
-            return;
+        if let Some((line, col)) = self.row_above_caret() {
+            let desired = self.preferred_col;
+            self.extend_selection_to(line, col);
+            self.preferred_col = desired.or(self.preferred_col);
         }
-        let desired = self.preferred_col.unwrap_or(self.col);
-        let line = self.line.saturating_sub(1);
-        self.extend_selection_to(line, desired);
     }
 
     fn extend_down(&mut self) {
-        if self.line + 1 >= self.buffer.get_line_count() {
-            return;
+        if let Some((line, col)) = self.row_below_caret() {
+            let desired = self.preferred_col;
+            self.extend_selection_to(line, col);
+            self.preferred_col = desired.or(self.preferred_col);
         }
-        let desired = self.preferred_col.unwrap_or(self.col);
-        let line = self.line + 1;
-        self.extend_selection_to(line, desired);
     }
 
     fn select_all(&mut self) {
-        let last_line = self.buffer.get_line_count().saturating_sub(1);
-        let last_col = grapheme_count(&self.buffer.get_line_content(last_line + 1));
-        self.selection = Some(Selection {
-            anchor: Caret { line: 0, col: 0 },
-            head: Caret {
-                line: last_line,
-                col: last_col,
-            },
-        });
-        self.set_cursor(last_line, last_col);
+        self.select_offsets(0, self.buffer.get_length());
+        self.render_version = self.render_version.wrapping_add(1);
     }
 
     fn delete_forward(&mut self) {
@@ -600,6 +2198,9 @@ impl App {
             self.delete_selection_range(from, to);
             self.set_cursor(from.line, from.col);
             self.selection = None;
+            self.refresh_dirty();
+            self.render_version = self.render_version.wrapping_add(1);
+            self.input_value.clear();
             return;
         }
 
@@ -611,40 +2212,207 @@ impl App {
             let len = end_b0.saturating_sub(start_b0);
             if len > 0 {
                 self.buffer.delete_at(self.line + 1, start_b0 + 1, len);
-                self.is_dirty = true;
+                self.refresh_dirty();
             }
         } else if self.line + 1 < self.buffer.get_line_count() {
+            // The whole line break, so a CRLF does not leave a lone LF behind
             let end_col1 = self.buffer.get_line_length(self.line + 1) + 1;
-            self.buffer.delete_at(self.line + 1, end_col1, 1);
-            self.is_dirty = true;
+            let eol_len = self.buffer.get_offset_at(self.line + 2, 1)
+                - self.buffer.get_offset_at(self.line + 1, end_col1);
+            self.buffer.delete_at(self.line + 1, end_col1, eol_len);
+            self.refresh_dirty();
         }
         self.render_version = self.render_version.wrapping_add(1);
         self.input_value.clear();
     }
+
+    // Ctrl+K / Ctrl+U: delete from the caret to the end (or start) of its line
+    fn delete_to_line_boundary(&mut self, to_end: bool) {
+        let line1 = self.line + 1;
+        let caret = self.offset_of(Caret {
+            line: self.line,
+            col: self.col,
+        });
+        let line_start = self.buffer.get_offset_at(line1, 1);
+        let range = if to_end {
+            let line_end = line_start + self.buffer.get_line_length(line1);
+            let next_line_start = if line1 < self.buffer.get_line_count() {
+                self.buffer.get_offset_at(line1 + 1, 1)
+            } else {
+                line_end
+            };
+            kill_to_end_range(caret, line_end, next_line_start)
+        } else {
+            kill_to_start_range(line_start, caret)
+        };
+
+        if !range.is_empty() {
+            self.buffer.delete(range.start, range.len());
+            self.refresh_dirty();
+        }
+        if !to_end {
+            self.set_cursor(self.line, 0);
+        }
+        self.selection = None;
+        self.preferred_col = Some(self.display_col());
+        self.render_version = self.render_version.wrapping_add(1);
+        self.input_value.clear();
+    }
 }
 
-async fn open() -> Result<(PathBuf, Vec<String>), Error> {
+async fn open() -> Result<(PathBuf, Vec<String>, FileEncoding), Error> {
     let file = rfd::AsyncFileDialog::new()
         .set_title("Open a text file...")
         .pick_file()
         .await
         .ok_or(Error::DialogClosed)?;
 
-    let path = file.path().to_path_buf();
+    load(file.path().to_path_buf()).await
+}
 
-    let chunks =
-        TextBufferBuilder::read_chunks_from_path(&path).map_err(|e| Error::IoError(e.kind()))?;
+async fn load(path: PathBuf) -> Result<(PathBuf, Vec<String>, FileEncoding), Error> {
+    let (chunks, encoding) = TextBufferBuilder::read_chunks_from_path_with_encoding(&path, None)
+        .map_err(|e| Error::IoError(e.kind()))?;
 
-    Ok((path, chunks))
+    Ok((path, chunks, encoding))
 }
 
-async fn save_as(content: String) -> Result<Option<PathBuf>, Error> {
-    let file = rfd::AsyncFileDialog::new()
-        .set_title("Save file as...")
-        .set_file_name("Untitled.txt")
-        .save_file()
-        .await
-        .ok_or(Error::DialogClosed)?;
+// What to do once unsaved changes are saved or discarded
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PendingAction {
+    New,
+    Open,
+    OpenPath(PathBuf), // a file dropped on the window
+}
+
+async fn confirm_unsaved() -> UnsavedChoice {
+    let answer = rfd::AsyncMessageDialog::new()
+        .set_level(rfd::MessageLevel::Warning)
+        .set_title("Unsaved changes")
+        .set_description("Save the changes to this file first?")
+        .set_buttons(rfd::MessageButtons::YesNoCancelCustom(
+            "Save".to_string(),
+            "Don't Save".to_string(),
+            "Cancel".to_string(),
+        ))
+        .show()
+        .await;
+
+    // Some platforms report custom buttons by label, others by position
+    match answer {
+        rfd::MessageDialogResult::Yes => UnsavedChoice::Save,
+        rfd::MessageDialogResult::No => UnsavedChoice::Discard,
+        rfd::MessageDialogResult::Custom(label) if label == "Save" => UnsavedChoice::Save,
+        rfd::MessageDialogResult::Custom(label) if label == "Don't Save" => UnsavedChoice::Discard,
+        _ => UnsavedChoice::Cancel,
+    }
+}
+
+async fn load_recent() -> Vec<PathBuf> {
+    recent::config_file()
+        .map(|file| recent::load(&file))
+        .unwrap_or_default()
+}
+
+async fn store_recent(list: Vec<PathBuf>) {
+    if let Some(file) = recent::config_file() {
+        let _ = recent::store(&file, &list);
+    }
+}
+
+// Ask whether to restore the unsaved changes kept in `recovery` for `path`. Declining
+// deletes the recovery file so the question doesn't come back.
+async fn offer_recovery(path: PathBuf, recovery: PathBuf) -> Option<(PathBuf, String)> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let answer = rfd::AsyncMessageDialog::new()
+        .set_level(rfd::MessageLevel::Warning)
+        .set_title("Recover unsaved changes")
+        .set_description(format!(
+            "{name} has unsaved changes from an earlier session. Restore them?"
+        ))
+        .set_buttons(rfd::MessageButtons::YesNo)
+        .show()
+        .await;
+
+    if answer != rfd::MessageDialogResult::Yes {
+        let _ = fs::remove_file(&recovery);
+        return None;
+    }
+    let text = fs::read_to_string(&recovery).ok()?;
+    Some((path, text))
+}
+
+// Delete the recovery file of `file`, e.g. once its changes are saved or discarded
+fn remove_recovery(file: Option<&PathBuf>) {
+    if let Some(path) = file.and_then(|file| recovery_path(file)) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+// Resolves after `duration` on the runtime's timer. The sleep starts on the first poll, so
+// building the task needs no runtime
+async fn delay(duration: Duration) {
+    tokio::time::sleep(duration).await;
+}
+
+// What toggling a Markdown mark does to a selection
+#[derive(Debug, PartialEq, Eq)]
+enum MarkToggle {
+    UnwrapInside,  // the selected text starts and ends with the mark
+    UnwrapOutside, // the mark is right before and right after the selection
+    Wrap,
+}
+
+// Whether the selected text, with `before` it on its first line and `after` it on its last,
+// is wrapped in `mark`. A run of three `*` holds both `*` and `**`, so italic text inside
+// bold text is told apart from bold text
+fn mark_toggle(before: &str, selected: &str, after: &str, mark: &str) -> MarkToggle {
+    let c = mark.as_bytes()[0];
+    let leading = |s: &str| s.bytes().take_while(|&b| b == c).count();
+    let trailing = |s: &str| s.bytes().rev().take_while(|&b| b == c).count();
+    // `*` is in runs of 1 and 3, `**` in runs of 2 and 3
+    let holds = |run: usize| run & mark.len() != 0;
+
+    let inner = leading(selected);
+    if inner < selected.len() && holds(inner) && holds(trailing(selected)) {
+        MarkToggle::UnwrapInside
+    } else if holds(trailing(before)) && holds(leading(after)) {
+        MarkToggle::UnwrapOutside
+    } else {
+        MarkToggle::Wrap
+    }
+}
+
+// Where a save writes to: a path picked in a dialog, or a known path
+#[derive(Debug, PartialEq)]
+enum SaveTarget {
+    Prompt,
+    Path(PathBuf),
+}
+
+// Saving an untitled buffer has to ask for a path first
+fn save_target(file: Option<&PathBuf>) -> SaveTarget {
+    match file {
+        Some(path) => SaveTarget::Path(path.clone()),
+        None => SaveTarget::Prompt,
+    }
+}
+
+// Prompt for a path, starting from the current file's folder and name if there is one
+async fn save_as(content: Vec<u8>, current: Option<PathBuf>) -> Result<Option<PathBuf>, Error> {
+    let mut dialog = rfd::AsyncFileDialog::new().set_title("Save file as...");
+    dialog = match &current {
+        Some(path) => {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let dialog = dialog.set_file_name(name);
+            match path.parent() {
+                Some(dir) => dialog.set_directory(dir),
+                None => dialog,
+            }
+        }
+        None => dialog.set_file_name("Untitled.txt"),
+    };
+    let file = dialog.save_file().await.ok_or(Error::DialogClosed)?;
 
     let path = file.path().to_path_buf();
     save_atomic(&path, &content).map_err(|e| Error::IoError(e.kind()))?;
@@ -652,74 +2420,11 @@ async fn save_as(content: String) -> Result<Option<PathBuf>, Error> {
     Ok(Some(path))
 }
 
-async fn save_to_path(path: PathBuf, content: String) -> Result<Option<PathBuf>, Error> {
+async fn save_to_path(path: PathBuf, content: Vec<u8>) -> Result<Option<PathBuf>, Error> {
     save_atomic(&path, &content).map_err(|e| Error::IoError(e.kind()))?;
     Ok(None)
 }
 
-fn save_atomic(dest: &std::path::Path, content: &str) -> std::io::Result<()> {
-    use std::ffi::OsString;
-    use std::fs::{self, OpenOptions};
-    use std::io::{BufWriter, Write};
-
-    // Create temp file path in same directory
-    let dir = dest
-        .parent()
-        .ok_or_else(|| std::io::Error::other("No parent directory"))?;
-    let file_name = dest
-        .file_name()
-        .ok_or_else(|| std::io::Error::other("No file name"))?;
-    let mut tmp_name = OsString::from(".");
-    tmp_name.push(file_name);
-    tmp_name.push(".tmp");
-    let tmp_path = dir.join(tmp_name);
-
-    // Create/truncate temp file
-    let tmp_file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(&tmp_path)?;
-
-    // Write content
-    let mut writer = BufWriter::new(tmp_file);
-    writer.write_all(content.as_bytes())?;
-    writer.flush()?;
-    writer.get_mut().sync_all()?;
-
-    // Copy permissions from existing file if it exists
-    if let Ok(meta) = fs::metadata(dest) {
-        let perms = meta.permissions();
-        fs::set_permissions(&tmp_path, perms)?;
-    }
-
-    // Atomically replace destination with temp
-    match fs::rename(&tmp_path, dest) {
-        Ok(_) => {}
-        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
-            // Fallback: remove target and retry
-            fs::remove_file(dest)?;
-            fs::rename(&tmp_path, dest)?;
-        }
-        Err(e) => return Err(e),
-    }
-
-    // Sync directory on Unix for crash consistency
-    #[cfg(target_family = "unix")]
-    {
-        use std::os::unix::fs::OpenOptionsExt;
-        if let Ok(dir_fd) = OpenOptions::new()
-            .read(true)
-            .custom_flags(libc::O_DIRECTORY)
-            .open(dir)
-        {
-            let _ = dir_fd.sync_all();
-        }
-    }
-
-    Ok(())
-}
-
 fn action<'a, EditorMessage: Clone + 'a>(
     content: impl Into<Element<'a, EditorMessage>>,
     on_press: Option<EditorMessage>,
@@ -778,46 +2483,22 @@ fn black_rule(_: &iced::Theme) -> rule::Style {
     }
 }
 
-fn top_bar_bg(_: &iced::Theme) -> iced::widget::container::Style {
-    iced::widget::container::Style {
-        text_color: None,
-        background: Some(iced::Background::Color(iced::Color::from_rgba8(
-            22, 23, 19, 1.0,
-        ))),
-        border: iced::Border {
-            color: iced::Color::TRANSPARENT,
-            width: 0.0,
-            radius: 0.0.into(),
-        },
-        shadow: iced::Shadow {
-            ..Default::default()
-        },
-    }
+fn top_bar_bg(colors: &ColorScheme) -> container::Style {
+    solid_bg(colors.top_bar_bg)
 }
 
-fn editor_bg(_: &Theme) -> container::Style {
-    container::Style {
-        text_color: None,
-        background: Some(iced::Background::Color(iced::Color::from_rgba8(
-            39, 40, 34, 1.0,
-        ))),
-        border: iced::Border {
-            color: iced::Color::TRANSPARENT,
-            width: 0.0,
-            radius: 0.0.into(),
-        },
-        shadow: Shadow {
-            ..Default::default()
-        },
-    }
+fn editor_bg(colors: &ColorScheme) -> container::Style {
+    solid_bg(colors.editor_bg)
+}
+
+fn bottom_bar_bg(colors: &ColorScheme) -> container::Style {
+    solid_bg(colors.bottom_bar_bg)
 }
 
-fn bottom_bar_bg(_: &Theme) -> container::Style {
+fn solid_bg(color: iced::Color) -> container::Style {
     container::Style {
         text_color: None,
-        background: Some(iced::Background::Color(iced::Color::from_rgba8(
-            32, 33, 28, 1.0,
-        ))),
+        background: Some(iced::Background::Color(color)),
         border: iced::Border {
             color: iced::Color::TRANSPARENT,
             width: 0.0,
@@ -833,6 +2514,142 @@ fn grapheme_count(s: &str) -> usize {
     s.graphemes(true).count()
 }
 
+// Status bar name of a line break
+fn eol_label(eol: Eol) -> &'static str {
+    match eol {
+        Eol::Lf => "LF",
+        Eol::CrLf => "CRLF",
+        Eol::Cr => "CR",
+    }
+}
+
+// Line break the status bar button switches to from `eol`
+fn next_eol(eol: Eol) -> Eol {
+    match eol {
+        Eol::Lf => Eol::CrLf,
+        Eol::CrLf => Eol::Cr,
+        Eol::Cr => Eol::Lf,
+    }
+}
+
+// Line spacing after `current` in the status bar's cycle
+fn next_line_spacing(current: f32) -> f32 {
+    LINE_SPACING_STEPS
+        .into_iter()
+        .find(|&step| step > current + f32::EPSILON)
+        .unwrap_or(LINE_SPACING_STEPS[0])
+}
+
+// Highlighter language of a file: its extension, or Markdown without one
+fn syntax_token(path: &std::path::Path) -> String {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .filter(|ext| !ext.is_empty())
+        .unwrap_or(DEFAULT_SYNTAX)
+        .to_string()
+}
+
+// Path shown in the status bar, keeping the end of long paths
+fn file_label(file: Option<&PathBuf>) -> String {
+    let Some(path) = file else {
+        return String::from("New file");
+    };
+    let path = path.display().to_string();
+    if path.chars().count() <= 60 {
+        return path;
+    }
+    let tail_start = path.char_indices().rev().nth(39).map_or(0, |(i, _)| i);
+    format!("...{}", &path[tail_start..])
+}
+
+// Word and character counts shown in the status bar
+fn count_label(stats: &TextStats) -> String {
+    let plural = |n: usize, one: &str| {
+        if n == 1 {
+            format!("1 {one}")
+        } else {
+            format!("{n} {one}s")
+        }
+    };
+    format!(
+        "{} • {}",
+        plural(stats.words, "word"),
+        plural(stats.chars, "char")
+    )
+}
+
+// Text of the statistics panel
+fn stats_label(stats: &TextStats, selection: Option<TextStats>) -> String {
+    let mut label = format!(
+        "{} lines · {} words · {} chars · {} bytes",
+        stats.lines, stats.words, stats.chars, stats.bytes
+    );
+    if let Some(selected) = selection {
+        label.push_str(&format!(
+            "  |  selected: {} lines · {} words · {} chars",
+            selected.lines, selected.words, selected.chars
+        ));
+    }
+    label
+}
+
+// Caret just past `text` inserted at `at`; any of LF, CRLF or CR starts a new line
+fn caret_after_insert(at: Caret, text: &str) -> Caret {
+    let breaks =
+        text.matches('\n').count() + text.matches('\r').count() - text.matches("\r\n").count();
+    let last_line = text.rsplit(['\n', '\r']).next().unwrap_or("");
+    if breaks == 0 {
+        Caret {
+            line: at.line,
+            col: at.col + grapheme_count(text),
+        }
+    } else {
+        Caret {
+            line: at.line + breaks,
+            col: grapheme_count(last_line),
+        }
+    }
+}
+
+// Bytes Ctrl+K removes: the rest of the line, or its line break when the caret is already
+// at the end, joining the next line
+fn kill_to_end_range(
+    caret: usize,
+    line_end: usize,
+    next_line_start: usize,
+) -> std::ops::Range<usize> {
+    if caret < line_end {
+        caret..line_end
+    } else {
+        caret..next_line_start
+    }
+}
+
+// Bytes Ctrl+U removes: from the start of the line to the caret
+fn kill_to_start_range(line_start: usize, caret: usize) -> std::ops::Range<usize> {
+    line_start..caret
+}
+
+// 0-based line for what was typed into the go-to-line bar, a 1-based line number clamped to
+// the document; None unless it is a number
+fn goto_target(input: &str, line_count: usize) -> Option<usize> {
+    let line: usize = input.trim().parse().ok()?;
+    Some(line.clamp(1, line_count.max(1)) - 1)
+}
+
+// Grapheme column of the bracket to match: the one under the caret, else the one before it
+fn bracket_near_caret(line: &str, col: usize) -> Option<usize> {
+    let is_bracket = |g: &str| matches!(g, "(" | ")" | "[" | "]" | "{" | "}");
+    let graphemes: Vec<&str> = line.graphemes(true).collect();
+    if graphemes.get(col).is_some_and(|g| is_bracket(g)) {
+        Some(col)
+    } else if col > 0 && graphemes.get(col - 1).is_some_and(|g| is_bracket(g)) {
+        Some(col - 1)
+    } else {
+        None
+    }
+}
+
 fn byte_col_for_grapheme_col(line: &str, grapheme_col0: usize) -> usize {
     // Return 0-based byte column corresponding to a 0-based grapheme column
     if grapheme_col0 == 0 {
@@ -848,6 +2665,45 @@ fn byte_col_for_grapheme_col(line: &str, grapheme_col0: usize) -> usize {
     bytes
 }
 
+// Keys while typing in the find bar; the rest belong to its inputs
+fn map_find_event(ev: Event, _status: event::Status, _id: window::Id) -> Option<EditorMessage> {
+    let Event::Keyboard(iced::keyboard::Event::KeyPressed { key, modifiers, .. }) = ev else {
+        return None;
+    };
+    match (key, modifiers) {
+        (Key::Character(ref c), m) if c.as_str() == "f" && m.command() => {
+            Some(EditorMessage::ToggleFind)
+        }
+        (Key::Named(Named::Escape), _) => Some(EditorMessage::ToggleFind),
+        (Key::Named(Named::F3), m) if m.shift() => Some(EditorMessage::FindPrev),
+        (Key::Named(Named::F3), _) => Some(EditorMessage::FindNext),
+        _ => None,
+    }
+}
+
+fn map_goto_event(ev: Event, _status: event::Status, _id: window::Id) -> Option<EditorMessage> {
+    let Event::Keyboard(iced::keyboard::Event::KeyPressed { key, modifiers, .. }) = ev else {
+        return None;
+    };
+    match (key, modifiers) {
+        (Key::Character(ref c), m) if c.as_str() == "g" && m.command() => {
+            Some(EditorMessage::GotoLine)
+        }
+        (Key::Named(Named::Escape), _) => Some(EditorMessage::GotoLine),
+        _ => None,
+    }
+}
+
+// A file dropped on the window. Dropping several sends one event each; the first starts
+// loading or asks about unsaved changes, and the others arrive while that is under way and
+// are ignored.
+fn map_drop_event(ev: Event, _status: event::Status, _id: window::Id) -> Option<EditorMessage> {
+    match ev {
+        Event::Window(window::Event::FileDropped(path)) => Some(EditorMessage::FileDropped(path)),
+        _ => None,
+    }
+}
+
 fn map_runtime_event(ev: Event, _status: event::Status, _id: window::Id) -> Option<EditorMessage> {
     if let Event::Keyboard(iced::keyboard::Event::KeyPressed { key, modifiers, .. }) = ev {
         match (key, modifiers) {
@@ -859,21 +2715,190 @@ fn map_runtime_event(ev: Event, _status: event::Status, _id: window::Id) -> Opti
                 Some(EditorMessage::SaveFile)
             }
 
+            // Zoom
+            (Key::Character(ref c), m) if matches!(c.as_str(), "=" | "+") && m.command() => {
+                Some(EditorMessage::ZoomIn)
+            }
+            (Key::Character(ref c), m) if c.as_str() == "-" && m.command() => {
+                Some(EditorMessage::ZoomOut)
+            }
+            (Key::Character(ref c), m) if c.as_str() == "0" && m.command() => {
+                Some(EditorMessage::ZoomReset)
+            }
+
+            // Delete to end / start of line
+            (Key::Character(ref c), m) if c.as_str() == "k" && m.command() => {
+                Some(EditorMessage::DeleteToLineEnd)
+            }
+            (Key::Character(ref c), m) if c.as_str() == "u" && m.command() => {
+                Some(EditorMessage::DeleteToLineStart)
+            }
+
+            (Key::Character(ref c), m) if c.as_str() == "f" && m.command() => {
+                Some(EditorMessage::ToggleFind)
+            }
+            (Key::Character(ref c), m) if c.as_str() == "g" && m.command() => {
+                Some(EditorMessage::GotoLine)
+            }
+
+            // Clipboard
+            (Key::Character(ref c), m) if c.as_str() == "c" && m.command() => {
+                Some(EditorMessage::Copy)
+            }
+            (Key::Character(ref c), m) if c.as_str() == "x" && m.command() => {
+                Some(EditorMessage::Cut)
+            }
+            (Key::Character(ref c), m) if c.as_str() == "v" && m.command() && !m.alt() => {
+                Some(EditorMessage::Paste)
+            }
+
+            // Undo / Redo
+            (Key::Character(ref c), m) if c.as_str() == "z" && m.command() && m.shift() => {
+                Some(EditorMessage::Redo)
+            }
+            (Key::Character(ref c), m) if c.as_str() == "z" && m.command() => {
+                Some(EditorMessage::Undo)
+            }
+            (Key::Character(ref c), m) if c.as_str() == "y" && m.command() => {
+                Some(EditorMessage::Redo)
+            }
+
             // Select All
             (Key::Character(ref c), m) if c.as_str() == "a" && m.command() => {
                 Some(EditorMessage::SelectAll)
             }
 
+            // Markdown list markers and headings on the selected lines
+            (Key::Character(ref c), m) if c.as_str() == "l" && m.command() && m.shift() => {
+                Some(EditorMessage::ToggleNumberedList)
+            }
+            (Key::Character(ref c), m) if c.as_str() == "l" && m.command() => {
+                Some(EditorMessage::ToggleBulletList)
+            }
+            (Key::Character(ref c), m)
+                if matches!(c.as_str(), "1" | "2" | "3" | "4" | "5" | "6") && m.command() =>
+            {
+                c.parse().ok().map(EditorMessage::SetHeadingLevel)
+            }
+
+            // Duplicate or move whole lines
+            (Key::Character(ref c), m) if c.as_str() == "d" && m.command() => {
+                Some(EditorMessage::DuplicateLines)
+            }
+            (Key::Named(Named::ArrowUp), m) if m.alt() => Some(EditorMessage::MoveLinesUp),
+            (Key::Named(Named::ArrowDown), m) if m.alt() => Some(EditorMessage::MoveLinesDown),
+            (Key::Character(ref c), m) if c.as_str() == "j" && m.command() => {
+                Some(EditorMessage::JoinLines)
+            }
+
+            // Markdown emphasis and code around the selection
+            (Key::Character(ref c), m) if c.as_str() == "b" && m.command() => {
+                Some(EditorMessage::ToggleBold)
+            }
+            (Key::Character(ref c), m) if c.as_str() == "i" && m.command() => {
+                Some(EditorMessage::ToggleItalic)
+            }
+            (Key::Character(ref c), m) if c.as_str() == "`" && m.command() => {
+                Some(EditorMessage::ToggleCode)
+            }
+
+            // Snippet expansion or a tab
+            (Key::Named(Named::Tab), m) if m.is_empty() => Some(EditorMessage::Tab),
+            (Key::Named(Named::Tab), m) if m == iced::keyboard::Modifiers::SHIFT => {
+                Some(EditorMessage::Dedent)
+            }
+
             // Delete / Backspace
             (Key::Named(Named::Delete), _) => Some(EditorMessage::DeleteForward),
             (Key::Named(Named::Backspace), _) => Some(EditorMessage::Backspace),
 
+            // Toggle or step the word under the caret
+            (Key::Named(Named::ArrowUp), m) if m.command() && m.shift() => {
+                Some(EditorMessage::ToggleWord { step: 1 })
+            }
+            (Key::Named(Named::ArrowDown), m) if m.command() && m.shift() => {
+                Some(EditorMessage::ToggleWord { step: -1 })
+            }
+
+            // Word-wise moves, extending the selection with Shift
+            (Key::Named(Named::ArrowLeft), m) if m.command() && m.shift() => {
+                Some(EditorMessage::ExtendWordLeft)
+            }
+            (Key::Named(Named::ArrowRight), m) if m.command() && m.shift() => {
+                Some(EditorMessage::ExtendWordRight)
+            }
+            (Key::Named(Named::ArrowLeft), m) if m.command() => Some(EditorMessage::MoveWordLeft),
+            (Key::Named(Named::ArrowRight), m) if m.command() => Some(EditorMessage::MoveWordRight),
+
             // Shift+Arrows extend selection
             (Key::Named(Named::ArrowLeft), m) if m.shift() => Some(EditorMessage::ExtendLeft),
             (Key::Named(Named::ArrowRight), m) if m.shift() => Some(EditorMessage::ExtendRight),
             (Key::Named(Named::ArrowUp), m) if m.shift() => Some(EditorMessage::ExtendUp),
             (Key::Named(Named::ArrowDown), m) if m.shift() => Some(EditorMessage::ExtendDown),
 
+            // Toggle soft wrap
+            (Key::Character(ref c), m) if c.as_str() == "z" && m.alt() => {
+                Some(EditorMessage::ToggleWrap)
+            }
+
+            // Flip line number alignment
+            (Key::Character(ref c), m) if c.as_str() == "l" && m.alt() => {
+                Some(EditorMessage::ToggleGutterAlign)
+            }
+
+            // Cycle the caret shape
+            (Key::Character(ref c), m) if c.as_str() == "c" && m.alt() => {
+                Some(EditorMessage::CycleCaretStyle)
+            }
+
+            // Toggle the statistics panel
+            (Key::Character(ref c), m) if c.as_str() == "i" && m.alt() => {
+                Some(EditorMessage::ToggleStats)
+            }
+
+            // Toggle read-only mode
+            (Key::Character(ref c), m) if c.as_str() == "r" && m.alt() => {
+                Some(EditorMessage::ToggleReadOnly)
+            }
+
+            // Toggle the Markdown preview pane
+            (Key::Character(ref c), m) if c.as_str() == "p" && m.alt() => {
+                Some(EditorMessage::TogglePreview)
+            }
+
+            // Toggle autosave to the recovery file
+            (Key::Character(ref c), m) if c.as_str() == "s" && m.alt() => {
+                Some(EditorMessage::ToggleAutosave)
+            }
+
+            // Toggle auto-indent
+            (Key::Character(ref c), m) if c.as_str() == "a" && m.alt() => {
+                Some(EditorMessage::ToggleAutoIndent)
+            }
+
+            // Toggle the trailing whitespace highlight
+            (Key::Character(ref c), m) if c.as_str() == "t" && m.alt() => {
+                Some(EditorMessage::ToggleTrailingWhitespace)
+            }
+
+            // Toggle marks on spaces, tabs and line ends
+            (Key::Character(ref c), m) if c.as_str() == "w" && m.alt() => {
+                Some(EditorMessage::ToggleWhitespace)
+            }
+
+            // Toggle the matching bracket highlight
+            (Key::Character(ref c), m) if c.as_str() == "b" && m.alt() => {
+                Some(EditorMessage::ToggleBracketHighlight)
+            }
+
+            // Line and document start / end
+            (Key::Named(Named::Home), m) if m.command() => Some(EditorMessage::MoveDocumentStart),
+            (Key::Named(Named::End), m) if m.command() => Some(EditorMessage::MoveDocumentEnd),
+            (Key::Named(Named::PageUp), _) => Some(EditorMessage::PageUp),
+            (Key::Named(Named::PageDown), _) => Some(EditorMessage::PageDown),
+            (Key::Named(Named::Home), _) => Some(EditorMessage::MoveHome),
+            (Key::Named(Named::End), _) => Some(EditorMessage::MoveEnd),
+
             // Plain arrows move caret (collapse selection)
             (Key::Named(Named::ArrowLeft), _) => Some(EditorMessage::MoveLeft),
             (Key::Named(Named::ArrowRight), _) => Some(EditorMessage::MoveRight),
@@ -886,3 +2911,1238 @@ fn map_runtime_event(ev: Event, _status: event::Status, _id: window::Id) -> Opti
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_prompts_only_without_a_path() {
+        assert_eq!(save_target(None), SaveTarget::Prompt);
+
+        let path = PathBuf::from("/tmp/notes.md");
+        assert_eq!(save_target(Some(&path)), SaveTarget::Path(path.clone()));
+    }
+
+    #[test]
+    fn restoring_saved_text_clears_dirty() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("ab".to_string()));
+        assert!(app.is_dirty);
+
+        let _ = app.update(EditorMessage::Backspace);
+        assert!(app.is_dirty);
+        let _ = app.update(EditorMessage::Backspace);
+        // Known once the edits settle
+        assert!(app.is_dirty);
+        let _ = app.update(EditorMessage::LineChangesDue(app.render_version));
+        assert!(!app.is_dirty);
+
+        // Undo back to the saved text is known at once
+        let _ = app.update(EditorMessage::Insert("c".to_string()));
+        assert!(app.is_dirty);
+        let _ = app.update(EditorMessage::Undo);
+        assert!(!app.is_dirty);
+    }
+
+    #[test]
+    fn typed_run_undoes_as_one_step() {
+        let (mut app, _) = App::new();
+        for c in ["a", "b", "c"] {
+            let _ = app.update(EditorMessage::Insert(c.to_string()));
+        }
+        let _ = app.update(EditorMessage::Enter);
+        let _ = app.update(EditorMessage::Insert("d".to_string()));
+        assert_eq!(app.buffer.get_text(), "abc\nd");
+
+        let _ = app.update(EditorMessage::Undo);
+        assert_eq!(app.buffer.get_text(), "abc\n");
+        assert_eq!((app.line, app.col), (1, 0));
+        let _ = app.update(EditorMessage::Undo);
+        let _ = app.update(EditorMessage::Undo);
+        assert_eq!(app.buffer.get_text(), "");
+        assert!(!app.is_dirty);
+
+        let _ = app.update(EditorMessage::Redo);
+        assert_eq!(app.buffer.get_text(), "abc");
+        assert_eq!((app.line, app.col), (0, 3));
+        assert_eq!(app.stats, app.buffer.stats());
+    }
+
+    #[test]
+    fn long_lines_scroll_sideways_with_the_caret() {
+        let (mut app, _) = App::new();
+        app.viewport_width = 400.0;
+        let _ = app.update(EditorMessage::Insert("word ".repeat(40)));
+        assert!(app.scroll_x > 0.0);
+
+        let cell = GlyphMetrics::shared(Font::MONOSPACE, app.font_size).cell_width();
+        let gutter = editor_canvas::gutter_width(&app.gutter, 1, cell);
+        let caret_x = app.col as f32 * cell - app.scroll_x;
+        assert!(caret_x >= 0.0 && caret_x + cell <= app.viewport_width - gutter);
+
+        app.set_cursor(0, 0);
+        let _ = app.update(EditorMessage::MoveRight);
+        assert_eq!(app.scroll_x, 0.0);
+
+        let _ = app.update(EditorMessage::Insert("word ".repeat(40)));
+        let _ = app.update(EditorMessage::ToggleWrap);
+        assert_eq!(app.scroll_x, 0.0);
+    }
+
+    #[test]
+    fn view_follows_the_caret_up_and_down() {
+        let (mut app, _) = App::new();
+        app.viewport_height = 200.0;
+        let line_height = app.line_height();
+
+        let _ = app.update(EditorMessage::Insert("line\n".repeat(40)));
+        assert_eq!(app.line, 40);
+        let caret_y = app.line as f32 * line_height - app.scroll_y;
+        assert!(caret_y >= 0.0 && caret_y + line_height <= app.viewport_height);
+
+        // Moving inside the view does not scroll
+        let scrolled = app.scroll_y;
+        let _ = app.update(EditorMessage::MoveUp);
+        assert_eq!(app.scroll_y, scrolled);
+
+        for _ in 0..39 {
+            let _ = app.update(EditorMessage::MoveUp);
+        }
+        assert_eq!(app.scroll_y, 0.0);
+
+        // Wrapped rows count, not lines
+        let _ = app.update(EditorMessage::ToggleWrap);
+        app.set_cursor(0, 0);
+        let _ = app.update(EditorMessage::Insert("word ".repeat(100)));
+        assert_eq!(app.caret_row(), 6);
+    }
+
+    #[test]
+    fn zoom_steps_and_clamps_the_font_size() {
+        let (mut app, _) = App::new();
+        let version = app.render_version;
+        let _ = app.update(EditorMessage::ZoomIn);
+        assert_eq!(app.font_size, DEFAULT_FONT_SIZE + ZOOM_STEP);
+        assert_ne!(app.render_version, version);
+        let _ = app.update(EditorMessage::ZoomOut);
+        let _ = app.update(EditorMessage::ZoomOut);
+        assert_eq!(app.font_size, DEFAULT_FONT_SIZE - ZOOM_STEP);
+        let _ = app.update(EditorMessage::ZoomReset);
+        assert_eq!(app.font_size, DEFAULT_FONT_SIZE);
+
+        let _ = app.update(EditorMessage::SetFontSize(500.0));
+        assert_eq!(app.font_size, *FONT_SIZE_RANGE.end());
+        let _ = app.update(EditorMessage::SetLineSpacing(2.0));
+        assert_eq!(app.line_height(), *FONT_SIZE_RANGE.end() * 2.0);
+        let _ = app.update(EditorMessage::SetLineSpacing(0.0));
+        assert_eq!(app.line_spacing, *LINE_SPACING_RANGE.start());
+
+        assert_eq!(next_line_spacing(1.4), 1.6);
+        assert_eq!(next_line_spacing(1.5), 1.6);
+        assert_eq!(next_line_spacing(2.0), 1.0);
+    }
+
+    #[test]
+    fn failed_save_keeps_the_dirty_state() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("a".to_string()));
+
+        // Cancelling the dialog changes nothing
+        let _ = app.update(EditorMessage::FileSaved(Err(Error::DialogClosed)));
+        assert!(app.is_dirty);
+        assert_eq!(app.error, None);
+
+        let denied = Error::IoError(std::io::ErrorKind::PermissionDenied);
+        let _ = app.update(EditorMessage::FileSaved(Err(denied)));
+        assert!(app.is_dirty);
+        assert_eq!(app.file, None);
+        assert_eq!(
+            app.error.as_deref(),
+            Some("Could not save file: permission denied")
+        );
+
+        let _ = app.update(EditorMessage::SaveFile);
+        let _ = app.update(EditorMessage::FileSaved(Ok(None)));
+        assert!(!app.is_dirty);
+        assert_eq!(app.error, None);
+    }
+
+    #[test]
+    fn typing_during_a_save_stays_dirty() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("a\nb".to_string()));
+        let _ = app.update(EditorMessage::SaveFile);
+        assert!(app.is_loading);
+
+        // Typed while the write was still running; the file holds only "a\nb"
+        let _ = app.update(EditorMessage::Insert("c".to_string()));
+        let _ = app.update(EditorMessage::FileSaved(Ok(None)));
+        assert!(app.is_dirty);
+        assert_eq!(app.saved_lines.as_slice(), ["a", "b"]);
+        assert_eq!(app.line_changes, vec![None, Some(LineChange::Modified)]);
+
+        // Undoing the late edit gets back to what was written
+        let _ = app.update(EditorMessage::Undo);
+        assert!(!app.is_dirty);
+    }
+
+    #[test]
+    fn save_as_switches_to_the_new_path() {
+        let (mut app, _) = App::new();
+        app.file = Some(PathBuf::from("/tmp/old.md"));
+        let _ = app.update(EditorMessage::Insert("a".to_string()));
+
+        let path = PathBuf::from("/tmp/new.md");
+        let _ = app.update(EditorMessage::SaveAs);
+        let _ = app.update(EditorMessage::FileSaved(Ok(Some(path.clone()))));
+        assert_eq!(app.file, Some(path.clone()));
+        assert!(!app.is_dirty);
+        assert_eq!(file_label(app.file.as_ref()), "/tmp/new.md");
+        assert_eq!(save_target(app.file.as_ref()), SaveTarget::Path(path));
+    }
+
+    #[test]
+    fn read_only_refuses_edits_but_not_moves() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("one two".to_string()));
+        let _ = app.update(EditorMessage::ToggleReadOnly);
+
+        for message in [
+            EditorMessage::Insert("x".to_string()),
+            EditorMessage::Enter,
+            EditorMessage::Backspace,
+            EditorMessage::Tab,
+            EditorMessage::Pasted("pasted".to_string()),
+            EditorMessage::Undo,
+        ] {
+            let _ = app.update(message);
+        }
+        assert_eq!(app.buffer.get_text(), "one two");
+
+        // Moving and selecting still work, and deleting the selection does not
+        let _ = app.update(EditorMessage::MoveWordLeft);
+        let _ = app.update(EditorMessage::ExtendWordRight);
+        assert_eq!(app.selected_text().as_deref(), Some("two"));
+        let _ = app.update(EditorMessage::DeleteForward);
+        assert_eq!(app.buffer.get_text(), "one two");
+
+        let _ = app.update(EditorMessage::ToggleReadOnly);
+        let _ = app.update(EditorMessage::Insert("2".to_string()));
+        assert_eq!(app.buffer.get_text(), "one 2");
+    }
+
+    #[test]
+    fn autosave_writes_the_recovery_file_until_saved() {
+        let dir = std::env::temp_dir().join(format!("mditor_autosave_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let recovery = dir.join(".notes.md.swp");
+
+        let (mut app, _) = App::new();
+        app.file = Some(dir.join("notes.md"));
+        app.autosave.edit_threshold = 3;
+        for c in ["a", "b"] {
+            let _ = app.update(EditorMessage::Insert(c.to_string()));
+        }
+        assert!(!recovery.exists());
+        let _ = app.update(EditorMessage::Insert("c".to_string()));
+        assert_eq!(fs::read_to_string(&recovery).unwrap(), "abc");
+
+        // A tick writes whatever changed since
+        let _ = app.update(EditorMessage::Enter);
+        let _ = app.update(EditorMessage::AutosaveTick);
+        assert_eq!(fs::read_to_string(&recovery).unwrap(), "abc\n");
+
+        let _ = app.update(EditorMessage::SaveFile);
+        let _ = app.update(EditorMessage::FileSaved(Ok(None)));
+        assert!(!recovery.exists());
+        let _ = app.update(EditorMessage::AutosaveTick);
+        assert!(!recovery.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn preview_catches_up_after_the_last_edit() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("# Hi".to_string()));
+        let _ = app.update(EditorMessage::TogglePreview);
+        assert!(matches!(
+            app.preview.as_deref(),
+            Some([Block::Heading(1, _)])
+        ));
+
+        let _ = app.update(EditorMessage::Enter);
+        let first = app.preview_due.unwrap();
+        let _ = app.update(EditorMessage::Insert("- item".to_string()));
+        let last = app.preview_due.unwrap();
+        assert_ne!(first, last);
+
+        // The first edit's timer finds a newer edit pending
+        let _ = app.update(EditorMessage::PreviewDue(first));
+        assert_eq!(app.preview.as_ref().unwrap().len(), 1);
+        let _ = app.update(EditorMessage::PreviewDue(last));
+        assert!(matches!(
+            app.preview.as_deref(),
+            Some([Block::Heading(1, _), Block::ListItem { .. }])
+        ));
+        assert_eq!(app.preview_due, None);
+
+        // Closed, edits leave it alone
+        let _ = app.update(EditorMessage::TogglePreview);
+        let _ = app.update(EditorMessage::Insert("x".to_string()));
+        assert_eq!((app.preview.as_ref(), app.preview_due), (None, None));
+    }
+
+    #[test]
+    fn marks_toggle_inside_or_around_the_selection() {
+        use MarkToggle::*;
+        assert_eq!(mark_toggle("a ", "word", " b", "**"), Wrap);
+        assert_eq!(mark_toggle("a ", "**word**", " b", "**"), UnwrapInside);
+        assert_eq!(mark_toggle("a **", "word", "** b", "**"), UnwrapOutside);
+        assert_eq!(mark_toggle("", "`x`", "", "`"), UnwrapInside);
+
+        // Italic text is not bold, bold text is not italic, and both make a run of three
+        assert_eq!(mark_toggle("", "*word*", "", "**"), Wrap);
+        assert_eq!(mark_toggle("**", "word", "**", "*"), Wrap);
+        assert_eq!(mark_toggle("***", "word", "***", "*"), UnwrapOutside);
+        assert_eq!(mark_toggle("", "***word***", "", "**"), UnwrapInside);
+
+        // Marks alone are not a wrapped selection; an empty pair around the caret goes
+        assert_eq!(mark_toggle("", "**", "", "*"), Wrap);
+        assert_eq!(mark_toggle("a *", "", "*", "*"), UnwrapOutside);
+        assert_eq!(mark_toggle("a*b", "", "c*", "*"), Wrap);
+    }
+
+    #[test]
+    fn emphasis_shortcuts_wrap_and_unwrap_the_selection() {
+        let (mut app, _) = App::new();
+        app.buffer.set_text("say hello there");
+        app.select_offsets(4, 9);
+
+        let _ = app.update(EditorMessage::ToggleBold);
+        assert_eq!(app.buffer.get_text(), "say **hello** there");
+        assert_eq!(app.selected_text().as_deref(), Some("hello"));
+        let _ = app.update(EditorMessage::ToggleItalic);
+        assert_eq!(app.buffer.get_text(), "say ***hello*** there");
+
+        // Each undoes in one step, and toggling again takes the marks off
+        let _ = app.update(EditorMessage::Undo);
+        assert_eq!(app.buffer.get_text(), "say **hello** there");
+        app.select_offsets(4, 13);
+        let _ = app.update(EditorMessage::ToggleBold);
+        assert_eq!(app.buffer.get_text(), "say hello there");
+        assert_eq!(app.selected_text().as_deref(), Some("hello"));
+
+        // Without a selection the caret goes between the new pair, which goes again if empty
+        app.selection = None;
+        app.set_cursor(0, 3);
+        let _ = app.update(EditorMessage::ToggleCode);
+        assert_eq!(app.buffer.get_text(), "say`` hello there");
+        assert_eq!((app.col, app.selection), (4, None));
+        let _ = app.update(EditorMessage::ToggleCode);
+        assert_eq!(app.buffer.get_text(), "say hello there");
+        assert_eq!(app.col, 3);
+    }
+
+    #[test]
+    fn selected_lines_gain_and_lose_list_markers_and_headings() {
+        let (mut app, _) = App::new();
+        app.buffer.set_text("one\ntwo\n\nthree\nafter");
+        // From inside the first line to the start of the last, which is left out
+        let _ = app.update(EditorMessage::BeginSelection { line: 0, column: 1 });
+        let _ = app.update(EditorMessage::ExtendSelectionTo { line: 4, column: 0 });
+
+        let _ = app.update(EditorMessage::ToggleBulletList);
+        assert_eq!(app.buffer.get_text(), "- one\n- two\n\n- three\nafter");
+        assert_eq!(
+            app.selection_range().map(|(a, b)| (a.col, b.line)),
+            Some((3, 4))
+        );
+        let _ = app.update(EditorMessage::ToggleNumberedList);
+        assert_eq!(app.buffer.get_text(), "1. one\n2. two\n\n3. three\nafter");
+        let _ = app.update(EditorMessage::ToggleNumberedList);
+        assert_eq!(app.buffer.get_text(), "one\ntwo\n\nthree\nafter");
+
+        // One undo step each
+        let _ = app.update(EditorMessage::Undo);
+        assert_eq!(app.buffer.get_text(), "1. one\n2. two\n\n3. three\nafter");
+
+        // Headings on the caret line, replacing the level, then taken off
+        app.selection = None;
+        app.set_cursor(4, 2);
+        let _ = app.update(EditorMessage::SetHeadingLevel(2));
+        assert_eq!(app.buffer.get_line_content(5), "## after");
+        assert_eq!(app.col, 5);
+        let _ = app.update(EditorMessage::SetHeadingLevel(1));
+        assert_eq!(app.buffer.get_line_content(5), "# after");
+        let _ = app.update(EditorMessage::SetHeadingLevel(1));
+        assert_eq!(app.buffer.get_line_content(5), "after");
+        assert_eq!(app.col, 2);
+    }
+
+    #[test]
+    fn duplicated_and_moved_lines_carry_the_caret() {
+        let (mut app, _) = App::new();
+        app.buffer.set_text("one\ntwo\nthree");
+        let _ = app.update(EditorMessage::BeginSelection { line: 0, column: 1 });
+        let _ = app.update(EditorMessage::ExtendSelectionTo { line: 1, column: 2 });
+
+        let _ = app.update(EditorMessage::DuplicateLines);
+        assert_eq!(app.buffer.get_text(), "one\ntwo\none\ntwo\nthree");
+        assert_eq!(
+            app.selection_range()
+                .map(|(a, b)| (a.line, a.col, b.line, b.col)),
+            Some((2, 1, 3, 2))
+        );
+
+        let _ = app.update(EditorMessage::MoveLinesDown);
+        assert_eq!(app.buffer.get_text(), "one\ntwo\nthree\none\ntwo");
+        assert_eq!((app.line, app.col), (4, 2));
+        // Already last
+        let _ = app.update(EditorMessage::MoveLinesDown);
+        assert_eq!(app.buffer.get_text(), "one\ntwo\nthree\none\ntwo");
+
+        let _ = app.update(EditorMessage::MoveLinesUp);
+        let _ = app.update(EditorMessage::Undo);
+        assert_eq!(app.buffer.get_text(), "one\ntwo\nthree\none\ntwo");
+    }
+
+    #[test]
+    fn joining_lines_leaves_the_caret_at_the_join() {
+        let (mut app, _) = App::new();
+        app.buffer.set_text("one\n  two\nthree");
+        let _ = app.update(EditorMessage::BeginSelection { line: 0, column: 1 });
+        let _ = app.update(EditorMessage::ExtendSelectionTo { line: 2, column: 2 });
+
+        let _ = app.update(EditorMessage::JoinLines);
+        assert_eq!(app.buffer.get_text(), "one two three");
+        assert_eq!((app.line, app.col), (0, 7));
+        assert!(app.selection.is_none());
+
+        // The last line has nothing to join with
+        let _ = app.update(EditorMessage::JoinLines);
+        assert_eq!(app.buffer.get_text(), "one two three");
+    }
+
+    #[test]
+    fn caret_blinks_without_a_new_render_and_shows_again_when_moved() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("a".to_string()));
+        let _ = app.update(EditorMessage::Insert("b".to_string()));
+        let version = app.render_version;
+        let later = || Instant::now() + CARET_BLINK;
+
+        // Kept on for a blink after the last keystroke
+        let _ = app.update(EditorMessage::CaretBlink(Instant::now()));
+        assert!(app.caret_visible);
+        let _ = app.update(EditorMessage::CaretBlink(later()));
+        assert!(!app.caret_visible);
+        assert_eq!(app.render_version, version);
+        // A blink does not end the typed run
+        let _ = app.update(EditorMessage::Insert("c".to_string()));
+        let _ = app.update(EditorMessage::Undo);
+        assert_eq!(app.buffer.get_text(), "");
+
+        assert!(app.caret_visible);
+        let _ = app.update(EditorMessage::CaretBlink(later()));
+        let _ = app.update(EditorMessage::Insert("d".to_string()));
+        let _ = app.update(EditorMessage::CaretBlink(later()));
+        assert!(!app.caret_visible);
+        let _ = app.update(EditorMessage::MoveLeft);
+        assert!(app.caret_visible);
+        let _ = app.update(EditorMessage::CaretBlink(Instant::now()));
+        assert!(app.caret_visible);
+    }
+
+    #[test]
+    fn tab_indents_and_shift_tab_outdents_selected_lines() {
+        let (mut app, _) = App::new();
+        app.tab = TabStyle::Spaces(4);
+        app.buffer.set_text("first\n  second\n\tthird\nlast");
+        // From the start of the first line into the third
+        let _ = app.update(EditorMessage::BeginSelection { line: 0, column: 0 });
+        let _ = app.update(EditorMessage::ExtendSelectionTo { line: 2, column: 3 });
+
+        let _ = app.update(EditorMessage::Tab);
+        assert_eq!(
+            app.buffer.get_text(),
+            "    first\n      second\n    \tthird\nlast"
+        );
+        let sel = app.selection.unwrap();
+        assert_eq!(
+            (sel.anchor, sel.head),
+            (Caret { line: 0, col: 0 }, Caret { line: 2, col: 7 })
+        );
+
+        let _ = app.update(EditorMessage::Dedent);
+        let _ = app.update(EditorMessage::Dedent);
+        // The first line had nothing left to take off the second time
+        assert_eq!(app.buffer.get_text(), "first\nsecond\nthird\nlast");
+        assert_eq!(app.selected_text().as_deref(), Some("first\nsecond\nth"));
+
+        // Hard tabs, and a selection ending at a line start leaves that line be
+        app.tab = TabStyle::Hard;
+        app.select_offsets(0, 13);
+        let _ = app.update(EditorMessage::Tab);
+        assert_eq!(app.buffer.get_text(), "\tfirst\n\tsecond\nthird\nlast");
+        let _ = app.update(EditorMessage::Undo);
+        assert_eq!(app.buffer.get_text(), "first\nsecond\nthird\nlast");
+    }
+
+    #[test]
+    fn dirty_marker_clears_on_save_and_new() {
+        let (mut app, _) = App::new();
+        assert_eq!(app.title(), "New file — Mditor");
+        assert_eq!(app.file_status(), "New file");
+
+        let _ = app.update(EditorMessage::Insert("a".to_string()));
+        assert_eq!(app.title(), "New file — Mditor (modified)");
+        assert_eq!(app.file_status(), "• New file");
+
+        let path = PathBuf::from("/tmp/notes.md");
+        let _ = app.update(EditorMessage::SaveAs);
+        let _ = app.update(EditorMessage::FileSaved(Ok(Some(path))));
+        assert_eq!(app.title(), "notes.md — Mditor");
+        assert_eq!(app.file_status(), "/tmp/notes.md");
+
+        let _ = app.update(EditorMessage::Insert("b".to_string()));
+        assert_eq!(app.file_status(), "• /tmp/notes.md");
+        let _ = app.run(PendingAction::New);
+        assert_eq!(app.title(), "New file — Mditor");
+        assert_eq!(app.file_status(), "New file");
+    }
+
+    #[test]
+    fn syntax_follows_the_file_extension() {
+        assert_eq!(syntax_token(&PathBuf::from("/notes/todo.md")), "md");
+        assert_eq!(syntax_token(&PathBuf::from("src/main.rs")), "rs");
+        assert_eq!(syntax_token(&PathBuf::from("README")), "md");
+        assert_eq!(syntax_token(&PathBuf::from(".bashrc")), "md");
+    }
+
+    #[test]
+    fn long_paths_keep_their_end() {
+        assert_eq!(file_label(None), "New file");
+        let long = PathBuf::from(format!("/home/{}/ñotes.md", "é".repeat(60)));
+        let label = file_label(Some(&long));
+        assert_eq!(label.chars().count(), 43);
+        assert!(label.starts_with("...") && label.ends_with("/ñotes.md"));
+    }
+
+    #[test]
+    fn new_file_waits_for_the_unsaved_changes_answer() {
+        let (mut app, _) = App::new();
+        app.file = Some(PathBuf::from("/tmp/notes.md"));
+        let _ = app.update(EditorMessage::Insert("a".to_string()));
+
+        // Cancel keeps everything
+        let _ = app.update(EditorMessage::NewFile);
+        assert_eq!(app.pending, Some(PendingAction::New));
+        let _ = app.update(EditorMessage::UnsavedChangesAnswered(UnsavedChoice::Cancel));
+        assert_eq!(app.pending, None);
+        assert_eq!(app.buffer.get_text(), "a");
+        assert_eq!(app.file, Some(PathBuf::from("/tmp/notes.md")));
+        assert!(app.is_dirty);
+
+        // A failed save keeps everything too
+        let _ = app.update(EditorMessage::NewFile);
+        let _ = app.update(EditorMessage::UnsavedChangesAnswered(UnsavedChoice::Save));
+        assert!(app.is_loading);
+        let denied = Error::IoError(std::io::ErrorKind::PermissionDenied);
+        let _ = app.update(EditorMessage::FileSaved(Err(denied)));
+        assert_eq!(app.pending, None);
+        assert_eq!(app.buffer.get_text(), "a");
+
+        // Saving, then the new file
+        let _ = app.update(EditorMessage::NewFile);
+        let _ = app.update(EditorMessage::UnsavedChangesAnswered(UnsavedChoice::Save));
+        let _ = app.update(EditorMessage::FileSaved(Ok(None)));
+        assert_eq!(app.buffer.get_text(), "");
+        assert_eq!(app.file, None);
+        assert!(!app.is_dirty);
+    }
+
+    #[test]
+    fn dropped_files_open_like_open_file() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("a".to_string()));
+
+        let first = PathBuf::from("/tmp/first.md");
+        let _ = app.update(EditorMessage::FileDropped(first.clone()));
+        assert_eq!(app.pending, Some(PendingAction::OpenPath(first)));
+        // The rest of a multi-file drop
+        let _ = app.update(EditorMessage::FileDropped(PathBuf::from("/tmp/second.md")));
+        assert_eq!(
+            app.pending,
+            Some(PendingAction::OpenPath("/tmp/first.md".into()))
+        );
+
+        let _ = app.update(EditorMessage::UnsavedChangesAnswered(
+            UnsavedChoice::Discard,
+        ));
+        assert_eq!(app.pending, None);
+        assert!(app.is_loading);
+
+        let event = Event::Window(window::Event::FileDropped(PathBuf::from("/tmp/x.md")));
+        assert!(matches!(
+            map_drop_event(event, event::Status::Ignored, window::Id::unique()),
+            Some(EditorMessage::FileDropped(_))
+        ));
+    }
+
+    #[test]
+    fn opened_and_saved_files_go_to_the_front_of_recent() {
+        let (mut app, _) = App::new();
+        let opened = |name: &str| {
+            EditorMessage::FileOpened(Ok((
+                PathBuf::from(name),
+                vec![String::new()],
+                FileEncoding::default(),
+            )))
+        };
+        let _ = app.update(opened("/notes/a.md"));
+        let _ = app.update(opened("/notes/b.md"));
+        let _ = app.update(EditorMessage::SaveAs);
+        let _ = app.update(EditorMessage::FileSaved(Ok(Some("/notes/c.md".into()))));
+        let _ = app.update(opened("/notes/b.md"));
+        assert_eq!(
+            app.recent,
+            ["/notes/b.md", "/notes/c.md", "/notes/a.md"].map(PathBuf::from)
+        );
+
+        // The stored list arriving late goes behind them
+        let stored = ["/old.md", "/notes/a.md"].map(PathBuf::from).to_vec();
+        let _ = app.update(EditorMessage::RecentLoaded(stored));
+        assert_eq!(
+            app.recent,
+            ["/notes/b.md", "/notes/c.md", "/notes/a.md", "/old.md"].map(PathBuf::from)
+        );
+
+        let _ = app.update(EditorMessage::OpenRecent("/old.md".into()));
+        assert!(app.is_loading);
+    }
+
+    #[test]
+    fn discarding_unsaved_changes() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("a\nb".to_string()));
+        let _ = app.update(EditorMessage::NewFile);
+        assert_eq!(app.buffer.get_text(), "a\nb");
+
+        let _ = app.update(EditorMessage::UnsavedChangesAnswered(
+            UnsavedChoice::Discard,
+        ));
+        assert_eq!(app.buffer.get_text(), "");
+        assert_eq!((app.line, app.col), (0, 0));
+        assert!(!app.is_dirty);
+
+        // Nothing to lose: no prompt
+        let _ = app.update(EditorMessage::OpenFile);
+        assert_eq!(app.pending, None);
+        assert!(app.is_loading);
+    }
+
+    #[test]
+    fn cut_and_paste_a_multi_line_selection() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("one\ntwo\nthree".to_string()));
+        let _ = app.update(EditorMessage::BeginSelection { line: 0, column: 2 });
+        let _ = app.update(EditorMessage::ExtendSelectionTo { line: 1, column: 1 });
+        assert_eq!(app.selected_text().as_deref(), Some("e\nt"));
+
+        let _ = app.update(EditorMessage::Cut);
+        assert_eq!(app.buffer.get_text(), "onwo\nthree");
+        assert_eq!(app.selected_text(), None);
+
+        // The input's own events do not touch the document
+        let _ = app.update(EditorMessage::Insert(String::new()));
+        assert_eq!(app.buffer.get_text(), "onwo\nthree");
+
+        // Paste replaces the selection and keeps line breaks
+        let _ = app.update(EditorMessage::BeginSelection { line: 1, column: 0 });
+        let _ = app.update(EditorMessage::ExtendSelectionTo { line: 1, column: 5 });
+        let _ = app.update(EditorMessage::Pasted("e\nt".to_string()));
+        assert_eq!(app.buffer.get_text(), "onwo\ne\nt");
+        assert_eq!((app.line, app.col), (2, 1));
+    }
+
+    #[test]
+    fn find_next_and_prev_select_matches() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("tea, té\ntea\nsteam tea".to_string()));
+        let _ = app.update(EditorMessage::ToggleFind);
+        let _ = app.update(EditorMessage::FindQueryChanged("tea".to_string()));
+        assert_eq!(app.find.as_ref().unwrap().label(), "4 matches");
+
+        // From the caret at the end, wrapping to the first match
+        let _ = app.update(EditorMessage::FindNext);
+        assert_eq!(app.selected_text().as_deref(), Some("tea"));
+        assert_eq!((app.line, app.col), (0, 3));
+        let _ = app.update(EditorMessage::FindNext);
+        assert_eq!((app.line, app.col), (1, 3));
+        assert_eq!(app.find.as_ref().unwrap().label(), "2 of 4");
+
+        let _ = app.update(EditorMessage::FindPrev);
+        let _ = app.update(EditorMessage::FindPrev);
+        assert_eq!((app.line, app.col), (2, 9));
+        assert_eq!(app.find.as_ref().unwrap().label(), "4 of 4");
+
+        // Closing leaves the match selected
+        let _ = app.update(EditorMessage::ToggleFind);
+        assert!(app.find.is_none() && !app.find_focused);
+        assert_eq!(app.selected_text().as_deref(), Some("tea"));
+    }
+
+    #[test]
+    fn replace_one_then_all() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("a-b a-b\na-b".to_string()));
+        let _ = app.update(EditorMessage::ToggleFind);
+        let _ = app.update(EditorMessage::FindQueryChanged("a-b".to_string()));
+        let _ = app.update(EditorMessage::ReplacementChanged("ab".to_string()));
+
+        // The first Replace only selects a match; the next one replaces it
+        let _ = app.update(EditorMessage::Replace);
+        assert_eq!(app.buffer.get_text(), "a-b a-b\na-b");
+        let _ = app.update(EditorMessage::Replace);
+        assert_eq!(app.buffer.get_text(), "ab a-b\na-b");
+        assert_eq!(app.find.as_ref().unwrap().label(), "1 of 2");
+
+        let _ = app.update(EditorMessage::ReplaceAll);
+        assert_eq!(app.buffer.get_text(), "ab ab\nab");
+        assert_eq!(app.find.as_ref().unwrap().label(), "No results");
+
+        // One undo step brings back every replaced match
+        let _ = app.update(EditorMessage::Undo);
+        assert_eq!(app.buffer.get_text(), "ab a-b\na-b");
+        assert_eq!(app.find.as_ref().unwrap().matches, vec![3, 7]);
+    }
+
+    #[test]
+    fn saving_moves_the_clean_state() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("a".to_string()));
+        let _ = app.update(EditorMessage::SaveFile);
+        let _ = app.update(EditorMessage::FileSaved(Ok(None)));
+        assert!(!app.is_dirty);
+
+        let _ = app.update(EditorMessage::Insert("b".to_string()));
+        assert!(app.is_dirty);
+        let _ = app.update(EditorMessage::Backspace);
+        let _ = app.update(EditorMessage::LineChangesDue(app.render_version));
+        assert!(!app.is_dirty);
+        let _ = app.update(EditorMessage::Backspace);
+        assert!(app.is_dirty);
+    }
+
+    #[test]
+    fn bracket_under_caret_wins_over_bracket_before() {
+        assert_eq!(bracket_near_caret("a(b)", 1), Some(1));
+        assert_eq!(bracket_near_caret("a(b)", 2), Some(1));
+        assert_eq!(bracket_near_caret("(b)", 3), Some(2));
+        assert_eq!(bracket_near_caret(")(", 1), Some(1));
+        assert_eq!(bracket_near_caret("abc", 1), None);
+        assert_eq!(bracket_near_caret("", 0), None);
+    }
+
+    #[test]
+    fn bracket_pair_follows_caret() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("é(x".to_string()));
+        let _ = app.update(EditorMessage::Enter);
+        let _ = app.update(EditorMessage::Insert(")".to_string()));
+        assert_eq!(
+            app.bracket_pair,
+            Some((Caret { line: 1, col: 0 }, Some(Caret { line: 0, col: 1 })))
+        );
+
+        let _ = app.update(EditorMessage::Insert("y".to_string()));
+        assert_eq!(app.bracket_pair, None);
+
+        // Switched off and on again
+        let _ = app.update(EditorMessage::MoveLeft);
+        assert!(app.bracket_pair.is_some());
+        let _ = app.update(EditorMessage::ToggleBracketHighlight);
+        assert_eq!(app.bracket_pair, None);
+        let _ = app.update(EditorMessage::ToggleBracketHighlight);
+        assert!(app.bracket_pair.is_some());
+    }
+
+    #[test]
+    fn unmatched_bracket_has_no_partner() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("[(x".to_string()));
+        let _ = app.update(EditorMessage::MoveLeft);
+        assert_eq!(app.bracket_pair, Some((Caret { line: 0, col: 1 }, None)));
+
+        let _ = app.update(EditorMessage::Insert(")".to_string()));
+        assert_eq!(
+            app.bracket_pair,
+            Some((Caret { line: 0, col: 2 }, Some(Caret { line: 0, col: 1 })))
+        );
+    }
+
+    #[test]
+    fn caret_lands_after_inserted_text() {
+        let at = Caret { line: 2, col: 3 };
+        assert_eq!(caret_after_insert(at, "é!"), Caret { line: 2, col: 5 });
+        assert_eq!(caret_after_insert(at, "\n"), Caret { line: 3, col: 0 });
+        assert_eq!(
+            caret_after_insert(at, "a\r\nb\rcd"),
+            Caret { line: 4, col: 2 }
+        );
+    }
+
+    #[test]
+    fn typing_over_selection_replaces_it() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("hello\nworld".to_string()));
+        let _ = app.update(EditorMessage::BeginSelection { line: 0, column: 2 });
+        let _ = app.update(EditorMessage::ExtendSelectionTo { line: 1, column: 3 });
+
+        let _ = app.update(EditorMessage::Enter);
+        assert_eq!(app.buffer.get_text(), "he\nld");
+        assert_eq!((app.line, app.col), (1, 0));
+        assert_eq!(app.selection, None);
+
+        let _ = app.update(EditorMessage::SelectAll);
+        let _ = app.update(EditorMessage::Insert("x\ny".to_string()));
+        assert_eq!(app.buffer.get_text(), "x\ny");
+        assert_eq!((app.line, app.col), (1, 1));
+    }
+
+    #[test]
+    fn gutter_markers_track_edits_since_save() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("a\nb".to_string()));
+        app.mark_saved();
+        assert!(app.line_changes.is_empty());
+
+        let _ = app.update(EditorMessage::Insert("c".to_string()));
+        let _ = app.update(EditorMessage::Enter);
+        assert!(app.line_changes.is_empty());
+        let _ = app.update(EditorMessage::LineChangesDue(app.render_version));
+        assert_eq!(
+            app.line_changes,
+            vec![None, Some(LineChange::Modified), Some(LineChange::Added)]
+        );
+
+        let _ = app.update(EditorMessage::Backspace);
+        let _ = app.update(EditorMessage::Backspace);
+        let _ = app.update(EditorMessage::LineChangesDue(app.render_version));
+        assert!(app.line_changes.is_empty());
+        assert!(!app.is_dirty);
+    }
+
+    #[test]
+    fn toggle_word_replaces_the_word_under_the_caret() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("wrap = True\nsize = 9".to_string()));
+        let _ = app.update(EditorMessage::BeginSelection { line: 0, column: 8 });
+
+        let _ = app.update(EditorMessage::ToggleWord { step: 1 });
+        assert_eq!(app.buffer.get_line_content(1), "wrap = False");
+        assert_eq!((app.line, app.col), (0, 12));
+
+        let _ = app.update(EditorMessage::MoveDown);
+        let _ = app.update(EditorMessage::ToggleWord { step: 1 });
+        let _ = app.update(EditorMessage::ToggleWord { step: 1 });
+        assert_eq!(app.buffer.get_line_content(2), "size = 11");
+
+        // Nothing to toggle on `=`
+        let _ = app.update(EditorMessage::BeginSelection { line: 1, column: 5 });
+        let _ = app.update(EditorMessage::ToggleWord { step: -1 });
+        assert_eq!(app.buffer.get_text(), "wrap = False\nsize = 11");
+    }
+
+    #[test]
+    fn kill_ranges_stop_at_line_boundaries() {
+        // "hello world\r\nnext": line ends at 11, next line starts at 13
+        assert_eq!(kill_to_end_range(6, 11, 13), 6..11);
+        assert_eq!(kill_to_end_range(11, 11, 13), 11..13);
+        // Last line: nothing left to join
+        assert_eq!(kill_to_end_range(17, 17, 17), 17..17);
+        assert_eq!(kill_to_start_range(13, 15), 13..15);
+    }
+
+    #[test]
+    fn delete_to_line_end_and_start() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert(
+            "hello world\r\nnext line".to_string(),
+        ));
+        let _ = app.update(EditorMessage::BeginSelection { line: 0, column: 5 });
+
+        let _ = app.update(EditorMessage::DeleteToLineEnd);
+        assert_eq!(app.buffer.get_text(), "hello\r\nnext line");
+        let _ = app.update(EditorMessage::DeleteToLineEnd);
+        assert_eq!(app.buffer.get_text(), "hellonext line");
+        assert_eq!((app.line, app.col), (0, 5));
+
+        let _ = app.update(EditorMessage::MoveRight);
+        let _ = app.update(EditorMessage::DeleteToLineStart);
+        assert_eq!(app.buffer.get_text(), "ext line");
+        assert_eq!((app.line, app.col), (0, 0));
+    }
+
+    #[test]
+    fn delete_removes_the_grapheme_or_line_break_after_the_caret() {
+        let (mut app, _) = App::new();
+        app.buffer.set_text("e\u{301}x\r\nnext");
+        app.set_cursor(0, 0);
+
+        let _ = app.update(EditorMessage::DeleteForward);
+        assert_eq!(app.buffer.get_text(), "x\r\nnext");
+        let _ = app.update(EditorMessage::MoveEnd);
+        let _ = app.update(EditorMessage::DeleteForward);
+        assert_eq!(app.buffer.get_text(), "xnext");
+        assert_eq!((app.line, app.col), (0, 1));
+
+        let _ = app.update(EditorMessage::MoveDocumentEnd);
+        let _ = app.update(EditorMessage::DeleteForward);
+        assert_eq!(app.buffer.get_text(), "xnext");
+
+        let _ = app.update(EditorMessage::SelectAll);
+        let _ = app.update(EditorMessage::DeleteForward);
+        assert_eq!(app.buffer.get_text(), "");
+    }
+
+    #[test]
+    fn select_all_covers_the_document_and_is_replaced_by_the_next_edit() {
+        let (mut app, _) = App::new();
+        app.buffer.set_text("first\r\nsecond\nthird");
+        app.set_cursor(1, 2);
+        let version = app.render_version;
+
+        let _ = app.update(EditorMessage::SelectAll);
+        assert_eq!(
+            app.selected_text().as_deref(),
+            Some("first\r\nsecond\nthird")
+        );
+        assert_eq!((app.line, app.col), (2, 5));
+        assert_ne!(app.render_version, version);
+
+        let _ = app.update(EditorMessage::Backspace);
+        assert_eq!(app.buffer.get_text(), "");
+        assert_eq!((app.line, app.col), (0, 0));
+    }
+
+    #[test]
+    fn goto_target_clamps_and_ignores_non_numbers() {
+        assert_eq!(goto_target("3", 10), Some(2));
+        assert_eq!(goto_target(" 7 ", 10), Some(6));
+        assert_eq!(goto_target("0", 10), Some(0));
+        assert_eq!(goto_target("99", 10), Some(9));
+        assert_eq!(goto_target("", 10), None);
+        assert_eq!(goto_target("12a", 10), None);
+        assert_eq!(goto_target("-1", 10), None);
+    }
+
+    #[test]
+    fn goto_line_moves_the_caret_to_the_line_start() {
+        let (mut app, _) = App::new();
+        app.buffer.set_text("one\ntwo\nthree\nfour");
+        app.set_cursor(0, 2);
+
+        let _ = app.update(EditorMessage::GotoLine);
+        let _ = app.update(EditorMessage::GotoLineChanged("x".to_string()));
+        let _ = app.update(EditorMessage::GotoLineSubmitted);
+        assert_eq!((app.line, app.col), (0, 2));
+        assert!(app.goto_line.is_some());
+
+        let _ = app.update(EditorMessage::GotoLineChanged("3".to_string()));
+        let _ = app.update(EditorMessage::GotoLineSubmitted);
+        assert_eq!((app.line, app.col), (2, 0));
+        assert!(app.goto_line.is_none());
+
+        let _ = app.update(EditorMessage::GotoLine);
+        let _ = app.update(EditorMessage::GotoLineChanged("40".to_string()));
+        let _ = app.update(EditorMessage::GotoLineSubmitted);
+        assert_eq!((app.line, app.col), (3, 0));
+    }
+
+    fn open_chunks(app: &mut App, chunks: &[&str]) {
+        let chunks = chunks.iter().map(|c| c.to_string()).collect();
+        let _ = app.update(EditorMessage::FileOpened(Ok((
+            PathBuf::from("/tmp/notes.txt"),
+            chunks,
+            FileEncoding::default(),
+        ))));
+    }
+
+    #[test]
+    fn line_ending_is_detected_on_open() {
+        let (mut app, _) = App::new();
+        open_chunks(&mut app, &["one\r\ntwo\r\n", "three\n"]);
+        assert_eq!(app.eol, Eol::CrLf);
+        open_chunks(&mut app, &["one\ntwo\n"]);
+        assert_eq!(app.eol, Eol::Lf);
+        // Mixed: the most frequent wins
+        open_chunks(&mut app, &["a\r\nb\nc\r\nd"]);
+        assert_eq!(app.eol, Eol::CrLf);
+        open_chunks(&mut app, &["a\r\nb\nc\nd"]);
+        assert_eq!(app.eol, Eol::Lf);
+        assert!(!app.is_dirty);
+    }
+
+    #[test]
+    fn enter_and_save_use_the_chosen_line_ending() {
+        let (mut app, _) = App::new();
+        open_chunks(&mut app, &["one\r\ntwo"]);
+        let _ = app.update(EditorMessage::MoveDocumentEnd);
+        let _ = app.update(EditorMessage::Enter);
+        assert_eq!(app.buffer.get_text(), "one\r\ntwo\r\n");
+        assert_eq!((app.line, app.col), (2, 0));
+
+        let _ = app.update(EditorMessage::Undo);
+        assert!(!app.is_dirty);
+        let _ = app.update(EditorMessage::SetLineEnding(Eol::Lf));
+        assert!(app.is_dirty);
+        assert_eq!(app.saved_bytes(), b"one\ntwo");
+        let _ = app.update(EditorMessage::SetLineEnding(Eol::CrLf));
+        assert!(!app.is_dirty);
+    }
+
+    #[test]
+    fn saving_writes_back_in_the_opened_encoding() {
+        let (mut app, _) = App::new();
+        let encoding = FileEncoding {
+            encoding: text_buffer::encoding_rs::UTF_16LE,
+            bom: true,
+        };
+        let _ = app.update(EditorMessage::FileOpened(Ok((
+            PathBuf::from("/tmp/wide.txt"),
+            vec!["é\r\n".to_string()],
+            encoding,
+        ))));
+        assert_eq!(app.encoding, encoding);
+        assert_eq!(app.saved_bytes(), b"\xFF\xFE\xE9\0\r\0\n\0");
+
+        let _ = app.update(EditorMessage::NewFile);
+        assert_eq!(app.encoding, FileEncoding::default());
+        assert_eq!(app.saved_bytes(), b"");
+    }
+
+    #[test]
+    fn saving_utf8_streams_the_buffer_to_disk() {
+        let dir = std::env::temp_dir().join(format!("mditor_save_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bom.txt");
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::FileOpened(Ok((
+            path.clone(),
+            vec!["one\r\ntwo".to_string()],
+            FileEncoding {
+                encoding: UTF_8,
+                bom: true,
+            },
+        ))));
+        let _ = app.update(EditorMessage::SetLineEnding(Eol::Lf));
+        let _ = app.update(EditorMessage::SaveFile);
+        assert_eq!(fs::read(&path).unwrap(), b"\xEF\xBB\xBFone\ntwo");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tab_expands_a_trigger_word() {
+        let (mut app, _) = App::new();
+        app.snippets = Snippets::default().with("main", "fn main() {\n    $0\n}");
+        let _ = app.update(EditorMessage::Insert("main".to_string()));
+        let _ = app.update(EditorMessage::Tab);
+        assert_eq!(app.buffer.get_text(), "fn main() {\n    \n}");
+        assert_eq!((app.line, app.col), (1, 4));
+
+        // Not a trigger: a plain tab
+        let _ = app.update(EditorMessage::Insert("x".to_string()));
+        let _ = app.update(EditorMessage::Tab);
+        assert_eq!(app.buffer.get_line_content(2), "    x\t");
+        assert_eq!((app.line, app.col), (1, 6));
+    }
+
+    #[test]
+    fn soft_tabs_reach_the_next_stop_and_shift_tab_dedents() {
+        let (mut app, _) = App::new();
+        app.tab = TabStyle::Spaces(4);
+        let _ = app.update(EditorMessage::Insert("ab".to_string()));
+        let _ = app.update(EditorMessage::Tab);
+        assert_eq!(app.buffer.get_text(), "ab  ");
+        app.set_cursor(0, 0);
+        let _ = app.update(EditorMessage::Tab);
+        let _ = app.update(EditorMessage::Tab);
+        assert_eq!(app.buffer.get_text(), "        ab  ");
+        assert_eq!(app.col, 8);
+
+        let _ = app.update(EditorMessage::Dedent);
+        assert_eq!(app.buffer.get_text(), "    ab  ");
+        assert_eq!(app.col, 4);
+        let _ = app.update(EditorMessage::Dedent);
+        let _ = app.update(EditorMessage::Dedent);
+        assert_eq!(app.buffer.get_text(), "ab  ");
+        assert_eq!(app.col, 0);
+
+        let _ = app.update(EditorMessage::ToggleTabStyle);
+        let _ = app.update(EditorMessage::Tab);
+        assert_eq!(app.buffer.get_text(), "\tab  ");
+    }
+
+    #[test]
+    fn enter_keeps_indentation_and_continues_lists() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("    - first".to_string()));
+        let _ = app.update(EditorMessage::Enter);
+        assert_eq!(app.buffer.get_text(), "    - first\n    - ");
+        assert_eq!((app.line, app.col), (1, 6));
+
+        // Enter on the empty item ends the list
+        let _ = app.update(EditorMessage::Enter);
+        assert_eq!(app.buffer.get_text(), "    - first\n    ");
+        assert_eq!((app.line, app.col), (1, 4));
+
+        // Outside Markdown only the indentation carries over
+        app.syntax = "rs".to_string();
+        let _ = app.update(EditorMessage::Insert("- x".to_string()));
+        let _ = app.update(EditorMessage::Enter);
+        assert_eq!(app.buffer.get_line_content(3), "    ");
+
+        let _ = app.update(EditorMessage::ToggleAutoIndent);
+        let _ = app.update(EditorMessage::Enter);
+        assert_eq!(app.buffer.get_line_content(4), "");
+        assert_eq!((app.line, app.col), (3, 0));
+    }
+
+    #[test]
+    fn home_and_end_move_along_the_line_and_document() {
+        let (mut app, _) = App::new();
+        app.buffer.set_text("first\n  \tnaïve line\nlast");
+        app.set_cursor(1, 8);
+
+        let _ = app.update(EditorMessage::MoveHome);
+        assert_eq!((app.line, app.col), (1, 3));
+        let _ = app.update(EditorMessage::MoveHome);
+        assert_eq!((app.line, app.col), (1, 0));
+        let _ = app.update(EditorMessage::MoveHome);
+        assert_eq!((app.line, app.col), (1, 3));
+
+        let _ = app.update(EditorMessage::MoveEnd);
+        assert_eq!((app.line, app.col), (1, 13));
+
+        let _ = app.update(EditorMessage::MoveDocumentEnd);
+        assert_eq!((app.line, app.col), (2, 4));
+        let _ = app.update(EditorMessage::MoveDocumentStart);
+        assert_eq!((app.line, app.col), (0, 0));
+    }
+
+    #[test]
+    fn page_moves_stop_at_the_document_edges() {
+        let (mut app, _) = App::new();
+        let text: Vec<String> = (0..50).map(|i| format!("line {i}")).collect();
+        app.buffer.set_text(&text.join("\n"));
+        // Ten rows and a bit
+        app.viewport_height = app.line_height() * 10.5;
+        app.set_cursor(3, 6);
+
+        let _ = app.update(EditorMessage::PageDown);
+        assert_eq!((app.line, app.col), (13, 6));
+        let _ = app.update(EditorMessage::PageUp);
+        let _ = app.update(EditorMessage::PageUp);
+        assert_eq!((app.line, app.col), (0, 6));
+
+        app.set_cursor(45, 4);
+        let _ = app.update(EditorMessage::PageDown);
+        assert_eq!((app.line, app.col), (49, 4));
+        let _ = app.update(EditorMessage::PageDown);
+        assert_eq!((app.line, app.col), (49, 4));
+    }
+
+    #[test]
+    fn ctrl_arrows_move_and_select_by_word() {
+        let (mut app, _) = App::new();
+        app.buffer.set_text("naïve café\nnext");
+        app.set_cursor(0, 0);
+
+        let _ = app.update(EditorMessage::MoveWordRight);
+        assert_eq!((app.line, app.col), (0, 5));
+        let _ = app.update(EditorMessage::ExtendWordRight);
+        assert_eq!((app.line, app.col), (0, 10));
+        assert_eq!(app.selected_text().as_deref(), Some(" café"));
+
+        let _ = app.update(EditorMessage::MoveWordRight);
+        assert_eq!((app.line, app.col), (1, 0));
+        let _ = app.update(EditorMessage::MoveWordLeft);
+        assert_eq!((app.line, app.col), (0, 10));
+        let _ = app.update(EditorMessage::MoveWordLeft);
+        assert_eq!((app.line, app.col), (0, 6));
+    }
+
+    #[test]
+    fn double_click_selects_a_word_and_triple_click_a_line() {
+        let (mut app, _) = App::new();
+        app.buffer.set_text("let café = 1;\nnext");
+
+        let _ = app.update(EditorMessage::SelectWord { line: 0, column: 6 });
+        assert_eq!(app.selected_text().as_deref(), Some("café"));
+        assert_eq!((app.line, app.col), (0, 8));
+
+        let _ = app.update(EditorMessage::SelectWord {
+            line: 0,
+            column: 10,
+        });
+        assert_eq!(app.selected_text(), None);
+        assert_eq!((app.line, app.col), (0, 10));
+
+        let _ = app.update(EditorMessage::SelectLine { line: 0 });
+        assert_eq!(app.selected_text().as_deref(), Some("let café = 1;\n"));
+        assert_eq!((app.line, app.col), (1, 0));
+
+        let _ = app.update(EditorMessage::SelectLine { line: 1 });
+        assert_eq!(app.selected_text().as_deref(), Some("next"));
+    }
+
+    #[test]
+    fn stats_follow_edits_and_selection() {
+        let (mut app, _) = App::new();
+        let _ = app.update(EditorMessage::Insert("one two\nthree".to_string()));
+        let _ = app.update(EditorMessage::Backspace);
+        let _ = app.update(EditorMessage::Enter);
+        assert_eq!(app.stats, app.buffer.stats());
+        assert_eq!(app.stats.lines, 3);
+
+        let _ = app.update(EditorMessage::BeginSelection { line: 0, column: 4 });
+        let _ = app.update(EditorMessage::ExtendSelectionTo { line: 1, column: 2 });
+        let selected = app.selection_stats().unwrap();
+        assert_eq!((selected.lines, selected.words, selected.chars), (2, 2, 6));
+        assert_eq!(
+            stats_label(&app.stats, Some(selected)),
+            "3 lines · 3 words · 13 chars · 13 bytes  |  selected: 2 lines · 2 words · 6 chars"
+        );
+        assert_eq!(count_label(&selected), "2 words • 6 chars");
+        assert_eq!(count_label(&TextStats::of("漢字!")), "2 words • 3 chars");
+        assert_eq!(count_label(&TextStats::of("a")), "1 word • 1 char");
+    }
+
+    #[test]
+    fn chrome_styles_follow_color_scheme() {
+        let colors = ColorScheme {
+            editor_bg: iced::Color::from_rgb8(1, 2, 3),
+            top_bar_bg: iced::Color::from_rgb8(4, 5, 6),
+            bottom_bar_bg: iced::Color::from_rgb8(7, 8, 9),
+            gutter_bg: iced::Color::from_rgb8(10, 11, 12),
+            ..ColorScheme::default()
+        };
+        let bg = |style: container::Style| style.background;
+
+        assert_eq!(
+            bg(editor_bg(&colors)),
+            Some(iced::Background::Color(colors.editor_bg))
+        );
+        assert_eq!(
+            bg(top_bar_bg(&colors)),
+            Some(iced::Background::Color(colors.top_bar_bg))
+        );
+        assert_eq!(
+            bg(bottom_bar_bg(&colors)),
+            Some(iced::Background::Color(colors.bottom_bar_bg))
+        );
+    }
+}
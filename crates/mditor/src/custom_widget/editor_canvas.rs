@@ -1,34 +1,178 @@
+use crate::model::caret_style::CaretStyle;
+use crate::model::color_scheme::ColorScheme;
 use crate::model::editor_message::EditorMessage;
+use crate::model::gutter_style::GutterStyle;
+use crate::model::line_change::LineChange;
+use crate::model::markdown_syntax::{MarkdownTokenizer, is_markdown};
+use crate::model::wrap::{self, wrap_rows};
 
+use super::glyph_metrics::{self, GlyphMetrics};
+use iced::advanced::text::Highlighter as _;
 use iced::{
-    Font, Rectangle, Renderer,
+    Color, Font, Rectangle, Renderer, highlighter,
     mouse::Cursor,
     widget::canvas::{self, Cache},
 };
+use std::ops::Range;
+use std::rc::Rc;
 use text_buffer::TextBuffer;
 use unicode_segmentation::UnicodeSegmentation;
-// TODOS: figure out how to get factor for any font. Right now just a constant that align with iced's FONT::MONOSPACE
-const MONO_CHAR_FACTOR: f32 = 0.585;
+// Width of the change marker strip between the line numbers and the text
+const CHANGE_STRIP_WIDTH: f32 = 3.0;
 
-#[derive(Debug, Default)] 
+// (line, col) of the bracket next to the caret and of its partner, if it has one
+pub type BracketPair = ((usize, usize), Option<(usize, usize)>);
+
+// Width of the gutter in front of the text of a document of `line_count` lines, for digits
+// `char_width` wide
+pub fn gutter_width(gutter: &GutterStyle, line_count: usize, char_width: f32) -> f32 {
+    gutter.width(digit_count(line_count), char_width)
+}
+
+// Digits in the line number `line_count`
+fn digit_count(line_count: usize) -> usize {
+    let mut n = line_count.max(1);
+    let mut digit_count = 0usize;
+    while n > 0 {
+        digit_count += 1;
+        n /= 10;
+    }
+    digit_count
+}
+
+#[derive(Debug, Default)]
 pub struct EditorCanvasCache {
     cache: std::cell::RefCell<Cache>,
     seen_version: std::cell::Cell<u64>,
     dragging: std::cell::Cell<bool>,
+    // Previous left press, to tell double and triple clicks from single ones
+    last_click: std::cell::Cell<Option<iced::advanced::mouse::Click>>,
+    highlighting: std::cell::RefCell<Option<Highlighting>>,
+    // The caret is drawn on a layer of its own, so blinking does not redraw the text
+    caret_cache: std::cell::RefCell<Cache>,
+    caret_shown: std::cell::Cell<bool>,
+    // Where the text last drawn put the caret: the text area it is clipped to, and its rectangle
+    caret_rect: std::cell::Cell<Option<(Rectangle, iced::Point, iced::Size)>>,
+}
+
+// Colored byte ranges of each line, kept until the text or the highlighter settings change.
+// Caret moves and other redraws reuse them; an edit re-tokenizes from the first changed line.
+// Lines are only tokenized as far down as has been shown.
+#[derive(Debug)]
+struct Highlighting {
+    settings: highlighter::Settings,
+    lines: Rc<Vec<String>>,
+    highlighter: Tokenizer,
+    spans: Vec<Vec<(Range<usize>, Color)>>,
+}
+
+// What colors the lines: the Markdown tokenizer for Markdown, the syntax highlighter and its
+// theme for anything else
+#[derive(Debug)]
+enum Tokenizer {
+    Markdown(MarkdownTokenizer),
+    Syntax(highlighter::Highlighter),
+}
+
+impl Tokenizer {
+    fn new(settings: &highlighter::Settings) -> Self {
+        if is_markdown(&settings.token) {
+            Tokenizer::Markdown(MarkdownTokenizer::default())
+        } else {
+            Tokenizer::Syntax(highlighter::Highlighter::new(settings))
+        }
+    }
+
+    fn change_line(&mut self, line: usize) {
+        match self {
+            Tokenizer::Markdown(tokenizer) => tokenizer.change_line(line),
+            Tokenizer::Syntax(highlighter) => highlighter.change_line(line),
+        }
+    }
+
+    fn current_line(&self) -> usize {
+        match self {
+            Tokenizer::Markdown(tokenizer) => tokenizer.current_line(),
+            Tokenizer::Syntax(highlighter) => highlighter.current_line(),
+        }
+    }
+
+    // Colored byte ranges of the next line
+    fn highlight_line(&mut self, text: &str) -> Vec<(Range<usize>, Color)> {
+        match self {
+            Tokenizer::Markdown(tokenizer) => tokenizer
+                .tokenize_line(text)
+                .into_iter()
+                .map(|(range, token)| (range, token.color()))
+                .collect(),
+            Tokenizer::Syntax(highlighter) => highlighter
+                .highlight_line(text)
+                .filter_map(|(range, highlight)| Some((range, highlight.color()?)))
+                .collect(),
+        }
+    }
+}
+
+impl Highlighting {
+    fn new(settings: &highlighter::Settings, lines: &Rc<Vec<String>>) -> Self {
+        Self {
+            settings: settings.clone(),
+            lines: Rc::clone(lines),
+            highlighter: Tokenizer::new(settings),
+            spans: Vec::new(),
+        }
+    }
+
+    // Bring the spans up to date with `lines`, tokenizing at least the lines before `end`
+    fn update(&mut self, lines: &Rc<Vec<String>>, end: usize) {
+        if !Rc::ptr_eq(&self.lines, lines) {
+            let unchanged = self
+                .lines
+                .iter()
+                .zip(lines.iter())
+                .take_while(|(old, new)| old == new)
+                .count();
+            self.lines = Rc::clone(lines);
+            if unchanged < self.spans.len() {
+                self.highlighter.change_line(unchanged);
+                self.spans.truncate(self.highlighter.current_line());
+            }
+        }
+        let end = end.min(self.lines.len());
+        for text in self.lines.get(self.spans.len()..end).unwrap_or_default() {
+            let spans = self.highlighter.highlight_line(text);
+            self.spans.push(spans);
+        }
+    }
 }
 
 pub struct EditorCanvas<'a> {
     buffer: &'a TextBuffer,
     font: Font,
     font_size: f32,
+    metrics: Rc<GlyphMetrics>,
     spacing: f32,
     cursor_line: usize,
     cursor_col: usize,
+    scroll_x: f32, // how far the text is scrolled to the left, in pixels
     render_version: u64,
+    caret_visible: bool, // false during the off phase of the blink
     selection: Option<((usize, usize), (usize, usize))>,
+    colors: ColorScheme,
+    gutter: GutterStyle,
+    caret_style: CaretStyle,
+    wrap_column: Option<usize>,
+    tab_width: Option<usize>,
+    bracket_pair: Option<BracketPair>,
+    line_changes: &'a [Option<LineChange>],
+    show_trailing_whitespace: bool,
+    show_whitespace: bool,
+    highlight: Option<highlighter::Settings>,
+    viewport: Option<(f32, f32)>,
 }
 
 impl<'a> EditorCanvas<'a> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         buffer: &'a TextBuffer,
         font: Font,
@@ -36,20 +180,100 @@ impl<'a> EditorCanvas<'a> {
         spacing: f32,
         cursor_line: usize,
         cursor_col: usize,
+        scroll_x: f32,
         render_version: u64,
+        caret_visible: bool,
     ) -> Self {
         EditorCanvas {
             buffer,
             font,
             font_size,
+            metrics: GlyphMetrics::shared(font, font_size),
             spacing,
             cursor_line,
             cursor_col,
+            scroll_x,
             render_version,
+            caret_visible,
             selection: None,
+            colors: ColorScheme::default(),
+            gutter: GutterStyle::default(),
+            caret_style: CaretStyle::default(),
+            wrap_column: None,
+            tab_width: None,
+            bracket_pair: None,
+            line_changes: &[],
+            show_trailing_whitespace: false,
+            show_whitespace: false,
+            highlight: None,
+            viewport: None,
         }
     }
 
+    pub fn with_color_scheme(mut self, colors: ColorScheme) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    pub fn with_gutter_style(mut self, gutter: GutterStyle) -> Self {
+        self.gutter = gutter;
+        self
+    }
+
+    pub fn with_caret_style(mut self, caret_style: CaretStyle) -> Self {
+        self.caret_style = caret_style;
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap_column: Option<usize>) -> Self {
+        self.wrap_column = wrap_column;
+        self
+    }
+
+    // Expand tabs to stops of `tab_width` cells; without it a tab is one cell
+    pub fn with_tab_width(mut self, tab_width: Option<usize>) -> Self {
+        self.tab_width = tab_width;
+        self
+    }
+
+    // Outline the bracket at (line, col) and its partner; without a partner it is drawn as unmatched
+    pub fn with_bracket_pair(mut self, pair: Option<BracketPair>) -> Self {
+        self.bracket_pair = pair;
+        self
+    }
+
+    // Change markers against the saved text, indexed by line
+    pub fn with_line_changes(mut self, line_changes: &'a [Option<LineChange>]) -> Self {
+        self.line_changes = line_changes;
+        self
+    }
+
+    // Fill trailing whitespace with the error color
+    pub fn with_trailing_whitespace(mut self, show: bool) -> Self {
+        self.show_trailing_whitespace = show;
+        self
+    }
+
+    // Mark spaces with a dot, tabs with an arrow and line ends with a pilcrow, over the text
+    // without moving it
+    pub fn with_whitespace(mut self, show: bool) -> Self {
+        self.show_whitespace = show;
+        self
+    }
+
+    // Color the text with the syntax highlighter; plain text without it
+    pub fn with_highlighting(mut self, settings: highlighter::Settings) -> Self {
+        self.highlight = Some(settings);
+        self
+    }
+
+    // Only draw what a viewport `height` tall shows when scrolled `top` down the canvas;
+    // everything down to the canvas bounds without it
+    pub fn with_viewport(mut self, top: f32, height: f32) -> Self {
+        self.viewport = (height > 0.0).then_some((top.max(0.0), height));
+        self
+    }
+
     pub fn with_selection(
         mut self,
         anchor_line: usize,
@@ -60,6 +284,112 @@ impl<'a> EditorCanvas<'a> {
         self.selection = Some(((anchor_line, anchor_col), (head_line, head_col)));
         self
     }
+
+    // Grapheme ranges of the display rows of a line; a single row when not wrapping
+    fn rows_of(&self, line: &str) -> Vec<std::ops::Range<usize>> {
+        match self.wrap_column {
+            Some(width) => wrap_rows(line, width, self.tab_width),
+            None => std::iter::once(0..line.graphemes(true).count()).collect(),
+        }
+    }
+
+    // X of each grapheme boundary of `row` of `line`, from the start of the row
+    fn row_advances(&self, line: &str, row: &Range<usize>) -> Vec<f32> {
+        self.metrics
+            .advances(wrap::slice_graphemes(line, row.clone()), self.tab_width)
+    }
+
+    // X of grapheme column `col` of `line` from the start of `row`
+    fn row_x(&self, line: &str, row: &Range<usize>, col: usize) -> f32 {
+        let col = col.clamp(row.start, row.end);
+        self.row_advances(line, row)[col - row.start]
+    }
+
+    // Grapheme column nearest to `x` pixels into `row`
+    fn column_in_row(&self, line: &str, rows: &[Range<usize>], row: usize, x: f32) -> usize {
+        let advances = self.row_advances(line, &rows[row]);
+        wrap::clamp_to_row(rows, row, glyph_metrics::boundary_at(&advances, x))
+    }
+
+    // Width of the grapheme at `col` of `line` for the caret: its advance, or one cell past
+    // the end of the row
+    fn caret_cell_width(&self, line: &str, row: &Range<usize>, col: usize) -> f32 {
+        if !(row.start..row.end).contains(&col) {
+            return self.metrics.cell_width();
+        }
+        let advances = self.row_advances(line, row);
+        let i = col - row.start;
+        (advances[i + 1] - advances[i]).max(1.0)
+    }
+
+    // Digits in the largest line number
+    fn digit_count(&self) -> usize {
+        digit_count(self.buffer.get_line_count())
+    }
+
+    // Map a point in canvas coordinates to a (line, column) caret position
+    fn hit_test(&self, p: iced::Point) -> (usize, usize) {
+        let line_height = self.font_size * self.spacing;
+        let char_width = self.metrics.cell_width();
+        let gutter_width = self.gutter.width(self.digit_count(), char_width);
+
+        let target_row = (p.y / line_height).floor().max(0.0) as usize;
+        let x = p.x.max(gutter_width) - gutter_width + self.scroll_x;
+
+        if self.wrap_column.is_none() {
+            let line = target_row.min(self.buffer.get_line_count().max(1) - 1);
+            let text = self.buffer.get_lines_range(line + 1, line + 2);
+            let text = text.first().map_or("", String::as_str);
+            let rows = self.rows_of(text);
+            return (line, self.column_in_row(text, &rows, 0, x));
+        }
+
+        let lines = self.buffer.snapshot_lines();
+        let mut row_base = 0;
+        for (line, text) in lines.iter().enumerate() {
+            let rows = self.rows_of(text);
+            if target_row < row_base + rows.len() || line + 1 == lines.len() {
+                let row = (target_row - row_base).min(rows.len() - 1);
+                return (line, self.column_in_row(text, &rows, row, x));
+            }
+            row_base += rows.len();
+        }
+        (0, 0)
+    }
+
+    // The lines with a row inside the canvas area `top..top + height`, as the index of the
+    // first one, where it is drawn and their text
+    fn visible_lines(&self, top: f32, height: f32) -> (usize, f32, Vec<String>) {
+        let line_height = self.font_size * self.spacing;
+        if self.wrap_column.is_none() {
+            let lines = line_window(top, height, line_height, self.buffer.get_line_count());
+            let y = lines.start as f32 * line_height;
+            let text = self.buffer.get_lines_range(lines.start + 1, lines.end + 1);
+            return (lines.start, y, text);
+        }
+
+        let lines = self.buffer.snapshot_lines();
+        let (mut first, mut y) = (0, 0.0);
+        for line in lines.iter() {
+            let bottom = y + self.rows_of(line).len() as f32 * line_height;
+            if bottom > top {
+                break;
+            }
+            first += 1;
+            y = bottom;
+        }
+        let mut bottom = y;
+        let text = lines[first..]
+            .iter()
+            .take_while(|line| {
+                let shown = bottom < top + height;
+                bottom += self.rows_of(line).len() as f32 * line_height;
+                shown
+            })
+            .cloned()
+            .collect();
+        (first, y, text)
+    }
 }
 
 impl<'a> canvas::Program<crate::model::editor_message::EditorMessage> for EditorCanvas<'a> {
@@ -69,51 +399,42 @@ impl<'a> canvas::Program<crate::model::editor_message::EditorMessage> for Editor
         &self,
         state: &Self::State,
         renderer: &Renderer,
-        _theme: &iced::Theme,
+        theme: &iced::Theme,
         bounds: iced::Rectangle,
         _cursor: iced::mouse::Cursor,
     ) -> Vec<iced::widget::canvas::Geometry<iced::Renderer>> {
-        let char_width = (self.font_size * MONO_CHAR_FACTOR).max(1.0);
+        let char_width = self.metrics.cell_width();
 
         // Invalidate cache if version changed
         if state.seen_version.get() != self.render_version {
             state.cache.borrow_mut().clear();
+            state.caret_cache.borrow_mut().clear();
             state.seen_version.set(self.render_version);
         }
+        if state.caret_shown.replace(self.caret_visible) != self.caret_visible {
+            state.caret_cache.borrow_mut().clear();
+        }
 
         let geometry = state
             .cache
             .borrow_mut()
             .draw(renderer, bounds.size(), |frame| {
-                let lines = self.buffer.get_lines_content();
-                let line_count = self.buffer.get_line_count();
-
                 let line_height = self.font_size * self.spacing;
-                let gutter_pad_left = 24.0;
-                let gutter_pad_right = 36.0;
-
-                let mut n = line_count.max(1);
-                let mut digit_count = 0usize;
-                while n > 0 {
-                    digit_count += 1;
-                    n /= 10;
-                }
-                let gutter_width =
-                    gutter_pad_left + (digit_count as f32) * char_width + gutter_pad_right;
+                let digit_count = self.digit_count();
+                let gutter_width = self.gutter.width(digit_count, char_width);
+                let mut caret_pos = None;
+                let mut caret_cell = char_width;
 
                 // Gutter
-                let gutter_bg = iced::Color::from_rgba8(39, 40, 34, 1.0);
                 frame.fill_rectangle(
                     iced::Point::new(0.0, 0.0),
                     iced::Size::new(gutter_width, bounds.height),
-                    gutter_bg,
+                    self.colors.gutter_bg,
                 );
 
                 let number_color = iced::Color::from_rgba8(180, 180, 180, 1.0);
                 let text_color = iced::Color::from_rgba8(255, 255, 255, 1.0);
 
-                let mut y = 0.0;
-
                 // Normalize selection
                 let selection = if let Some(((a_line, a_col), (h_line, h_col))) = self.selection {
                     let (mut s_line, mut s_col) = (a_line, a_col);
@@ -127,15 +448,31 @@ impl<'a> canvas::Program<crate::model::editor_message::EditorMessage> for Editor
                     None
                 };
 
-                for (i, line) in lines.iter().enumerate() {
-                    if y > bounds.height + line_height {
-                        break;
+                let (top, height) = self.viewport.unwrap_or((0.0, bounds.height));
+                let (first, top_y, visible) = self.visible_lines(top, height);
+
+                let mut highlighting = state.highlighting.borrow_mut();
+                let spans: &[Vec<(Range<usize>, Color)>] = match &self.highlight {
+                    Some(settings) => {
+                        let lines = self.buffer.snapshot_lines();
+                        let h = match highlighting.take() {
+                            Some(h) if h.settings == *settings => highlighting.insert(h),
+                            _ => highlighting.insert(Highlighting::new(settings, &lines)),
+                        };
+                        h.update(&lines, first + visible.len());
+                        &h.spans
                     }
+                    None => &[],
+                };
 
-                    let number_str = (i + 1).to_string();
-                    let number_len = number_str.len() as f32;
-                    let number_width = number_len * char_width;
-                    let number_x = gutter_width - gutter_pad_right - number_width;
+                let mut y = top_y;
+                for (i, line) in (first..).zip(&visible) {
+                    let number = i + 1;
+
+                    let number_str = number.to_string();
+                    let number_x = self
+                        .gutter
+                        .number_x(number_str.len(), digit_count, char_width);
 
                     frame.fill_text(iced::widget::canvas::Text {
                         content: number_str,
@@ -146,60 +483,215 @@ impl<'a> canvas::Program<crate::model::editor_message::EditorMessage> for Editor
                         ..Default::default()
                     });
 
-                    // Selection background for this line
-                    if let Some(((s_line, s_col), (e_line, e_col))) = selection {
-                        if i >= s_line && i <= e_line {
-                            let grapheme_len = line.graphemes(true).count();
-                            let (start_col, end_col) = if s_line == e_line {
-                                (s_col.min(grapheme_len), e_col.min(grapheme_len))
-                            } else if i == s_line {
-                                (s_col.min(grapheme_len), grapheme_len)
-                            } else if i == e_line {
-                                (0, e_col.min(grapheme_len))
+                    let height = self.rows_of(line).len() as f32 * line_height;
+                    if let Some(Some(change)) = self.line_changes.get(i) {
+                        let (origin, size) = change_marker(
+                            *change,
+                            gutter_width - self.gutter.pad_right / 2.0,
+                            y,
+                            height,
+                        );
+                        let color = match change {
+                            LineChange::Added => self.colors.line_added,
+                            LineChange::Modified => self.colors.line_modified,
+                            LineChange::Deleted => self.colors.line_deleted,
+                        };
+                        frame.fill_rectangle(origin, size, color);
+                    }
+                    y += height;
+                }
+
+                // Text, scrolled sideways, and clipped so it cannot run into the gutter
+                let text_area = Rectangle::new(
+                    iced::Point::new(gutter_width, 0.0),
+                    iced::Size::new((bounds.width - gutter_width).max(0.0), bounds.height),
+                );
+                let text_x = gutter_width - self.scroll_x;
+                let current_line_bg = current_line_color(theme);
+                let whitespace_color = whitespace_color(theme);
+                let line_count = self.buffer.get_line_count();
+                frame.with_clip(text_area, |frame| {
+                    let mut y = top_y;
+                    for (i, line) in (first..).zip(&visible) {
+                        let rows = self.rows_of(line);
+
+                        if i == self.cursor_line {
+                            let row = wrap::row_of(&rows, self.cursor_col);
+                            let x = self.row_x(line, &rows[row], self.cursor_col);
+                            caret_pos = Some((text_x + x, y + row as f32 * line_height));
+                            if self.caret_style != CaretStyle::Bar {
+                                caret_cell =
+                                    self.caret_cell_width(line, &rows[row], self.cursor_col);
+                            }
+
+                            // Behind every row of the caret line, across the whole text area
+                            // however far it is scrolled; selection and text paint over it
+                            frame.fill_rectangle(
+                                text_area.position() + iced::Vector::new(0.0, y),
+                                iced::Size::new(text_area.width, rows.len() as f32 * line_height),
+                                current_line_bg,
+                            );
+                        }
+
+                        // Selection columns covered on this line
+                        let selected = match selection {
+                            Some(((s_line, s_col), (e_line, e_col)))
+                                if i >= s_line && i <= e_line =>
+                            {
+                                let start = if i == s_line { s_col } else { 0 };
+                                let end = if i == e_line { e_col } else { usize::MAX };
+                                Some((start, end))
+                            }
+                            _ => None,
+                        };
+
+                        // Outline matched brackets on this line, or a lone unmatched one
+                        if let Some((a, b)) = self.bracket_pair {
+                            let color = if b.is_some() {
+                                self.colors.bracket_match
                             } else {
-                                (0, grapheme_len)
+                                self.colors.bracket_unmatched
                             };
-                            if end_col > start_col {
-                                let x0 = gutter_width + (start_col as f32) * char_width;
-                                let w = ((end_col - start_col) as f32) * char_width;
-                                let h = line_height;
-                                let color = iced::Color::from_rgba8(100, 150, 255, 0.25);
+                            let brackets = std::iter::once(a).chain(b);
+                            for col in brackets.filter(|(l, _)| *l == i).map(|(_, c)| c) {
+                                let row = wrap::row_of(&rows, col);
+                                let (origin, size) = bracket_box(
+                                    text_x,
+                                    y + row as f32 * line_height,
+                                    self.row_x(line, &rows[row], col),
+                                    self.caret_cell_width(line, &rows[row], col),
+                                    line_height,
+                                );
+                                frame.stroke(
+                                    &canvas::Path::rectangle(origin, size),
+                                    canvas::Stroke::default().with_width(1.0).with_color(color),
+                                );
+                            }
+                        }
+
+                        // Grapheme columns of trailing whitespace on this line
+                        let trailing = if self.show_trailing_whitespace {
+                            let range = trailing_whitespace(line);
+                            let start = line[..range.start].graphemes(true).count();
+                            Some((start, start + line[range].graphemes(true).count()))
+                        } else {
+                            None
+                        };
+
+                        for row in &rows {
+                            let advances = self.row_advances(line, row);
+                            let x_of =
+                                |col: usize| advances[col.clamp(row.start, row.end) - row.start];
+
+                            if let Some((start, end)) = trailing {
+                                let start_col = start.clamp(row.start, row.end);
+                                let end_col = end.clamp(row.start, row.end);
+                                if end_col > start_col {
+                                    let x0 = x_of(start_col);
+                                    frame.fill_rectangle(
+                                        iced::Point::new((text_x + x0).floor(), y),
+                                        iced::Size::new(x_of(end_col) - x0, line_height),
+                                        self.colors.trailing_whitespace,
+                                    );
+                                }
+                            }
+
+                            // Selection background for this row
+                            if let Some((start_col, end_col, to_edge)) =
+                                selected.and_then(|selected| selection_span(selected, row))
+                            {
+                                let x0 = text_x + x_of(start_col);
+                                let w = if to_edge {
+                                    bounds.width - x0
+                                } else {
+                                    x_of(end_col) - x_of(start_col)
+                                };
                                 frame.fill_rectangle(
                                     iced::Point::new(x0.floor(), y),
-                                    iced::Size::new(w.max(1.0), h),
-                                    color,
+                                    iced::Size::new(w.max(1.0), line_height),
+                                    self.colors.selection,
                                 );
                             }
+
+                            let row_text = wrap::slice_graphemes(line, row.clone());
+                            let row_byte: usize =
+                                line.graphemes(true).take(row.start).map(str::len).sum();
+                            let line_spans = spans.get(i).map_or(&[][..], Vec::as_slice);
+                            for (x, content, color) in row_runs(
+                                row_text, row_byte, &advances, char_width, line_spans, text_color,
+                            ) {
+                                frame.fill_text(iced::widget::canvas::Text {
+                                    color,
+                                    content,
+                                    font: self.font,
+                                    size: self.font_size.into(),
+                                    position: iced::Point::new(text_x + x, y),
+                                    ..Default::default()
+                                });
+                            }
+
+                            if self.show_whitespace {
+                                // The last line has no break to mark
+                                let line_end = (rows.last() == Some(row) && i + 1 < line_count)
+                                    .then(|| advances[advances.len() - 1]);
+                                for (x, mark) in
+                                    whitespace_marks(row_text, &advances, char_width, line_end)
+                                {
+                                    frame.fill_text(iced::widget::canvas::Text {
+                                        content: mark.to_string(),
+                                        font: self.font,
+                                        size: self.font_size.into(),
+                                        color: whitespace_color,
+                                        position: iced::Point::new(text_x + x, y),
+                                        horizontal_alignment: iced::alignment::Horizontal::Center,
+                                        ..Default::default()
+                                    });
+                                }
+                            }
+
+                            y += line_height;
                         }
                     }
+                });
 
-                    let x_text = gutter_width;
-                    frame.fill_text(iced::widget::canvas::Text {
-                        color: text_color,
-                        content: line.clone(),
-                        font: self.font,
-                        size: self.font_size.into(),
-                        position: iced::Point::new(x_text, y),
-                        ..Default::default()
-                    });
+                state
+                    .caret_rect
+                    .set(caret_pos.map(|(caret_x, caret_y_top)| {
+                        let (origin, size) = caret_rect(
+                            self.caret_style,
+                            caret_x,
+                            caret_y_top,
+                            caret_cell,
+                            line_height,
+                        );
+                        (text_area, origin, size)
+                    }));
+            });
 
-                    y += line_height;
+        let caret = state
+            .caret_cache
+            .borrow_mut()
+            .draw(renderer, bounds.size(), |frame| {
+                let Some((text_area, origin, size)) = state.caret_rect.get() else {
+                    return;
+                };
+                if !self.caret_visible {
+                    return;
                 }
-
-                let caret_line = self.cursor_line as f32;
-                let caret_col = self.cursor_col as f32;
-                let caret_x = gutter_width + caret_col * char_width;
-                let caret_y_top = caret_line * line_height;
-                let caret_color = iced::Color::from_rgba8(255, 255, 255, 0.8);
-                let caret_width = 1.0;
-                frame.fill_rectangle(
-                    iced::Point::new(caret_x.floor(), caret_y_top),
-                    iced::Size::new(caret_width, line_height),
-                    caret_color,
-                );
+                let alpha = match self.caret_style {
+                    CaretStyle::Block => 0.35,
+                    CaretStyle::Bar | CaretStyle::Underline => 0.8,
+                };
+                frame.with_clip(text_area, |frame| {
+                    frame.fill_rectangle(
+                        origin,
+                        size,
+                        iced::Color::from_rgba8(255, 255, 255, alpha),
+                    );
+                });
             });
 
-        vec![geometry]
+        vec![geometry, caret]
     }
 
     fn update(
@@ -212,93 +704,358 @@ impl<'a> canvas::Program<crate::model::editor_message::EditorMessage> for Editor
         canvas::event::Status,
         Option<crate::model::editor_message::EditorMessage>,
     ) {
+        use iced::advanced::mouse::click;
         use iced::mouse;
 
         match event {
             canvas::Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
                 if let Some(p) = cursor.position_in(bounds) {
-                    let line_height = self.font_size * self.spacing;
-                    let char_width = MONO_CHAR_FACTOR * self.font_size;
-
-                    let mut n = self.buffer.get_line_count().max(1);
-                    let mut digit_count = 0usize;
-                    while n > 0 {
-                        digit_count += 1;
-                        n /= 10;
-                    }
-                    let gutter_width = 24.0 + (digit_count as f32) * char_width + 36.0;
-
-                    let mut line = (p.y / line_height).floor().max(0.0) as usize;
-                    let line_count = self.buffer.get_line_count();
-                    if line_count > 0 {
-                        line = line.min(line_count.saturating_sub(1));
-                    } else {
-                        line = 0;
-                    }
-                    let approx_col = ((p.x - gutter_width).max(0.0) / char_width)
-                        .round()
-                        .max(0.0) as usize;
-
-                    let line_text = self.buffer.get_line_content(line + 1);
-                    let grapheme_len = line_text.graphemes(true).count();
-                    let column = approx_col.min(grapheme_len);
+                    let (line, column) = self.hit_test(p);
+                    let click = click::Click::new(p, mouse::Button::Left, state.last_click.get());
+                    state.last_click.set(Some(click));
 
                     state.cache.borrow_mut().clear();
-                    state.dragging.set(true);
-                    return (
-                        canvas::event::Status::Captured,
-                        Some(EditorMessage::BeginSelection { line, column }),
-                    );
-                }
-            }
-            canvas::Event::Mouse(mouse::Event::CursorMoved { .. }) => {
-                if state.dragging.get() {
-                    if let Some(p) = cursor.position_in(bounds) {
-                        let line_height = self.font_size * self.spacing;
-                        let char_width = MONO_CHAR_FACTOR * self.font_size;
-
-                        let mut n = self.buffer.get_line_count().max(1);
-                        let mut digit_count = 0usize;
-                        while n > 0 {
-                            digit_count += 1;
-                            n /= 10;
+                    let message = match click.kind() {
+                        click::Kind::Single => {
+                            state.dragging.set(true);
+                            EditorMessage::BeginSelection { line, column }
                         }
-                        let gutter_width = 24.0 + (digit_count as f32) * char_width + 36.0;
-
-                        let mut line = (p.y / line_height).floor().max(0.0) as usize;
-                        let line_count = self.buffer.get_line_count();
-                        if line_count > 0 {
-                            line = line.min(line_count.saturating_sub(1));
-                        } else {
-                            line = 0;
-                        }
-                        let approx_col = ((p.x - gutter_width).max(0.0) / char_width)
-                            .round()
-                            .max(0.0) as usize;
-
-                        let line_text = self.buffer.get_line_content(line + 1);
-                        let grapheme_len = line_text.graphemes(true).count();
-                        let column = approx_col.min(grapheme_len);
-
-                        state.cache.borrow_mut().clear();
-                        return (
-                            canvas::event::Status::Captured,
-                            Some(EditorMessage::ExtendSelectionTo { line, column }),
-                        );
-                    }
+                        click::Kind::Double => EditorMessage::SelectWord { line, column },
+                        click::Kind::Triple => EditorMessage::SelectLine { line },
+                    };
+                    return (canvas::event::Status::Captured, Some(message));
                 }
             }
-            canvas::Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
-                if state.dragging.replace(false) {
+            canvas::Event::Mouse(mouse::Event::CursorMoved { .. }) if state.dragging.get() => {
+                if let Some(p) = cursor.position_in(bounds) {
+                    let (line, column) = self.hit_test(p);
+
+                    state.cache.borrow_mut().clear();
                     return (
                         canvas::event::Status::Captured,
-                        Some(EditorMessage::EndSelection),
+                        Some(EditorMessage::ExtendSelectionTo { line, column }),
                     );
                 }
             }
+            canvas::Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left))
+                if state.dragging.replace(false) =>
+            {
+                return (
+                    canvas::event::Status::Captured,
+                    Some(EditorMessage::EndSelection),
+                );
+            }
             canvas::Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) => {}
             _ => {}
         }
         (canvas::event::Status::Ignored, None)
     }
 }
+
+// Faint wash of the theme's text color, so it lightens dark themes and darkens light ones
+fn current_line_color(theme: &iced::Theme) -> Color {
+    Color {
+        a: 0.06,
+        ..theme.palette().text
+    }
+}
+
+// Fainter still than the current line, for the whitespace marks
+fn whitespace_color(theme: &iced::Theme) -> Color {
+    Color {
+        a: 0.3,
+        ..theme.palette().text
+    }
+}
+
+// Whitespace marks of a display row, as the x each is centered on and the mark: a dot in the
+// middle of each space, an arrow in the first cell of each tab, and a pilcrow in the cell
+// past the text at `line_end` when the row ends its line. `advances` are the x of each
+// grapheme boundary of the row, and `cell` is the width of a cell
+fn whitespace_marks(
+    row_text: &str,
+    advances: &[f32],
+    cell: f32,
+    line_end: Option<f32>,
+) -> Vec<(f32, char)> {
+    let mut marks: Vec<(f32, char)> = row_text
+        .graphemes(true)
+        .enumerate()
+        .filter_map(|(i, grapheme)| match grapheme {
+            " " => Some(((advances[i] + advances[i + 1]) / 2.0, '·')),
+            "\t" => Some((advances[i] + cell / 2.0, '→')),
+            _ => None,
+        })
+        .collect();
+    marks.extend(line_end.map(|x| (x + cell / 2.0, '¶')));
+    marks
+}
+
+// Pieces of a display row to draw, as (x, text, color). `advances` are the x of each grapheme
+// boundary of the row and `row_byte` is where the row starts in its line, which is what
+// `spans` are relative to. Graphemes of one color are drawn together, but each non-ASCII
+// grapheme gets a piece of its own at its measured x, so shaping a run cannot drift from the
+// positions the caret and clicks use. Tabs become the spaces filling their advance.
+fn row_runs(
+    row_text: &str,
+    row_byte: usize,
+    advances: &[f32],
+    cell: f32,
+    spans: &[(Range<usize>, Color)],
+    default: Color,
+) -> Vec<(f32, String, Color)> {
+    let mut runs: Vec<(f32, String, Color)> = Vec::new();
+    let mut span = 0;
+    let mut joinable = false;
+    for (i, (offset, grapheme)) in row_text.grapheme_indices(true).enumerate() {
+        let byte = row_byte + offset;
+        while span < spans.len() && spans[span].0.end <= byte {
+            span += 1;
+        }
+        let color = match spans.get(span) {
+            Some((range, color)) if range.start <= byte => *color,
+            _ => default,
+        };
+        let text = if grapheme == "\t" {
+            let cells = ((advances[i + 1] - advances[i]) / cell).round() as usize;
+            " ".repeat(cells.max(1))
+        } else {
+            grapheme.to_string()
+        };
+
+        match runs.last_mut() {
+            Some((_, run, run_color)) if joinable && grapheme.is_ascii() && *run_color == color => {
+                run.push_str(&text);
+            }
+            _ => runs.push((advances[i], text, color)),
+        }
+        joinable = grapheme.is_ascii();
+    }
+    runs
+}
+
+// Lines of a document of `line_count` one-row lines that have a row inside `top..top + height`
+fn line_window(top: f32, height: f32, line_height: f32, line_count: usize) -> Range<usize> {
+    let first = (top.max(0.0) / line_height).floor() as usize;
+    let end = ((top.max(0.0) + height) / line_height).ceil() as usize;
+    first.min(line_count)..end.min(line_count)
+}
+
+// Caret rectangle for a grapheme cell at (`x`, `y`) that is `cell_width` wide
+fn caret_rect(
+    style: CaretStyle,
+    x: f32,
+    y: f32,
+    cell_width: f32,
+    line_height: f32,
+) -> (iced::Point, iced::Size) {
+    let x = x.floor();
+    match style {
+        CaretStyle::Bar => (iced::Point::new(x, y), iced::Size::new(1.0, line_height)),
+        CaretStyle::Block => (
+            iced::Point::new(x, y),
+            iced::Size::new(cell_width, line_height),
+        ),
+        CaretStyle::Underline => (
+            iced::Point::new(x, y + line_height - 2.0),
+            iced::Size::new(cell_width, 2.0),
+        ),
+    }
+}
+
+// Grapheme columns of `row` inside the line's selected columns `start..end` (`end` past the
+// line when the selection goes on), and whether the band runs on to the right edge because
+// the selection continues on the next row or line
+fn selection_span(
+    (start, end): (usize, usize),
+    row: &std::ops::Range<usize>,
+) -> Option<(usize, usize, bool)> {
+    if start > row.end || end < row.start {
+        return None;
+    }
+    let start_col = start.max(row.start);
+    let end_col = end.min(row.end);
+    let to_edge = end > row.end;
+    (end_col > start_col || to_edge).then_some((start_col, end_col, to_edge))
+}
+
+// Byte range of the whitespace ending `line`; empty at the end of the line when there is none
+fn trailing_whitespace(line: &str) -> std::ops::Range<usize> {
+    line.trim_end().len()..line.len()
+}
+
+// Outline around the glyph `width` wide at `x` into the row drawn at `row_y`
+fn bracket_box(
+    text_x: f32,
+    row_y: f32,
+    x: f32,
+    width: f32,
+    line_height: f32,
+) -> (iced::Point, iced::Size) {
+    (
+        iced::Point::new((text_x + x).floor() + 0.5, row_y + 0.5),
+        iced::Size::new(width.ceil(), line_height - 1.0),
+    )
+}
+
+// Marker in the change strip at `x` for a line drawn from `y` spanning `height`: a bar
+// down the whole line, or for deleted lines above it a short tick at its top edge
+fn change_marker(change: LineChange, x: f32, y: f32, height: f32) -> (iced::Point, iced::Size) {
+    match change {
+        LineChange::Added | LineChange::Modified => (
+            iced::Point::new(x, y),
+            iced::Size::new(CHANGE_STRIP_WIDTH, height),
+        ),
+        LineChange::Deleted => (
+            iced::Point::new(x, y - 2.0),
+            iced::Size::new(CHANGE_STRIP_WIDTH * 2.0, 4.0),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailing_whitespace_covers_only_the_line_end() {
+        assert_eq!(trailing_whitespace("let x = 1;   "), 10..13);
+        assert_eq!(trailing_whitespace("\tindent\t\t"), 7..9);
+        assert_eq!(trailing_whitespace(" \t  "), 0..4);
+        assert_eq!(trailing_whitespace("done"), 4..4);
+        assert_eq!(trailing_whitespace(""), 0..0);
+    }
+
+    #[test]
+    fn only_lines_inside_the_viewport_are_laid_out() {
+        assert_eq!(line_window(0.0, 100.0, 20.0, 1000), 0..5);
+        assert_eq!(line_window(430.0, 100.0, 20.0, 1000), 21..27);
+        assert_eq!(line_window(430.0, 100.0, 20.0, 24), 21..24);
+
+        let text: String = (0..1000).map(|i| format!("line {i}\n")).collect();
+        let mut buffer = text_buffer::TextBufferBuilder::new().finish();
+        buffer.set_text(&text);
+        let canvas = EditorCanvas::new(&buffer, Font::MONOSPACE, 10.0, 2.0, 0, 0, 0.0, 0, true);
+        let (first, y, lines) = canvas.visible_lines(430.0, 100.0);
+        assert_eq!((first, y), (21, 420.0));
+        assert_eq!(lines.first().map(String::as_str), Some("line 21"));
+        assert_eq!(lines.len(), 6);
+
+        // Wrapped to two rows a line
+        let canvas = canvas.with_wrap(Some(5));
+        let (first, y, lines) = canvas.visible_lines(430.0, 100.0);
+        assert_eq!((first, y), (10, 400.0));
+        assert_eq!(lines.first().map(String::as_str), Some("line 10"));
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn current_line_color_follows_the_theme() {
+        let dark = current_line_color(&iced::Theme::Dark);
+        let light = current_line_color(&iced::Theme::Light);
+        assert!(dark.r > 0.5 && light.r < 0.5);
+        assert!(dark.a < 0.1 && light.a < 0.1);
+    }
+
+    #[test]
+    fn caret_rect_follows_style() {
+        let rect = |style| caret_rect(style, 84.75, 40.0, 16.5, 20.0);
+        assert_eq!(
+            rect(CaretStyle::Bar),
+            (iced::Point::new(84.0, 40.0), iced::Size::new(1.0, 20.0))
+        );
+        assert_eq!(
+            rect(CaretStyle::Block),
+            (iced::Point::new(84.0, 40.0), iced::Size::new(16.5, 20.0))
+        );
+        assert_eq!(
+            rect(CaretStyle::Underline),
+            (iced::Point::new(84.0, 58.0), iced::Size::new(16.5, 2.0))
+        );
+        assert_eq!(CaretStyle::Underline.cycled(), CaretStyle::Bar);
+    }
+
+    #[test]
+    fn selection_bands_run_to_the_edge_inside_the_selection() {
+        // Within one row
+        assert_eq!(selection_span((2, 5), &(0..10)), Some((2, 5, false)));
+        // First line of a longer selection, and an empty interior line
+        assert_eq!(selection_span((4, usize::MAX), &(0..6)), Some((4, 6, true)));
+        assert_eq!(selection_span((0, usize::MAX), &(0..0)), Some((0, 0, true)));
+        // Caret-only selection draws nothing
+        assert_eq!(selection_span((3, 3), &(0..10)), None);
+
+        // Wrapped line: rows 0..4 and 4..8, selection 2..6
+        assert_eq!(selection_span((2, 6), &(0..4)), Some((2, 4, true)));
+        assert_eq!(selection_span((2, 6), &(4..8)), Some((4, 6, false)));
+        // Selection starting on the second row skips the first
+        assert_eq!(selection_span((5, 7), &(0..4)), None);
+        assert_eq!(selection_span((0, 3), &(4..8)), None);
+    }
+
+    #[test]
+    fn row_runs_follow_spans_and_advances() {
+        let white = Color::WHITE;
+        let red = Color::from_rgb8(255, 0, 0);
+
+        // "# Tï\tle": heading marker colored, a wide ï on its own, the tab filling up to the
+        // stop at 80
+        let advances = [0.0, 10.0, 20.0, 30.0, 50.0, 80.0, 90.0, 100.0];
+        let runs = row_runs("# Tï\tle", 0, &advances, 10.0, &[(0..1, red)], white);
+        assert_eq!(
+            runs,
+            vec![
+                (0.0, "#".to_string(), red),
+                (10.0, " T".to_string(), white),
+                (30.0, "ï".to_string(), white),
+                (50.0, "   le".to_string(), white),
+            ]
+        );
+
+        // A wrapped row starting at byte 6 of its line
+        let advances = [0.0, 10.0, 20.0, 30.0, 40.0];
+        let runs = row_runs(
+            "le x",
+            6,
+            &advances,
+            10.0,
+            &[(0..2, red), (7..9, red)],
+            white,
+        );
+        assert_eq!(
+            runs,
+            vec![
+                (0.0, "l".to_string(), white),
+                (10.0, "e ".to_string(), red),
+                (30.0, "x".to_string(), white),
+            ]
+        );
+        assert!(row_runs("", 0, &[0.0], 10.0, &[], white).is_empty());
+    }
+
+    #[test]
+    fn whitespace_marks_sit_on_the_text_advances() {
+        // "a b\tc": the tab fills from 30 to the stop at 80
+        let advances = [0.0, 10.0, 20.0, 30.0, 80.0, 90.0];
+        assert_eq!(
+            whitespace_marks("a b\tc", &advances, 10.0, Some(90.0)),
+            [(15.0, '·'), (35.0, '→'), (95.0, '¶')]
+        );
+        // An ideographic space is not marked, nor is the end of a row that wraps
+        assert_eq!(
+            whitespace_marks("\u{3000}x ", &[0.0, 20.0, 30.0, 40.0], 10.0, None),
+            [(35.0, '·')]
+        );
+        assert!(whitespace_marks("", &[0.0], 10.0, None).is_empty());
+    }
+
+    #[test]
+    fn bracket_box_sits_on_the_glyph_cell() {
+        let (origin, size) = bracket_box(60.0, 40.0, 24.75, 8.25, 20.0);
+        assert_eq!(origin, iced::Point::new(84.5, 40.5));
+        assert_eq!(size, iced::Size::new(9.0, 19.0));
+
+        let (origin, _) = bracket_box(60.0, 0.0, 0.0, 8.25, 20.0);
+        assert_eq!(origin, iced::Point::new(60.5, 0.5));
+    }
+}
@@ -0,0 +1,176 @@
+use iced::advanced::text::{self as adv_text, Paragraph as _};
+use iced::{Font, Renderer};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use unicode_segmentation::UnicodeSegmentation;
+
+// Advance widths of text in one font and size, measured with the renderer's text shaping.
+// ASCII is taken to be one cell wide, so pure-ASCII text never needs shaping; any other
+// grapheme is measured the first time it shows up and remembered.
+#[derive(Debug)]
+pub struct GlyphMetrics {
+    font: Font,
+    font_size: f32,
+    cell: f32,
+    widths: RefCell<HashMap<String, f32>>,
+}
+
+thread_local! {
+    static SHARED: RefCell<HashMap<(Font, u32), Rc<GlyphMetrics>>> = RefCell::default();
+}
+
+impl GlyphMetrics {
+    pub fn new(font: Font, font_size: f32) -> Self {
+        // Ten digits average out the rounding of a single advance
+        let cell = measure(font, font_size, "0000000000") / 10.0;
+        Self {
+            font,
+            font_size,
+            cell: if cell > 0.0 { cell } else { font_size * 0.6 },
+            widths: RefCell::default(),
+        }
+    }
+
+    // Metrics for `font` at `font_size`, shared by everything drawing or measuring with it
+    pub fn shared(font: Font, font_size: f32) -> Rc<Self> {
+        SHARED.with(|shared| {
+            Rc::clone(
+                shared
+                    .borrow_mut()
+                    .entry((font, font_size.to_bits()))
+                    .or_insert_with(|| Rc::new(Self::new(font, font_size))),
+            )
+        })
+    }
+
+    // Advance of an ASCII character, and of a tab without tab stops
+    pub fn cell_width(&self) -> f32 {
+        self.cell
+    }
+
+    // Advance of one grapheme other than a tab
+    pub fn grapheme_width(&self, grapheme: &str) -> f32 {
+        if grapheme.is_ascii() {
+            return self.cell;
+        }
+        *self
+            .widths
+            .borrow_mut()
+            .entry(grapheme.to_string())
+            .or_insert_with(|| measure(self.font, self.font_size, grapheme))
+    }
+
+    // X of each grapheme boundary of `text`, from 0 before the first grapheme to its full
+    // width after the last. Tabs run to the next stop every `tab_width` cells.
+    pub fn advances(&self, text: &str, tab_width: Option<usize>) -> Vec<f32> {
+        if text.is_ascii() && !text.contains('\t') {
+            return (0..=text.len()).map(|i| i as f32 * self.cell).collect();
+        }
+        advances(text, self.cell, tab_width, |g| self.grapheme_width(g))
+    }
+
+    // Width of `text`, with tabs expanded as in `advances`
+    pub fn width(&self, text: &str, tab_width: Option<usize>) -> f32 {
+        self.advances(text, tab_width)
+            .last()
+            .copied()
+            .unwrap_or(0.0)
+    }
+}
+
+// `GlyphMetrics::advances` with the width of each grapheme other than a tab given by `width`
+fn advances(
+    text: &str,
+    cell: f32,
+    tab_width: Option<usize>,
+    width: impl Fn(&str) -> f32,
+) -> Vec<f32> {
+    let mut x = 0.0;
+    let mut out = Vec::with_capacity(text.len() + 1);
+    out.push(x);
+    for grapheme in text.graphemes(true) {
+        x = match (grapheme, tab_width) {
+            ("\t", Some(tab_width)) => {
+                let stop = tab_width.max(1) as f32 * cell;
+                // Nudged so a position a rounding error short of a stop counts as on it
+                ((x / stop + 1e-3).floor() + 1.0) * stop
+            }
+            ("\t", None) => x + cell,
+            _ => x + width(grapheme),
+        };
+        out.push(x);
+    }
+    out
+}
+
+// Index of the grapheme boundary in `advances` nearest to `x`
+pub fn boundary_at(advances: &[f32], x: f32) -> usize {
+    let after = advances.partition_point(|&a| a < x);
+    match (after.checked_sub(1), advances.get(after)) {
+        (Some(before), Some(&next)) if x - advances[before] < next - x => before,
+        (Some(before), None) => before,
+        _ => after,
+    }
+}
+
+fn measure(font: Font, font_size: f32, text: &str) -> f32 {
+    <Renderer as adv_text::Renderer>::Paragraph::with_text(adv_text::Text {
+        content: text,
+        bounds: iced::Size::INFINITY,
+        size: font_size.into(),
+        line_height: adv_text::LineHeight::default(),
+        font,
+        horizontal_alignment: iced::alignment::Horizontal::Left,
+        vertical_alignment: iced::alignment::Vertical::Top,
+        shaping: adv_text::Shaping::Advanced,
+        wrapping: adv_text::Wrapping::None,
+    })
+    .min_width()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Full-width CJK two cells, everything else one
+    fn fake_width(grapheme: &str) -> f32 {
+        if grapheme
+            .chars()
+            .any(|c| ('\u{4e00}'..='\u{9fff}').contains(&c))
+        {
+            20.0
+        } else {
+            10.0
+        }
+    }
+
+    #[test]
+    fn advances_add_up_grapheme_widths_and_tab_stops() {
+        assert_eq!(
+            advances("a中b", 10.0, Some(4), fake_width),
+            vec![0.0, 10.0, 30.0, 40.0]
+        );
+        assert_eq!(
+            advances("中\tx", 10.0, Some(4), fake_width),
+            vec![0.0, 20.0, 40.0, 50.0]
+        );
+        assert_eq!(
+            advances("abcd\t", 10.0, Some(4), fake_width),
+            vec![0.0, 10.0, 20.0, 30.0, 40.0, 80.0]
+        );
+        assert_eq!(advances("\t", 10.0, None, fake_width), vec![0.0, 10.0]);
+        assert_eq!(advances("", 10.0, Some(4), fake_width), vec![0.0]);
+    }
+
+    #[test]
+    fn boundary_at_picks_the_nearer_edge() {
+        let advances = [0.0, 10.0, 30.0, 40.0];
+        assert_eq!(boundary_at(&advances, -5.0), 0);
+        assert_eq!(boundary_at(&advances, 4.0), 0);
+        assert_eq!(boundary_at(&advances, 6.0), 1);
+        assert_eq!(boundary_at(&advances, 21.0), 2);
+        assert_eq!(boundary_at(&advances, 19.0), 1);
+        assert_eq!(boundary_at(&advances, 100.0), 3);
+    }
+}
@@ -1,2 +1,3 @@
 pub mod editor_canvas;
+pub mod glyph_metrics;
 pub mod menu;
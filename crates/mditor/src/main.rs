@@ -6,7 +6,7 @@ use app::App;
 use iced::Font;
 
 pub fn main() -> iced::Result {
-    iced::application("Mditor", App::update, App::view)
+    iced::application(App::title, App::update, App::view)
         .theme(App::theme)
         .default_font(Font::MONOSPACE)
         .subscription(App::subscription)
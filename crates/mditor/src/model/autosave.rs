@@ -0,0 +1,79 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// When unsaved changes are copied to the recovery file next to the document: every
+/// `interval`, or as soon as `edit_threshold` edits piled up since the last copy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Autosave {
+    pub enabled: bool,
+    pub interval: Duration,
+    pub edit_threshold: usize,
+}
+
+impl Default for Autosave {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: Duration::from_secs(30),
+            edit_threshold: 300,
+        }
+    }
+}
+
+impl Autosave {
+    /// Whether to write the recovery file now, with `edits` made since it was last written.
+    /// A tick of the timer writes any edits; between ticks only a full threshold does.
+    pub fn due(&self, edits: usize, tick: bool) -> bool {
+        self.enabled && edits > 0 && (tick || edits >= self.edit_threshold)
+    }
+}
+
+/// The recovery file of `path`: `.<name>.swp` in the same directory.
+pub fn recovery_path(path: &Path) -> Option<PathBuf> {
+    let mut name = OsString::from(".");
+    name.push(path.file_name()?);
+    name.push(".swp");
+    Some(path.with_file_name(name))
+}
+
+/// The recovery file of `path`, if there is one written after `path` was last modified.
+pub fn newer_recovery(path: &Path) -> Option<PathBuf> {
+    let recovery = recovery_path(path)?;
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    (modified(&recovery)? > modified(path)?).then_some(recovery)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_on_ticks_or_after_enough_edits() {
+        let autosave = Autosave {
+            edit_threshold: 3,
+            ..Autosave::default()
+        };
+        assert!(!autosave.due(0, true));
+        assert!(autosave.due(1, true));
+        assert!(!autosave.due(2, false));
+        assert!(autosave.due(3, false));
+        assert!(autosave.due(10, false));
+
+        let off = Autosave {
+            enabled: false,
+            ..autosave
+        };
+        assert!(!off.due(10, true));
+    }
+
+    #[test]
+    fn recovery_file_sits_next_to_the_document() {
+        assert_eq!(
+            recovery_path(Path::new("/notes/todo.md")),
+            Some(PathBuf::from("/notes/.todo.md.swp"))
+        );
+        assert_eq!(recovery_path(Path::new("/")), None);
+    }
+}
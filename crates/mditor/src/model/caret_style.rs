@@ -0,0 +1,22 @@
+/// Shape of the caret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaretStyle {
+    /// Thin vertical bar before the grapheme.
+    #[default]
+    Bar,
+    /// Translucent box over the whole grapheme.
+    Block,
+    /// Thin line under the grapheme.
+    Underline,
+}
+
+impl CaretStyle {
+    /// The next style, wrapping around.
+    pub fn cycled(self) -> Self {
+        match self {
+            CaretStyle::Bar => CaretStyle::Block,
+            CaretStyle::Block => CaretStyle::Underline,
+            CaretStyle::Underline => CaretStyle::Bar,
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use iced::Color;
+
+/// Colors for the editor chrome (bars, editor and gutter backgrounds, gutter change
+/// markers, trailing whitespace, selection, bracket outlines), kept apart from the syntax
+/// highlighting theme.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorScheme {
+    pub editor_bg: Color,
+    pub top_bar_bg: Color,
+    pub bottom_bar_bg: Color,
+    pub gutter_bg: Color,
+    pub line_added: Color,
+    pub line_modified: Color,
+    pub line_deleted: Color,
+    pub trailing_whitespace: Color,
+    pub selection: Color,
+    pub bracket_match: Color,
+    /// Outline of a bracket next to the caret that has no partner.
+    pub bracket_unmatched: Color,
+}
+
+impl Default for ColorScheme {
+    fn default() -> Self {
+        Self {
+            editor_bg: Color::from_rgba8(39, 40, 34, 1.0),
+            top_bar_bg: Color::from_rgba8(22, 23, 19, 1.0),
+            bottom_bar_bg: Color::from_rgba8(32, 33, 28, 1.0),
+            gutter_bg: Color::from_rgba8(39, 40, 34, 1.0),
+            line_added: Color::from_rgba8(88, 160, 72, 1.0),
+            line_modified: Color::from_rgba8(66, 135, 200, 1.0),
+            line_deleted: Color::from_rgba8(200, 70, 70, 1.0),
+            trailing_whitespace: Color::from_rgba8(220, 50, 47, 0.45),
+            selection: Color::from_rgba8(100, 150, 255, 0.25),
+            bracket_match: Color::from_rgba8(200, 200, 200, 0.6),
+            bracket_unmatched: Color::from_rgba8(230, 160, 40, 0.9),
+        }
+    }
+}
@@ -1,31 +1,147 @@
 use super::error::Error;
+use iced::widget::scrollable::Viewport;
 use std::path::PathBuf;
+use std::time::Instant;
+use text_buffer::{Eol, FileEncoding};
 
 #[derive(Debug, Clone)]
 pub enum EditorMessage {
     NewFile,
     OpenFile,
-    FileOpened(Result<(PathBuf, Vec<String>), Error>),
+    FileDropped(PathBuf),
+    OpenRecent(PathBuf),
+    RecentLoaded(Vec<PathBuf>),
+    FileOpened(Result<(PathBuf, Vec<String>, FileEncoding), Error>),
     SaveFile,
     SaveAs,
     FileSaved(Result<Option<PathBuf>, Error>),
-    ActivateEditor,
-    DeactivateEditor,
-    SetCursor { line: usize, column: usize },
     Insert(String),
     Backspace,
     Enter,
+    Tab,
+    Dedent,
+    ToggleTabStyle,
+    ToggleAutoIndent,
+    SetLineEnding(Eol),
     MoveLeft,
     MoveRight,
     MoveUp,
     MoveDown,
+    MoveHome,
+    MoveEnd,
+    MoveDocumentStart,
+    MoveDocumentEnd,
+    PageUp,
+    PageDown,
+    MoveWordLeft,
+    MoveWordRight,
+    ExtendWordLeft,
+    ExtendWordRight,
     BeginSelection { line: usize, column: usize },
     ExtendSelectionTo { line: usize, column: usize },
     EndSelection,
+    SelectWord { line: usize, column: usize },
+    SelectLine { line: usize },
     SelectAll,
     DeleteForward,
+    DeleteToLineEnd,
+    DeleteToLineStart,
     ExtendLeft,
     ExtendRight,
     ExtendUp,
     ExtendDown,
+    ToggleWrap,
+    ToggleGutterAlign,
+    CycleCaretStyle,
+    ToggleTrailingWhitespace,
+    ToggleWhitespace,
+    ToggleBracketHighlight,
+    ToggleStats,
+    ToggleReadOnly,
+    TogglePreview,
+    PreviewDue(u64),
+    LineChangesDue(u64),
+    ToggleWord { step: i64 },
+    ToggleBold,
+    ToggleItalic,
+    ToggleCode,
+    ToggleBulletList,
+    ToggleNumberedList,
+    SetHeadingLevel(usize),
+    DuplicateLines,
+    MoveLinesUp,
+    MoveLinesDown,
+    JoinLines,
+    Undo,
+    Redo,
+    Copy,
+    Cut,
+    Paste,
+    Pasted(String),
+    ToggleFind,
+    FindQueryChanged(String),
+    ReplacementChanged(String),
+    FindNext,
+    FindPrev,
+    Replace,
+    ReplaceAll,
+    GotoLine,
+    GotoLineChanged(String),
+    GotoLineSubmitted,
+    UnsavedChangesAnswered(UnsavedChoice),
+    AutosaveTick,
+    CaretBlink(Instant),
+    ToggleAutosave,
+    Recovered { path: PathBuf, text: String },
+    Scrolled(Viewport),
+    SetFontSize(f32),
+    SetLineSpacing(f32),
+    ZoomIn,
+    ZoomOut,
+    ZoomReset,
+}
+
+impl EditorMessage {
+    /// Whether handling the message changes the document, which read-only mode refuses.
+    /// Cut counts: in read-only mode it neither copies nor removes the selection.
+    pub fn edits_text(&self) -> bool {
+        matches!(
+            self,
+            Self::Insert(_)
+                | Self::Backspace
+                | Self::Enter
+                | Self::Tab
+                | Self::Dedent
+                | Self::SetLineEnding(_)
+                | Self::DeleteForward
+                | Self::DeleteToLineEnd
+                | Self::DeleteToLineStart
+                | Self::ToggleWord { .. }
+                | Self::ToggleBold
+                | Self::ToggleItalic
+                | Self::ToggleCode
+                | Self::ToggleBulletList
+                | Self::ToggleNumberedList
+                | Self::SetHeadingLevel(_)
+                | Self::DuplicateLines
+                | Self::MoveLinesUp
+                | Self::MoveLinesDown
+                | Self::JoinLines
+                | Self::Undo
+                | Self::Redo
+                | Self::Cut
+                | Self::Paste
+                | Self::Pasted(_)
+                | Self::Replace
+                | Self::ReplaceAll
+        )
+    }
+}
+
+/// Answer to the prompt shown before unsaved changes would be discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsavedChoice {
+    Save,
+    Discard,
+    Cancel,
 }
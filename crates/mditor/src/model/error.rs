@@ -1,3 +1,4 @@
+use std::fmt;
 use std::io;
 
 #[derive(Debug, Clone)]
@@ -5,3 +6,12 @@ pub enum Error {
     DialogClosed,
     IoError(io::ErrorKind),
 }
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DialogClosed => write!(f, "dialog closed"),
+            Error::IoError(kind) => write!(f, "{kind}"),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+/// State of the find and replace bar.
+#[derive(Debug, Clone, Default)]
+pub struct Find {
+    pub query: String,
+    pub replacement: String,
+    /// Byte offsets of the matches of `query`, in order.
+    pub matches: Vec<usize>,
+    /// Index into `matches` of the match shown as selected.
+    pub current: Option<usize>,
+}
+
+impl Find {
+    /// Replace the matches after the query or the document changed. The current match is
+    /// kept only if it still exists.
+    pub fn set_matches(&mut self, matches: Vec<usize>) {
+        let current = self.current.and_then(|i| self.matches.get(i).copied());
+        self.current = current.and_then(|offset| matches.binary_search(&offset).ok());
+        self.matches = matches;
+    }
+
+    /// Index of the first match starting at or after `offset`, wrapping around to the first.
+    pub fn next_from(&self, offset: usize) -> Option<usize> {
+        if self.matches.is_empty() {
+            return None;
+        }
+        let i = self.matches.partition_point(|&m| m < offset);
+        Some(if i == self.matches.len() { 0 } else { i })
+    }
+
+    /// Index of the last match starting before `offset`, wrapping around to the last.
+    pub fn prev_from(&self, offset: usize) -> Option<usize> {
+        if self.matches.is_empty() {
+            return None;
+        }
+        let i = self.matches.partition_point(|&m| m < offset);
+        Some(i.checked_sub(1).unwrap_or(self.matches.len() - 1))
+    }
+
+    /// Status shown next to the query: "2 of 5", "5 matches" before moving to one, or
+    /// "No results".
+    pub fn label(&self) -> String {
+        match (self.current, self.matches.len()) {
+            (_, 0) if self.query.is_empty() => String::new(),
+            (_, 0) => String::from("No results"),
+            (Some(i), n) => format!("{} of {n}", i + 1),
+            (None, 1) => String::from("1 match"),
+            (None, n) => format!("{n} matches"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(matches: &[usize]) -> Find {
+        Find {
+            query: "x".to_string(),
+            matches: matches.to_vec(),
+            ..Find::default()
+        }
+    }
+
+    #[test]
+    fn next_and_prev_wrap_around() {
+        let find = find(&[3, 10, 20]);
+        assert_eq!(find.next_from(0), Some(0));
+        assert_eq!(find.next_from(10), Some(1));
+        assert_eq!(find.next_from(11), Some(2));
+        assert_eq!(find.next_from(21), Some(0));
+
+        assert_eq!(find.prev_from(10), Some(0));
+        assert_eq!(find.prev_from(25), Some(2));
+        assert_eq!(find.prev_from(3), Some(2));
+
+        assert_eq!(Find::default().next_from(0), None);
+        assert_eq!(Find::default().prev_from(0), None);
+    }
+
+    #[test]
+    fn current_match_survives_only_if_still_found() {
+        let mut find = find(&[3, 10, 20]);
+        find.current = Some(1);
+        find.set_matches(vec![0, 10]);
+        assert_eq!(find.current, Some(1));
+        assert_eq!(find.label(), "2 of 2");
+
+        find.set_matches(vec![0, 11]);
+        assert_eq!(find.current, None);
+        assert_eq!(find.label(), "2 matches");
+
+        find.set_matches(Vec::new());
+        assert_eq!(find.label(), "No results");
+        find.query.clear();
+        assert_eq!(find.label(), "");
+    }
+}
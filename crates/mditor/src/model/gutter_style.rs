@@ -0,0 +1,88 @@
+/// Which side of the gutter line numbers hug.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+    Left,
+    #[default]
+    Right,
+}
+
+/// Line number placement in the gutter: alignment and the padding on either side of the
+/// number column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GutterStyle {
+    pub align: Align,
+    pub pad_left: f32,
+    pub pad_right: f32,
+}
+
+impl Default for GutterStyle {
+    fn default() -> Self {
+        Self {
+            align: Align::Right,
+            pad_left: 24.0,
+            pad_right: 36.0,
+        }
+    }
+}
+
+impl Align {
+    pub fn flipped(self) -> Self {
+        match self {
+            Align::Left => Align::Right,
+            Align::Right => Align::Left,
+        }
+    }
+}
+
+impl GutterStyle {
+    /// Gutter width fitting numbers of up to `digit_count` digits.
+    pub fn width(&self, digit_count: usize, char_width: f32) -> f32 {
+        self.pad_left + digit_count as f32 * char_width + self.pad_right
+    }
+
+    /// X of a number `number_len` digits long, in a gutter sized for `digit_count` digits.
+    pub fn number_x(&self, number_len: usize, digit_count: usize, char_width: f32) -> f32 {
+        match self.align {
+            Align::Left => self.pad_left,
+            Align::Right => {
+                self.width(digit_count, char_width)
+                    - self.pad_right
+                    - number_len as f32 * char_width
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width_grows_with_digit_count() {
+        let style = GutterStyle::default();
+        assert_eq!(style.width(1, 8.0), 68.0);
+        assert_eq!(style.width(3, 8.0), 84.0);
+
+        let tight = GutterStyle {
+            pad_left: 4.0,
+            pad_right: 6.0,
+            ..style
+        };
+        assert_eq!(tight.width(2, 8.0), 26.0);
+    }
+
+    #[test]
+    fn number_x_per_alignment() {
+        let right = GutterStyle::default();
+        // Numbers end at the same x regardless of their length
+        assert_eq!(right.number_x(3, 3, 8.0), 24.0);
+        assert_eq!(right.number_x(1, 3, 8.0), 40.0);
+
+        let left = GutterStyle {
+            align: Align::Left,
+            ..right
+        };
+        assert_eq!(left.number_x(3, 3, 8.0), 24.0);
+        assert_eq!(left.number_x(1, 3, 8.0), 24.0);
+    }
+}
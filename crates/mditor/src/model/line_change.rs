@@ -0,0 +1,106 @@
+use text_buffer::DiffOp;
+
+/// How a line of the current text differs from the saved text, for the gutter strip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange {
+    Added,
+    Modified,
+    /// Saved lines were removed next to this line.
+    Deleted,
+}
+
+/// Change marker for each of the `line_count` current lines, from a diff of the saved lines
+/// against them. In a change replacing lines, the first replaced lines are modified and any
+/// extra ones added; a pure deletion marks the line after it, or the last line at the end.
+pub fn line_changes(ops: &[DiffOp], line_count: usize) -> Vec<Option<LineChange>> {
+    let mut marks = vec![None; line_count];
+    let mut line = 0;
+    let mut i = 0;
+    while i < ops.len() {
+        if let DiffOp::Equal(n) = ops[i] {
+            line += n;
+            i += 1;
+            continue;
+        }
+
+        // A change: the runs up to the next equal stretch
+        let (mut deleted, mut inserted) = (0, 0);
+        while let Some(op) = ops.get(i) {
+            match *op {
+                DiffOp::Delete(n) => deleted += n,
+                DiffOp::Insert(n) => inserted += n,
+                DiffOp::Equal(_) => break,
+            }
+            i += 1;
+        }
+
+        for offset in 0..inserted {
+            let change = if offset < deleted {
+                LineChange::Modified
+            } else {
+                LineChange::Added
+            };
+            if let Some(mark) = marks.get_mut(line + offset) {
+                *mark = Some(change);
+            }
+        }
+        if inserted == 0 && deleted > 0 && line_count > 0 {
+            let mark = &mut marks[line.min(line_count - 1)];
+            mark.get_or_insert(LineChange::Deleted);
+        }
+        line += inserted;
+    }
+    marks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DiffOp::*;
+    use LineChange::*;
+
+    #[test]
+    fn marks_added_and_modified_lines() {
+        // Line 1 replaced, two lines appended after line 3
+        let ops = [Equal(1), Delete(1), Insert(1), Equal(2), Insert(2)];
+        assert_eq!(
+            line_changes(&ops, 6),
+            vec![None, Some(Modified), None, None, Some(Added), Some(Added)]
+        );
+
+        // Three lines replaced by five
+        let ops = [Delete(3), Insert(5), Equal(1)];
+        assert_eq!(
+            line_changes(&ops, 6),
+            vec![
+                Some(Modified),
+                Some(Modified),
+                Some(Modified),
+                Some(Added),
+                Some(Added),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn deletions_mark_the_adjacent_line() {
+        let ops = [Equal(2), Delete(3), Equal(2)];
+        assert_eq!(line_changes(&ops, 4), vec![None, None, Some(Deleted), None]);
+
+        // At the end there is no following line, so the last line carries the marker
+        let ops = [Equal(2), Delete(1)];
+        assert_eq!(line_changes(&ops, 2), vec![None, Some(Deleted)]);
+
+        // Two lines deleted at the start, one modified right after
+        let ops = [Delete(2), Equal(1), Delete(1), Insert(1)];
+        assert_eq!(line_changes(&ops, 2), vec![Some(Deleted), Some(Modified)]);
+
+        // More lines removed than replaced still only marks the replacement lines
+        let ops = [Equal(1), Delete(4), Insert(2)];
+        assert_eq!(
+            line_changes(&ops, 3),
+            vec![None, Some(Modified), Some(Modified)]
+        );
+    }
+}
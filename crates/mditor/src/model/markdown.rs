@@ -0,0 +1,399 @@
+use super::color_scheme::ColorScheme;
+use super::editor_message::EditorMessage;
+use iced::widget::{column, container, horizontal_rule, rich_text, row, span, text};
+use iced::{Color, Element, Font, Length, Padding, font};
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+// Text size of paragraphs; headings scale up from it
+const BASE_SIZE: f32 = 14.0;
+// Width of a list marker, and the indent of each level of nesting
+const INDENT: f32 = 20.0;
+const LINK_COLOR: Color = Color::from_rgb(0.35, 0.6, 0.95);
+const QUOTE_COLOR: Color = Color::from_rgb(0.6, 0.6, 0.55);
+
+/// A run of text with a single style.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub strong: bool,
+    pub emphasis: bool,
+    pub strikethrough: bool,
+    pub code: bool,
+    /// Destination of the link the text belongs to.
+    pub link: Option<String>,
+}
+
+/// One block of the rendered preview. Nested lists are flattened into items with a depth.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Block {
+    /// Level 1 to 6.
+    Heading(u8, Vec<Span>),
+    Paragraph(Vec<Span>),
+    /// A paragraph inside a block quote.
+    Quote(Vec<Span>),
+    /// A paragraph of a list item, `depth` 1 for a top level list. `marker` is "•", "3." or a
+    /// task box for the item's first paragraph and empty for the ones after it.
+    ListItem {
+        depth: usize,
+        marker: String,
+        spans: Vec<Span>,
+    },
+    Code {
+        language: String,
+        text: String,
+    },
+    Rule,
+}
+
+/// The blocks of a Markdown document.
+pub fn parse(markdown: &str) -> Vec<Block> {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut builder = Builder::default();
+    for event in Parser::new_ext(markdown, options) {
+        builder.event(event);
+    }
+    builder.finish_block();
+    builder.blocks
+}
+
+/// The preview of `blocks`, as a column to put in a scrollable.
+pub fn view<'a>(blocks: &'a [Block], colors: &ColorScheme) -> Element<'a, EditorMessage> {
+    let code_bg = colors.top_bar_bg;
+    column(blocks.iter().map(|block| {
+        match block {
+            Block::Heading(level, spans) => {
+                let size = BASE_SIZE * [2.0, 1.6, 1.35, 1.15, 1.0, 0.9][usize::from(*level - 1)];
+                let bold = Font {
+                    weight: font::Weight::Bold,
+                    ..Font::DEFAULT
+                };
+                rich_text(styled(spans, code_bg, bold)).size(size).into()
+            }
+            Block::Paragraph(spans) => paragraph(spans, code_bg),
+            Block::Quote(spans) => container(
+                rich_text(styled(spans, code_bg, Font::DEFAULT))
+                    .size(BASE_SIZE)
+                    .color(QUOTE_COLOR),
+            )
+            .padding(Padding::ZERO.left(INDENT))
+            .into(),
+            Block::ListItem {
+                depth,
+                marker,
+                spans,
+            } => row![
+                text(marker.as_str())
+                    .font(Font::DEFAULT)
+                    .size(BASE_SIZE)
+                    .width(Length::Fixed(INDENT)),
+                paragraph(spans, code_bg),
+            ]
+            .padding(Padding::ZERO.left(INDENT * (depth - 1) as f32))
+            .into(),
+            Block::Code { text: code, .. } => container(text(code.as_str()).size(BASE_SIZE - 1.0))
+                .padding(8)
+                .width(Length::Fill)
+                .style(move |_| container::Style {
+                    background: Some(code_bg.into()),
+                    ..container::Style::default()
+                })
+                .into(),
+            Block::Rule => horizontal_rule(1).into(),
+        }
+    }))
+    .spacing(10)
+    .padding(16)
+    .width(Length::Fill)
+    .into()
+}
+
+fn paragraph<'a>(spans: &'a [Span], code_bg: Color) -> Element<'a, EditorMessage> {
+    rich_text(styled(spans, code_bg, Font::DEFAULT))
+        .size(BASE_SIZE)
+        .into()
+}
+
+// Spans to draw, in `base` unless bold, italic or code
+fn styled(
+    spans: &[Span],
+    code_bg: Color,
+    base: Font,
+) -> Vec<iced::widget::text::Span<'_, EditorMessage>> {
+    spans
+        .iter()
+        .map(|s| {
+            let mut font = if s.code { Font::MONOSPACE } else { base };
+            if s.strong {
+                font.weight = font::Weight::Bold;
+            }
+            if s.emphasis {
+                font.style = font::Style::Italic;
+            }
+            span(s.text.as_str())
+                .font(font)
+                .strikethrough(s.strikethrough)
+                .underline(s.link.is_some())
+                .color_maybe(s.link.as_ref().map(|_| LINK_COLOR))
+                .background_maybe(s.code.then_some(code_bg))
+        })
+        .collect()
+}
+
+// Blocks collected from the parser's events, with the inline style and nesting at the
+// current event
+#[derive(Debug, Default)]
+struct Builder {
+    blocks: Vec<Block>,
+    spans: Vec<Span>,
+    style: Span, // text empty; the style of text at this point
+    strong: usize,
+    emphasis: usize,
+    strikethrough: usize,
+    heading: Option<u8>,
+    quotes: usize,
+    lists: Vec<Option<u64>>, // the next number of each open ordered list
+    marker: Option<String>,  // of the list item whose first paragraph is still to come
+    code: Option<(String, String)>, // language and text of the open code block
+}
+
+impl Builder {
+    fn event(&mut self, event: Event<'_>) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => match &mut self.code {
+                Some((_, code)) => code.push_str(&text),
+                None => self.push_text(&text, false),
+            },
+            Event::Code(code) => self.push_text(&code, true),
+            Event::Html(html) | Event::InlineHtml(html) => self.push_text(&html, false),
+            Event::InlineMath(math) | Event::DisplayMath(math) => self.push_text(&math, true),
+            Event::FootnoteReference(label) => self.push_text(&format!("[{label}]"), false),
+            Event::SoftBreak => self.push_text(" ", false),
+            Event::HardBreak => self.push_text("\n", false),
+            Event::Rule => {
+                self.finish_block();
+                self.blocks.push(Block::Rule);
+            }
+            Event::TaskListMarker(done) => {
+                self.marker = Some(String::from(if done { "☑" } else { "☐" }));
+            }
+        }
+    }
+
+    fn start(&mut self, tag: Tag<'_>) {
+        match tag {
+            Tag::Heading { level, .. } => {
+                self.finish_block();
+                self.heading = Some(level as u8);
+            }
+            Tag::BlockQuote(_) => {
+                self.finish_block();
+                self.quotes += 1;
+            }
+            Tag::CodeBlock(kind) => {
+                self.finish_block();
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => {
+                        info.split_whitespace().next().unwrap_or("").to_string()
+                    }
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.code = Some((language, String::new()));
+            }
+            Tag::List(start) => {
+                // The text of the item the list is nested in comes first
+                self.finish_block();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.finish_block();
+                self.marker = Some(match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}.", *number - 1)
+                    }
+                    _ => String::from("•"),
+                });
+            }
+            Tag::Emphasis => self.emphasis += 1,
+            Tag::Strong => self.strong += 1,
+            Tag::Strikethrough => self.strikethrough += 1,
+            Tag::Link { dest_url, .. } => self.style.link = Some(dest_url.to_string()),
+            _ => {}
+        }
+        self.restyle();
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::HtmlBlock => self.finish_block(),
+            TagEnd::BlockQuote(_) => {
+                self.finish_block();
+                self.quotes -= 1;
+            }
+            TagEnd::CodeBlock => {
+                if let Some((language, mut text)) = self.code.take() {
+                    if text.ends_with('\n') {
+                        text.pop();
+                    }
+                    self.blocks.push(Block::Code { language, text });
+                }
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+            }
+            // A tight item's text is not wrapped in a paragraph
+            TagEnd::Item => self.finish_block(),
+            TagEnd::Emphasis => self.emphasis -= 1,
+            TagEnd::Strong => self.strong -= 1,
+            TagEnd::Strikethrough => self.strikethrough -= 1,
+            TagEnd::Link => self.style.link = None,
+            _ => {}
+        }
+        self.restyle();
+    }
+
+    fn restyle(&mut self) {
+        self.style.strong = self.strong > 0;
+        self.style.emphasis = self.emphasis > 0;
+        self.style.strikethrough = self.strikethrough > 0;
+    }
+
+    // Add text in the current style, joining it to the previous span when that has the same
+    fn push_text(&mut self, text: &str, code: bool) {
+        let style = Span {
+            code,
+            ..self.style.clone()
+        };
+        match self.spans.last_mut() {
+            Some(last)
+                if Span {
+                    text: String::new(),
+                    ..last.clone()
+                } == style =>
+            {
+                last.text.push_str(text);
+            }
+            _ => self.spans.push(Span {
+                text: text.to_string(),
+                ..style
+            }),
+        }
+    }
+
+    // Turn the spans collected so far into a block of the kind the current nesting makes
+    fn finish_block(&mut self) {
+        if self.spans.is_empty() && self.marker.is_none() {
+            self.heading = None;
+            return;
+        }
+        let spans = std::mem::take(&mut self.spans);
+        let block = if let Some(level) = self.heading.take() {
+            Block::Heading(level, spans)
+        } else if !self.lists.is_empty() {
+            Block::ListItem {
+                depth: self.lists.len(),
+                marker: self.marker.take().unwrap_or_default(),
+                spans,
+            }
+        } else if self.quotes > 0 {
+            Block::Quote(spans)
+        } else {
+            Block::Paragraph(spans)
+        };
+        self.blocks.push(block);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(text: &str) -> Span {
+        Span {
+            text: text.to_string(),
+            ..Span::default()
+        }
+    }
+
+    #[test]
+    fn headings_paragraphs_and_code() {
+        let blocks = parse("# Title\n\nSome text\nwrapped.\n\n```rust\nfn main() {}\n```\n\n---\n");
+        assert_eq!(
+            blocks,
+            [
+                Block::Heading(1, vec![plain("Title")]),
+                Block::Paragraph(vec![plain("Some text wrapped.")]),
+                Block::Code {
+                    language: String::from("rust"),
+                    text: String::from("fn main() {}"),
+                },
+                Block::Rule,
+            ]
+        );
+        assert_eq!(parse(""), []);
+    }
+
+    #[test]
+    fn inline_styles_become_spans() {
+        let blocks = parse("a **b *c*** `d` [e](https://example.com) ~~f~~");
+        let Block::Paragraph(spans) = &blocks[0] else {
+            panic!("{blocks:?}");
+        };
+        let styles: Vec<_> = spans
+            .iter()
+            .map(|s| (s.text.as_str(), s.strong, s.emphasis, s.code))
+            .collect();
+        assert_eq!(
+            styles,
+            [
+                ("a ", false, false, false),
+                ("b ", true, false, false),
+                ("c", true, true, false),
+                (" ", false, false, false),
+                ("d", false, false, true),
+                (" ", false, false, false),
+                ("e", false, false, false),
+                (" ", false, false, false),
+                ("f", false, false, false),
+            ]
+        );
+        assert_eq!(spans[6].link.as_deref(), Some("https://example.com"));
+        assert!(spans[8].strikethrough);
+    }
+
+    #[test]
+    fn nested_lists_flatten_with_depth_and_numbers() {
+        let blocks = parse("- one\n  1. two\n  2. three\n- [x] done\n\n> quoted\n");
+        let items: Vec<_> = blocks
+            .iter()
+            .map(|block| match block {
+                Block::ListItem {
+                    depth,
+                    marker,
+                    spans,
+                } => (*depth, marker.as_str(), spans[0].text.as_str()),
+                Block::Quote(spans) => (0, ">", spans[0].text.as_str()),
+                other => panic!("{other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            items,
+            [
+                (1, "•", "one"),
+                (2, "1.", "two"),
+                (2, "2.", "three"),
+                (1, "☑", "done"),
+                (0, ">", "quoted"),
+            ]
+        );
+
+        // Later paragraphs of a loose item go without a marker
+        let blocks = parse("3. first\n\n   second\n");
+        assert!(matches!(&blocks[..], [
+            Block::ListItem { marker: a, .. },
+            Block::ListItem { marker: b, .. },
+        ] if a == "3." && b.is_empty()));
+    }
+}
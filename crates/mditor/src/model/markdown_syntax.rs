@@ -0,0 +1,497 @@
+use iced::Color;
+use std::ops::Range;
+
+/// What a piece of Markdown source is, for coloring it in the editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    /// A whole ATX heading line, `#` marks included.
+    Heading,
+    Emphasis,
+    Strong,
+    /// An inline code span with its backticks, or a line inside a fenced code block.
+    Code,
+    /// The opening or closing line of a fenced code block.
+    Fence,
+    ListMarker,
+    /// The bracketed text of a link or image.
+    LinkText,
+    /// The parenthesized destination of a link or image, or an `<autolink>`.
+    LinkUrl,
+}
+
+impl Token {
+    /// Color the editor draws the token in.
+    pub fn color(self) -> Color {
+        match self {
+            Token::Heading => Color::from_rgb8(249, 38, 114),
+            Token::Emphasis => Color::from_rgb8(230, 219, 116),
+            Token::Strong => Color::from_rgb8(253, 151, 31),
+            Token::Code => Color::from_rgb8(166, 226, 46),
+            Token::Fence => Color::from_rgb8(117, 113, 94),
+            Token::ListMarker => Color::from_rgb8(174, 129, 255),
+            Token::LinkText => Color::from_rgb8(102, 217, 239),
+            Token::LinkUrl => Color::from_rgb8(120, 150, 180),
+        }
+    }
+}
+
+/// Whether the highlighter token (a file extension or language name) means Markdown.
+pub fn is_markdown(token: &str) -> bool {
+    matches!(
+        token.to_ascii_lowercase().as_str(),
+        "md" | "markdown" | "mdown" | "mkd"
+    )
+}
+
+// An open fenced code block: its fence character and length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fence {
+    marker: u8,
+    len: usize,
+}
+
+/// Splits Markdown into tokens a line at a time. The only thing carried from one line to
+/// the next is whether a fenced code block is open, which is remembered for every line so
+/// tokenizing can resume from any line after an edit.
+#[derive(Debug, Default)]
+pub struct MarkdownTokenizer {
+    // The open fence at the start of each line tokenized so far
+    starts: Vec<Option<Fence>>,
+    fence: Option<Fence>,
+}
+
+impl MarkdownTokenizer {
+    /// Continue from the 0-based `line`, forgetting it and every line after it. Lines not
+    /// tokenized yet are left as they are.
+    pub fn change_line(&mut self, line: usize) {
+        if line < self.starts.len() {
+            self.fence = self.starts[line];
+            self.starts.truncate(line);
+        }
+    }
+
+    /// The 0-based line the next call to [`Self::tokenize_line`] is for.
+    pub fn current_line(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Tokens of the next line, as sorted, non-overlapping byte ranges. Text without a
+    /// token has no range; where tokens nest, the innermost one wins.
+    pub fn tokenize_line(&mut self, line: &str) -> Vec<(Range<usize>, Token)> {
+        self.starts.push(self.fence);
+        let whole = || vec![(0..line.len(), Token::Fence)];
+
+        if let Some(fence) = self.fence {
+            if closes(line, fence) {
+                self.fence = None;
+                return whole();
+            }
+            return if line.is_empty() {
+                Vec::new()
+            } else {
+                vec![(0..line.len(), Token::Code)]
+            };
+        }
+        if let Some(fence) = opens(line) {
+            self.fence = Some(fence);
+            return whole();
+        }
+
+        let mut tokens = Vec::new();
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        let rest = &line[indent..];
+        let mut text_start = 0;
+        if indent < 4 && is_heading(rest) {
+            tokens.push((0..line.len(), Token::Heading));
+        } else if let Some(len) = list_marker(rest) {
+            tokens.push((indent..indent + len, Token::ListMarker));
+            text_start = indent + len;
+        }
+        inline_tokens(line, text_start, &mut tokens);
+        flatten(line.len(), tokens)
+    }
+}
+
+// Up to three spaces, then three or more backticks or tildes; a backtick fence's info
+// string cannot contain a backtick
+fn opens(line: &str) -> Option<Fence> {
+    let rest = line.trim_start_matches(' ');
+    if line.len() - rest.len() > 3 {
+        return None;
+    }
+    let marker = *rest.as_bytes().first()?;
+    if marker != b'`' && marker != b'~' {
+        return None;
+    }
+    let len = rest.len() - rest.trim_start_matches(marker as char).len();
+    let info = &rest[len..];
+    (len >= 3 && !(marker == b'`' && info.contains('`'))).then_some(Fence { marker, len })
+}
+
+// At least as long a run of the fence's character, with nothing after it but spaces
+fn closes(line: &str, fence: Fence) -> bool {
+    let rest = line.trim_start_matches(' ');
+    if line.len() - rest.len() > 3 {
+        return false;
+    }
+    let after = rest.trim_start_matches(fence.marker as char);
+    rest.len() - after.len() >= fence.len && after.trim().is_empty()
+}
+
+// One to six `#`, then a space or the end of the line
+fn is_heading(rest: &str) -> bool {
+    let level = rest.len() - rest.trim_start_matches('#').len();
+    (1..=6).contains(&level) && matches!(rest.as_bytes().get(level), None | Some(b' ' | b'\t'))
+}
+
+// Byte length of a bullet ("- ") or ordered ("12. ", "3) ") list marker starting `rest`,
+// the space after it included
+fn list_marker(rest: &str) -> Option<usize> {
+    let bytes = rest.as_bytes();
+    let digits = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+    let len = match bytes.get(digits) {
+        Some(b'-' | b'*' | b'+') if digits == 0 => 1,
+        Some(b'.' | b')') if (1..=9).contains(&digits) => digits + 1,
+        _ => return None,
+    };
+    match bytes.get(len) {
+        Some(b' ' | b'\t') => Some(len + 1),
+        None => Some(len),
+        _ => None,
+    }
+}
+
+// A run of `*` or `_` that may still open emphasis
+#[derive(Debug)]
+struct Delimiter {
+    marker: u8,
+    end: usize, // the run is what is left before this
+    len: usize,
+}
+
+// Code spans, links, autolinks and emphasis of `line` from `start` on
+fn inline_tokens(line: &str, start: usize, tokens: &mut Vec<(Range<usize>, Token)>) {
+    let bytes = line.as_bytes();
+    let mut openers: Vec<Delimiter> = Vec::new();
+    let mut skip: Option<Range<usize>> = None; // a link destination, not scanned for emphasis
+    let mut i = start;
+    while i < bytes.len() {
+        if let Some(range) = skip.as_ref().filter(|range| range.start == i) {
+            i = range.end;
+            skip = None;
+            continue;
+        }
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => {
+                let run = run_len(bytes, i);
+                match closing_backticks(bytes, i + run, run) {
+                    Some(end) => {
+                        tokens.push((i..end, Token::Code));
+                        i = end;
+                    }
+                    None => i += run,
+                }
+            }
+            b'<' => match autolink(line, i) {
+                Some(end) => {
+                    tokens.push((i..end, Token::LinkUrl));
+                    i = end;
+                }
+                None => i += 1,
+            },
+            b'[' | b'!' => {
+                let open = if bytes[i] == b'!' { i + 1 } else { i };
+                match link(bytes, open) {
+                    Some((close, url_end)) => {
+                        tokens.push((i..close + 1, Token::LinkText));
+                        tokens.push((close + 1..url_end, Token::LinkUrl));
+                        skip = Some(close + 1..url_end);
+                        i = open + 1;
+                    }
+                    None => i += 1,
+                }
+            }
+            marker @ (b'*' | b'_') => {
+                let len = run_len(bytes, i);
+                emphasis(line, marker, i, len, &mut openers, tokens);
+                i += len;
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+// Match the `*` or `_` run `at..at + len` against the open runs before it, or leave it
+// open. Two marks on each side make strong emphasis, one makes emphasis
+fn emphasis(
+    line: &str,
+    marker: u8,
+    at: usize,
+    mut len: usize,
+    openers: &mut Vec<Delimiter>,
+    tokens: &mut Vec<(Range<usize>, Token)>,
+) {
+    let before = line[..at].chars().next_back();
+    let after = line[at + len..].chars().next();
+    let space = |c: Option<char>| c.is_none_or(char::is_whitespace);
+    let word = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+    let mut can_open = !space(after);
+    let mut can_close = !space(before);
+    if marker == b'_' {
+        // snake_case is not emphasis
+        can_open &= !word(before);
+        can_close &= !word(after);
+    }
+
+    let mut end = at;
+    while can_close && len > 0 {
+        let Some(index) = openers.iter().rposition(|d| d.marker == marker) else {
+            break;
+        };
+        openers.truncate(index + 1);
+        let opener = &mut openers[index];
+        let used = if opener.len >= 2 && len >= 2 { 2 } else { 1 };
+        let token = if used == 2 {
+            Token::Strong
+        } else {
+            Token::Emphasis
+        };
+        tokens.push((opener.end - used..end + used, token));
+        opener.end -= used;
+        opener.len -= used;
+        end += used;
+        len -= used;
+        if opener.len == 0 {
+            openers.pop();
+        }
+    }
+    if can_open && len > 0 {
+        openers.push(Delimiter {
+            marker,
+            end: end + len,
+            len,
+        });
+    }
+}
+
+// Length of the run of the byte at `at`
+fn run_len(bytes: &[u8], at: usize) -> usize {
+    bytes[at..].iter().take_while(|&&b| b == bytes[at]).count()
+}
+
+// End of the first run of exactly `len` backticks at or after `from`
+fn closing_backticks(bytes: &[u8], mut from: usize, len: usize) -> Option<usize> {
+    while from < bytes.len() {
+        if bytes[from] == b'`' {
+            let run = run_len(bytes, from);
+            if run == len {
+                return Some(from + run);
+            }
+            from += run;
+        } else {
+            from += 1;
+        }
+    }
+    None
+}
+
+// For a `[` at `open`, the offset of its `]` and the end of the `(destination)` right after
+fn link(bytes: &[u8], open: usize) -> Option<(usize, usize)> {
+    if bytes.get(open) != Some(&b'[') {
+        return None;
+    }
+    let close = matching(bytes, open, b'[', b']')?;
+    if bytes.get(close + 1) != Some(&b'(') {
+        return None;
+    }
+    Some((close, matching(bytes, close + 1, b'(', b')')? + 1))
+}
+
+// Offset of the bracket closing the one at `open`, skipping escaped ones
+fn matching(bytes: &[u8], open: usize, left: u8, right: u8) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = open;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b if b == left => depth += 1,
+            b if b == right => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+// End of an autolink like `<https://example.com>` starting at `at`
+fn autolink(line: &str, at: usize) -> Option<usize> {
+    let inside = &line[at + 1..];
+    let len = inside.find('>')?;
+    let target = &inside[..len];
+    let scheme = target.split_once(':')?.0;
+    let valid = scheme.len() >= 2
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c))
+        && !target.contains(char::is_whitespace);
+    valid.then_some(at + len + 2)
+}
+
+// Tokens that may nest, as sorted ranges that don't overlap: each token is painted over the
+// longer ones around it, then runs of one token are joined
+fn flatten(len: usize, mut tokens: Vec<(Range<usize>, Token)>) -> Vec<(Range<usize>, Token)> {
+    tokens.sort_by_key(|(range, _)| std::cmp::Reverse(range.len()));
+    let mut painted = vec![None; len];
+    for (range, token) in tokens {
+        painted[range].fill(Some(token));
+    }
+
+    let mut flat: Vec<(Range<usize>, Token)> = Vec::new();
+    for (i, token) in painted.into_iter().enumerate() {
+        let Some(token) = token else { continue };
+        match flat.last_mut() {
+            Some((range, last)) if range.end == i && *last == token => range.end = i + 1,
+            _ => flat.push((i..i + 1, token)),
+        }
+    }
+    flat
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(line: &str) -> Vec<(&str, Token)> {
+        MarkdownTokenizer::default()
+            .tokenize_line(line)
+            .into_iter()
+            .map(|(range, token)| (&line[range], token))
+            .collect()
+    }
+
+    #[test]
+    fn headings_lists_and_links() {
+        assert_eq!(tokens("## Title"), [("## Title", Token::Heading)]);
+        assert_eq!(tokens("#hashtag"), []);
+        assert_eq!(
+            tokens("  - item"),
+            [("- ", Token::ListMarker)],
+            "the text after a marker is plain"
+        );
+        assert_eq!(tokens("12. step"), [("12. ", Token::ListMarker)]);
+        assert_eq!(tokens("-not a list"), []);
+        assert_eq!(
+            tokens("see [the_docs](a_b_c) or <https://x.io>"),
+            [
+                ("[the_docs]", Token::LinkText),
+                ("(a_b_c)", Token::LinkUrl),
+                ("<https://x.io>", Token::LinkUrl),
+            ]
+        );
+        assert_eq!(tokens("[not a link] (no)"), []);
+    }
+
+    #[test]
+    fn code_spans_hide_emphasis_marks() {
+        assert_eq!(
+            tokens("a `*b*` c"),
+            [("`*b*`", Token::Code)],
+            "no emphasis inside code"
+        );
+        assert_eq!(
+            tokens("*x `y*` z*"),
+            [
+                ("*x ", Token::Emphasis),
+                ("`y*`", Token::Code),
+                (" z*", Token::Emphasis),
+            ]
+        );
+        // A longer run needs a run as long to close it
+        assert_eq!(tokens("``a ` b``"), [("``a ` b``", Token::Code)]);
+        assert_eq!(tokens("`open"), []);
+    }
+
+    #[test]
+    fn nested_and_unmatched_emphasis() {
+        assert_eq!(
+            tokens("*a **b** c*"),
+            [
+                ("*a ", Token::Emphasis),
+                ("**b**", Token::Strong),
+                (" c*", Token::Emphasis),
+            ]
+        );
+        assert_eq!(
+            tokens("***both***"),
+            [
+                ("*", Token::Emphasis),
+                ("**both**", Token::Strong),
+                ("*", Token::Emphasis),
+            ]
+        );
+        assert_eq!(tokens("__strong__"), [("__strong__", Token::Strong)]);
+        assert_eq!(tokens("snake_case_name"), []);
+        assert_eq!(tokens("2 * 3 * 4"), []);
+        assert_eq!(tokens("**unclosed *em*"), [("*em*", Token::Emphasis)]);
+        // A bullet is not an emphasis mark
+        assert_eq!(
+            tokens("* *item*"),
+            [("* ", Token::ListMarker), ("*item*", Token::Emphasis)]
+        );
+    }
+
+    #[test]
+    fn fences_carry_across_lines_and_resume_after_edits() {
+        let lines = [
+            "text",
+            "```rust",
+            "let *x* = 1;",
+            "",
+            "````",
+            "```",
+            "*after*",
+        ];
+        let mut tokenizer = MarkdownTokenizer::default();
+        let all: Vec<_> = lines
+            .iter()
+            .map(|line| tokenizer.tokenize_line(line))
+            .collect();
+        let kinds: Vec<_> = all
+            .iter()
+            .map(|tokens| tokens.first().map(|(_, token)| *token))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                None,
+                Some(Token::Fence),
+                Some(Token::Code),
+                None,
+                Some(Token::Fence), // a longer run closes it too
+                Some(Token::Fence), // and this opens another
+                Some(Token::Code),
+            ]
+        );
+
+        // Going back to the line of the first fence gives the same tokens again
+        tokenizer.change_line(2);
+        assert_eq!(tokenizer.current_line(), 2);
+        let again: Vec<_> = lines[2..]
+            .iter()
+            .map(|line| tokenizer.tokenize_line(line))
+            .collect();
+        assert_eq!(again, all[2..]);
+
+        let mut tokenizer = MarkdownTokenizer::default();
+        assert_eq!(tokenizer.tokenize_line("~~~"), [(0..3, Token::Fence)]);
+        assert_eq!(tokenizer.tokenize_line("```"), [(0..3, Token::Code)]);
+        assert_eq!(tokenizer.tokenize_line("~~~~ "), [(0..5, Token::Fence)]);
+        assert!(opens("``` a`b").is_none());
+    }
+}
@@ -1,2 +1,15 @@
+pub mod autosave;
+pub mod caret_style;
+pub mod color_scheme;
 pub mod editor_message;
 pub mod error;
+pub mod find;
+pub mod gutter_style;
+pub mod line_change;
+pub mod markdown;
+pub mod markdown_syntax;
+pub mod recent;
+pub mod scroll;
+pub mod snippet;
+pub mod tab_style;
+pub mod wrap;
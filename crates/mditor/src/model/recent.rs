@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use text_buffer::save_atomic;
+
+/// Most files kept in the recent files list.
+pub const MAX_RECENT: usize = 10;
+
+/// Move `path` to the front of the most recently used `list`, keeping at most `cap` paths.
+pub fn push_recent(list: &mut Vec<PathBuf>, path: PathBuf, cap: usize) {
+    list.retain(|p| *p != path);
+    list.insert(0, path);
+    list.truncate(cap);
+}
+
+/// Where the list is kept between sessions: `mditor/recent.json` in the OS config directory.
+pub fn config_file() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("mditor").join("recent.json"))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Stored {
+    #[serde(default)]
+    recent: Vec<PathBuf>,
+}
+
+/// The list stored in `file`; empty if it is missing or unreadable.
+pub fn load(file: &Path) -> Vec<PathBuf> {
+    fs::read(file)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<Stored>(&bytes).ok())
+        .map(|stored| stored.recent)
+        .unwrap_or_default()
+}
+
+/// Write `list` to `file`, creating its directory if needed.
+pub fn store(file: &Path, list: &[PathBuf]) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let stored = Stored {
+        recent: list.to_vec(),
+    };
+    save_atomic(file, &serde_json::to_vec_pretty(&stored)?)
+}
+
+/// Entry of the recent files drop-down.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecentFile(pub PathBuf);
+
+impl fmt::Display for RecentFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.display())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_moves_to_front_dedupes_and_caps() {
+        let mut list = Vec::new();
+        for name in ["a", "b", "c"] {
+            push_recent(&mut list, PathBuf::from(name), 3);
+        }
+        assert_eq!(list, ["c", "b", "a"].map(PathBuf::from));
+
+        push_recent(&mut list, PathBuf::from("a"), 3);
+        assert_eq!(list, ["a", "c", "b"].map(PathBuf::from));
+
+        push_recent(&mut list, PathBuf::from("d"), 3);
+        assert_eq!(list, ["d", "a", "c"].map(PathBuf::from));
+    }
+
+    #[test]
+    fn stores_and_loads_the_list() {
+        let dir = std::env::temp_dir().join(format!("mditor_recent_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let file = dir.join("config").join("recent.json");
+        assert!(load(&file).is_empty());
+
+        let list = ["/notes/a.md", "/notes/b.md"].map(PathBuf::from);
+        store(&file, &list).unwrap();
+        assert_eq!(load(&file), list);
+
+        fs::write(&file, "not json").unwrap();
+        assert!(load(&file).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
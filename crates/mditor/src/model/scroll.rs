@@ -0,0 +1,30 @@
+/// Scroll offset that brings `start..end` into a view `view` long, moving as little as
+/// possible from `offset` and leaving `margin` clear at the edge it scrolls toward. When the
+/// span does not fit, its start wins.
+pub fn reveal(offset: f32, start: f32, end: f32, view: f32, margin: f32) -> f32 {
+    if start - margin < offset {
+        (start - margin).max(0.0)
+    } else if end + margin > offset + view {
+        (end + margin - view).min(start - margin).max(0.0)
+    } else {
+        offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reveal_scrolls_only_as_far_as_needed() {
+        // Already in view
+        assert_eq!(reveal(100.0, 150.0, 160.0, 200.0, 20.0), 100.0);
+        // Past the far edge, then before the near one
+        assert_eq!(reveal(100.0, 300.0, 310.0, 200.0, 20.0), 130.0);
+        assert_eq!(reveal(100.0, 110.0, 120.0, 200.0, 20.0), 90.0);
+        // Never before the start
+        assert_eq!(reveal(100.0, 10.0, 20.0, 200.0, 20.0), 0.0);
+        // Wider than the view: show its start
+        assert_eq!(reveal(0.0, 300.0, 600.0, 200.0, 20.0), 280.0);
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+/// Marks where the caret lands in an expansion.
+pub const CARET_PLACEHOLDER: &str = "$0";
+
+/// Trigger words that expand into longer text when Tab is pressed right after them.
+#[derive(Debug, Clone, Default)]
+pub struct Snippets {
+    expansions: HashMap<String, String>,
+}
+
+/// What expanding a trigger does to the line: replace the `trigger_len` bytes before the
+/// caret with `text`, then put the caret `caret` bytes into it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    pub trigger_len: usize,
+    pub text: String,
+    pub caret: usize,
+}
+
+impl Snippets {
+    /// A few general-purpose snippets.
+    pub fn builtin() -> Self {
+        Self::default()
+            .with("todo", "// TODO: $0")
+            .with("fn", "fn $0() {\n}")
+            .with("link", "[$0]()")
+            .with("code", "```$0\n```")
+    }
+
+    /// Add or replace the expansion of `trigger`. Without a `$0` the caret ends up after the
+    /// expansion.
+    pub fn with(mut self, trigger: &str, expansion: &str) -> Self {
+        self.expansions
+            .insert(trigger.to_string(), expansion.to_string());
+        self
+    }
+
+    /// Expansion for the word ending `before_caret` (the line up to the caret), if it is a
+    /// trigger.
+    pub fn expand(&self, before_caret: &str) -> Option<Expansion> {
+        let word_start = before_caret
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| c.is_alphanumeric() || c == '_')
+            .last()?
+            .0;
+        let trigger = &before_caret[word_start..];
+        let expansion = self.expansions.get(trigger)?;
+
+        let (text, caret) = match expansion.find(CARET_PLACEHOLDER) {
+            Some(at) => (expansion.replacen(CARET_PLACEHOLDER, "", 1), at),
+            None => (expansion.clone(), expansion.len()),
+        };
+        Some(Expansion {
+            trigger_len: trigger.len(),
+            text,
+            caret,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trigger_before_caret_expands() {
+        let snippets = Snippets::default()
+            .with("todo", "// TODO: $0")
+            .with("pair", "($0)")
+            .with("sig", "-- me");
+
+        assert_eq!(
+            snippets.expand("    todo"),
+            Some(Expansion {
+                trigger_len: 4,
+                text: "// TODO: ".to_string(),
+                caret: 9,
+            })
+        );
+        assert_eq!(snippets.expand("x = pair").map(|e| e.caret), Some(1));
+        // No placeholder: caret after the expansion
+        assert_eq!(snippets.expand("sig").map(|e| e.caret), Some(5));
+    }
+
+    #[test]
+    fn other_words_do_not_expand() {
+        let snippets = Snippets::builtin();
+        assert_eq!(snippets.expand("mytodo"), None);
+        assert_eq!(snippets.expand("todo "), None);
+        assert_eq!(snippets.expand(""), None);
+    }
+}
@@ -0,0 +1,27 @@
+/// What the Tab key inserts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TabStyle {
+    /// Spaces up to the next stop, with stops every `n` columns.
+    Spaces(usize),
+    /// A tab character.
+    #[default]
+    Hard,
+}
+
+impl TabStyle {
+    /// Switch between hard tabs and `spaces` wide soft tabs.
+    pub fn toggled(self, spaces: usize) -> Self {
+        match self {
+            TabStyle::Spaces(_) => TabStyle::Hard,
+            TabStyle::Hard => TabStyle::Spaces(spaces),
+        }
+    }
+
+    /// Columns in one indentation unit, where a hard tab spans `tab_width`.
+    pub fn indent_width(self, tab_width: usize) -> usize {
+        match self {
+            TabStyle::Spaces(n) => n,
+            TabStyle::Hard => tab_width,
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Split a line into display rows at most `width` cells wide, breaking after the last
+/// whitespace of a row when there is one. A grapheme takes one cell, except that a tab
+/// runs to the next stop every `tab_width` cells from the start of its row, as the canvas
+/// draws it. Rows are ranges of 0-based grapheme columns; an empty line is a single
+/// empty row.
+pub fn wrap_rows(line: &str, width: usize, tab_width: Option<usize>) -> Vec<Range<usize>> {
+    let width = width.max(1);
+    let graphemes: Vec<&str> = line.graphemes(true).collect();
+    let mut rows = Vec::new();
+    let mut start = 0;
+    let mut x = 0;
+    let mut i = 0;
+
+    while i < graphemes.len() {
+        let next = advance(x, graphemes[i], tab_width);
+        if next > width && i > start {
+            let end = (start + 1..=i)
+                .rev()
+                .find(|&j| graphemes[j - 1].chars().all(char::is_whitespace))
+                .unwrap_or(i);
+            rows.push(start..end);
+            start = end;
+            // Graphemes carried onto the new row only move left, so they still fit
+            x = graphemes[start..i]
+                .iter()
+                .fold(0, |x, g| advance(x, g, tab_width));
+            continue;
+        }
+        x = next;
+        i += 1;
+    }
+    rows.push(start..graphemes.len());
+    rows
+}
+
+// Cell after `grapheme` when it starts `x` cells into its row
+fn advance(x: usize, grapheme: &str, tab_width: Option<usize>) -> usize {
+    match (grapheme, tab_width) {
+        ("\t", Some(tab_width)) => (x / tab_width.max(1) + 1) * tab_width.max(1),
+        _ => x + 1,
+    }
+}
+
+// Cell of each grapheme boundary of `row` of `line`, from 0 before its first grapheme
+fn row_cells(line: &str, row: &Range<usize>, tab_width: Option<usize>) -> Vec<usize> {
+    let mut x = 0;
+    std::iter::once(0)
+        .chain(slice_graphemes(line, row.clone()).graphemes(true).map(|g| {
+            x = advance(x, g, tab_width);
+            x
+        }))
+        .collect()
+}
+
+/// Row holding a grapheme column. A column on a row boundary belongs to the row it starts.
+pub fn row_of(rows: &[Range<usize>], col: usize) -> usize {
+    rows.iter().rposition(|r| r.start <= col).unwrap_or(0)
+}
+
+/// Display (row, x) of a grapheme column of `line`, with `x` in cells from the start of
+/// the row as `wrap_rows` measures them.
+pub fn visual_position(
+    line: &str,
+    rows: &[Range<usize>],
+    col: usize,
+    tab_width: Option<usize>,
+) -> (usize, usize) {
+    let row = row_of(rows, col);
+    let offset = col.saturating_sub(rows[row].start).min(rows[row].len());
+    (row, row_cells(line, &rows[row], tab_width)[offset])
+}
+
+/// Grapheme column of `line` at display column `x` of `row`: the last boundary at or
+/// before `x`, so a caret inside a tab's cells lands before the tab.
+pub fn column_at(
+    line: &str,
+    rows: &[Range<usize>],
+    row: usize,
+    x: usize,
+    tab_width: Option<usize>,
+) -> usize {
+    let row = row.min(rows.len() - 1);
+    let cells = row_cells(line, &rows[row], tab_width);
+    let offset = cells.partition_point(|&c| c <= x) - 1;
+    clamp_to_row(rows, row, offset)
+}
+
+/// Grapheme column `offset` graphemes into `row`. Only the last row can place the caret
+/// past its final grapheme; elsewhere that spot is the start of the next row.
+pub fn clamp_to_row(rows: &[Range<usize>], row: usize, offset: usize) -> usize {
+    let last = rows.len() - 1;
+    let r = &rows[row.min(last)];
+    let max = if row >= last || r.is_empty() {
+        r.end
+    } else {
+        r.end - 1
+    };
+    (r.start + offset).min(max)
+}
+
+/// Column one display row below `col` at display column `x`, or `None` when `col` is
+/// already on the last row of its line.
+pub fn row_below(
+    line: &str,
+    rows: &[Range<usize>],
+    col: usize,
+    x: usize,
+    tab_width: Option<usize>,
+) -> Option<usize> {
+    let row = row_of(rows, col);
+    (row + 1 < rows.len()).then(|| column_at(line, rows, row + 1, x, tab_width))
+}
+
+/// Column one display row above `col` at display column `x`, or `None` when `col` is
+/// on the first row of its line.
+pub fn row_above(
+    line: &str,
+    rows: &[Range<usize>],
+    col: usize,
+    x: usize,
+    tab_width: Option<usize>,
+) -> Option<usize> {
+    let row = row_of(rows, col);
+    (row > 0).then(|| column_at(line, rows, row - 1, x, tab_width))
+}
+
+/// Slice of `line` covering a range of grapheme columns.
+pub fn slice_graphemes(line: &str, cols: Range<usize>) -> &str {
+    let mut start = line.len();
+    let mut end = line.len();
+    for (i, (byte, _)) in line.grapheme_indices(true).enumerate() {
+        if i == cols.start {
+            start = byte;
+        }
+        if i == cols.end {
+            end = byte;
+            break;
+        }
+    }
+    &line[start.min(end)..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = "hello brave new world";
+
+    #[test]
+    fn wraps_after_whitespace() {
+        let rows = wrap_rows(LINE, 10, None);
+        assert_eq!(rows, vec![0..6, 6..16, 16..21]);
+        assert_eq!(slice_graphemes(LINE, rows[1].clone()), "brave new ");
+
+        // No whitespace to break at: hard split
+        assert_eq!(wrap_rows("abcdefgh", 3, None), vec![0..3, 3..6, 6..8]);
+        assert_eq!(wrap_rows("", 3, None), vec![0..0]);
+    }
+
+    #[test]
+    fn column_maps_to_visual_row() {
+        let rows = wrap_rows(LINE, 10, None);
+        assert_eq!(visual_position(LINE, &rows, 0, None), (0, 0));
+        assert_eq!(visual_position(LINE, &rows, 5, None), (0, 5));
+        // Boundary column starts the next row
+        assert_eq!(visual_position(LINE, &rows, 6, None), (1, 0));
+        assert_eq!(visual_position(LINE, &rows, 18, None), (2, 2));
+        assert_eq!(visual_position(LINE, &rows, 21, None), (2, 5));
+    }
+
+    #[test]
+    fn moving_down_keeps_display_column() {
+        let rows = wrap_rows(LINE, 10, None);
+        // From "he|llo" down to "br|ave"
+        assert_eq!(row_below(LINE, &rows, 2, 2, None), Some(8));
+        // Display column past a shorter row clamps before that row's end
+        assert_eq!(row_below(LINE, &rows, 8, 9, None), Some(21));
+        assert_eq!(row_above(LINE, &rows, 20, 9, None), Some(15));
+        assert_eq!(row_above(LINE, &rows, 15, 9, None), Some(5));
+        // Last row: movement continues on the next logical line
+        assert_eq!(row_below(LINE, &rows, 20, 4, None), None);
+        assert_eq!(row_above(LINE, &rows, 3, 3, None), None);
+
+        let next = wrap_rows("ab", 10, None);
+        assert_eq!(column_at("ab", &next, 0, 4, None), 2);
+    }
+
+    #[test]
+    fn tabs_take_the_cells_the_canvas_draws() {
+        let line = "a\tb\tc";
+        // Counted as one cell each the line fits; expanded to stops of 4 it does not
+        assert_eq!(wrap_rows(line, 6, None), vec![0..5]);
+        let rows = wrap_rows(line, 6, Some(4));
+        assert_eq!(rows, vec![0..2, 2..5]);
+
+        // The second tab starts a cell into its row and runs to the stop at 4
+        assert_eq!(visual_position(line, &rows, 4, Some(4)), (1, 4));
+        assert_eq!(visual_position(line, &rows, 1, Some(4)), (0, 1));
+        assert_eq!(column_at(line, &rows, 1, 4, Some(4)), 4);
+        // Inside the tab's cells: before the tab
+        assert_eq!(column_at(line, &rows, 1, 2, Some(4)), 3);
+        // Four cells in on the row below is after the second tab; back up, the end of
+        // the first row clamps before its tab
+        assert_eq!(row_below(line, &rows, 1, 4, Some(4)), Some(4));
+        assert_eq!(row_above(line, &rows, 4, 4, Some(4)), Some(1));
+    }
+}
@@ -4,6 +4,13 @@ use std::rc::{Rc, Weak};
 type NodeRef = Rc<RefCell<TreeNode>>;
 type WeakNodeRef = Weak<RefCell<TreeNode>>;
 
+// How `PieceTree::find_all_opts` matches
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    pub case_insensitive: bool,
+    pub whole_word: bool,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BufferCursor {
     line: usize,
@@ -14,6 +21,14 @@ impl BufferCursor {
     pub fn new(line: usize, column: usize) -> Self {
         Self { line, column }
     }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -90,6 +105,45 @@ impl StringBuffer {
 
         line_starts
     }
+
+    // Append `text` and extend `line_starts` by scanning only the appended region.
+    // A '\r' ending the current buffer followed by a '\n' starting `text` is one CRLF break,
+    // so the line start recorded after the '\r' moves past the '\n'.
+    fn append_and_extend_line_starts(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+
+        let base = self.buffer.len();
+        let mut skip = 0;
+        if text.as_bytes()[0] == b'\n' && self.buffer.as_bytes().last() == Some(&b'\r') {
+            if let Some(last) = self.line_starts.last_mut() {
+                *last = base + 1;
+            }
+            skip = 1;
+        }
+
+        self.buffer.push_str(text);
+        let appended = Self::create_line_starts(&text[skip..]);
+        self.line_starts
+            .extend(appended.iter().skip(1).map(|start| start + base + skip));
+    }
+
+    // Byte offset at which 0-based `line` starts, None past the last line
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.line_starts.get(line).copied()
+    }
+
+    // 0-based line containing `byte`; offsets past the end belong to the last line
+    pub fn line_of_byte(&self, byte: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= byte) - 1
+    }
+
+    // Cursor pointing just past the last byte of the buffer
+    fn end_cursor(&self) -> BufferCursor {
+        let line = self.line_starts.len() - 1;
+        BufferCursor::new(line, self.buffer.len() - self.line_starts[line])
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -123,23 +177,52 @@ impl TreeNode {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct PieceTree {
     root: Option<NodeRef>,
-    buffers: Vec<StringBuffer>,
+    // Shared with clones; only the change buffer (0) is ever appended to, copied first if
+    // a clone still uses it
+    buffers: Vec<Rc<StringBuffer>>,
     length: usize,
     line_count: usize,
-    eol: &'static str,
+}
+
+// A clone gets its own copy of the nodes but shares the backing buffers, so it costs one
+// node per piece rather than a copy of the text. Editing either tree leaves the other as it
+// was.
+impl Clone for PieceTree {
+    fn clone(&self) -> Self {
+        Self {
+            root: self
+                .root
+                .as_ref()
+                .map(|root| Self::clone_subtree(root, None)),
+            buffers: self.buffers.clone(),
+            length: self.length,
+            line_count: self.line_count,
+        }
+    }
 }
 
 impl PieceTree {
     pub fn new(chunks: &mut [StringBuffer]) -> Self {
+        Self::with_capacity(chunks, 0)
+    }
+
+    // `capacity` is the expected document size in bytes. Whatever the chunks don't
+    // already cover is reserved in the change buffer for upcoming inserts.
+    pub fn with_capacity(chunks: &mut [StringBuffer], capacity: usize) -> Self {
+        let loaded: usize = chunks.iter().map(|chunk| chunk.buffer.len()).sum();
+        let mut buffers = Vec::with_capacity(chunks.len() + 1);
+        buffers.push(Rc::new(StringBuffer::new(String::with_capacity(
+            capacity.saturating_sub(loaded),
+        ))));
+
         let mut tree = Self {
             root: None,
-            buffers: vec![StringBuffer::new(String::new())],
+            buffers,
             line_count: 1,
             length: 0,
-            eol: "\n",
         };
 
         if chunks.is_empty() {
@@ -147,6 +230,7 @@ impl PieceTree {
         };
 
         let mut last_node: Option<NodeRef> = None;
+        let mut boundaries = Vec::with_capacity(chunks.len());
         for (i, chunk) in chunks.iter().enumerate() {
             let piece = Piece::new(
                 i + 1,
@@ -158,8 +242,16 @@ impl PieceTree {
                 chunk.buffer.len(),
                 chunk.line_starts.len() - 1,
             );
-            tree.buffers.push(chunk.clone());
+            tree.buffers.push(Rc::new(chunk.clone()));
+            // An empty chunk keeps its buffer index but gets no piece
+            if chunk.buffer.is_empty() {
+                continue;
+            }
             last_node = tree.rb_insert_right(last_node, piece);
+            boundaries.push(boundaries.last().copied().unwrap_or(0) + chunk.buffer.len());
+        }
+        for offset in boundaries {
+            tree.join_split_crlf(offset);
         }
 
         tree.compute_buffer_metadata();
@@ -318,15 +410,13 @@ impl PieceTree {
 
             if piece.end.column == 0 {
                 // The piece ends exactly at the start of a line. If the character
-                // before this line is '\r', mark dangling and undo previous push.
+                // before this line is '\r', mark dangling and undo previous push,
+                // keeping that line pending until the next piece resolves the CR.
                 if end_line_start > 0
                     && Self::char_code_at(buffer, end_line_start - 1) == Some(b'\r')
                 {
                     dangling_cr = true;
-                    if !lines.is_empty() {
-                        lines.pop();
-                    }
-                    current_line.clear();
+                    current_line = lines.pop().unwrap_or_default();
                 } else {
                     current_line.clear();
                 }
@@ -361,34 +451,109 @@ impl PieceTree {
     }
 
     pub fn get_line_content(&self, line_number: usize) -> String {
-        let lines = self.get_lines_content();
-        if line_number == 0 {
-            return String::new();
+        let mut line = String::new();
+        self.write_line_content(line_number, &mut line);
+        line
+    }
+
+    // Clear `out` and fill it with a 1-based line without its EOL, reading only the pieces
+    // that line spans; empty for line 0 or past the end
+    pub fn write_line_content(&self, line_number: usize, out: &mut String) {
+        out.clear();
+        if line_number == 0 || line_number > self.line_count() {
+            return;
         }
-        if line_number <= lines.len() {
-            return lines[line_number - 1].clone();
+        let start = self.get_offset_at(line_number, 1);
+        let end = if line_number < self.line_count() {
+            self.get_offset_at(line_number + 1, 1)
+        } else {
+            self.len()
+        };
+        self.append_range(start, end, out);
+
+        if out.ends_with("\r\n") {
+            out.truncate(out.len() - 2);
+        } else if out.ends_with(['\n', '\r']) {
+            out.truncate(out.len() - 1);
         }
-        String::new()
     }
 
-    fn parent_of(node: &NodeRef) -> Option<NodeRef> {
-        node.borrow().parent.as_ref().and_then(|w| w.upgrade())
+    // Text of `start..end`, with `end` clamped to the document length. Only the pieces in
+    // range are read; a character cut by either end of the range is left out.
+    pub fn get_text_in_range(&self, start: usize, end: usize) -> String {
+        let end = end.min(self.len());
+        if start >= end {
+            return String::new();
+        }
+        let mut out = String::with_capacity(end - start);
+        self.append_range(start, end, &mut out);
+        out
     }
 
-    fn is_left_child_of_parent(&self, node: &NodeRef) -> Option<bool> {
-        let parent = Self::parent_of(node)?;
-        let pb = parent.borrow();
-        if let Some(ref l) = pb.left {
-            if Rc::ptr_eq(l, node) {
-                return Some(true);
+    // Append the document text in `start..end` to `out`, reading only the pieces in range.
+    // A character cut by either end of the range is left out.
+    pub fn append_range(&self, start: usize, end: usize, out: &mut String) {
+        let Some((mut node, mut remainder, _)) = self.node_at(start) else {
+            return;
+        };
+        let mut left = end.saturating_sub(start);
+        while left > 0 {
+            {
+                let nb = node.borrow();
+                let piece = &nb.piece;
+                let buffer = &self.buffers[piece.buffer_idx];
+                let piece_start = buffer.line_starts[piece.start.line] + piece.start.column;
+                let text = &buffer.buffer;
+                let mut from = piece_start + remainder;
+                let take = piece.length.saturating_sub(remainder).min(left);
+                let mut to = from + take;
+                while !text.is_char_boundary(from) {
+                    from += 1;
+                }
+                while to > from && !text.is_char_boundary(to) {
+                    to -= 1;
+                }
+                out.push_str(&text[from..to]);
+                left -= take;
             }
-        }
-        if let Some(ref r) = pb.right {
-            if Rc::ptr_eq(r, node) {
-                return Some(false);
+            remainder = 0;
+            match self.next(&node) {
+                Some(next) => node = next,
+                None => break,
             }
         }
-        None
+    }
+
+    // Copy of `node` and everything below it, hung under `parent`
+    fn clone_subtree(node: &NodeRef, parent: Option<&NodeRef>) -> NodeRef {
+        let nb = node.borrow();
+        let copy = Rc::new(RefCell::new(TreeNode {
+            piece: nb.piece.clone(),
+            color: nb.color,
+            parent: parent.map(Rc::downgrade),
+            left: None,
+            right: None,
+            size_left: nb.size_left,
+            lf_left: nb.lf_left,
+        }));
+        let left = nb
+            .left
+            .as_ref()
+            .map(|l| Self::clone_subtree(l, Some(&copy)));
+        let right = nb
+            .right
+            .as_ref()
+            .map(|r| Self::clone_subtree(r, Some(&copy)));
+        {
+            let mut cb = copy.borrow_mut();
+            cb.left = left;
+            cb.right = right;
+        }
+        copy
+    }
+
+    fn parent_of(node: &NodeRef) -> Option<NodeRef> {
+        node.borrow().parent.as_ref().and_then(|w| w.upgrade())
     }
 
     fn set_parent(child: &NodeRef, parent: Option<&NodeRef>) {
@@ -406,13 +571,6 @@ impl PieceTree {
         node.borrow_mut().color = color;
     }
 
-    fn left_of(node: &NodeRef) -> Option<NodeRef> {
-        node.borrow().left.clone()
-    }
-    fn right_of(node: &NodeRef) -> Option<NodeRef> {
-        node.borrow().right.clone()
-    }
-
     fn leftmost(&self, mut x: NodeRef) -> NodeRef {
         loop {
             let left_opt = { x.borrow().left.clone() };
@@ -768,7 +926,7 @@ impl PieceTree {
         self.length = len;
     }
 
-    fn recompute_tree_metadata(&mut self, mut x: NodeRef) {
+    fn recompute_tree_metadata(&mut self, x: NodeRef) {
         // Recompute size_left and lf_left for x and all its ancestors
         let mut cur: Option<NodeRef> = Some(x.clone());
         while let Some(n) = cur {
@@ -796,6 +954,8 @@ impl PieceTree {
 
     // Find node at document offset.
     // Returns (node, remainder within node.piece, node_start_offset)
+    // An empty piece is never returned: at its offset the next piece with text is, or the
+    // previous one when none follows
     fn node_at(&self, mut offset: usize) -> Option<(NodeRef, usize, usize)> {
         let mut x_opt = self.root.clone();
         let mut node_start_offset = 0usize;
@@ -816,6 +976,9 @@ impl PieceTree {
             } else if size_left + piece_len >= offset {
                 node_start_offset += size_left;
                 let remainder = offset - size_left;
+                if piece_len == 0 {
+                    return self.non_empty_around(&x, node_start_offset);
+                }
                 return Some((x.clone(), remainder, node_start_offset));
             } else {
                 offset -= size_left + piece_len;
@@ -826,6 +989,40 @@ impl PieceTree {
         None
     }
 
+    // The nearest piece with text before `node`, stepping over empty ones
+    fn prev_with_text(&self, node: &NodeRef) -> Option<NodeRef> {
+        let mut prev = self.prev(node);
+        while let Some(p) = prev {
+            if p.borrow().piece.length > 0 {
+                return Some(p);
+            }
+            prev = self.prev(&p);
+        }
+        None
+    }
+
+    // The nearest piece with text after `node`, stepping over empty ones
+    fn next_with_text(&self, node: &NodeRef) -> Option<NodeRef> {
+        let mut next = self.next(node);
+        while let Some(n) = next {
+            if n.borrow().piece.length > 0 {
+                return Some(n);
+            }
+            next = self.next(&n);
+        }
+        None
+    }
+
+    // `node_at` for an offset landing on the empty piece `node`, which starts at `offset`
+    fn non_empty_around(&self, node: &NodeRef, offset: usize) -> Option<(NodeRef, usize, usize)> {
+        if let Some(next) = self.next_with_text(node) {
+            return Some((next, 0, offset));
+        }
+        let prev = self.prev_with_text(node)?;
+        let len = prev.borrow().piece.length;
+        Some((prev, len, offset - len))
+    }
+
     // Convert a remainder within node.piece to its BufferCursor within the backing buffer
     fn position_in_buffer(&self, node: &NodeRef, remainder: usize) -> BufferCursor {
         let nb = node.borrow();
@@ -835,6 +1032,10 @@ impl PieceTree {
 
         let start_offset = line_starts[piece.start.line] + piece.start.column;
         let end_offset = line_starts[piece.end.line] + piece.end.column;
+        if start_offset >= end_offset {
+            // Nothing to search in an empty piece
+            return piece.start;
+        }
         let target = (start_offset + remainder).min(end_offset);
 
         let mut low = piece.start.line;
@@ -907,10 +1108,25 @@ impl PieceTree {
         end.line.saturating_sub(start.line)
     }
 
-    // Build pieces for a given text. This baseline creates new backing buffers (not buffer 0)
-    // to avoid cross-boundary CRLF complexities in the mutable change buffer.
+    // Build pieces for a given text. Small texts are appended to the shared change buffer
+    // (buffer 0); large texts are split into new backing buffers of roughly AVG_BUF bytes.
     fn create_new_pieces(&mut self, mut text: &str) -> Vec<Piece> {
         const AVG_BUF: usize = 65535;
+
+        if text.len() <= AVG_BUF {
+            let change_buffer = Rc::make_mut(&mut self.buffers[0]);
+            // Never let a '\r' already in the change buffer pair up with a leading '\n':
+            // the earlier piece ending at that '\r' would have its line starts shifted.
+            if text.starts_with('\n') && change_buffer.buffer.ends_with('\r') {
+                change_buffer.append_and_extend_line_starts("_");
+            }
+            let start = change_buffer.end_cursor();
+            change_buffer.append_and_extend_line_starts(text);
+            let end = change_buffer.end_cursor();
+
+            return vec![Piece::new(0, start, end, text.len(), end.line - start.line)];
+        }
+
         let mut pieces: Vec<Piece> = Vec::new();
 
         while !text.is_empty() {
@@ -951,10 +1167,10 @@ impl PieceTree {
             let chunk = &text[..split];
             let line_starts = StringBuffer::create_line_starts(chunk);
             let buf_idx = self.buffers.len();
-            self.buffers.push(StringBuffer {
+            self.buffers.push(Rc::new(StringBuffer {
                 buffer: chunk.to_string(),
                 line_starts: line_starts.clone(),
-            });
+            }));
 
             let end_line = line_starts.len() - 1;
             let end_col = chunk.len() - line_starts[end_line];
@@ -1083,14 +1299,45 @@ impl PieceTree {
     }
 
     // Insert `value` at document offset `offset`
-    pub fn insert(&mut self, mut offset: usize, value: &str) {
+    pub fn insert(&mut self, offset: usize, value: &str) {
         if value.is_empty() {
             return;
         }
+        let offset = offset.min(self.length);
+        self.insert_pieces(offset, value);
+        self.join_split_crlf(offset);
+        self.join_split_crlf(offset + value.len());
+        self.compute_buffer_metadata();
+    }
+
+    // Replace the `len` chars at `offset` with `text`, updating the document totals once
+    pub fn replace(&mut self, offset: usize, len: usize, text: &str) {
+        self.replace_many([(offset, len, text)]);
+    }
+
+    // Make each `(offset, len, text)` replacement in turn, every offset counted in the text
+    // the ones before it left, and update the document totals once at the end
+    pub fn replace_many<'a>(&mut self, edits: impl IntoIterator<Item = (usize, usize, &'a str)>) {
+        for (offset, len, text) in edits {
+            let offset = offset.min(self.length);
+            let len = len.min(self.length - offset);
+            if len == 0 && text.is_empty() {
+                continue;
+            }
+            self.delete_pieces(offset, len);
+            self.insert_pieces(offset, text);
+            self.join_split_crlf(offset);
+            self.join_split_crlf(offset + text.len());
+            // Clamping and deleting up to the end need the length; the line count can wait
+            self.length = self.length - len + text.len();
+        }
+        self.compute_buffer_metadata();
+    }
 
-        // clamp
-        if offset > self.length {
-            offset = self.length;
+    // Body of `insert` for an in-range offset; leaves `length` and `line_count` stale
+    fn insert_pieces(&mut self, offset: usize, value: &str) {
+        if value.is_empty() || self.try_append_to_change_piece(offset, value) {
+            return;
         }
 
         let new_pieces = self.create_new_pieces(value);
@@ -1105,7 +1352,6 @@ impl PieceTree {
                     self.rb_insert_left(None, p)
                 };
             }
-            self.compute_buffer_metadata();
             return;
         }
 
@@ -1119,12 +1365,12 @@ impl PieceTree {
                 for p in new_pieces {
                     last = self.rb_insert_right(last, p);
                 }
-                self.compute_buffer_metadata();
                 return;
             }
         };
 
         let piece_len = { node.borrow().piece.length };
+        let first_new;
         if node_start_offset == offset {
             // insert to the left of node
             // Insert pieces in order: last piece first to the left to maintain sequence
@@ -1132,6 +1378,7 @@ impl PieceTree {
             for p in new_pieces.iter().rev() {
                 cur_left_of = self.rb_insert_left(cur_left_of, p.clone());
             }
+            first_new = cur_left_of;
         } else if node_start_offset + piece_len > offset {
             // Insert in the middle: split node into left and right
             let split_pos = self.position_in_buffer(&node, remainder);
@@ -1150,6 +1397,7 @@ impl PieceTree {
             for p in new_pieces {
                 last = self.rb_insert_right(last, p);
             }
+            first_new = self.next(&node);
             if right_piece.length > 0 {
                 self.rb_insert_right(last, right_piece);
             }
@@ -1159,20 +1407,286 @@ impl PieceTree {
             for p in new_pieces {
                 last = self.rb_insert_right(last, p);
             }
+            first_new = self.next(&node);
         }
 
-        self.compute_buffer_metadata();
+        // Typing appends to the change buffer right where the previous piece ended
+        if let Some(first) = first_new {
+            self.try_merge_with_previous(&first);
+        }
+    }
+
+    // Typing fast path: when `offset` is the end of the piece that ends the change buffer,
+    // append `value` to that buffer and grow the piece instead of adding a node
+    fn try_append_to_change_piece(&mut self, offset: usize, value: &str) -> bool {
+        const MAX_APPEND: usize = 256;
+
+        if value.len() >= MAX_APPEND {
+            return false;
+        }
+        let Some((node, remainder, _)) = self.node_at(offset) else {
+            return false;
+        };
+        let (buf, start, end, length) = {
+            let nb = node.borrow();
+            let p = &nb.piece;
+            (p.buffer_idx, p.start, p.end, p.length)
+        };
+        let change_buffer = &self.buffers[0];
+        if buf != 0 || remainder != length || end != change_buffer.end_cursor() {
+            return false;
+        }
+        // A '\n' after a '\r' already in the buffer would join them into one line break and
+        // move line starts earlier pieces rely on; leave that to the general path
+        if value.starts_with('\n') && change_buffer.buffer.ends_with('\r') {
+            return false;
+        }
+
+        let change_buffer = Rc::make_mut(&mut self.buffers[0]);
+        change_buffer.append_and_extend_line_starts(value);
+        let new_end = change_buffer.end_cursor();
+        let piece = self.piece_from_range(0, start, new_end);
+        node.borrow_mut().piece = piece;
+        self.recompute_tree_metadata(node);
+        true
+    }
+
+    // Number of pieces in the tree
+    #[cfg(test)]
+    fn node_count(&self) -> usize {
+        let mut count = 0;
+        self.for_each_inorder(|_| {
+            count += 1;
+            true
+        });
+        count
+    }
+
+    // Fold `node` into the piece before it when both cover adjacent bytes of one buffer,
+    // removing `node` from the tree. Returns whether they were merged.
+    fn try_merge_with_previous(&mut self, node: &NodeRef) -> bool {
+        let Some(prev) = self.prev(node) else {
+            return false;
+        };
+        let (buf, start, end) = {
+            let nb = node.borrow();
+            (nb.piece.buffer_idx, nb.piece.start, nb.piece.end)
+        };
+        let (prev_buf, prev_start, prev_end) = {
+            let pb = prev.borrow();
+            (pb.piece.buffer_idx, pb.piece.start, pb.piece.end)
+        };
+        if prev_buf != buf || prev_end != start {
+            return false;
+        }
+
+        let merged = self.piece_from_range(buf, prev_start, end);
+        prev.borrow_mut().piece = merged;
+        self.recompute_tree_metadata(prev);
+        self.rb_delete(node.clone());
+        true
+    }
+
+    // Unlink `z` from the tree and restore the red-black properties
+    fn rb_delete(&mut self, z: NodeRef) {
+        let (z_left, z_right) = {
+            let zb = z.borrow();
+            (zb.left.clone(), zb.right.clone())
+        };
+        let mut removed_color = Self::node_color(Some(&z));
+        let x: Option<NodeRef>;
+        let x_parent: Option<NodeRef>;
+
+        match (z_left, z_right) {
+            (None, right) => {
+                x = right;
+                x_parent = Self::parent_of(&z);
+                self.transplant(&z, x.clone());
+            }
+            (left, None) => {
+                x = left;
+                x_parent = Self::parent_of(&z);
+                self.transplant(&z, x.clone());
+            }
+            (Some(z_left), Some(z_right)) => {
+                // Successor y takes z's place and color
+                let y = self.leftmost(z_right.clone());
+                removed_color = Self::node_color(Some(&y));
+                x = y.borrow().right.clone();
+                if Rc::ptr_eq(&Self::parent_of(&y).expect("successor has a parent"), &z) {
+                    x_parent = Some(y.clone());
+                } else {
+                    x_parent = Self::parent_of(&y);
+                    self.transplant(&y, x.clone());
+                    y.borrow_mut().right = Some(z_right.clone());
+                    Self::set_parent(&z_right, Some(&y));
+                }
+                self.transplant(&z, Some(y.clone()));
+                y.borrow_mut().left = Some(z_left.clone());
+                Self::set_parent(&z_left, Some(&y));
+                let z_color = Self::node_color(Some(&z));
+                Self::set_color(&y, z_color);
+            }
+        }
+
+        {
+            let mut zb = z.borrow_mut();
+            zb.left = None;
+            zb.right = None;
+            zb.parent = None;
+        }
+        // Every node whose left subtree changed is on the path up from x's parent
+        if let Some(ref p) = x_parent {
+            self.recompute_tree_metadata(p.clone());
+        }
+        if removed_color == NodeColor::Black {
+            self.fix_delete(x, x_parent);
+        }
+    }
+
+    // Put `v` where `u` hangs from its parent (or the root)
+    fn transplant(&mut self, u: &NodeRef, v: Option<NodeRef>) {
+        let parent = Self::parent_of(u);
+        match parent {
+            None => self.root = v.clone(),
+            Some(ref p) => {
+                let u_is_left = {
+                    let pb = p.borrow();
+                    pb.left.as_ref().is_some_and(|l| Rc::ptr_eq(l, u))
+                };
+                let mut pb = p.borrow_mut();
+                if u_is_left {
+                    pb.left = v.clone();
+                } else {
+                    pb.right = v.clone();
+                }
+            }
+        }
+        if let Some(ref v) = v {
+            Self::set_parent(v, parent.as_ref());
+        }
+    }
+
+    // Rebalance after removing a black node; `x` took its place under `x_parent`
+    fn fix_delete(&mut self, mut x: Option<NodeRef>, mut x_parent: Option<NodeRef>) {
+        while Self::node_color(x.as_ref()) == NodeColor::Black {
+            let Some(parent) = x_parent.clone() else {
+                break; // x is the root
+            };
+            let x_is_left = {
+                let pb = parent.borrow();
+                match (&pb.left, &x) {
+                    (Some(l), Some(x)) => Rc::ptr_eq(l, x),
+                    (None, None) => true,
+                    _ => false,
+                }
+            };
+
+            if x_is_left {
+                let mut w = parent
+                    .borrow()
+                    .right
+                    .clone()
+                    .expect("sibling of a black node");
+                if Self::node_color(Some(&w)) == NodeColor::Red {
+                    Self::set_color(&w, NodeColor::Black);
+                    Self::set_color(&parent, NodeColor::Red);
+                    self.left_rotate(parent.clone());
+                    w = parent.borrow().right.clone().expect("sibling after rotate");
+                }
+                let (w_left, w_right) = {
+                    let wb = w.borrow();
+                    (wb.left.clone(), wb.right.clone())
+                };
+                if Self::node_color(w_left.as_ref()) == NodeColor::Black
+                    && Self::node_color(w_right.as_ref()) == NodeColor::Black
+                {
+                    Self::set_color(&w, NodeColor::Red);
+                    x_parent = Self::parent_of(&parent);
+                    x = Some(parent);
+                } else {
+                    if Self::node_color(w_right.as_ref()) == NodeColor::Black {
+                        if let Some(ref wl) = w_left {
+                            Self::set_color(wl, NodeColor::Black);
+                        }
+                        Self::set_color(&w, NodeColor::Red);
+                        self.right_rotate(w.clone());
+                        w = parent.borrow().right.clone().expect("sibling after rotate");
+                    }
+                    let parent_color = Self::node_color(Some(&parent));
+                    Self::set_color(&w, parent_color);
+                    Self::set_color(&parent, NodeColor::Black);
+                    if let Some(ref wr) = w.borrow().right {
+                        Self::set_color(wr, NodeColor::Black);
+                    }
+                    self.left_rotate(parent);
+                    x = self.root.clone();
+                    x_parent = None;
+                }
+            } else {
+                let mut w = parent
+                    .borrow()
+                    .left
+                    .clone()
+                    .expect("sibling of a black node");
+                if Self::node_color(Some(&w)) == NodeColor::Red {
+                    Self::set_color(&w, NodeColor::Black);
+                    Self::set_color(&parent, NodeColor::Red);
+                    self.right_rotate(parent.clone());
+                    w = parent.borrow().left.clone().expect("sibling after rotate");
+                }
+                let (w_left, w_right) = {
+                    let wb = w.borrow();
+                    (wb.left.clone(), wb.right.clone())
+                };
+                if Self::node_color(w_left.as_ref()) == NodeColor::Black
+                    && Self::node_color(w_right.as_ref()) == NodeColor::Black
+                {
+                    Self::set_color(&w, NodeColor::Red);
+                    x_parent = Self::parent_of(&parent);
+                    x = Some(parent);
+                } else {
+                    if Self::node_color(w_left.as_ref()) == NodeColor::Black {
+                        if let Some(ref wr) = w_right {
+                            Self::set_color(wr, NodeColor::Black);
+                        }
+                        Self::set_color(&w, NodeColor::Red);
+                        self.left_rotate(w.clone());
+                        w = parent.borrow().left.clone().expect("sibling after rotate");
+                    }
+                    let parent_color = Self::node_color(Some(&parent));
+                    Self::set_color(&w, parent_color);
+                    Self::set_color(&parent, NodeColor::Black);
+                    if let Some(ref wl) = w.borrow().left {
+                        Self::set_color(wl, NodeColor::Black);
+                    }
+                    self.right_rotate(parent);
+                    x = self.root.clone();
+                    x_parent = None;
+                }
+            }
+        }
+        if let Some(ref x) = x {
+            Self::set_color(x, NodeColor::Black);
+        }
     }
 
     // Delete `cnt` chars starting at `offset`
-    pub fn delete(&mut self, offset: usize, mut cnt: usize) {
+    pub fn delete(&mut self, offset: usize, cnt: usize) {
         if cnt == 0 || self.root.is_none() || offset >= self.length {
             return;
         }
 
         // clamp to end
-        if offset + cnt > self.length {
-            cnt = self.length - offset;
+        self.delete_pieces(offset, cnt.min(self.length - offset));
+        self.join_split_crlf(offset);
+        self.compute_buffer_metadata();
+    }
+
+    // Body of `delete` for an in-range span; leaves `length` and `line_count` stale
+    fn delete_pieces(&mut self, offset: usize, cnt: usize) {
+        if cnt == 0 {
+            return;
         }
 
         // Find start and end positions
@@ -1197,14 +1711,8 @@ impl PieceTree {
             let end_cursor = self.position_in_buffer(&start_node, end_rem);
 
             if start_node_start == offset && cnt == start_node.borrow().piece.length {
-                // delete entire node -> baseline: make it empty (no RB delete yet)
-                let buf_idx = start_node.borrow().piece.buffer_idx;
-                let empty_piece = self.piece_from_range(buf_idx, start_cursor, start_cursor);
-                {
-                    let mut nb = start_node.borrow_mut();
-                    nb.piece = empty_piece;
-                }
-                self.recompute_tree_metadata(start_node.clone());
+                // delete entire node
+                self.rb_delete(start_node);
             } else if start_node_start == offset {
                 // delete head
                 self.delete_node_head(&start_node, end_cursor);
@@ -1215,8 +1723,6 @@ impl PieceTree {
                 // delete middle => shrink and insert right piece
                 self.shrink_node(&start_node, start_cursor, end_cursor);
             }
-
-            self.compute_buffer_metadata();
             return;
         }
 
@@ -1225,42 +1731,93 @@ impl PieceTree {
         let start_cursor = self.position_in_buffer(&start_node, start_rem);
         self.delete_node_tail(&start_node, start_cursor);
 
-        // 2) zero out all nodes strictly between start_node and end_node
-        let mut cur_opt = {
-            // successor of start_node
-            // If it has right child, successor is leftmost of right subtree
-            // else climb up to first parent where we are in its left subtree
-            let cur = start_node.clone();
-            // use next()
-            self.next(&cur)
-        };
-        while let Some(cur) = cur_opt.clone() {
+        // 2) collect all nodes strictly between start_node and end_node
+        let mut emptied = Vec::new();
+        let mut cur_opt = self.next(&start_node);
+        while let Some(cur) = cur_opt {
             if Rc::ptr_eq(&cur, &end_node) {
                 break;
             }
-            // zero out piece
-            let buf_idx = { cur.borrow().piece.buffer_idx };
-            let zero =
-                self.piece_from_range(buf_idx, BufferCursor::new(0, 0), BufferCursor::new(0, 0));
-            {
-                let mut nb = cur.borrow_mut();
-                nb.piece = zero;
-            }
-            self.recompute_tree_metadata(cur.clone());
-
             cur_opt = self.next(&cur);
+            emptied.push(cur);
         }
 
         // 3) trim head of end node
         let end_cursor = self.position_in_buffer(&end_node, end_rem);
         // For end node, we need to delete head up to end_cursor
-        let end_start_cursor = {
-            let nb = end_node.borrow();
-            nb.piece.start
-        };
         self.delete_node_head(&end_node, end_cursor);
 
-        self.compute_buffer_metadata();
+        // 4) unlink every node left without text; rb_delete relinks nodes rather than moving
+        // pieces between them, so the ones still to go stay valid
+        for node in [start_node, end_node] {
+            if node.borrow().piece.length == 0 {
+                emptied.push(node);
+            }
+        }
+        for node in emptied {
+            self.rb_delete(node);
+        }
+    }
+
+    // A '\r' ending the piece before `offset` and a '\n' starting the piece after it are one
+    // line break, but each piece counts one. Move the pair into a piece of its own so that
+    // line counts add up across pieces
+    fn join_split_crlf(&mut self, offset: usize) {
+        let Some((node, remainder, _)) = self.node_at(offset) else {
+            return;
+        };
+        let (prev, next) = if remainder == 0 {
+            (self.prev_with_text(&node), Some(node))
+        } else if remainder == node.borrow().piece.length {
+            let next = self.next_with_text(&node);
+            (Some(node), next)
+        } else {
+            return;
+        };
+        let (Some(prev), Some(next)) = (prev, next) else {
+            return;
+        };
+        if self.piece_bytes(&prev).last() != Some(&b'\r')
+            || self.piece_bytes(&next).first() != Some(&b'\n')
+        {
+            return;
+        }
+
+        let prev_len = prev.borrow().piece.length;
+        let cr = self.position_in_buffer(&prev, prev_len - 1);
+        self.delete_node_tail(&prev, cr);
+        let after_lf = self.position_in_buffer(&next, 1);
+        self.delete_node_head(&next, after_lf);
+
+        let mut last = Some(prev.clone());
+        for piece in self.create_new_pieces("\r\n") {
+            last = self.rb_insert_right(last, piece);
+        }
+        for node in [prev, next] {
+            if node.borrow().piece.length == 0 {
+                self.rb_delete(node);
+            }
+        }
+    }
+
+    // inorder predecessor
+    fn prev(&self, node: &NodeRef) -> Option<NodeRef> {
+        if let Some(l) = { node.borrow().left.clone() } {
+            return Some(self.rightmost(l));
+        }
+        // climb up
+        let mut cur = node.clone();
+        while let Some(p) = Self::parent_of(&cur) {
+            let is_right = {
+                let pb = p.borrow();
+                pb.right.as_ref().is_some_and(|r| Rc::ptr_eq(r, &cur))
+            };
+            if is_right {
+                return Some(p);
+            }
+            cur = p;
+        }
+        None
     }
 
     // inorder successor
@@ -1433,51 +1990,427 @@ impl PieceTree {
 
     // Get the full document text by concatenating all pieces in-order
     pub fn get_text(&self) -> String {
-        let mut out = String::new();
-        self.for_each_inorder(|node| {
-            let nb = node.borrow();
-            let piece = &nb.piece;
-            if piece.length == 0 {
-                return true;
-            }
-            let buf_idx = piece.buffer_idx;
-            if buf_idx >= self.buffers.len() {
-                return true;
-            }
-            let buffer = &self.buffers[buf_idx].buffer;
-            let line_starts = &self.buffers[buf_idx].line_starts;
-
-            let start = line_starts[piece.start.line] + piece.start.column;
-            let end = line_starts[piece.end.line] + piece.end.column;
-            if start <= end && end <= buffer.len() {
-                out.push_str(&buffer[start..end]);
-            }
-            true
-        });
+        let mut out = String::with_capacity(self.len());
+        self.for_each_chunk(|chunk| out.push_str(chunk));
         out
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    fn doc(tree: &PieceTree) -> String {
-        tree.get_lines_content().join("\n")
+    // Write the document to `w` a piece at a time, without building it as one string. Stops
+    // at the first failed write.
+    pub fn write_to<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        let mut result = Ok(());
+        self.for_each_inorder(|node| {
+            result = w.write_all(self.piece_text(node).as_bytes());
+            result.is_ok()
+        });
+        result
     }
 
-    #[test]
-    fn lines_basic_unix() {
-        let mut chunks = vec![StringBuffer::new("Hello\nWorld".to_string())];
-        let tree = PieceTree::new(chunks.as_mut_slice());
-
-        let lines = tree.get_lines_content();
-        assert_eq!(lines, vec!["Hello", "World"]);
-
-        assert_eq!(tree.get_line_content(1), "Hello");
-        assert_eq!(tree.get_line_content(2), "World");
-        // Out of range returns empty
-        assert_eq!(tree.get_line_content(3), "");
+    // Byte at document `offset`, read straight from the backing buffer of its piece
+    pub fn byte_at(&self, offset: usize) -> Option<u8> {
+        let (node, remainder) = self.piece_containing(offset)?;
+        self.piece_bytes(&node).get(remainder).copied()
+    }
+
+    // Char starting at document `offset`, decoded from its piece and, when the char straddles
+    // a piece boundary, the pieces after it. None past the end, inside a char or on invalid
+    // UTF-8.
+    pub fn char_at(&self, offset: usize) -> Option<char> {
+        let (mut node, mut remainder) = self.piece_containing(offset)?;
+        let mut bytes = [0u8; 4];
+        let mut filled = 0;
+        let mut width = 1;
+        loop {
+            for &byte in &self.piece_bytes(&node)[remainder..] {
+                if filled == 0 {
+                    width = match byte {
+                        0x00..=0x7F => 1,
+                        0xC2..=0xDF => 2,
+                        0xE0..=0xEF => 3,
+                        0xF0..=0xF4 => 4,
+                        _ => return None,
+                    };
+                }
+                bytes[filled] = byte;
+                filled += 1;
+                if filled == width {
+                    return std::str::from_utf8(&bytes[..width]).ok()?.chars().next();
+                }
+            }
+            node = self.next(&node)?;
+            remainder = 0;
+        }
+    }
+
+    // Offset of the first byte from `offset` on for which `f` returns true, reading forwards or
+    // (from `offset` itself) backwards a piece at a time. Gives up after `limit` bytes.
+    pub fn find_byte<F: FnMut(u8) -> bool>(
+        &self,
+        offset: usize,
+        backward: bool,
+        limit: usize,
+        mut f: F,
+    ) -> Option<usize> {
+        let (mut node, remainder) = self.piece_containing(offset)?;
+        let mut seen = 0;
+        if backward {
+            // Document offset just past the bytes still to visit
+            let mut end = offset + 1;
+            let mut bytes = &self.piece_bytes(&node)[..=remainder];
+            loop {
+                for (i, &byte) in bytes.iter().enumerate().rev() {
+                    if seen == limit {
+                        return None;
+                    }
+                    seen += 1;
+                    if f(byte) {
+                        return Some(end - bytes.len() + i);
+                    }
+                }
+                end -= bytes.len();
+                node = self.prev(&node)?;
+                bytes = self.piece_bytes(&node);
+            }
+        } else {
+            let mut start = offset;
+            let mut bytes = &self.piece_bytes(&node)[remainder..];
+            loop {
+                for (i, &byte) in bytes.iter().enumerate() {
+                    if seen == limit {
+                        return None;
+                    }
+                    seen += 1;
+                    if f(byte) {
+                        return Some(start + i);
+                    }
+                }
+                start += bytes.len();
+                node = self.next(&node)?;
+                bytes = self.piece_bytes(&node);
+            }
+        }
+    }
+
+    // The node whose piece holds the byte at document `offset`, and the offset within the
+    // piece. `node_at` may stop at the very end of a piece, so move on to the next one then.
+    fn piece_containing(&self, offset: usize) -> Option<(NodeRef, usize)> {
+        if offset >= self.len() {
+            return None;
+        }
+        let (mut node, mut remainder, _) = self.node_at(offset)?;
+        loop {
+            let length = node.borrow().piece.length;
+            if remainder < length {
+                return Some((node, remainder));
+            }
+            remainder -= length;
+            node = self.next(&node)?;
+        }
+    }
+
+    // Document byte offsets of every non-overlapping occurrence of `needle`, in order. The
+    // pieces are streamed, keeping just enough of the previous ones to catch matches that
+    // straddle a piece boundary. An empty needle finds nothing.
+    pub fn find_all(&self, needle: &str) -> Vec<usize> {
+        self.find_all_opts(needle, SearchOptions::default())
+    }
+
+    // `find_all` with options. Case-insensitive matching folds ASCII letters only, so other
+    // characters must match exactly. Whole words must not touch an ASCII letter, digit or `_`
+    // on either side.
+    pub fn find_all_opts(&self, needle: &str, opts: SearchOptions) -> Vec<usize> {
+        let needle = needle.as_bytes();
+        let mut found = Vec::new();
+        if needle.is_empty() {
+            return found;
+        }
+        let is_word = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+        let matches_at = |hay: &[u8]| {
+            if opts.case_insensitive {
+                hay.eq_ignore_ascii_case(needle)
+            } else {
+                hay == needle
+            }
+        };
+
+        // Unsearched tail of the text so far, starting at document offset `window_start`,
+        // and the byte just before it
+        let mut window: Vec<u8> = Vec::new();
+        let mut window_start = 0;
+        let mut before: Option<u8> = None;
+        // A whole-word match needs the byte after it, so it waits for the next piece
+        let lookahead = usize::from(opts.whole_word);
+        let mut scan = |window: &mut Vec<u8>, at_end: bool| {
+            let lookahead = if at_end { 0 } else { lookahead };
+            let mut i = 0;
+            while i + needle.len() + lookahead <= window.len() {
+                let end = i + needle.len();
+                let prev = if i == 0 { before } else { Some(window[i - 1]) };
+                let bounded = !opts.whole_word
+                    || (!prev.is_some_and(is_word)
+                        && !window.get(end).is_some_and(|&b| is_word(b)));
+                if bounded && matches_at(&window[i..end]) {
+                    found.push(window_start + i);
+                    i = end;
+                } else {
+                    i += 1;
+                }
+            }
+            // Anything before `i` is matched or can no longer start a match
+            if i > 0 {
+                before = Some(window[i - 1]);
+            }
+            window.drain(..i);
+            window_start += i;
+        };
+        self.for_each_chunk(|chunk| {
+            window.extend_from_slice(chunk.as_bytes());
+            scan(&mut window, false);
+        });
+        scan(&mut window, true);
+        found
+    }
+
+    // Hash of the document text, independent of how it is split into pieces
+    pub fn content_hash(&self) -> u64 {
+        use std::hash::Hasher;
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.for_each_chunk(|chunk| hasher.write(chunk.as_bytes()));
+        hasher.finish()
+    }
+
+    // Check the red-black shape, parent links, cached subtree sums and piece bounds.
+    // Meant for tests and debugging; walks the whole tree.
+    pub fn verify_invariants(&self) -> Result<(), String> {
+        let Some(root) = &self.root else {
+            return match (self.length, self.line_count) {
+                (0, 1) => Ok(()),
+                other => Err(format!("empty tree reports (length, line_count) {other:?}")),
+            };
+        };
+        if root.borrow().color != NodeColor::Black {
+            return Err("root is red".to_string());
+        }
+        if Self::parent_of(root).is_some() {
+            return Err("root has a parent".to_string());
+        }
+
+        let (_, size, lf) = self.verify_subtree(root)?;
+        if size != self.length {
+            return Err(format!("length {} but pieces sum to {size}", self.length));
+        }
+        if lf + 1 != self.line_count {
+            return Err(format!(
+                "line_count {} but pieces hold {lf} breaks",
+                self.line_count
+            ));
+        }
+        Ok(())
+    }
+
+    // Returns (black height, total size, total line feeds) of the subtree at `node`
+    fn verify_subtree(&self, node: &NodeRef) -> Result<(usize, usize, usize), String> {
+        let nb = node.borrow();
+        let piece = &nb.piece;
+
+        let buffer = self
+            .buffers
+            .get(piece.buffer_idx)
+            .ok_or_else(|| format!("piece refers to missing buffer {}", piece.buffer_idx))?;
+        let in_bounds = |c: BufferCursor| {
+            c.line < buffer.line_starts.len()
+                && buffer.line_starts[c.line] + c.column <= buffer.buffer.len()
+        };
+        if !in_bounds(piece.start) || !in_bounds(piece.end) {
+            return Err(format!("piece {piece:?} is out of its buffer"));
+        }
+        let start = self.offset_in_buffer(piece.buffer_idx, piece.start);
+        let end = self.offset_in_buffer(piece.buffer_idx, piece.end);
+        if start > end || end - start != piece.length {
+            return Err(format!("piece {piece:?} spans {start}..{end}"));
+        }
+
+        let mut sums = Vec::with_capacity(2);
+        for child in [&nb.left, &nb.right] {
+            match child {
+                Some(c) => {
+                    let parent = Self::parent_of(c);
+                    if !parent.is_some_and(|p| Rc::ptr_eq(&p, node)) {
+                        return Err("child does not point back to its parent".to_string());
+                    }
+                    if nb.color == NodeColor::Red && c.borrow().color == NodeColor::Red {
+                        return Err("red node has a red child".to_string());
+                    }
+                    sums.push(self.verify_subtree(c)?);
+                }
+                None => sums.push((1, 0, 0)),
+            }
+        }
+        let (left, right) = (sums[0], sums[1]);
+
+        if left.0 != right.0 {
+            return Err(format!("black heights differ: {} vs {}", left.0, right.0));
+        }
+        if nb.size_left != left.1 || nb.lf_left != left.2 {
+            return Err(format!(
+                "cached (size_left, lf_left) ({}, {}) but left subtree has ({}, {})",
+                nb.size_left, nb.lf_left, left.1, left.2
+            ));
+        }
+
+        let black = usize::from(nb.color == NodeColor::Black);
+        Ok((
+            left.0 + black,
+            left.1 + piece.length + right.1,
+            left.2 + piece.line_feed_cnt + right.2,
+        ))
+    }
+
+    // Visit the text of every non-empty piece in document order
+    pub fn for_each_chunk<F: FnMut(&str)>(&self, mut f: F) {
+        self.for_each_inorder(|node| {
+            let text = self.piece_text(node);
+            if !text.is_empty() {
+                f(text);
+            }
+            true
+        });
+    }
+
+    // Text a node's piece covers; empty for a piece that does not fit its buffer
+    fn piece_text(&self, node: &NodeRef) -> &str {
+        let nb = node.borrow();
+        let piece = &nb.piece;
+        let Some(buffer) = self.buffers.get(piece.buffer_idx) else {
+            return "";
+        };
+        let line_starts = &buffer.line_starts;
+        let start = line_starts[piece.start.line] + piece.start.column;
+        let end = line_starts[piece.end.line] + piece.end.column;
+        buffer.buffer.get(start..end).unwrap_or("")
+    }
+
+    // Bytes a node's piece covers, which unlike `piece_text` may start or end inside a char
+    fn piece_bytes(&self, node: &NodeRef) -> &[u8] {
+        let nb = node.borrow();
+        let piece = &nb.piece;
+        let Some(buffer) = self.buffers.get(piece.buffer_idx) else {
+            return &[];
+        };
+        let line_starts = &buffer.line_starts;
+        let start = line_starts[piece.start.line] + piece.start.column;
+        let end = line_starts[piece.end.line] + piece.end.column;
+        buffer.buffer.as_bytes().get(start..end).unwrap_or(&[])
+    }
+
+    // Lines without their EOLs, produced one at a time by walking the pieces in order. Yields
+    // exactly what `get_lines_content` returns.
+    pub fn lines(&self) -> PieceTreeLines<'_> {
+        self.lines_from(0)
+    }
+
+    // 1-based lines `start_line..end_line`, clamped to the document. Finds the first line
+    // through the tree's line counts instead of scanning from the top.
+    pub fn line_range(&self, start_line: usize, end_line: usize) -> Vec<String> {
+        let start_line = start_line.max(1);
+        let end_line = end_line.min(self.line_count() + 1);
+        if start_line >= end_line {
+            return Vec::new();
+        }
+        self.lines_from(self.get_offset_at(start_line, 1))
+            .take(end_line - start_line)
+            .collect()
+    }
+
+    // Lines from document offset `offset`, which should start a line
+    fn lines_from(&self, offset: usize) -> PieceTreeLines<'_> {
+        let (node, pos) = match self.node_at(offset) {
+            Some((node, remainder, _)) => (Some(node), remainder),
+            None => (None, 0),
+        };
+        PieceTreeLines {
+            tree: self,
+            node,
+            pos,
+            line: String::new(),
+            skip_lf: false,
+            done: false,
+        }
+    }
+}
+
+// Iterator returned by `PieceTree::lines`
+pub struct PieceTreeLines<'a> {
+    tree: &'a PieceTree,
+    node: Option<NodeRef>,
+    // Byte position in the current node's text
+    pos: usize,
+    line: String,
+    // The last piece ended in CR; an LF opening the next one belongs to it
+    skip_lf: bool,
+    done: bool,
+}
+
+impl Iterator for PieceTreeLines<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        while let Some(node) = self.node.clone() {
+            let text = self.tree.piece_text(&node);
+            let rest = text.get(self.pos..).unwrap_or("");
+            if std::mem::take(&mut self.skip_lf) && rest.starts_with('\n') {
+                self.pos += 1;
+                continue;
+            }
+            match rest.find(['\r', '\n']) {
+                Some(i) => {
+                    self.line.push_str(&rest[..i]);
+                    self.pos += i + 1;
+                    if rest.as_bytes()[i] == b'\r' {
+                        if rest[i + 1..].starts_with('\n') {
+                            self.pos += 1;
+                        } else if i + 1 == rest.len() {
+                            self.skip_lf = true;
+                        }
+                    }
+                    return Some(std::mem::take(&mut self.line));
+                }
+                None => {
+                    self.line.push_str(rest);
+                    self.node = self.tree.next(&node);
+                    self.pos = 0;
+                }
+            }
+        }
+        if self.done {
+            return None;
+        }
+        self.done = true;
+        Some(std::mem::take(&mut self.line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(tree: &PieceTree) -> String {
+        tree.get_lines_content().join("\n")
+    }
+
+    #[test]
+    fn lines_basic_unix() {
+        let mut chunks = vec![StringBuffer::new("Hello\nWorld".to_string())];
+        let tree = PieceTree::new(chunks.as_mut_slice());
+
+        let lines = tree.get_lines_content();
+        assert_eq!(lines, vec!["Hello", "World"]);
+
+        assert_eq!(tree.get_line_content(1), "Hello");
+        assert_eq!(tree.get_line_content(2), "World");
+        // Out of range returns empty
+        assert_eq!(tree.get_line_content(3), "");
     }
 
     #[test]
@@ -1495,6 +2428,116 @@ mod tests {
         assert_eq!(tree.get_line_content(4), "");
     }
 
+    #[test]
+    fn write_line_content_matches_get_lines_content() {
+        let mut chunks = vec![
+            StringBuffer::new("héllo\r\nwörld\n".to_string()),
+            StringBuffer::new("日本語\r\n\nend".to_string()),
+        ];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        tree.insert(3, "✓\r\nnew ");
+        tree.insert(tree.len(), "\r\n");
+
+        let lines = tree.get_lines_content();
+        assert_eq!(lines.len(), tree.line_count());
+        let mut out = String::from("stale");
+        for (i, line) in lines.iter().enumerate() {
+            tree.write_line_content(i + 1, &mut out);
+            assert_eq!(&out, line, "line {}", i + 1);
+            assert_eq!(tree.get_line_content(i + 1), *line);
+        }
+
+        // The buffer is cleared even when there is nothing to write
+        tree.write_line_content(lines.len() + 1, &mut out);
+        assert_eq!(out, "");
+        out.push_str("stale");
+        tree.write_line_content(0, &mut out);
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn replace_spanning_several_pieces() {
+        let mut chunks = vec![
+            StringBuffer::new("one\ntwo\n".to_string()),
+            StringBuffer::new("three\r\n".to_string()),
+            StringBuffer::new("four".to_string()),
+        ];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        tree.insert(4, "2\n");
+
+        // From inside "two" through "three\r\n" into "four"
+        tree.replace(8, 10, "X\nY\nZ");
+        assert_eq!(tree.get_text(), "one\n2\ntwX\nY\nZour");
+        assert_eq!(tree.line_count(), 5);
+        assert_eq!(tree.len(), 16);
+        tree.verify_invariants().unwrap();
+
+        // Plain delete and plain insert
+        tree.replace(0, 4, "");
+        assert_eq!(tree.get_text(), "2\ntwX\nY\nZour");
+        tree.replace(tree.len(), 0, "\nend");
+        assert_eq!(tree.get_text(), "2\ntwX\nY\nZour\nend");
+        assert_eq!(tree.line_count(), 5);
+
+        // Out-of-range span is clamped; empty replacement of nothing does nothing
+        tree.replace(9, 100, "!");
+        assert_eq!(tree.get_text(), "2\ntwX\nY\nZ!");
+        assert_eq!(tree.line_count(), 4);
+        tree.replace(100, 5, "");
+        assert_eq!(tree.get_text(), "2\ntwX\nY\nZ!");
+        tree.verify_invariants().unwrap();
+    }
+
+    #[test]
+    fn replace_many_counts_lines_once_at_the_end() {
+        let mut chunks = vec![
+            StringBuffer::new("one\ntwo\r".to_string()),
+            StringBuffer::new("\nthree".to_string()),
+        ];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+
+        // The tail goes first, so the later edits delete up to an end that has moved
+        tree.replace_many([(9, 100, ""), (7, 2, "\n"), (0, 3, "1\n2\r")]);
+        assert_eq!(tree.get_text(), "1\n2\r\ntwo\n");
+        assert_eq!(tree.line_count(), 4);
+        assert_eq!(tree.len(), 9);
+        tree.verify_invariants().unwrap();
+    }
+
+    #[test]
+    fn get_text_in_range_within_and_across_pieces() {
+        let mut chunks = vec![
+            StringBuffer::new("ab\r\ncd".to_string()),
+            StringBuffer::new("ñ€\r\n".to_string()),
+            StringBuffer::new("tail".to_string()),
+        ];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        tree.insert(1, "XY");
+        let text = tree.get_text();
+        assert_eq!(text, "aXYb\r\ncdñ€\r\ntail");
+
+        // Single piece, several pieces, CRLF pairs on either side
+        assert_eq!(tree.get_text_in_range(1, 3), "XY");
+        assert_eq!(tree.get_text_in_range(2, 10), "Yb\r\ncdñ");
+        assert_eq!(tree.get_text_in_range(4, 5), "\r");
+        assert_eq!(tree.get_text_in_range(5, 16), "\ncdñ€\r\nt");
+        for start in 0..text.len() {
+            for end in start..=text.len() {
+                if text.is_char_boundary(start) && text.is_char_boundary(end) {
+                    assert_eq!(tree.get_text_in_range(start, end), text[start..end]);
+                }
+            }
+        }
+
+        // Cut characters are dropped, `end` is clamped, empty and inverted ranges are empty
+        assert_eq!(tree.get_text_in_range(9, 12), "");
+        assert_eq!(tree.get_text_in_range(9, 13), "€");
+        assert_eq!(tree.get_text_in_range(8, 11), "ñ");
+        assert_eq!(tree.get_text_in_range(15, 100), "tail");
+        assert_eq!(tree.get_text_in_range(3, 3), "");
+        assert_eq!(tree.get_text_in_range(7, 2), "");
+    }
+
     #[test]
     fn lines_multiple_chunks() {
         // Split across pieces without CR/LF boundary complications
@@ -1528,6 +2571,17 @@ mod tests {
         assert_eq!(tree.get_line_content(4), "");
     }
 
+    #[test]
+    fn line_before_a_cr_ending_a_piece_is_kept() {
+        // "one\r" ends its piece at the start of a line; the next piece decides the CR
+        let mut chunks = vec![
+            StringBuffer::new("zero\none\r".to_string()),
+            StringBuffer::new("two".to_string()),
+        ];
+        let tree = PieceTree::new(chunks.as_mut_slice());
+        assert_eq!(tree.get_lines_content(), vec!["zero", "one", "two"]);
+    }
+
     #[test]
     fn insert_into_empty_and_append() {
         let mut chunks: Vec<StringBuffer> = vec![];
@@ -1633,6 +2687,391 @@ mod tests {
         assert_eq!(tree.get_lines_content(), vec!["ab"]);
     }
 
+    #[test]
+    fn invariants_hold_across_edits() {
+        let mut chunks = vec![
+            StringBuffer::new("first chunk\n".to_string()),
+            StringBuffer::new("second\r\nchunk".to_string()),
+        ];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        assert_eq!(tree.verify_invariants(), Ok(()));
+
+        let mut state = 17usize;
+        for i in 0..200 {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345) % (1 << 31);
+            let offset = state % (tree.len() + 1);
+            if i % 3 == 2 && !tree.is_empty() {
+                let len = (state >> 8) % 4 + 1;
+                tree.delete(offset.min(tree.len() - 1), len);
+            } else {
+                tree.insert(offset, ["a", "line\n", "xy"][state % 3]);
+            }
+            assert_eq!(tree.verify_invariants(), Ok(()), "after edit {i}");
+        }
+
+        // A stale cached sum is reported
+        let root = tree.root.clone().unwrap();
+        root.borrow_mut().size_left += 1;
+        assert!(tree.verify_invariants().is_err());
+    }
+
+    // Lines of `text` split at LF, CRLF and lone CR, as the tree counts them
+    fn model_lines(text: &str) -> Vec<String> {
+        text.replace("\r\n", "\n")
+            .replace('\r', "\n")
+            .split('\n')
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn line_count_matches_the_lines_after_random_edits() {
+        for seed in 0..20usize {
+            let mut chunks = vec![
+                StringBuffer::new("one\r\ntwo\n".to_string()),
+                StringBuffer::new("\nthree\r".to_string()),
+            ];
+            let mut tree = PieceTree::new(chunks.as_mut_slice());
+            let mut expected = tree.get_text();
+
+            let mut state = seed;
+            for i in 0..300 {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345) % (1 << 31);
+                let offset = state % (expected.len() + 1);
+                if i % 2 == 1 && !expected.is_empty() {
+                    // Short deletes often take half of a CRLF or the final line break
+                    let offset = if state % 5 == 0 {
+                        expected.len() - 1
+                    } else {
+                        offset.min(expected.len() - 1)
+                    };
+                    let len = ((state >> 8) % 3 + 1).min(expected.len() - offset);
+                    tree.delete(offset, len);
+                    expected.replace_range(offset..offset + len, "");
+                } else {
+                    let text = ["\n", "\r", "\r\n", "ab", "c\n", "\rd"][(state >> 4) % 6];
+                    tree.insert(offset, text);
+                    expected.insert_str(offset, text);
+                }
+
+                let lines = tree.get_lines_content();
+                assert_eq!(tree.get_text(), expected, "seed {seed}, edit {i}");
+                assert_eq!(lines, model_lines(&expected), "seed {seed}, edit {i}");
+                assert_eq!(tree.line_count(), lines.len(), "seed {seed}, edit {i}");
+                assert_eq!(tree.len(), expected.len());
+                assert_eq!(tree.verify_invariants(), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn crlf_split_across_pieces_is_one_line_break() {
+        let mut chunks = vec![
+            StringBuffer::new("a\r".to_string()),
+            StringBuffer::new("\nb".to_string()),
+        ];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        assert_eq!(tree.line_count(), 2);
+        assert_eq!(tree.get_offset_at(2, 1), 3);
+
+        // Pulled apart, then brought together by deleting what was between them
+        tree.insert(2, "x");
+        assert_eq!(tree.get_lines_content(), vec!["a", "x", "b"]);
+        assert_eq!(tree.line_count(), 3);
+        tree.delete(2, 1);
+        assert_eq!(tree.get_text(), "a\r\nb");
+        assert_eq!(tree.line_count(), 2);
+        assert_eq!(tree.get_offset_at(2, 1), 3);
+
+        // Typed right after a CR
+        tree.insert(2, "\r");
+        tree.insert(3, "\n");
+        assert_eq!(tree.get_text(), "a\r\r\n\nb");
+        assert_eq!(tree.line_count(), 4);
+        tree.delete(1, 4);
+        assert_eq!(tree.get_text(), "ab");
+        assert_eq!(tree.line_count(), 1);
+        assert_eq!(tree.verify_invariants(), Ok(()));
+
+        // An empty chunk between the CR and the LF
+        let mut chunks = vec![
+            StringBuffer::new("a\r".to_string()),
+            StringBuffer::new(String::new()),
+            StringBuffer::new("\nb".to_string()),
+        ];
+        let tree = PieceTree::new(chunks.as_mut_slice());
+        assert_eq!(tree.get_text(), "a\r\nb");
+        assert_eq!(tree.line_count(), 2);
+        assert_eq!(tree.get_lines_content(), vec!["a", "b"]);
+        assert_eq!(tree.get_offset_at(2, 1), 3);
+
+        let mut chunks = vec![
+            StringBuffer::new("a\r".to_string()),
+            StringBuffer::new(String::new()),
+            StringBuffer::new("x\nb".to_string()),
+        ];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        tree.delete(2, 1);
+        assert_eq!(tree.get_text(), "a\r\nb");
+        assert_eq!(tree.line_count(), 2);
+        assert_eq!(tree.get_offset_at(2, 1), 3);
+
+        // An empty piece left in the tree between them is stepped over
+        let mut chunks = vec![
+            StringBuffer::new("a\r".to_string()),
+            StringBuffer::new("y".to_string()),
+            StringBuffer::new("x\nb".to_string()),
+        ];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        let first = tree.leftmost(tree.root.clone().unwrap());
+        let node = tree.next(&first).unwrap();
+        let start = node.borrow().piece.start;
+        node.borrow_mut().piece = tree.piece_from_range(2, start, start);
+        tree.recompute_tree_metadata(node);
+        tree.compute_buffer_metadata();
+        assert_eq!(tree.get_text(), "a\rx\nb");
+        tree.delete(2, 1);
+        assert_eq!(tree.get_text(), "a\r\nb");
+        assert_eq!(tree.line_count(), 2);
+        assert_eq!(tree.verify_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn edits_at_an_empty_piece_use_its_neighbors() {
+        let mut chunks = vec![
+            StringBuffer::new("one ".to_string()),
+            StringBuffer::new("two ".to_string()),
+            StringBuffer::new("three".to_string()),
+        ];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+
+        // Deleting a whole interior piece unlinks it; inserting at the seam still lands there
+        tree.delete(4, 4);
+        assert_eq!(tree.node_count(), 2);
+        tree.insert(4, "2 ");
+        assert_eq!(tree.get_text(), "one 2 three");
+        assert_eq!(tree.verify_invariants(), Ok(()));
+
+        // An empty piece left in the tree is stepped over
+        let (node, _, _) = tree.node_at(4).unwrap();
+        let buf = node.borrow().piece.buffer_idx;
+        let start = node.borrow().piece.start;
+        node.borrow_mut().piece = tree.piece_from_range(buf, start, start);
+        tree.recompute_tree_metadata(node.clone());
+        tree.compute_buffer_metadata();
+        assert_eq!(tree.get_text(), "one three");
+        let (found, remainder, start) = tree.node_at(4).unwrap();
+        assert!(found.borrow().piece.length > 0);
+        assert_eq!((remainder, start), (0, 4));
+
+        tree.insert(4, "2 ");
+        tree.delete(4, 2);
+        tree.insert(4, "two ");
+        assert_eq!(tree.get_text(), "one two three");
+        assert_eq!(tree.get_offset_at(1, 9), 8);
+        assert_eq!(tree.get_position_at(8).column, 9);
+        assert_eq!(tree.verify_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn typing_merges_adjacent_pieces() {
+        let mut chunks = vec![StringBuffer::new("fn main() {\n}\n".to_string())];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        let mut expected = tree.get_text();
+
+        let mut caret = 12;
+        for i in 0..1000 {
+            let ch = ["a", "b", "\n", " ", "é"][i % 5];
+            tree.insert(caret, ch);
+            expected.insert_str(caret, ch);
+            caret += ch.len();
+        }
+        assert_eq!(tree.get_text(), expected);
+        assert_eq!(tree.verify_invariants(), Ok(()));
+        // The original text split around the typed run, which is a single piece
+        assert_eq!(tree.node_count(), 3);
+    }
+
+    #[test]
+    fn typing_at_the_caret_keeps_the_piece_count_flat() {
+        // The document and keystrokes of the `typing` bench
+        let type_into = |scattered: bool| {
+            let initial = "lorem ipsum dolor sit amet\n".repeat(100);
+            let mut tree = PieceTree::new(&mut [StringBuffer::new(initial)]);
+            let mut caret = tree.len() / 2;
+            for i in 0..1_000 {
+                if scattered {
+                    caret = (i * 7919) % (tree.len() + 1);
+                }
+                tree.insert(caret, "x");
+                caret += 1;
+            }
+            tree.node_count()
+        };
+
+        // The original text split around one growing piece, however long the run
+        assert_eq!(type_into(false), 3);
+        // Without a caret to follow, every keystroke adds pieces
+        assert!(type_into(true) > 1_000);
+    }
+
+    #[test]
+    fn typing_grows_the_change_piece_in_place() {
+        let mut chunks = vec![StringBuffer::new("abc\r\n".to_string())];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        tree.insert(5, "x");
+        let nodes = tree.node_count();
+
+        let mut expected = tree.get_text();
+        for ch in ["y", "€", "\r", "z", "ß", "\r\n", "😀"] {
+            tree.insert(tree.len(), ch);
+            expected.push_str(ch);
+            assert_eq!(tree.node_count(), nodes, "after {ch:?}");
+            assert_eq!(tree.get_text(), expected);
+            assert_eq!(tree.verify_invariants(), Ok(()));
+        }
+        assert_eq!(tree.get_lines_content(), vec!["abc", "xy€", "zß", "😀"]);
+
+        // An LF typed after a CR takes the general path, starting a new piece
+        tree.insert(tree.len(), "\r");
+        tree.insert(tree.len(), "\n");
+        assert_eq!(tree.node_count(), nodes + 1);
+        assert_eq!(tree.get_lines_content(), vec!["abc", "xy€", "zß", "😀", ""]);
+        assert_eq!(tree.verify_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn rb_delete_keeps_the_tree_balanced() {
+        let mut chunks: Vec<StringBuffer> = vec![];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        // Inserting at the front never lands next to the previous piece, so nothing merges
+        for i in 0..300 {
+            tree.insert(0, &format!("{}\n", i % 10));
+        }
+        assert_eq!(tree.node_count(), 300);
+
+        let mut state = 7usize;
+        while tree.root.is_some() {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345) % (1 << 31);
+            let (node, _, start) = tree.node_at(state % tree.len()).unwrap();
+            let piece_len = node.borrow().piece.length;
+            let mut expected = tree.get_text();
+            expected.replace_range(start..start + piece_len, "");
+
+            tree.rb_delete(node);
+            tree.compute_buffer_metadata();
+            assert_eq!(tree.get_text(), expected);
+            assert_eq!(tree.verify_invariants(), Ok(()));
+            if tree.is_empty() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn find_all_spans_piece_boundaries() {
+        let mut chunks = vec![StringBuffer::new("needle in a hay".to_string())];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        tree.insert(tree.len(), "st");
+        tree.insert(tree.len(), "ack, ne");
+        tree.insert(tree.len(), "e");
+        tree.insert(tree.len(), "dle");
+        tree.insert(0, "x");
+        tree.delete(0, 1);
+        // The typed run is one piece, and the deleted one leaves no empty node behind
+        assert_eq!(tree.node_count(), 2);
+        assert_eq!(tree.get_text(), "needle in a haystack, needle");
+
+        assert_eq!(tree.find_all("needle"), vec![0, 22]);
+        assert_eq!(tree.find_all("haystack"), vec![12]);
+        assert_eq!(tree.find_all("e"), vec![1, 2, 5, 23, 24, 27]);
+        assert_eq!(tree.find_all("le"), vec![4, 26]);
+        assert_eq!(tree.find_all("hay needle"), Vec::<usize>::new());
+        assert_eq!(tree.find_all(""), Vec::<usize>::new());
+
+        // Non-overlapping, left to right
+        let mut chunks = vec![StringBuffer::new("aa".to_string())];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        tree.insert(2, "a");
+        tree.insert(3, "a");
+        assert_eq!(tree.find_all("aa"), vec![0, 2]);
+        assert_eq!(tree.find_all("aaa"), vec![0]);
+    }
+
+    #[test]
+    fn find_all_opts_case_and_whole_words() {
+        let mut chunks = vec![StringBuffer::new("Cat, concatenate the CAT".to_string())];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        tree.insert(tree.len(), "_cat cat");
+        tree.insert(tree.len(), "s cAt");
+        assert_eq!(tree.get_text(), "Cat, concatenate the CAT_cat cats cAt");
+
+        let opts = |case_insensitive, whole_word| SearchOptions {
+            case_insensitive,
+            whole_word,
+        };
+        assert_eq!(tree.find_all("cat"), vec![8, 25, 29]);
+        assert_eq!(
+            tree.find_all_opts("cat", opts(true, false)),
+            vec![0, 8, 21, 25, 29, 34]
+        );
+        // Inside "concatenate", glued by `_` or followed by "s" is not a whole word
+        assert_eq!(
+            tree.find_all_opts("cat", opts(false, true)),
+            Vec::<usize>::new()
+        );
+        assert_eq!(tree.find_all_opts("cat", opts(true, true)), vec![0, 34]);
+        assert_eq!(tree.find_all_opts("CAT_CAT", opts(true, true)), vec![21]);
+        assert_eq!(tree.find_all_opts("cats", opts(false, true)), vec![29]);
+        // Non-ASCII letters only match exactly
+        let mut chunks = vec![StringBuffer::new("Äpfel äpfel".to_string())];
+        let tree = PieceTree::new(chunks.as_mut_slice());
+        assert_eq!(tree.find_all_opts("äpfel", opts(true, false)), vec![7]);
+        // Word characters are ASCII-only, so a non-ASCII letter counts as a boundary
+        assert_eq!(tree.find_all_opts("PFEL", opts(true, true)), vec![2, 9]);
+    }
+
+    #[test]
+    fn lines_iterator_matches_get_lines_content() {
+        let cases: [&[&str]; 5] = [
+            &["Hello\nWorld"],
+            &["abc\r\ndef\r\n", "xyz"],
+            &["one\ntwo\n"],
+            &["split\r", "\nacross\r", "lone cr\n"],
+            &[""],
+        ];
+        for chunks in cases {
+            let mut chunks: Vec<StringBuffer> = chunks
+                .iter()
+                .map(|c| StringBuffer::new(c.to_string()))
+                .collect();
+            let mut tree = PieceTree::new(chunks.as_mut_slice());
+            let expected = tree.get_lines_content();
+            assert_eq!(tree.lines().collect::<Vec<_>>(), expected);
+
+            tree.insert(tree.len() / 2, "mid\nd\r\nle");
+            tree.insert(tree.len(), "\n");
+            let expected = tree.get_lines_content();
+            assert_eq!(tree.lines().collect::<Vec<_>>(), expected);
+            assert_eq!(tree.line_range(1, expected.len() + 1), expected);
+        }
+    }
+
+    #[test]
+    fn line_range_skips_to_the_first_line() {
+        let text: String = (1..=50).map(|i| format!("line {i}\r\n")).collect();
+        let mut chunks = vec![StringBuffer::new(text)];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        tree.insert(tree.get_offset_at(20, 1), "new\n");
+        tree.insert(tree.get_offset_at(40, 1), "x");
+
+        assert_eq!(tree.line_range(19, 22), vec!["line 19", "new", "line 20"]);
+        assert_eq!(tree.line_range(40, 41), vec!["xline 39"]);
+        assert_eq!(tree.line_range(50, 100), vec!["line 49", "line 50", ""]);
+        assert_eq!(tree.line_range(60, 70), Vec::<String>::new());
+        assert_eq!(tree.line_range(3, 3), Vec::<String>::new());
+    }
+
     #[test]
     fn get_text_and_line_length() {
         let mut chunks: Vec<StringBuffer> = vec![];
@@ -1644,6 +3083,99 @@ mod tests {
         assert_eq!(tree.get_line_length(3), 0);
     }
 
+    #[test]
+    fn write_to_streams_the_pieces_in_order() {
+        let mut chunks = vec![StringBuffer::new("first\r\nsecond\n".to_string())];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        tree.insert(5, " line");
+        tree.insert(0, "» ");
+        tree.delete(tree.len() - 3, 2);
+
+        let mut out = Vec::new();
+        tree.write_to(&mut out).unwrap();
+        assert_eq!(out, tree.get_text().into_bytes());
+
+        let mut empty = Vec::new();
+        PieceTree::new(&mut []).write_to(&mut empty).unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn byte_and_char_at_read_across_pieces() {
+        let mut chunks = vec![StringBuffer::new("(aé)\n".to_string())];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        tree.insert(0, "日");
+        tree.insert(tree.len(), "🦀]");
+        tree.insert(4, "[x");
+        let text = tree.get_text();
+        assert_eq!(text, "日([xaé)\n🦀]");
+
+        for (offset, c) in text.char_indices() {
+            assert_eq!(tree.char_at(offset), Some(c), "offset {offset}");
+        }
+        for (offset, &byte) in text.as_bytes().iter().enumerate() {
+            assert_eq!(tree.byte_at(offset), Some(byte));
+        }
+        assert_eq!(tree.char_at(1), None);
+        assert_eq!(tree.byte_at(text.len()), None);
+        assert_eq!(tree.char_at(text.len()), None);
+
+        // An insert inside "é" and a delete of it leave the char's two bytes in two pieces
+        let e = text.find('é').unwrap();
+        tree.insert(e + 1, "-");
+        tree.delete(e + 1, 1);
+        assert_eq!(tree.byte_at(e), Some(0xC3));
+        assert_eq!(tree.byte_at(e + 1), Some(0xA9));
+        assert_eq!(tree.char_at(e), Some('é'));
+        assert_eq!(tree.char_at(e + 2), Some(')'));
+
+        assert_eq!(PieceTree::new(&mut []).byte_at(0), None);
+        assert_eq!(PieceTree::new(&mut []).char_at(0), None);
+    }
+
+    #[test]
+    fn find_byte_walks_the_pieces_either_way() {
+        let mut chunks = vec![StringBuffer::new("ab|cd".to_string())];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        tree.insert(5, "ef|");
+        tree.insert(0, "|x");
+        assert_eq!(tree.get_text(), "|xab|cdef|");
+        assert!(tree.node_count() >= 3);
+
+        let bar = |byte: u8| byte == b'|';
+        assert_eq!(tree.find_byte(0, false, 100, bar), Some(0));
+        assert_eq!(tree.find_byte(1, false, 100, bar), Some(4));
+        assert_eq!(tree.find_byte(5, false, 100, bar), Some(9));
+        assert_eq!(tree.find_byte(8, true, 100, bar), Some(4));
+        assert_eq!(tree.find_byte(3, true, 100, bar), Some(0));
+        assert_eq!(tree.find_byte(9, true, 100, bar), Some(9));
+
+        // Out of range, or too far
+        assert_eq!(tree.find_byte(10, false, 100, bar), None);
+        assert_eq!(tree.find_byte(5, false, 4, bar), None);
+        assert_eq!(tree.find_byte(5, false, 5, bar), Some(9));
+        assert_eq!(tree.find_byte(3, true, 3, bar), None);
+    }
+
+    #[test]
+    fn content_hash_ignores_piece_layout() {
+        let mut chunks = vec![StringBuffer::new("hello world".to_string())];
+        let whole = PieceTree::new(chunks.as_mut_slice());
+
+        let mut chunks: Vec<StringBuffer> = vec![];
+        let mut pieced = PieceTree::new(chunks.as_mut_slice());
+        pieced.insert(0, "world");
+        pieced.insert(0, "hello ");
+        pieced.insert(3, "XY");
+        pieced.delete(3, 2);
+
+        assert_eq!(pieced.get_text(), whole.get_text());
+        assert_eq!(pieced.content_hash(), whole.content_hash());
+
+        pieced.insert(0, "!");
+        assert_ne!(pieced.content_hash(), whole.content_hash());
+    }
+
     #[test]
     fn offset_and_position_roundtrip() {
         let mut chunks: Vec<StringBuffer> = vec![];
@@ -1673,6 +3205,159 @@ mod tests {
         assert_eq!((p.line, p.column), (3, 4));
     }
 
+    // Every (line, column) of `tree` maps to an offset and back
+    fn assert_positions_round_trip(tree: &PieceTree) {
+        for (i, line) in tree.get_lines_content().iter().enumerate() {
+            for column in 1..=line.len() + 1 {
+                let offset = tree.get_offset_at(i + 1, column);
+                let p = tree.get_position_at(offset);
+                assert_eq!(
+                    (p.line, p.column),
+                    (i + 1, column),
+                    "offset {offset} of {:?}",
+                    tree.get_text()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn positions_round_trip_in_crlf_documents_built_by_edits() {
+        // A CRLF typed as two edits
+        let mut chunks: Vec<StringBuffer> = vec![];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        tree.insert(0, "a\r");
+        tree.insert(2, "\nb");
+        assert_eq!(tree.get_offset_at(2, 1), 3);
+        let p = tree.get_position_at(3);
+        assert_eq!((p.line, p.column), (2, 1));
+        let p = tree.get_position_at(4);
+        assert_eq!((p.line, p.column), (2, 2));
+        assert_positions_round_trip(&tree);
+
+        for seed in 0..20usize {
+            let mut chunks = vec![StringBuffer::new("first\r".to_string())];
+            let mut tree = PieceTree::new(chunks.as_mut_slice());
+            let mut state = seed;
+            for _ in 0..60 {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345) % (1 << 31);
+                // Edits take whole chars
+                let text = tree.get_text();
+                let boundary = |from: usize| {
+                    (from..=text.len())
+                        .find(|&i| text.is_char_boundary(i))
+                        .unwrap()
+                };
+                let offset = boundary(state % (text.len() + 1));
+                if (state >> 8) % 4 == 0 && offset < text.len() {
+                    tree.delete(offset, boundary(offset + 1) - offset);
+                } else {
+                    tree.insert(
+                        offset,
+                        ["\r", "\n", "\r\n", "ab", "é\r\n"][(state >> 4) % 5],
+                    );
+                }
+                assert_positions_round_trip(&tree);
+            }
+        }
+    }
+
+    #[test]
+    fn line_start_and_line_of_byte() {
+        let buffer = StringBuffer::new("ab\r\ncd\nef".to_string());
+        assert_eq!(buffer.line_start(0), Some(0));
+        assert_eq!(buffer.line_start(1), Some(4));
+        assert_eq!(buffer.line_start(2), Some(7));
+        assert_eq!(buffer.line_start(3), None);
+
+        // At line starts
+        assert_eq!(buffer.line_of_byte(0), 0);
+        assert_eq!(buffer.line_of_byte(4), 1);
+        assert_eq!(buffer.line_of_byte(7), 2);
+        // Between line starts, including the CR and LF of a CRLF
+        assert_eq!(buffer.line_of_byte(2), 0);
+        assert_eq!(buffer.line_of_byte(3), 0);
+        assert_eq!(buffer.line_of_byte(6), 1);
+        // Past the end
+        assert_eq!(buffer.line_of_byte(100), 2);
+    }
+
+    #[test]
+    fn append_extends_line_starts_incrementally() {
+        let appends = [
+            "abc", "\n", "de\r", "\nf", "\r", "\r\n", "g\n\nh", "\r", "x",
+        ];
+        let mut buf = StringBuffer::new(String::new());
+        let mut full = String::new();
+        for text in appends {
+            buf.append_and_extend_line_starts(text);
+            full.push_str(text);
+            assert_eq!(buf.buffer, full);
+            assert_eq!(buf.line_starts, StringBuffer::create_line_starts(&full));
+        }
+    }
+
+    #[test]
+    fn append_joins_crlf_split_across_appends() {
+        let mut buf = StringBuffer::new("a\r".to_string());
+        assert_eq!(buf.line_starts, vec![0, 2]);
+        buf.append_and_extend_line_starts("\nb");
+        assert_eq!(buf.line_starts, vec![0, 3]);
+        assert_eq!(buf.line_starts, StringBuffer::create_line_starts("a\r\nb"));
+    }
+
+    #[test]
+    fn small_inserts_share_change_buffer() {
+        let mut chunks: Vec<StringBuffer> = vec![];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+
+        tree.insert(0, "one\r");
+        // The change buffer now ends with '\r'; a leading '\n' must not join it
+        tree.insert(0, "\nzero");
+        tree.insert(tree.len(), "two\r\n");
+        tree.insert(tree.len(), "three");
+
+        assert_eq!(tree.buffers.len(), 1);
+        let change = &tree.buffers[0];
+        assert_eq!(
+            change.line_starts,
+            StringBuffer::create_line_starts(&change.buffer)
+        );
+        assert_eq!(tree.get_text(), "\nzeroone\rtwo\r\nthree");
+        assert_eq!(
+            tree.get_lines_content(),
+            vec!["", "zeroone", "two", "three"]
+        );
+        assert_eq!(tree.line_count(), 4);
+    }
+
+    #[test]
+    fn with_capacity_reserves_change_buffer() {
+        let mut chunks: Vec<StringBuffer> = vec![];
+        let mut tree = PieceTree::with_capacity(chunks.as_mut_slice(), 16 * 1024);
+        let reserved = tree.buffers[0].buffer.capacity();
+        let start = tree.buffers[0].buffer.as_ptr();
+        assert!(reserved >= 16 * 1024);
+
+        let mut expected = String::new();
+        for i in 0..1024 {
+            let line = format!("{i}\n");
+            tree.insert(tree.len(), &line);
+            expected.push_str(&line);
+        }
+        assert!(expected.len() <= reserved);
+        // Typing within the hint never moved the change buffer
+        assert_eq!(tree.buffers[0].buffer.as_ptr(), start);
+        assert_eq!(tree.buffers[0].buffer.capacity(), reserved);
+        assert_eq!(tree.get_text(), expected);
+
+        // Chunks already cover the hint, so nothing extra is reserved
+        let mut chunks = vec![StringBuffer::new("a".repeat(1024))];
+        let tree = PieceTree::with_capacity(chunks.as_mut_slice(), 1024);
+        assert!(tree.buffers.capacity() >= 2);
+        assert_eq!(tree.buffers[0].buffer.capacity(), 0);
+    }
+
     #[test]
     fn utf8_safe_split_and_crlf_boundary() {
         // Pattern: multi-byte chars + CRLF
@@ -1733,4 +3418,27 @@ mod tests {
         // Verify the last (trailing) line is empty.
         assert_eq!(tree.get_line_length(repeats + 1), 0);
     }
+
+    #[test]
+    fn clone_is_independent_and_shares_the_text() {
+        let mut chunks = vec![StringBuffer::new("first\nsecond\n".to_string())];
+        let mut tree = PieceTree::new(chunks.as_mut_slice());
+        tree.insert(6, "inserted ");
+        let mut copy = tree.clone();
+        assert!(Rc::ptr_eq(&tree.buffers[1], &copy.buffers[1]));
+        assert!(Rc::ptr_eq(&tree.buffers[0], &copy.buffers[0]));
+
+        tree.insert(0, "> ");
+        tree.delete(tree.len() - 3, 3);
+        copy.insert(copy.len(), "third");
+        assert_eq!(tree.get_text(), "> first\ninserted seco");
+        assert_eq!(copy.get_text(), "first\ninserted second\nthird");
+        assert_eq!((tree.line_count(), copy.line_count()), (2, 3));
+        tree.verify_invariants().unwrap();
+        copy.verify_invariants().unwrap();
+
+        // Appending to the change buffer copied it rather than writing into the shared one
+        assert!(!Rc::ptr_eq(&tree.buffers[0], &copy.buffers[0]));
+        assert!(Rc::ptr_eq(&tree.buffers[1], &copy.buffers[1]));
+    }
 }
@@ -1,6 +1,9 @@
+mod session;
+
 use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
 use piece_tree::{PieceTree, StringBuffer};
 use rope::Rope;
+use session::{TextStore, record_session, replay};
 use std::hint::black_box;
 
 fn bench_creation(c: &mut Criterion) {
@@ -404,12 +407,113 @@ fn bench_serialize(c: &mut Criterion) {
     group.finish();
 }
 
+// Typing one character at a time, at the caret (sequential) or at spread out positions
+fn bench_typing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("typing");
+
+    let type_into = |tree: &mut PieceTree, count: usize, scattered: bool| {
+        let mut caret = tree.len() / 2;
+        for i in 0..count {
+            if scattered {
+                caret = (i * 7919) % (tree.len() + 1);
+            }
+            tree.insert(caret, "x");
+            caret += 1;
+        }
+    };
+    let initial = "lorem ipsum dolor sit amet\n".repeat(100);
+
+    for count in [100, 1_000].iter() {
+        for (name, scattered) in [("sequential", false), ("scattered", true)] {
+            group.throughput(Throughput::Elements(*count as u64));
+            group.bench_with_input(BenchmarkId::new(name, count), count, |b, &count| {
+                b.iter_batched(
+                    || PieceTree::new(&mut [StringBuffer::new(initial.clone())]),
+                    |mut tree| {
+                        type_into(&mut tree, count, scattered);
+                        black_box(tree);
+                    },
+                    criterion::BatchSize::SmallInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+// Fetching a screenful of lines from the middle of a document, against fetching every line
+fn bench_viewport_lines(c: &mut Criterion) {
+    let mut group = c.benchmark_group("viewport_lines");
+    let visible = 50;
+
+    for lines in [10_000, 100_000].iter() {
+        let text: String = (0..*lines).map(|i| format!("line number {i}\n")).collect();
+        let mut tree = PieceTree::new(&mut [StringBuffer::new(text)]);
+        // Scattered edits so the lines span several pieces
+        for i in 0..100 {
+            tree.insert((i * 7919) % tree.len(), "x");
+        }
+        let first = lines / 2;
+
+        group.throughput(Throughput::Elements(visible as u64));
+        group.bench_with_input(BenchmarkId::new("line_range", lines), &tree, |b, tree| {
+            b.iter(|| black_box(tree.line_range(black_box(first), first + visible)))
+        });
+        group.bench_with_input(BenchmarkId::new("all_lines", lines), &tree, |b, tree| {
+            b.iter(|| {
+                let all = tree.get_lines_content();
+                black_box(all[first - 1..first - 1 + visible].to_vec())
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_session<S: TextStore>(
+    group: &mut criterion::BenchmarkGroup<criterion::measurement::WallTime>,
+    name: &str,
+    lines: usize,
+    initial: &str,
+    keys: &[session::Keystroke],
+) {
+    group.bench_with_input(BenchmarkId::new(name, lines), &lines, |b, _| {
+        b.iter_batched(
+            || S::from_text(initial),
+            |mut store| {
+                let drawn = replay(&mut store, black_box(keys));
+                black_box((store, drawn));
+            },
+            criterion::BatchSize::SmallInput,
+        )
+    });
+}
+
+fn bench_editor_session(c: &mut Criterion) {
+    let mut group = c.benchmark_group("editor_session");
+    group.sample_size(20);
+
+    let keys = record_session(500, 42);
+    for lines in [100, 1_000].iter() {
+        let initial = "lorem ipsum dolor sit amet, consectetur adipiscing\n".repeat(*lines);
+
+        group.throughput(Throughput::Elements(keys.len() as u64));
+        bench_session::<Rope>(&mut group, "rope", *lines, &initial, &keys);
+        bench_session::<ropey::Rope>(&mut group, "ropey", *lines, &initial, &keys);
+        bench_session::<String>(&mut group, "string", *lines, &initial, &keys);
+        bench_session::<PieceTree>(&mut group, "piece_tree", *lines, &initial, &keys);
+    }
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_creation,
     bench_insert_operations,
     bench_delete_operations,
     bench_slice_operations,
-    bench_serialize
+    bench_serialize,
+    bench_typing,
+    bench_viewport_lines,
+    bench_editor_session
 );
 criterion_main!(benches);
@@ -0,0 +1,266 @@
+// Editor-session replay shared by the benchmarks and the smoke test in tests/.
+// Replays keystrokes the way the GUI drives its buffer: every edit resolves the caret's
+// (line, column) to an offset, and every keystroke is followed by reading all lines to draw.
+
+use piece_tree::{PieceTree, StringBuffer};
+use rope::Rope;
+
+// The operations the editor needs from a text backend. Lines and columns are 0-based,
+// columns and offsets are in bytes.
+pub trait TextStore {
+    fn from_text(text: &str) -> Self;
+    fn insert(&mut self, offset: usize, text: &str);
+    fn delete(&mut self, offset: usize, len: usize);
+    fn offset_at(&self, line: usize, column: usize) -> usize;
+    fn line(&self, line: usize) -> String;
+    fn line_count(&self) -> usize;
+    fn lines(&self) -> Vec<String>;
+    // Only the smoke test compares final text
+    #[allow(dead_code)]
+    fn text(&self) -> String;
+}
+
+impl TextStore for Rope {
+    fn from_text(text: &str) -> Self {
+        Rope::from(text)
+    }
+
+    fn insert(&mut self, offset: usize, text: &str) {
+        Rope::insert(self, offset, text);
+    }
+
+    fn delete(&mut self, offset: usize, len: usize) {
+        Rope::delete(self, offset..offset + len);
+    }
+
+    // No line index yet: scan for the line start
+    fn offset_at(&self, line: usize, column: usize) -> usize {
+        if line == 0 {
+            return column;
+        }
+        let mut seen = 0;
+        for (i, b) in self.chunks().flat_map(str::bytes).enumerate() {
+            if b == b'\n' {
+                seen += 1;
+                if seen == line {
+                    return i + 1 + column;
+                }
+            }
+        }
+        self.len()
+    }
+
+    fn line(&self, line: usize) -> String {
+        self.lines().nth(line).unwrap_or_default()
+    }
+
+    fn line_count(&self) -> usize {
+        self.new_lines() + 1
+    }
+
+    fn lines(&self) -> Vec<String> {
+        Rope::lines(self).collect()
+    }
+
+    fn text(&self) -> String {
+        self.collect_leaves()
+    }
+}
+
+impl TextStore for ropey::Rope {
+    fn from_text(text: &str) -> Self {
+        ropey::Rope::from_str(text)
+    }
+
+    fn insert(&mut self, offset: usize, text: &str) {
+        let char_idx = self.byte_to_char(offset);
+        ropey::Rope::insert(self, char_idx, text);
+    }
+
+    fn delete(&mut self, offset: usize, len: usize) {
+        let start = self.byte_to_char(offset);
+        let end = self.byte_to_char(offset + len);
+        self.remove(start..end);
+    }
+
+    fn offset_at(&self, line: usize, column: usize) -> usize {
+        self.line_to_byte(line) + column
+    }
+
+    fn line(&self, line: usize) -> String {
+        let mut line = ropey::Rope::line(self, line).to_string();
+        if line.ends_with('\n') {
+            line.pop();
+        }
+        line
+    }
+
+    fn line_count(&self) -> usize {
+        self.len_lines()
+    }
+
+    fn lines(&self) -> Vec<String> {
+        (0..self.len_lines())
+            .map(|i| TextStore::line(self, i))
+            .collect()
+    }
+
+    fn text(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl TextStore for PieceTree {
+    fn from_text(text: &str) -> Self {
+        let mut chunks = vec![StringBuffer::new(text.to_string())];
+        PieceTree::new(chunks.as_mut_slice())
+    }
+
+    fn insert(&mut self, offset: usize, text: &str) {
+        PieceTree::insert(self, offset, text);
+    }
+
+    fn delete(&mut self, offset: usize, len: usize) {
+        PieceTree::delete(self, offset, len);
+    }
+
+    fn offset_at(&self, line: usize, column: usize) -> usize {
+        self.get_offset_at(line + 1, column + 1)
+    }
+
+    fn line(&self, line: usize) -> String {
+        self.get_line_content(line + 1)
+    }
+
+    fn line_count(&self) -> usize {
+        PieceTree::line_count(self)
+    }
+
+    fn lines(&self) -> Vec<String> {
+        self.get_lines_content()
+    }
+
+    fn text(&self) -> String {
+        self.get_text()
+    }
+}
+
+impl TextStore for String {
+    fn from_text(text: &str) -> Self {
+        text.to_string()
+    }
+
+    fn insert(&mut self, offset: usize, text: &str) {
+        self.insert_str(offset, text);
+    }
+
+    fn delete(&mut self, offset: usize, len: usize) {
+        self.replace_range(offset..offset + len, "");
+    }
+
+    fn offset_at(&self, line: usize, column: usize) -> usize {
+        let start = match line {
+            0 => 0,
+            _ => self
+                .match_indices('\n')
+                .nth(line - 1)
+                .map_or(self.len(), |(i, _)| i + 1),
+        };
+        start + column
+    }
+
+    fn line(&self, line: usize) -> String {
+        self.split('\n').nth(line).unwrap_or_default().to_string()
+    }
+
+    fn line_count(&self) -> usize {
+        self.matches('\n').count() + 1
+    }
+
+    fn lines(&self) -> Vec<String> {
+        self.split('\n').map(str::to_string).collect()
+    }
+
+    fn text(&self) -> String {
+        self.clone()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Keystroke {
+    Type(char),
+    Enter,
+    Backspace,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+// Deterministic keystroke mix: mostly typing, with newlines, corrections and caret moves
+pub fn record_session(len: usize, seed: u64) -> Vec<Keystroke> {
+    let mut state = seed;
+    let mut next = move || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 33) as usize
+    };
+    (0..len)
+        .map(|_| match next() % 100 {
+            0..=69 => Keystroke::Type((b'a' + (next() % 26) as u8) as char),
+            70..=79 => Keystroke::Type(' '),
+            80..=84 => Keystroke::Enter,
+            85..=89 => Keystroke::Backspace,
+            90..=92 => Keystroke::Up,
+            93..=95 => Keystroke::Down,
+            96..=97 => Keystroke::Left,
+            _ => Keystroke::Right,
+        })
+        .collect()
+}
+
+// Replay `keys` with the caret starting at the top; returns the total bytes of lines read
+// for drawing, so the reads can't be optimized away.
+pub fn replay<S: TextStore>(store: &mut S, keys: &[Keystroke]) -> usize {
+    let (mut line, mut col) = (0usize, 0usize);
+    let mut drawn = 0;
+    for key in keys {
+        let line_len = store.line(line).len();
+        col = col.min(line_len);
+        match *key {
+            Keystroke::Type(c) => {
+                let offset = store.offset_at(line, col);
+                store.insert(offset, c.encode_utf8(&mut [0; 4]));
+                col += 1;
+            }
+            Keystroke::Enter => {
+                let offset = store.offset_at(line, col);
+                store.insert(offset, "\n");
+                line += 1;
+                col = 0;
+            }
+            Keystroke::Backspace => {
+                if col > 0 {
+                    store.delete(store.offset_at(line, col) - 1, 1);
+                    col -= 1;
+                } else if line > 0 {
+                    let prev_len = store.line(line - 1).len();
+                    store.delete(store.offset_at(line, 0) - 1, 1);
+                    line -= 1;
+                    col = prev_len;
+                }
+            }
+            Keystroke::Up => line = line.saturating_sub(1),
+            Keystroke::Down => {
+                if line + 1 < store.line_count() {
+                    line += 1;
+                }
+            }
+            Keystroke::Left => col = col.saturating_sub(1),
+            Keystroke::Right => col = (col + 1).min(line_len),
+        }
+        drawn += store.lines().iter().map(String::len).sum::<usize>();
+    }
+    drawn
+}
@@ -92,6 +92,45 @@ impl Node {
         }
     }
 
+    // join two trees by hanging the shorter one off the facing edge of the taller one, at the level
+    // where the heights match, so every leaf stays at the same depth
+    pub fn concat(left: &Rc<Node>, right: &Rc<Node>) -> Rc<Node> {
+        if left.len() == 0 {
+            return Rc::clone(right);
+        }
+        if right.len() == 0 {
+            return Rc::clone(left);
+        }
+        let nodes = if left.height() >= right.height() {
+            Self::append_lower(left, right)
+        } else {
+            Self::prepend_lower(left, right)
+        };
+        Self::create_root(&nodes)
+    }
+
+    // `left` followed by the no taller `right`, as nodes as tall as `left`
+    fn append_lower(left: &Rc<Node>, right: &Rc<Node>) -> Vec<Rc<Node>> {
+        if left.height() == right.height() {
+            return vec![Rc::clone(left), Rc::clone(right)];
+        }
+        let (last, rest) = left.children().split_last().unwrap();
+        let mut children = rest.to_vec();
+        children.extend(Self::append_lower(last, right));
+        Self::create_parent_branches(&children)
+    }
+
+    // the shorter `left` followed by `right`, as nodes as tall as `right`
+    fn prepend_lower(left: &Rc<Node>, right: &Rc<Node>) -> Vec<Rc<Node>> {
+        if left.height() == right.height() {
+            return vec![Rc::clone(left), Rc::clone(right)];
+        }
+        let (first, rest) = right.children().split_first().unwrap();
+        let mut children = Self::prepend_lower(left, first);
+        children.extend_from_slice(rest);
+        Self::create_parent_branches(&children)
+    }
+
     // create parent branch(es) for node(s)
     pub fn create_parent_branches(children: &[Rc<Node>]) -> Vec<Rc<Node>> {
         if children.is_empty() {
@@ -105,6 +144,7 @@ impl Node {
         for chunk in children.chunks(parent_capacity) {
             let branch_children = chunk.to_vec();
             let mut keys: Vec<usize> = Vec::new();
+            let mut line_keys: Vec<usize> = Vec::new();
             let mut length: usize = 0;
             let mut new_lines: usize = 0;
 
@@ -112,6 +152,7 @@ impl Node {
                 length += child.len();
                 keys.push(length);
                 new_lines += child.new_lines();
+                line_keys.push(new_lines);
             }
 
             if let Some(last_child) = chunk.last() {
@@ -124,6 +165,7 @@ impl Node {
                 children: branch_children,
                 height: children.first().unwrap().height() + 1,
                 keys,
+                line_keys,
                 length,
             })))
         }
@@ -144,9 +186,13 @@ impl Node {
 
     // remove nodes that are not necessary for the tree to have all of its data by traversing to the left
     // currently just used after deletion when it leaves a series of nodes from root to a certain nodes that each have a single child
+    // re-split leaves can also leave more nodes than fit under one parent, which then still need a common root
     pub fn truncate_root(nodes: &[Rc<Node>]) -> Rc<Node> {
         let mut curr_nodes = nodes;
         while !curr_nodes.is_empty() {
+            if curr_nodes.len() > 1 {
+                return Self::create_root(curr_nodes);
+            }
             let root = curr_nodes.first().unwrap();
             if root.is_leaf() {
                 return Rc::clone(root);
@@ -173,6 +219,80 @@ impl Node {
         }
     }
 
+    // byte at `index`, found through the keys rather than by walking the leaves
+    pub fn byte_at(&self, index: usize) -> Option<u8> {
+        match self {
+            Self::Branch(branch) => {
+                let (child, index_in_child) = branch.find_child_by_index(index);
+                branch.children()[child].byte_at(index_in_child)
+            }
+            Self::Leaf(leaf) => leaf.as_str().as_bytes().get(index).copied(),
+        }
+    }
+
+    // char whose bytes include `index`; leaves only split between chars, so it is within one leaf
+    pub fn char_at(&self, index: usize) -> Option<char> {
+        match self {
+            Self::Branch(branch) => {
+                let (child, index_in_child) = branch.find_child_by_index(index);
+                branch.children()[child].char_at(index_in_child)
+            }
+            Self::Leaf(leaf) => {
+                let text = leaf.as_str();
+                if index >= text.len() {
+                    return None;
+                }
+                let start = (0..=index).rev().find(|&i| text.is_char_boundary(i))?;
+                text[start..].chars().next()
+            }
+        }
+    }
+
+    // byte offset where line `line` starts, i.e. just past the `line`th '\n'; the length if there are fewer lines
+    pub fn line_to_byte(&self, line: usize) -> usize {
+        if line == 0 {
+            return 0;
+        }
+        match self {
+            Self::Branch(branch) => {
+                let (child, line_in_child) = branch.find_child_by_line(line);
+                let offset = if child == 0 {
+                    0
+                } else {
+                    branch.keys()[child - 1]
+                };
+                offset + branch.children()[child].line_to_byte(line_in_child)
+            }
+            Self::Leaf(leaf) => leaf
+                .as_str()
+                .match_indices('\n')
+                .nth(line - 1)
+                .map_or(leaf.len(), |(pos, _)| pos + 1),
+        }
+    }
+
+    // number of '\n' before byte offset `index`, i.e. the line it is on
+    pub fn byte_to_line(&self, index: usize) -> usize {
+        match self {
+            Self::Branch(branch) => {
+                let (child, index_in_child) = branch.find_child_by_index(index);
+                let lines = if child == 0 {
+                    0
+                } else {
+                    branch.line_keys[child - 1]
+                };
+                lines + branch.children()[child].byte_to_line(index_in_child)
+            }
+            Self::Leaf(leaf) => {
+                let bytes = leaf.as_str().as_bytes();
+                bytes[..index.min(bytes.len())]
+                    .iter()
+                    .filter(|&&b| b == b'\n')
+                    .count()
+            }
+        }
+    }
+
     // Just a help function to make sure a leaves are at the same height
     #[allow(dead_code)]
     pub fn check_leaves_same_depths(&self) -> Result<(), String> {
@@ -215,6 +335,8 @@ pub struct Branch {
     height: usize,
     length: usize,
     keys: Vec<usize>,
+    // new lines up to and including each child but the last, as `keys` is for lengths
+    line_keys: Vec<usize>,
     children: Vec<Rc<Node>>,
 }
 
@@ -250,6 +372,14 @@ impl Branch {
         }
     }
 
+    // return the index of the child holding the `line`th new line and which of its own new lines that is,
+    // the last child if there are fewer new lines
+    pub fn find_child_by_line(&self, line: usize) -> (usize, usize) {
+        let pos = self.line_keys.partition_point(|&lines| lines < line);
+        let before = if pos == 0 { 0 } else { self.line_keys[pos - 1] };
+        (pos, line - before)
+    }
+
     // return the indexes of the children and the real ranges in the them
     pub fn find_children_by_range(&self, range: Range<usize>) -> Vec<(usize, Range<usize>)> {
         if range.is_empty() {
@@ -474,5 +604,26 @@ impl Leaf {
 
 #[cfg(test)]
 mod test {
-    // maybe write some test for this as well
+    use super::*;
+
+    #[test]
+    fn find_child_by_line_skips_children_without_the_line() {
+        // Children with 2, 0, 3 and 1 new lines
+        let leaves: Vec<Rc<Node>> = ["a\nb\n", "cd", "\n\ne\n", "f\ng"]
+            .into_iter()
+            .map(|text| Rc::new(Node::Leaf(Leaf::from(text))))
+            .collect();
+        let root = Node::create_root(&leaves);
+        let Node::Branch(branch) = root.as_ref() else {
+            panic!("expected a branch");
+        };
+        assert_eq!(branch.line_keys, vec![2, 2, 5]);
+
+        assert_eq!(branch.find_child_by_line(1), (0, 1));
+        assert_eq!(branch.find_child_by_line(2), (0, 2));
+        assert_eq!(branch.find_child_by_line(3), (2, 1));
+        assert_eq!(branch.find_child_by_line(5), (2, 3));
+        assert_eq!(branch.find_child_by_line(6), (3, 1));
+        assert_eq!(branch.find_child_by_line(9), (3, 4));
+    }
 }
@@ -1,9 +1,15 @@
 mod node;
 
-use node::Node;
-use std::ops::Range;
+use node::{Leaf, Node};
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
+use std::ops::{Add, Range};
 use std::rc::Rc;
 use std::{cmp, fmt};
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete, UnicodeSegmentation};
+
+const READ_BLOCK_SIZE: usize = 64 * 1024;
 
 #[derive(Debug, Clone)]
 pub struct Rope {
@@ -15,6 +21,52 @@ impl Rope {
         Rope { node: Node::new() }
     }
 
+    // build a rope from `reader` one block at a time, so the whole text never sits in one String.
+    // A char cut off at the end of a block is finished from the next one; invalid UTF-8 is an
+    // `InvalidData` error.
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<Rope> {
+        let mut block = vec![0u8; READ_BLOCK_SIZE];
+        let mut filled = 0;
+        let mut leaves: Vec<Rc<Node>> = Vec::new();
+
+        loop {
+            let n = match reader.read(&mut block[filled..]) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            filled += n;
+            // Short reads are collected into a full block so leaves don't come out tiny
+            if n != 0 && filled < block.len() {
+                continue;
+            }
+
+            let valid = match std::str::from_utf8(&block[..filled]) {
+                Ok(_) => filled,
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            };
+            let text = std::str::from_utf8(&block[..valid]).expect("valid UTF-8 prefix");
+            leaves.extend(Leaf::split_text_to_leaves(text));
+            block.copy_within(valid..filled, 0);
+            filled -= valid;
+
+            if n == 0 {
+                break;
+            }
+        }
+
+        if filled > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream ended inside a UTF-8 character",
+            ));
+        }
+        Ok(Rope {
+            node: Node::create_root(&leaves),
+        })
+    }
+
     pub fn len(&self) -> usize {
         self.node.len()
     }
@@ -38,13 +90,48 @@ impl Rope {
         self.node = self.node.insert(cmp::min(index, self.len()), text);
     }
 
+    // insert one char without allocating a String for it
+    pub fn insert_char(&mut self, index: usize, c: char) {
+        let mut buf = [0u8; 4];
+        self.insert(index, c.encode_utf8(&mut buf));
+    }
+
+    // a cursor at byte `position` for a run of edits there, moved back to the start of the char
+    // it falls inside
+    pub fn cursor_at(&mut self, position: usize) -> RopeCursor<'_> {
+        let position = self.floor_char_boundary(cmp::min(position, self.len()));
+        RopeCursor {
+            rope: self,
+            position,
+        }
+    }
+
     pub fn delete(&mut self, range: Range<usize>) {
         self.node = self
             .node
             .delete(cmp::min(range.start, self.len())..cmp::min(range.end, self.len()));
     }
 
-    pub fn slice(&self, range: Range<usize>) -> RopeSlice {
+    // add `other` to the end, sharing its nodes instead of copying its text
+    pub fn append(&mut self, other: &Rope) {
+        self.node = Node::concat(&self.node, &other.node);
+    }
+
+    // keep `[0, at)` and return the rest as its own rope, the complement of `append`
+    pub fn split_off(&mut self, at: usize) -> Rope {
+        let at = cmp::min(at, self.len());
+        if at == 0 {
+            return std::mem::take(self);
+        }
+        if at == self.len() {
+            return Rope::new();
+        }
+        let rest = self.slice_to_rope(at..self.len());
+        self.node = self.node.slice(0..at);
+        rest
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> RopeSlice<'_> {
         RopeSlice {
             rope: self,
             start: cmp::min(range.start, self.len()),
@@ -60,19 +147,243 @@ impl Rope {
         }
     }
 
-    pub fn chunks(&self) -> ChunkIter {
-        ChunkIter::new(self)
+    pub fn chunks(&self) -> ChunkIter<'_> {
+        ChunkIter::new(self, 0..self.len())
     }
 
     pub fn chars(&self) -> impl Iterator<Item = char> {
         self.chunks().flat_map(|chunk| chunk.chars())
     }
 
-    pub fn lines(&self) -> LineIter {
-        LineIter::new(self)
+    pub fn bytes(&self) -> impl Iterator<Item = u8> {
+        self.chunks().flat_map(|chunk| chunk.bytes())
+    }
+
+    pub fn lines(&self) -> LineIter<'_> {
+        LineIter::new(self.chunks())
+    }
+
+    // byte at `index`, in O(height)
+    pub fn byte_at(&self, index: usize) -> Option<u8> {
+        if index >= self.len() {
+            return None;
+        }
+        self.node.byte_at(index)
+    }
+
+    // char whose UTF-8 bytes include `index`
+    pub fn char_at(&self, index: usize) -> Option<char> {
+        if index >= self.len() {
+            return None;
+        }
+        self.node.char_at(index)
     }
 
-    // TODO: lines, columnes conversion to integrate to editor
+    // Lines are separated by '\n' alone, the same ones `new_lines` counts, and numbered from 0.
+    // Columns count chars (not graphemes) from the start of the line.
+
+    // byte offset where `line` starts, or the length past the last line
+    pub fn line_to_byte(&self, line: usize) -> usize {
+        self.node.line_to_byte(line)
+    }
+
+    // line that byte `offset` is on; a '\n' belongs to the line it ends
+    pub fn byte_to_line(&self, offset: usize) -> usize {
+        self.node.byte_to_line(cmp::min(offset, self.len()))
+    }
+
+    // byte offset of char column `col` on `line`, clamped to the end of the line before its line break
+    pub fn line_col_to_byte(&self, line: usize, col: usize) -> usize {
+        let start = self.line_to_byte(line);
+        let line_text = self.slice(start..self.line_to_byte(line + 1)).to_string();
+        let content = line_text.strip_suffix('\n').unwrap_or(&line_text);
+        let content = content.strip_suffix('\r').unwrap_or(content);
+        start
+            + content
+                .char_indices()
+                .nth(col)
+                .map_or(content.len(), |(pos, _)| pos)
+    }
+
+    // line and char column of byte `offset`, which must be on a char boundary
+    pub fn byte_to_line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = cmp::min(offset, self.len());
+        let line = self.byte_to_line(offset);
+        let col = self
+            .slice(self.line_to_byte(line)..offset)
+            .to_string()
+            .chars()
+            .count();
+        (line, col)
+    }
+
+    // byte offset of the `char_idx`th char, or the length past the last one
+    pub fn char_to_byte(&self, char_idx: usize) -> usize {
+        let mut chars_left = char_idx;
+        let mut offset = 0;
+        for chunk in self.chunks() {
+            let chars = chunk.chars().count();
+            if chars_left < chars {
+                return offset + chunk.char_indices().nth(chars_left).unwrap().0;
+            }
+            chars_left -= chars;
+            offset += chunk.len();
+        }
+        offset
+    }
+
+    // number of chars before byte `byte_idx`, which should be on a char boundary
+    pub fn byte_to_char(&self, byte_idx: usize) -> usize {
+        let mut bytes_left = cmp::min(byte_idx, self.len());
+        let mut chars = 0;
+        for chunk in self.chunks() {
+            let take = cmp::min(bytes_left, chunk.len());
+            // every byte that does not continue a char starts one
+            chars += chunk.as_bytes()[..take]
+                .iter()
+                .filter(|&&b| b & 0xC0 != 0x80)
+                .count();
+            bytes_left -= take;
+            if bytes_left == 0 {
+                break;
+            }
+        }
+        chars
+    }
+
+    // byte offset of the `grapheme_idx`th extended grapheme cluster, or the length past the last one
+    pub fn grapheme_to_byte(&self, grapheme_idx: usize) -> usize {
+        match grapheme_idx.checked_sub(1) {
+            None => 0,
+            Some(n) => self.grapheme_boundaries().nth(n).unwrap_or(self.len()),
+        }
+    }
+
+    // number of whole graphemes before byte `byte_idx`; inside a grapheme that is the grapheme's own index
+    pub fn byte_to_grapheme(&self, byte_idx: usize) -> usize {
+        self.grapheme_boundaries()
+            .take_while(|&boundary| boundary <= byte_idx)
+            .count()
+    }
+
+    // the grapheme boundary after (or before) `offset`, or None at the end (or start). Only the text
+    // around `offset` is read, in a window that doubles until the cursor can decide.
+    fn grapheme_boundary_near(&self, offset: usize, forward: bool) -> Option<usize> {
+        let mut reach = 16;
+        loop {
+            let start = self.floor_char_boundary(offset.saturating_sub(reach));
+            let end = self.ceil_char_boundary(cmp::min(offset + reach, self.len()));
+            let window = self.slice(start..end).to_string();
+            let mut cursor = GraphemeCursor::new(offset, self.len(), true);
+            let found = if forward {
+                cursor.next_boundary(&window, start)
+            } else {
+                cursor.prev_boundary(&window, start)
+            };
+            match found {
+                Ok(boundary) => return boundary,
+                // Asked for text before or after the window
+                Err(
+                    GraphemeIncomplete::PreContext(_)
+                    | GraphemeIncomplete::PrevChunk
+                    | GraphemeIncomplete::NextChunk,
+                ) => reach *= 2,
+                Err(e) => panic!("no grapheme boundary near {offset}: {e:?}"),
+            }
+        }
+    }
+
+    // `index` moved back to the start of the char it is in
+    fn floor_char_boundary(&self, mut index: usize) -> usize {
+        while self.byte_at(index).is_some_and(|b| b & 0xC0 == 0x80) {
+            index -= 1;
+        }
+        index
+    }
+
+    // `index` moved on to the start of the next char if it is inside one
+    fn ceil_char_boundary(&self, mut index: usize) -> usize {
+        while self.byte_at(index).is_some_and(|b| b & 0xC0 == 0x80) {
+            index += 1;
+        }
+        index
+    }
+
+    // Byte offsets of the grapheme boundaries after 0, up to and including the length. A grapheme
+    // can straddle leaves, so the last one of each chunk is held back until the next chunk shows
+    // whether it goes on.
+    fn grapheme_boundaries(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut chunks = self.chunks();
+        // Text from the last boundary handed out, and where it starts in the rope
+        let mut window = String::new();
+        let mut window_start = 0;
+        let mut found: VecDeque<usize> = VecDeque::new();
+
+        std::iter::from_fn(move || {
+            loop {
+                if let Some(boundary) = found.pop_front() {
+                    return Some(boundary);
+                }
+                let Some(chunk) = chunks.next() else {
+                    if window.is_empty() {
+                        return None;
+                    }
+                    window_start += window.len();
+                    window.clear();
+                    return Some(window_start);
+                };
+                window.push_str(chunk);
+                let starts: Vec<usize> = window.grapheme_indices(true).map(|(i, _)| i).collect();
+                if let Some(&last) = starts.last() {
+                    found.extend(starts[1..].iter().map(|start| window_start + start));
+                    window.drain(..last);
+                    window_start += last;
+                }
+            }
+        })
+    }
+
+    // byte offset of the first occurrence of `needle`, Some(0) for an empty one. The last
+    // `needle.len() - 1` bytes of each chunk are carried over so a match across leaves is found.
+    pub fn find(&self, needle: &str) -> Option<usize> {
+        if needle.is_empty() {
+            return Some(0);
+        }
+        let needle = needle.as_bytes();
+        let mut window: Vec<u8> = Vec::new();
+        let mut window_start = 0;
+        for chunk in self.chunks() {
+            window.extend_from_slice(chunk.as_bytes());
+            if let Some(pos) = window.windows(needle.len()).position(|w| w == needle) {
+                return Some(window_start + pos);
+            }
+            let drop = window.len() - cmp::min(window.len(), needle.len() - 1);
+            window.drain(..drop);
+            window_start += drop;
+        }
+        None
+    }
+
+    // byte offset of the last occurrence of `needle`, walking the chunks from the end; like
+    // `str::rfind`, an empty needle is found at the length
+    pub fn rfind(&self, needle: &str) -> Option<usize> {
+        if needle.is_empty() {
+            return Some(self.len());
+        }
+        let needle = needle.as_bytes();
+        let chunks: Vec<&str> = self.chunks().collect();
+        let mut window: Vec<u8> = Vec::new();
+        let mut window_start = self.len();
+        for chunk in chunks.into_iter().rev() {
+            window.splice(0..0, chunk.bytes());
+            window_start -= chunk.len();
+            if let Some(pos) = window.windows(needle.len()).rposition(|w| w == needle) {
+                return Some(window_start + pos);
+            }
+            window.truncate(needle.len() - 1);
+        }
+        None
+    }
 
     pub fn collect_leaves(&self) -> String {
         let mut result = String::with_capacity(self.len());
@@ -100,12 +411,144 @@ impl fmt::Display for Rope {
     }
 }
 
+impl PartialEq for Rope {
+    fn eq(&self, other: &Rope) -> bool {
+        self.len() == other.len() && chunks_eq(self.chunks(), other.chunks())
+    }
+}
+
+impl Eq for Rope {}
+
+impl PartialEq<str> for Rope {
+    fn eq(&self, other: &str) -> bool {
+        self.len() == other.len() && chunks_eq(self.chunks(), std::iter::once(other))
+    }
+}
+
+impl PartialEq<&str> for Rope {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
+
+// Hashes the bytes in fixed-size blocks, so the result does not depend on where the leaves split
+impl Hash for Rope {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        const BLOCK: usize = 64;
+        let mut block = [0u8; BLOCK];
+        let mut filled = 0;
+        for chunk in self.chunks() {
+            let mut bytes = chunk.as_bytes();
+            while !bytes.is_empty() {
+                let take = cmp::min(BLOCK - filled, bytes.len());
+                block[filled..filled + take].copy_from_slice(&bytes[..take]);
+                filled += take;
+                bytes = &bytes[take..];
+                if filled == BLOCK {
+                    state.write(&block);
+                    filled = 0;
+                }
+            }
+        }
+        state.write(&block[..filled]);
+        state.write_usize(self.len());
+    }
+}
+
+// whether two runs of chunks spell out the same bytes, wherever each is cut
+fn chunks_eq<'a, 'b>(
+    mut left: impl Iterator<Item = &'a str>,
+    mut right: impl Iterator<Item = &'b str>,
+) -> bool {
+    let mut left_bytes: &[u8] = &[];
+    let mut right_bytes: &[u8] = &[];
+    loop {
+        if left_bytes.is_empty() {
+            match left.next() {
+                Some(chunk) => left_bytes = chunk.as_bytes(),
+                None => return right_bytes.is_empty() && right.all(str::is_empty),
+            }
+            continue;
+        }
+        if right_bytes.is_empty() {
+            match right.next() {
+                Some(chunk) => right_bytes = chunk.as_bytes(),
+                None => return false,
+            }
+            continue;
+        }
+        let common = cmp::min(left_bytes.len(), right_bytes.len());
+        if left_bytes[..common] != right_bytes[..common] {
+            return false;
+        }
+        left_bytes = &left_bytes[common..];
+        right_bytes = &right_bytes[common..];
+    }
+}
+
+impl Add for Rope {
+    type Output = Rope;
+
+    fn add(mut self, other: Rope) -> Rope {
+        self.append(&other);
+        self
+    }
+}
+
 impl Default for Rope {
     fn default() -> Self {
         Self::new()
     }
 }
 
+// A byte position in a rope that follows the edits made through it, so typing at a caret never
+// has to find its place again from a line and column. Stepping over graphemes looks only at the
+// text next to the position.
+pub struct RopeCursor<'a> {
+    rope: &'a mut Rope,
+    position: usize,
+}
+
+impl<'a> RopeCursor<'a> {
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn rope(&self) -> &Rope {
+        self.rope
+    }
+
+    // insert `text` at the cursor and move past it
+    pub fn insert(&mut self, text: &str) {
+        self.rope.insert(self.position, text);
+        self.position += text.len();
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        let mut buf = [0u8; 4];
+        self.insert(c.encode_utf8(&mut buf));
+    }
+
+    // delete the grapheme before the cursor, like Backspace; nothing at the start
+    pub fn delete_back(&mut self) {
+        if let Some(start) = self.rope.grapheme_boundary_near(self.position, false) {
+            self.rope.delete(start..self.position);
+            self.position = start;
+        }
+    }
+
+    // move over `graphemes` graphemes, backwards when negative, stopping at either end
+    pub fn move_by(&mut self, graphemes: isize) {
+        let forward = graphemes > 0;
+        for _ in 0..graphemes.unsigned_abs() {
+            match self.rope.grapheme_boundary_near(self.position, forward) {
+                Some(boundary) => self.position = boundary,
+                None => break,
+            }
+        }
+    }
+}
+
 pub struct RopeSlice<'a> {
     rope: &'a Rope,
     start: usize,
@@ -121,6 +564,28 @@ impl<'a> RopeSlice<'a> {
         self.len() == 0
     }
 
+    // the part of this slice at `range`, relative to the slice and clamped to it
+    pub fn slice(&self, range: Range<usize>) -> RopeSlice<'a> {
+        let start = cmp::min(self.start + range.start, self.end);
+        RopeSlice {
+            rope: self.rope,
+            start,
+            end: cmp::min(self.start + range.end, self.end).max(start),
+        }
+    }
+
+    pub fn chunks(&self) -> ChunkIter<'a> {
+        ChunkIter::new(self.rope, self.start..self.end)
+    }
+
+    pub fn chars(&self) -> impl Iterator<Item = char> + 'a {
+        self.chunks().flat_map(|chunk| chunk.chars())
+    }
+
+    pub fn lines(&self) -> LineIter<'a> {
+        LineIter::new(self.chunks())
+    }
+
     pub fn collect_leaves(&self) -> String {
         let mut buf = String::with_capacity(self.len());
         self.rope.node.write_to(&mut buf, self.start..self.end);
@@ -134,13 +599,21 @@ impl<'a> fmt::Display for RopeSlice<'a> {
     }
 }
 
+// Leaves in order, cut down to the bytes in `range`; subtrees outside it are skipped whole
 pub struct ChunkIter<'a> {
     stack: Vec<&'a Node>,
+    range: Range<usize>,
+    // byte offset where the node on top of the stack starts
+    offset: usize,
 }
 
 impl<'a> ChunkIter<'a> {
-    fn new(rope: &'a Rope) -> Self {
-        let mut iter = Self { stack: Vec::new() };
+    fn new(rope: &'a Rope, range: Range<usize>) -> Self {
+        let mut iter = Self {
+            stack: Vec::new(),
+            range,
+            offset: 0,
+        };
         iter.stack.push(&rope.node);
         iter
     }
@@ -151,8 +624,22 @@ impl<'a> Iterator for ChunkIter<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(node) = self.stack.pop() {
+            let node_start = self.offset;
+            if node_start >= self.range.end {
+                self.stack.clear();
+                return None;
+            }
+            if node_start + node.len() <= self.range.start {
+                self.offset += node.len();
+                continue;
+            }
             match node {
-                Node::Leaf(leaf) => return Some(leaf.as_str()),
+                Node::Leaf(leaf) => {
+                    self.offset += leaf.len();
+                    let from = self.range.start.saturating_sub(node_start);
+                    let to = cmp::min(self.range.end - node_start, leaf.len());
+                    return Some(&leaf.as_str()[from..to]);
+                }
                 Node::Branch(branch) => {
                     for child in branch.children().iter().rev() {
                         self.stack.push(child);
@@ -164,22 +651,38 @@ impl<'a> Iterator for ChunkIter<'a> {
     }
 }
 
+// Lines split on "\n", "\r\n" and a lone "\r"
 pub struct LineIter<'a> {
     chunk_iter: ChunkIter<'a>,
     current_chunk: Option<&'a str>,
     chunk_position: usize,
     buffer: String,
+    // The last line ended with a '\r' at the end of a chunk; a leading '\n' completes it
+    skip_lf: bool,
+    // Yield the text after the last line break even when it is empty, as editors show it
+    trailing_empty: bool,
+    finished: bool,
 }
 
 impl<'a> LineIter<'a> {
-    fn new(rope: &'a Rope) -> Self {
+    fn new(chunk_iter: ChunkIter<'a>) -> Self {
         Self {
-            chunk_iter: rope.chunks(),
+            chunk_iter,
             current_chunk: None,
             chunk_position: 0,
             buffer: String::new(),
+            skip_lf: false,
+            trailing_empty: false,
+            finished: false,
         }
     }
+
+    // Also yield the empty line after a final line break, and a single empty line for an
+    // empty rope: "a\n" gives ["a", ""] and "" gives [""]
+    pub fn with_trailing_empty_line(mut self) -> Self {
+        self.trailing_empty = true;
+        self
+    }
 }
 
 impl<'a> Iterator for LineIter<'a> {
@@ -195,18 +698,30 @@ impl<'a> Iterator for LineIter<'a> {
             let chunk = match self.current_chunk {
                 Some(chunk) => chunk,
                 None => {
-                    if self.buffer.is_empty() {
+                    if self.finished || (self.buffer.is_empty() && !self.trailing_empty) {
                         return None;
-                    } else {
-                        return Some(std::mem::take(&mut self.buffer));
                     }
+                    self.finished = true;
+                    return Some(std::mem::take(&mut self.buffer));
                 }
             };
 
+            if std::mem::take(&mut self.skip_lf) && chunk[self.chunk_position..].starts_with('\n') {
+                self.chunk_position += 1;
+            }
+
             let remaining = &chunk[self.chunk_position..];
-            if let Some(newline_pos) = remaining.find('\n') {
+            if let Some(newline_pos) = remaining.find(['\n', '\r']) {
                 self.buffer.push_str(&remaining[..newline_pos]);
-                self.chunk_position += newline_pos + 1;
+                let mut eol_len = 1;
+                if remaining.as_bytes()[newline_pos] == b'\r' {
+                    match remaining.as_bytes().get(newline_pos + 1) {
+                        Some(b'\n') => eol_len = 2,
+                        Some(_) => {}
+                        None => self.skip_lf = true,
+                    }
+                }
+                self.chunk_position += newline_pos + eol_len;
                 if self.chunk_position >= chunk.len() {
                     self.current_chunk = None;
                 }
@@ -298,6 +813,59 @@ mod tests {
         assert_eq!(hello_vec, iter_vec);
     }
 
+    #[test]
+    fn lines_iter_splits_every_line_ending() {
+        let expected = vec!["classic", "mac", "", "dos", "unix", "end"];
+        let rope = Rope::from("classic\rmac\r\rdos\r\nunix\nend");
+        assert_eq!(rope.lines().collect::<Vec<_>>(), expected);
+
+        // CRLF split across two equal leaves still breaks a single line
+        let text = format!(
+            "{}\r\n{}",
+            "a".repeat(node::MAX_CHUNK_SIZE - 1),
+            "b".repeat(node::MAX_CHUNK_SIZE - 1)
+        );
+        let split = Rope::from(text.as_str());
+        assert!(split.chunks().any(|chunk| chunk.ends_with('\r')));
+        let lines: Vec<String> = split.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "a".repeat(node::MAX_CHUNK_SIZE - 1),
+                "b".repeat(node::MAX_CHUNK_SIZE - 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_iter_with_trailing_empty_line() {
+        let lines = |text: &str| -> Vec<String> {
+            Rope::from(text)
+                .lines()
+                .with_trailing_empty_line()
+                .collect()
+        };
+        assert_eq!(lines(""), vec![""]);
+        assert_eq!(lines("\n"), vec!["", ""]);
+        assert_eq!(lines("a\n"), vec!["a", ""]);
+        assert_eq!(lines("a\nb"), vec!["a", "b"]);
+        assert_eq!(lines("a\r\nb\r\n"), vec!["a", "b", ""]);
+
+        // Default mode still drops it
+        assert_eq!(Rope::from("a\n").lines().collect::<Vec<_>>(), vec!["a"]);
+        assert_eq!(Rope::new().lines().count(), 0);
+
+        // A CRLF split across leaves strips the '\r' the same way
+        let a = "a".repeat(node::MAX_CHUNK_SIZE - 1);
+        let b = "b".repeat(node::MAX_CHUNK_SIZE - 3);
+        let rope = Rope::from(format!("{a}\r\n{b}\r\n").as_str());
+        assert!(rope.chunks().any(|chunk| chunk.ends_with('\r')));
+        assert_eq!(
+            rope.lines().with_trailing_empty_line().collect::<Vec<_>>(),
+            vec![a, b, String::new()]
+        );
+    }
+
     #[test]
     fn empty_lines_iter() {
         let new_lines_vec: Vec<String> = vec![
@@ -366,6 +934,506 @@ mod tests {
         assert_eq!(hello_rope.new_lines(), hello_string.matches('\n').count());
     }
 
+    // Byte offsets of every line start in `text`, the reference for the conversions
+    fn line_starts(text: &str) -> Vec<usize> {
+        std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(pos, _)| pos + 1))
+            .collect()
+    }
+
+    #[test]
+    fn line_byte_conversion_matches_the_text() {
+        let text: String = (0..300)
+            .map(|i| match i % 4 {
+                0 => String::new(),
+                1 => format!("line {i} ünïcödé 🦀"),
+                2 => "x".repeat(i % 37),
+                _ => format!("{i}\r"),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let rope = Rope::from(text.as_str());
+        assert!(rope.height() > 2);
+
+        let starts = line_starts(&text);
+        for (line, &start) in starts.iter().enumerate() {
+            assert_eq!(rope.line_to_byte(line), start);
+        }
+        assert_eq!(rope.line_to_byte(starts.len()), text.len());
+        assert_eq!(rope.line_to_byte(starts.len() + 10), text.len());
+
+        for offset in 0..=text.len() {
+            let line = starts.partition_point(|&s| s <= offset) - 1;
+            assert_eq!(rope.byte_to_line(offset), line, "offset {offset}");
+        }
+        assert_eq!(rope.byte_to_line(text.len() + 10), starts.len() - 1);
+
+        // Offsets on a '\n' belong to the line it ends
+        for (pos, _) in text.match_indices('\n') {
+            let line = rope.byte_to_line(pos);
+            assert_eq!(rope.byte_to_line(pos + 1), line + 1);
+            assert_eq!(rope.line_to_byte(line + 1), pos + 1);
+        }
+    }
+
+    #[test]
+    fn line_col_conversion_counts_chars() {
+        let text = "ab\nçé🦀x\n\nlast\r\n🦀";
+        let rope = Rope::from(text);
+
+        assert_eq!(rope.line_col_to_byte(0, 1), 1);
+        assert_eq!(rope.line_col_to_byte(1, 2), 3 + "çé".len());
+        assert_eq!(rope.line_col_to_byte(1, 3), 3 + "çé🦀".len());
+        // Columns past the line end stop before the line break
+        assert_eq!(rope.line_col_to_byte(0, 9), 2);
+        assert_eq!(
+            rope.line_col_to_byte(2, 1),
+            text.find("\n\nlast").unwrap() + 1
+        );
+        assert_eq!(rope.line_col_to_byte(3, 9), text.find("\r\n").unwrap());
+        assert_eq!(rope.line_col_to_byte(4, 1), text.len());
+
+        for (offset, _) in text.char_indices().chain([(text.len(), ' ')]) {
+            let (line, col) = rope.byte_to_line_col(offset);
+            let start = line_starts(text)[line];
+            assert_eq!(col, text[start..offset].chars().count());
+            if !matches!(text.as_bytes().get(offset), Some(b'\n' | b'\r')) {
+                assert_eq!(rope.line_col_to_byte(line, col), offset);
+            }
+        }
+        assert_eq!(rope.byte_to_line_col(2), (0, 2));
+        assert_eq!(rope.byte_to_line_col(text.len()), (4, 1));
+        assert_eq!(Rope::new().byte_to_line_col(0), (0, 0));
+        assert_eq!(Rope::new().line_col_to_byte(3, 3), 0);
+    }
+
+    #[test]
+    fn line_conversion_on_a_ten_thousand_line_rope() {
+        let text: String = (0..10_000)
+            .map(|i| format!("{}\n", "y".repeat(i % 7)))
+            .collect();
+        let rope = Rope::from(text.as_str());
+        assert_eq!(rope.new_lines(), 10_000);
+        assert!(rope.height() <= 5, "height {}", rope.height());
+        rope.node.check_leaves_same_depths().unwrap();
+
+        let starts = line_starts(&text);
+        for (line, &start) in starts.iter().enumerate() {
+            assert_eq!(rope.line_to_byte(line), start);
+            assert_eq!(rope.byte_to_line(start), line);
+            if start > 0 {
+                assert_eq!(rope.byte_to_line(start - 1), line - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn line_conversion_follows_edits() {
+        let mut text = "one\ntwo\nthree\n".repeat(40);
+        let mut rope = Rope::from(text.as_str());
+        rope.insert(50, "\n\nnew\n");
+        text.insert_str(50, "\n\nnew\n");
+        rope.delete(100..180);
+        text.replace_range(100..180, "");
+
+        let starts = line_starts(&text);
+        for (line, &start) in starts.iter().enumerate() {
+            assert_eq!(rope.line_to_byte(line), start);
+            assert_eq!(rope.byte_to_line(start), line);
+        }
+    }
+
+    #[test]
+    fn char_and_byte_indices_round_trip() {
+        let text = "ab👨‍👩‍👧‍👦çd\n你好🦀e";
+        let rope = Rope::from(text);
+        assert!(rope.height() > 1);
+
+        for (char_idx, (byte_idx, _)) in text.char_indices().enumerate() {
+            assert_eq!(rope.char_to_byte(char_idx), byte_idx);
+            assert_eq!(rope.byte_to_char(byte_idx), char_idx);
+        }
+        let chars = text.chars().count();
+        assert_eq!(rope.char_to_byte(chars), text.len());
+        assert_eq!(rope.char_to_byte(chars + 5), text.len());
+        assert_eq!(rope.byte_to_char(text.len() + 5), chars);
+        assert_eq!(Rope::new().char_to_byte(3), 0);
+    }
+
+    #[test]
+    fn grapheme_indices_keep_clusters_whole() {
+        let family = "👨‍👩‍👧‍👦";
+        let flags = "🇫🇷🇯🇵";
+        let text = format!("a{family}b{flags}e\u{301}{family}");
+        let rope = Rope::from(text.as_str());
+        // The family emoji and the flags straddle leaves
+        assert!(rope.chunks().all(|chunk| chunk.len() < family.len()));
+
+        let expected: Vec<usize> = text
+            .grapheme_indices(true)
+            .map(|(i, _)| i)
+            .chain([text.len()])
+            .collect();
+        for (grapheme_idx, &byte_idx) in expected.iter().enumerate() {
+            assert_eq!(rope.grapheme_to_byte(grapheme_idx), byte_idx);
+            assert_eq!(rope.byte_to_grapheme(byte_idx), grapheme_idx);
+        }
+        assert_eq!(expected.len() - 1, 7);
+        assert_eq!(rope.grapheme_to_byte(20), text.len());
+
+        // A byte inside the family belongs to it
+        assert_eq!(rope.byte_to_grapheme(1 + family.len() / 2), 1);
+        assert_eq!(Rope::new().grapheme_to_byte(1), 0);
+        assert_eq!(Rope::new().byte_to_grapheme(0), 0);
+    }
+
+    #[test]
+    fn append_joins_trees_without_reinserting() {
+        let left_text: String = (0..5000)
+            .map(|i| char::from(b'a' + (i % 26) as u8))
+            .collect();
+        let right_text: String = (0..5000)
+            .map(|i| if i % 50 == 0 { '\n' } else { 'é' })
+            .collect();
+        let mut rope = Rope::from(left_text.as_str());
+        rope.append(&Rope::from(right_text.as_str()));
+        assert_eq!(rope.to_string(), left_text.clone() + &right_text);
+        assert_eq!(rope.len(), left_text.len() + right_text.len());
+        assert_eq!(rope.new_lines(), 100);
+        rope.node.check_leaves_same_depths().unwrap();
+
+        // Trees of different heights, either way round
+        let small = Rope::from("tiny");
+        let taller = rope.clone() + small.clone();
+        assert_eq!(taller.to_string(), format!("{left_text}{right_text}tiny"));
+        taller.node.check_leaves_same_depths().unwrap();
+        let taller = small.clone() + rope.clone();
+        assert_eq!(taller.to_string(), format!("tiny{left_text}{right_text}"));
+        taller.node.check_leaves_same_depths().unwrap();
+        assert_eq!(taller.line_to_byte(1), "tiny".len() + left_text.len() + 1);
+
+        // Edits keep working on the joined tree
+        let mut edited = taller;
+        edited.delete(2..5004);
+        edited.insert(1, "X");
+        let mut expected = format!("tiny{left_text}{right_text}");
+        expected.replace_range(2..5004, "");
+        expected.insert(1, 'X');
+        assert_eq!(edited.to_string(), expected);
+        edited.node.check_leaves_same_depths().unwrap();
+
+        // Empty sides
+        let mut empty = Rope::new();
+        empty.append(&small);
+        assert_eq!(empty.to_string(), "tiny");
+        assert_eq!((small + Rope::new()).to_string(), "tiny");
+    }
+
+    #[test]
+    fn split_off_divides_the_rope() {
+        let text: String = (0..300).map(|i| format!("line {i} ✓\n")).collect();
+        let boundaries = text.char_indices().map(|(i, _)| i).chain([text.len()]);
+        for at in boundaries.step_by(37) {
+            let mut left = Rope::from(text.as_str());
+            let right = left.split_off(at);
+            assert_eq!(left.to_string(), &text[..at]);
+            assert_eq!(right.to_string(), &text[at..]);
+            assert_eq!(left.new_lines() + right.new_lines(), 300);
+            left.node.check_leaves_same_depths().unwrap();
+            right.node.check_leaves_same_depths().unwrap();
+
+            left.append(&right);
+            assert_eq!(left.to_string(), text);
+        }
+
+        // Re-split leaves used to leave the root of a large slice with a sibling that was dropped
+        let rope = Rope::from(text.as_str());
+        assert_eq!(rope.slice_to_rope(0..text.len()).to_string(), text);
+
+        let mut rope = Rope::from(text.as_str());
+        let all = rope.split_off(0);
+        assert!(rope.is_empty());
+        assert_eq!(all.to_string(), text);
+
+        let mut rope = Rope::from(text.as_str());
+        assert!(rope.split_off(text.len() + 10).is_empty());
+        assert_eq!(rope.to_string(), text);
+        assert!(Rope::new().split_off(0).is_empty());
+    }
+
+    #[test]
+    fn equal_content_compares_and_hashes_equal() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let hash = |rope: &Rope| {
+            let mut hasher = DefaultHasher::new();
+            rope.hash(&mut hasher);
+            hasher.finish()
+        };
+        let text = "The quick brown fox 🦊 jumps over the lazy dog\n".repeat(8);
+
+        let built = Rope::from(text.as_str());
+        let mut typed = Rope::new();
+        for (i, c) in text.char_indices() {
+            typed.insert(i, &c.to_string());
+        }
+        let mut edited = Rope::from("dog\n");
+        edited.insert(0, &text[..text.len() - 4]);
+        assert!(built.chunks().count() > 1);
+        assert_ne!(
+            built.chunks().collect::<Vec<_>>(),
+            typed.chunks().collect::<Vec<_>>()
+        );
+
+        assert_eq!(built, typed);
+        assert_eq!(built, edited);
+        assert_eq!(hash(&built), hash(&typed));
+        assert_eq!(hash(&built), hash(&edited));
+        assert!(built == text.as_str());
+        assert_eq!(built, *text.as_str());
+
+        let mut different = built.clone();
+        different.delete(5..6);
+        different.insert(5, "Q");
+        assert_ne!(built, different);
+        assert_ne!(hash(&built), hash(&different));
+        assert_ne!(built, Rope::from(&text[1..]));
+        let shorter = &text[..text.len() - 1];
+        assert!(built != shorter);
+        assert_eq!(Rope::new(), Rope::from(""));
+    }
+
+    #[test]
+    fn find_matches_across_chunk_boundaries() {
+        // "needle" cut in two by the first leaf boundary, then once more inside the third leaf
+        let text = format!(
+            "{}needle{}needle{}",
+            "a".repeat(node::MAX_CHUNK_SIZE - 3),
+            "b".repeat(node::MAX_CHUNK_SIZE + 7),
+            "c".repeat(node::MAX_CHUNK_SIZE)
+        );
+        let rope = Rope::from(text.as_str());
+        let first = node::MAX_CHUNK_SIZE - 3;
+        assert!(rope.chunks().next().unwrap().ends_with("nee"));
+
+        assert_eq!(rope.find("needle"), Some(first));
+        assert_eq!(rope.find("needle"), text.find("needle"));
+        assert_eq!(rope.rfind("needle"), text.rfind("needle"));
+        assert_ne!(rope.find("needle"), rope.rfind("needle"));
+        assert_eq!(rope.find("aneedleb"), text.find("aneedleb"));
+        assert_eq!(rope.rfind("aneedleb"), Some(first - 1));
+        assert_eq!(rope.find(&text), Some(0));
+        assert_eq!(rope.rfind(&text), Some(0));
+
+        assert_eq!(rope.find("needles"), None);
+        assert_eq!(rope.rfind("ca"), None);
+        assert_eq!(rope.find(""), Some(0));
+        assert_eq!(rope.rfind(""), Some(text.len()));
+        assert_eq!(Rope::new().find("a"), None);
+
+        // Every position of a repeated needle, found in both directions
+        let text = "xyz".repeat(40);
+        let rope = Rope::from(text.as_str());
+        for start in 0..text.len() {
+            let sliced = rope.slice_to_rope(start..text.len());
+            assert_eq!(sliced.find("zx"), text[start..].find("zx"));
+            assert_eq!(sliced.rfind("yzx"), text[start..].rfind("yzx"));
+        }
+    }
+
+    #[test]
+    fn slice_iterates_its_own_range() {
+        let text: String = (0..40).map(|i| format!("row {i}: héllo\r\n")).collect();
+        let rope = Rope::from(text.as_str());
+        assert!(rope.height() > 2);
+
+        let snap = |i: usize| (i..).find(|&i| text.is_char_boundary(i)).unwrap();
+
+        for range in [0..text.len(), 5..200, 17..18, 100..100, 333..text.len()] {
+            let range = snap(range.start)..snap(range.end);
+            let slice = rope.slice(range.clone());
+            let expected = &text[range];
+            assert_eq!(slice.chunks().collect::<String>(), expected);
+            assert_eq!(slice.chars().collect::<String>(), expected);
+            assert_eq!(
+                slice.lines().collect::<Vec<_>>(),
+                expected.lines().collect::<Vec<_>>()
+            );
+        }
+
+        // Sub-slices are relative to the parent and stay inside it
+        let (start, end) = (snap(40), snap(300));
+        let (inner_start, inner_end) = (snap(start + 10), snap(start + 50));
+        let slice = rope.slice(start..end);
+        let inner = slice.slice(inner_start - start..inner_end - start);
+        assert_eq!(inner.to_string(), &text[inner_start..inner_end]);
+        assert_eq!(
+            inner.chars().collect::<String>(),
+            &text[inner_start..inner_end]
+        );
+        let tail = snap(start + 200);
+        assert_eq!(slice.slice(tail - start..900).to_string(), &text[tail..end]);
+        assert!(slice.slice(500..600).is_empty());
+    }
+
+    #[test]
+    fn from_reader_matches_from_str() {
+        // Multi-byte chars straddle the block boundaries
+        let text: String = (0..20_000)
+            .map(|i| format!("{}🦀é\n", "x".repeat(i % 5)))
+            .collect();
+        assert!(text.len() > 2 * READ_BLOCK_SIZE);
+        assert!(
+            !text.is_char_boundary(READ_BLOCK_SIZE) || !text.is_char_boundary(2 * READ_BLOCK_SIZE)
+        );
+        let expected = Rope::from(text.as_str());
+
+        let rope = Rope::from_reader(io::Cursor::new(text.clone().into_bytes())).unwrap();
+        assert_eq!(rope, expected);
+        assert_eq!(rope.new_lines(), 20_000);
+        rope.node.check_leaves_same_depths().unwrap();
+
+        // Short reads ending `cut` and `2 * cut` bytes in, inside the first chars and
+        // across a block
+        let bytes = text.as_bytes();
+        for cut in [1, 3, 4093, READ_BLOCK_SIZE - 1] {
+            let reader = io::Cursor::new(&bytes[..cut])
+                .chain(io::Cursor::new(&bytes[cut..2 * cut]))
+                .chain(io::Cursor::new(&bytes[2 * cut..]));
+            assert_eq!(Rope::from_reader(reader).unwrap(), expected);
+        }
+
+        // Cut inside a char between two cursors
+        let crab = "a🦀b".as_bytes();
+        let split = io::Cursor::new(&crab[..2]).chain(io::Cursor::new(&crab[2..]));
+        assert_eq!(Rope::from_reader(split).unwrap(), "a🦀b");
+        assert!(Rope::from_reader(io::empty()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn from_reader_rejects_invalid_utf8() {
+        let invalid = Rope::from_reader(io::Cursor::new(b"ab\xFFcd".to_vec()));
+        assert_eq!(invalid.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let truncated = Rope::from_reader(io::Cursor::new(&"a🦀".as_bytes()[..3]));
+        assert_eq!(truncated.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn byte_and_char_access() {
+        let text: String = (0..50).map(|i| format!("{i}: naïve 😀 日本\n")).collect();
+        let rope = Rope::from(text.as_str());
+        assert!(rope.height() > 2);
+
+        assert_eq!(rope.bytes().count(), rope.len());
+        assert!(rope.bytes().eq(text.bytes()));
+
+        // A spread of offsets standing in for random access, plus every leaf edge
+        let mut offsets: Vec<usize> = (0..text.len()).step_by(7).collect();
+        let mut edge = 0;
+        for chunk in rope.chunks() {
+            offsets.extend([edge, edge + chunk.len() - 1]);
+            edge += chunk.len();
+        }
+        for offset in offsets {
+            assert_eq!(rope.byte_at(offset), Some(text.as_bytes()[offset]));
+            let start = (0..=offset)
+                .rev()
+                .find(|&i| text.is_char_boundary(i))
+                .unwrap();
+            assert_eq!(rope.char_at(offset), text[start..].chars().next());
+        }
+
+        let emoji = text.find('😀').unwrap();
+        for offset in emoji..emoji + 4 {
+            assert_eq!(rope.char_at(offset), Some('😀'));
+        }
+        assert_eq!(rope.byte_at(text.len()), None);
+        assert_eq!(rope.char_at(text.len()), None);
+        assert_eq!(Rope::new().byte_at(0), None);
+        assert_eq!(Rope::new().char_at(0), None);
+    }
+
+    #[test]
+    fn cursor_typing_matches_a_string() {
+        // Text model: the same edits on a String, with the grapheme rules applied to all of it
+        fn boundary(text: &str, offset: usize, forward: bool) -> Option<usize> {
+            let mut cursor = GraphemeCursor::new(offset, text.len(), true);
+            if forward {
+                cursor.next_boundary(text, 0).unwrap()
+            } else {
+                cursor.prev_boundary(text, 0).unwrap()
+            }
+        }
+
+        let keys = [
+            'a', 'b', ' ', '\n', 'é', 'e', '\u{301}', '🦀', '🇫', '🇷', '👨', '\u{200d}',
+        ];
+        let initial = "start 🇯🇵 text\n".repeat(5);
+        let mut rope = Rope::from(initial.as_str());
+        let mut expected = initial.clone();
+        let mut cursor = rope.cursor_at(6);
+        let mut position = 6;
+        let mut seed: u32 = 12345;
+
+        for step in 0..1000 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let pick = (seed >> 16) as usize;
+            match pick % 10 {
+                0 | 1 => {
+                    cursor.delete_back();
+                    if let Some(start) = boundary(&expected, position, false) {
+                        expected.replace_range(start..position, "");
+                        position = start;
+                    }
+                }
+                2 => {
+                    let by = (pick / 10 % 7) as isize - 3;
+                    cursor.move_by(by);
+                    for _ in 0..by.unsigned_abs() {
+                        match boundary(&expected, position, by > 0) {
+                            Some(next) => position = next,
+                            None => break,
+                        }
+                    }
+                }
+                _ => {
+                    let c = keys[pick / 10 % keys.len()];
+                    cursor.insert_char(c);
+                    expected.insert(position, c);
+                    position += c.len_utf8();
+                }
+            }
+            assert_eq!(cursor.position(), position, "step {step}");
+            if step % 100 == 0 {
+                assert_eq!(*cursor.rope(), *expected.as_str());
+            }
+        }
+        assert_eq!(rope, *expected.as_str());
+        rope.node.check_leaves_same_depths().unwrap();
+
+        let mut typed = Rope::new();
+        for (i, c) in "héllo 🦀".char_indices() {
+            typed.insert_char(i, c);
+        }
+        assert_eq!(typed, "héllo 🦀");
+    }
+
+    #[test]
+    fn cursor_inside_a_char_starts_at_that_char() {
+        let mut rope = Rope::from("aé🦀b");
+        // Byte 2 is inside "é", byte 5 inside the crab
+        let mut cursor = rope.cursor_at(2);
+        assert_eq!(cursor.position(), 1);
+        cursor.move_by(1);
+        assert_eq!(cursor.position(), 3);
+
+        let mut cursor = rope.cursor_at(5);
+        assert_eq!(cursor.position(), 3);
+        cursor.delete_back();
+        assert_eq!(cursor.position(), 1);
+        assert_eq!(rope, "a🦀b");
+    }
+
     #[test]
     fn slicing() {
         let hello_rope = Rope::from("Hello world! I am a rope.");
@@ -0,0 +1,24 @@
+#[path = "../benches/session/mod.rs"]
+mod session;
+
+use piece_tree::PieceTree;
+use rope::Rope;
+use session::{TextStore, record_session, replay};
+
+fn run<S: TextStore>(initial: &str, keys: &[session::Keystroke]) -> (String, usize) {
+    let mut store = S::from_text(initial);
+    let drawn = replay(&mut store, keys);
+    (store.text(), drawn)
+}
+
+#[test]
+fn backends_agree_on_a_tiny_session() {
+    let initial = "fn main() {\n    println!(\"hi\");\n}\n";
+    let keys = record_session(300, 7);
+
+    let expected = run::<String>(initial, &keys);
+    assert_ne!(expected.0, initial);
+    assert_eq!(run::<Rope>(initial, &keys), expected);
+    assert_eq!(run::<ropey::Rope>(initial, &keys), expected);
+    assert_eq!(run::<PieceTree>(initial, &keys), expected);
+}
@@ -0,0 +1,49 @@
+use std::cmp::Reverse;
+use std::ops::Range;
+
+/// Edits collected by [`crate::TextBuffer::batch`]. Every range and offset refers to the
+/// document as it was before the batch, whatever else the batch changes, so a list of
+/// matches can be replaced without shifting offsets by hand.
+#[derive(Debug, Default)]
+pub struct BatchEdit {
+    edits: Vec<(Range<usize>, String)>,
+}
+
+impl BatchEdit {
+    /// Replace the bytes in `range` with `text`.
+    pub fn replace(&mut self, range: Range<usize>, text: &str) {
+        self.edits.push((range, text.to_string()));
+    }
+
+    /// Insert `text` at byte `offset`. Several inserts at one offset keep their order.
+    pub fn insert(&mut self, offset: usize, text: &str) {
+        self.replace(offset..offset, text);
+    }
+
+    /// Delete the bytes in `range`.
+    pub fn delete(&mut self, range: Range<usize>) {
+        self.replace(range, "");
+    }
+
+    /// The edits clamped to a document `len` bytes long, last offset first, so applying them
+    /// in turn never moves the ones still to come. An edit overlapping one that starts
+    /// after it is dropped, as are edits that change nothing.
+    pub(crate) fn into_ordered(mut self, len: usize) -> Vec<(Range<usize>, String)> {
+        // Of the edits at one offset the last is applied first, ending up after the others
+        self.edits.reverse();
+        self.edits.sort_by_key(|(range, _)| Reverse(range.start));
+
+        let mut limit = len;
+        let mut ordered = Vec::with_capacity(self.edits.len());
+        for (range, text) in self.edits {
+            let end = range.end.min(len);
+            let start = range.start.min(end);
+            if end > limit || (start == end && text.is_empty()) {
+                continue;
+            }
+            limit = start;
+            ordered.push((start..end, text));
+        }
+        ordered
+    }
+}
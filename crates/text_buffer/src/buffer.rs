@@ -1,29 +1,433 @@
-use piece_tree::{BufferCursor, PieceTree, StringBuffer};
+use crate::batch::BatchEdit;
+use crate::column::ColumnMode;
+use crate::eol::{self, Eol, EolMode};
+use crate::history::{Edit, EditPosition, History};
+use crate::indent::leading_whitespace;
+use crate::save::save_atomic_with;
+use crate::snapshot::Snapshot;
+use crate::stats::{ChangeEvent, StatsWriter, TextStats};
+use piece_tree::{BufferCursor, PieceTree, SearchOptions, StringBuffer};
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
 use std::str::FromStr;
+use unicode_segmentation::UnicodeSegmentation;
 
 /// Public alias for positions (1-based line/column), forwarded from piece_tree.
 pub type Position = BufferCursor;
 
+// Bytes read on each side of an edit for its `ChangeEvent`
+const CHANGE_CONTEXT: usize = 64;
+
+// Bytes `matching_bracket` reads looking for a partner before it gives up
+const BRACKET_SCAN_LIMIT: usize = 1 << 20;
+
 #[derive(Debug)]
 pub struct TextBuffer {
     tree: PieceTree,
+    // Lines shared with renderers, dropped on every edit
+    lines_snapshot: RefCell<Option<Rc<Vec<String>>>>,
+    // Edits since the last `take_changes`, once `record_changes` was called
+    changes: Option<Vec<ChangeEvent>>,
+    listeners: ChangeListeners,
+    history: History,
+}
+
+/// Called by [`TextBuffer`] after each edit, see [`TextBuffer::on_change`].
+pub type ChangeCallback = Box<dyn FnMut(&ChangeEvent)>;
+
+// Callbacks registered with `TextBuffer::on_change`
+#[derive(Default)]
+struct ChangeListeners(Vec<ChangeCallback>);
+
+impl std::fmt::Debug for ChangeListeners {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} listeners", self.0.len())
+    }
+}
+
+/// A copy with the same text, undo history and recorded changes. The copy shares the
+/// backing text with the original but edits to either don't affect the other. Callbacks
+/// registered with [`TextBuffer::on_change`] stay with the original.
+impl Clone for TextBuffer {
+    fn clone(&self) -> Self {
+        Self {
+            tree: self.tree.clone(),
+            lines_snapshot: self.lines_snapshot.clone(),
+            changes: self.changes.clone(),
+            listeners: ChangeListeners::default(),
+            history: self.history.clone(),
+        }
+    }
 }
 
 impl TextBuffer {
     /// Build from multiple chunks
-    pub fn from_chunks(mut chunks: Vec<StringBuffer>) -> Self {
-        let tree = PieceTree::new(chunks.as_mut_slice());
-        Self { tree }
+    pub fn from_chunks(chunks: Vec<StringBuffer>) -> Self {
+        Self::from_chunks_with_capacity(chunks, 0)
+    }
+
+    /// Build from multiple chunks, hinting the expected document size in bytes.
+    pub fn from_chunks_with_capacity(mut chunks: Vec<StringBuffer>, capacity: usize) -> Self {
+        let tree = PieceTree::with_capacity(chunks.as_mut_slice(), capacity);
+        Self {
+            tree,
+            lines_snapshot: RefCell::new(None),
+            changes: None,
+            listeners: ChangeListeners::default(),
+            history: History::default(),
+        }
     }
 
     /// Insert `value` at byte `offset` in the document.
     pub fn insert(&mut self, offset: usize, value: &str) {
-        self.tree.insert(offset, value);
+        let offset = offset.min(self.get_length());
+        if !value.is_empty() {
+            self.edit(offset, offset, value);
+        }
     }
 
     /// Delete `len` bytes starting at byte `offset`.
     pub fn delete(&mut self, offset: usize, len: usize) {
-        self.tree.delete(offset, len);
+        let end = offset.saturating_add(len).min(self.get_length());
+        if offset < end {
+            self.edit(offset, end, "");
+        }
+    }
+
+    // Replace the in-range bytes `start..end` with `text` as a new undoable edit
+    fn edit(&mut self, start: usize, end: usize, text: &str) {
+        let removed = self.apply(start, end, text);
+        self.history.record(Edit {
+            offset: start,
+            removed,
+            inserted: text.to_string(),
+        });
+    }
+
+    // Replace the in-range bytes `start..end` with `text`, returning the removed text
+    fn apply(&mut self, start: usize, end: usize, text: &str) -> String {
+        self.lines_snapshot.take();
+        let removed = self.tree.get_text_in_range(start, end);
+        let change = self.change_event(start, &removed, text);
+        self.tree.replace(start, end - start, text);
+        self.notify(change);
+        removed
+    }
+
+    /// Revert the newest edit group. Returns where the caret goes (after the text restored
+    /// by the group's first edit), or `None` when there is nothing to undo.
+    pub fn undo(&mut self) -> Option<EditPosition> {
+        let edits = self.history.undo()?.to_vec();
+        let mut caret = 0;
+        for edit in edits.iter().rev() {
+            let end = edit.offset + edit.inserted.len();
+            self.apply(edit.offset, end, &edit.removed);
+            caret = edit.offset + edit.removed.len();
+        }
+        Some(self.edit_position(caret))
+    }
+
+    /// Make the newest undone edit group again. Returns where the caret goes (after the text
+    /// of the group's last edit), or `None` when there is nothing to redo; any new edit after
+    /// an undo drops what could be redone.
+    pub fn redo(&mut self) -> Option<EditPosition> {
+        let edits = self.history.redo()?.to_vec();
+        let mut caret = 0;
+        for edit in &edits {
+            let end = edit.offset + edit.removed.len();
+            self.apply(edit.offset, end, &edit.inserted);
+            caret = edit.offset + edit.inserted.len();
+        }
+        Some(self.edit_position(caret))
+    }
+
+    fn edit_position(&self, offset: usize) -> EditPosition {
+        EditPosition {
+            offset,
+            position: self.get_position_at(offset),
+        }
+    }
+
+    /// Undo the edits until the matching [`TextBuffer::end_group`] as a single step, e.g. a
+    /// run of typed characters. Groups may nest; only the outermost one counts.
+    pub fn begin_group(&mut self) {
+        self.history.begin_group();
+    }
+
+    /// Close the group opened by the matching [`TextBuffer::begin_group`].
+    pub fn end_group(&mut self) {
+        self.history.end_group();
+    }
+
+    /// Identifies the text by its place in the edit history: every edit makes a new version,
+    /// and undo and redo go back to the versions they restore. Comparing versions tells
+    /// whether the text is still as it was, e.g. when saved, without reading it.
+    pub fn version(&self) -> u64 {
+        self.history.version()
+    }
+
+    /// Forget all undo and redo steps, e.g. after loading a different file.
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Start keeping a [`ChangeEvent`] for every edit, to be collected with
+    /// [`TextBuffer::take_changes`].
+    pub fn record_changes(&mut self) {
+        self.changes.get_or_insert_with(Vec::new);
+    }
+
+    /// The edits made since the last call, oldest first; empty unless recording.
+    pub fn take_changes(&mut self) -> Vec<ChangeEvent> {
+        self.changes
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Call `callback` after every edit, undo and redo with what it changed, e.g. to
+    /// re-highlight just the affected lines. Callbacks run in the order they were added.
+    pub fn on_change(&mut self, callback: ChangeCallback) {
+        self.listeners.0.push(callback);
+    }
+
+    // Describe replacing `removed` at `start` with `inserted`, before the tree changes. None
+    // when nobody is recording or listening.
+    fn change_event(&self, start: usize, removed: &str, inserted: &str) -> Option<ChangeEvent> {
+        if !self.watched() {
+            return None;
+        }
+        let end = start + removed.len();
+        // Words never run across whitespace, so the context can stop at the nearest one
+        let mut before = String::new();
+        self.tree
+            .append_range(start.saturating_sub(CHANGE_CONTEXT), start, &mut before);
+        if let Some((i, _)) = before.char_indices().rfind(|(_, c)| c.is_whitespace()) {
+            before.drain(..i);
+        }
+        let mut after = String::new();
+        self.tree
+            .append_range(end, end + CHANGE_CONTEXT, &mut after);
+        if let Some((i, c)) = after.char_indices().find(|(_, c)| c.is_whitespace()) {
+            after.truncate(i + c.len_utf8());
+        }
+
+        Some(ChangeEvent {
+            offset: start,
+            removed: removed.to_string(),
+            inserted: inserted.to_string(),
+            before,
+            after,
+        })
+    }
+
+    // Pass a change made to the tree on to the listeners, then the recorded changes
+    // Whether anyone sees each edit as it is made
+    fn watched(&self) -> bool {
+        self.changes.is_some() || !self.listeners.0.is_empty()
+    }
+
+    fn notify(&mut self, change: Option<ChangeEvent>) {
+        let Some(change) = change else {
+            return;
+        };
+        for listener in &mut self.listeners.0 {
+            listener(&change);
+        }
+        if let Some(changes) = self.changes.as_mut() {
+            changes.push(change);
+        }
+    }
+
+    /// Line, word, character and byte counts of the whole document, read a piece at a time.
+    pub fn stats(&self) -> TextStats {
+        let mut writer = StatsWriter::default();
+        // Counting cannot fail
+        let _ = self.tree.write_to(&mut writer);
+        writer.finish()
+    }
+
+    /// Replace the bytes in `range` with `text`.
+    pub fn replace(&mut self, range: Range<usize>, text: &str) {
+        let start = range.start.min(self.get_length());
+        let end = range.end.clamp(start, self.get_length());
+        if start < end || !text.is_empty() {
+            self.edit(start, end, text);
+        }
+    }
+
+    /// Make all the edits `f` adds to the batch as one undo step. Their offsets refer to the
+    /// document before the batch, and they are applied from the end backwards; see
+    /// [`BatchEdit`]. Unless change events are wanted, the line count and length are worked
+    /// out once for the whole batch rather than after every edit.
+    pub fn batch(&mut self, f: impl FnOnce(&mut BatchEdit)) {
+        let mut batch = BatchEdit::default();
+        f(&mut batch);
+        let edits = batch.into_ordered(self.get_length());
+        self.begin_group();
+        if self.watched() {
+            // Each event describes the text as the edits before it left it
+            for (range, text) in edits {
+                self.edit(range.start, range.end, &text);
+            }
+        } else {
+            // Every edit lies before the ones applied ahead of it, so what it removes can be
+            // read from the document as it is now
+            self.lines_snapshot.take();
+            let removed: Vec<String> = edits
+                .iter()
+                .map(|(range, _)| self.tree.get_text_in_range(range.start, range.end))
+                .collect();
+            self.tree.replace_many(
+                edits
+                    .iter()
+                    .map(|(range, text)| (range.start, range.len(), text.as_str())),
+            );
+            for ((range, inserted), removed) in edits.into_iter().zip(removed) {
+                self.history.record(Edit {
+                    offset: range.start,
+                    removed,
+                    inserted,
+                });
+            }
+        }
+        self.end_group();
+    }
+
+    /// Replace the selection between byte offsets `anchor` and `head` (in either order) with
+    /// `text`, as when typing, pasting or pressing Enter over it. An empty selection just
+    /// inserts. Returns the caret offset, just past the inserted text.
+    pub fn type_over_selection(&mut self, anchor: usize, head: usize, text: &str) -> usize {
+        let start = anchor.min(head);
+        let end = anchor.max(head);
+        self.replace(start..end, text);
+        start + text.len()
+    }
+
+    /// Copy the 1-based `lines` (end exclusive) to just below themselves, as one undo step.
+    /// When the last of them is the document's last line and has no line break, `eol` is
+    /// put between it and the copy.
+    pub fn duplicate_lines(&mut self, lines: Range<usize>, eol: Eol) {
+        let end = lines.end.min(self.get_line_count() + 1);
+        if lines.start == 0 || lines.start >= end {
+            return;
+        }
+        let start = self.line_start(lines.start);
+        let after = self.line_start(end);
+        let text = self.get_text_in_range(start, after);
+        if end > self.get_line_count() {
+            self.insert(after, &format!("{}{text}", eol.as_str()));
+        } else {
+            self.insert(after, &text);
+        }
+    }
+
+    /// Swap the 1-based `lines` (end exclusive) with the line above them, or below when
+    /// `down`, as one undo step. Every line break stays where it was, so a last line without
+    /// one still has none. Returns false, changing nothing, when there is no line to swap
+    /// with.
+    pub fn move_lines(&mut self, lines: Range<usize>, down: bool) -> bool {
+        let count = self.get_line_count();
+        if lines.start == 0 || lines.start >= lines.end || lines.end > count + 1 {
+            return false;
+        }
+        let (first, end) = match down {
+            false if lines.start > 1 => (lines.start - 1, lines.end),
+            true if lines.end <= count => (lines.start, lines.end + 1),
+            _ => return false,
+        };
+
+        let mut contents = self.get_lines_range(first, end);
+        let breaks: Vec<String> = (first..end)
+            .map(|line| {
+                let content_end = self.get_offset_at(line, self.get_line_max_column(line));
+                self.get_text_in_range(content_end, self.line_start(line + 1))
+            })
+            .collect();
+        if down {
+            contents.rotate_right(1);
+        } else {
+            contents.rotate_left(1);
+        }
+        let text: String = contents
+            .iter()
+            .zip(&breaks)
+            .map(|(content, eol)| format!("{content}{eol}"))
+            .collect();
+        let range = self.line_start(first)..self.line_start(end);
+        self.replace(range, &text);
+        true
+    }
+
+    /// Join the 1-based `lines` (end exclusive) into one, or a single line with the one after
+    /// it, as one undo step. The spaces and tabs around each line break become one space,
+    /// or nothing next to an empty line. Returns the offset of the last join, or `None`
+    /// when there is no line to join with.
+    pub fn join_lines(&mut self, lines: Range<usize>) -> Option<usize> {
+        let last = lines.end.saturating_sub(1).max(lines.start + 1);
+        if lines.start == 0 || last > self.get_line_count() {
+            return None;
+        }
+
+        let joins: Vec<(Range<usize>, &str)> = (lines.start..last)
+            .map(|line| {
+                let content = self.get_line_content(line);
+                let kept = content.trim_end_matches([' ', '\t']).len();
+                let next = self.get_line_content(line + 1);
+                let indent = leading_whitespace(&next).len();
+                let start = self.get_offset_at(line, kept + 1);
+                let end = self.get_offset_at(line + 1, indent + 1);
+                let space = if kept == 0 || indent == next.len() {
+                    ""
+                } else {
+                    " "
+                };
+                (start..end, space)
+            })
+            .collect();
+        // Each join shifts the ones after it by what it removed
+        let shift: usize = joins[..joins.len() - 1]
+            .iter()
+            .map(|(range, space)| range.len() - space.len())
+            .sum();
+        let caret = joins.last().map(|(range, _)| range.start - shift)?;
+        self.batch(|batch| {
+            for (range, space) in joins {
+                batch.replace(range, space);
+            }
+        });
+        Some(caret)
+    }
+
+    // Offset of a 1-based line's start, or the length past the last line
+    fn line_start(&self, line: usize) -> usize {
+        if line > self.get_line_count() {
+            self.get_length()
+        } else {
+            self.get_offset_at(line, 1)
+        }
+    }
+
+    /// Replace the entire contents with `text`, e.g. on reload or when applying formatter
+    /// output. The piece tree is rebuilt from scratch instead of deleting and re-inserting.
+    /// The buffer keeps no caret: positions held by callers refer to the old text and should
+    /// be clamped to the new line count and line lengths.
+    pub fn set_text(&mut self, text: &str) {
+        self.lines_snapshot.take();
+        let removed = self.get_text();
+        let change = self.change_event(0, &removed, text);
+        self.history.record(Edit {
+            offset: 0,
+            removed,
+            inserted: text.to_string(),
+        });
+        let mut tree = PieceTree::new(&mut []);
+        tree.insert(0, text);
+        self.tree = tree;
+        self.notify(change);
     }
 
     /// Convenience: insert at (line, column), both 1-based.
@@ -43,6 +447,73 @@ impl TextBuffer {
         self.tree.get_text()
     }
 
+    /// Text of the byte range `start..end`, without reading the rest of the document. `end`
+    /// is clamped to the length; characters cut by the range are left out.
+    pub fn get_text_in_range(&self, start: usize, end: usize) -> String {
+        self.tree.get_text_in_range(start, end)
+    }
+
+    /// Byte offsets of the non-overlapping matches of `needle`, in order.
+    pub fn find_all(&self, needle: &str, opts: SearchOptions) -> Vec<usize> {
+        self.tree.find_all_opts(needle, opts)
+    }
+
+    /// Hash of the complete text, stable across edits that restore the same content.
+    pub fn content_hash(&self) -> u64 {
+        self.tree.content_hash()
+    }
+
+    /// The most frequent line break in the document, `Eol::Lf` when there is none.
+    pub fn detect_eol(&self) -> Eol {
+        let mut counts = eol::EolCounts::default();
+        self.tree.for_each_chunk(|chunk| counts.add(chunk));
+        counts.majority()
+    }
+
+    /// Rewrite every line break of the document as `to`, as one undoable edit. The line
+    /// count stays the same, and a document without a trailing line break gets none.
+    pub fn convert_line_endings(&mut self, to: Eol) {
+        let lines = self.snapshot_lines();
+        let converted = lines.join(to.as_str());
+        if converted.len() != self.get_length() || converted != self.get_text() {
+            self.set_text(&converted);
+        }
+    }
+
+    /// The current text, read-only and unaffected by later edits.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::new(self.tree.clone())
+    }
+
+    /// Write the document to `writer` as it is, a piece at a time rather than as one
+    /// [`TextBuffer::get_text`] copy.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.tree.write_to(writer)
+    }
+
+    /// Write the document to `writer`, rewriting line breaks per `mode`. A document without
+    /// a trailing line break is written without one.
+    pub fn save_to_writer<W: Write>(&self, mut writer: W, mode: EolMode) -> io::Result<()> {
+        match mode {
+            EolMode::Preserve => self.write_to(&mut writer)?,
+            EolMode::Normalize(eol) => {
+                for (i, line) in self.snapshot_lines().iter().enumerate() {
+                    if i > 0 {
+                        writer.write_all(eol.as_str().as_bytes())?;
+                    }
+                    writer.write_all(line.as_bytes())?;
+                }
+            }
+        }
+        writer.flush()
+    }
+
+    /// Save the document to `path` with [`save_atomic_with`], streaming it into the temp
+    /// file as [`TextBuffer::save_to_writer`] does.
+    pub fn save_to_path<P: AsRef<Path>>(&self, path: P, mode: EolMode) -> io::Result<()> {
+        save_atomic_with(path, |writer| self.save_to_writer(writer, mode))
+    }
+
     /// Get the number of lines (1-based; empty doc => 1 line).
     pub fn get_line_count(&self) -> usize {
         self.tree.line_count()
@@ -58,11 +529,156 @@ impl TextBuffer {
         self.tree.get_line_content(line_number)
     }
 
+    /// Clear `out` and write the content of a line (1-based) into it, reusing its allocation.
+    pub fn write_line_content(&self, line_number: usize, out: &mut String) {
+        self.tree.write_line_content(line_number, out);
+    }
+
     /// Get all lines (without EOL).
     pub fn get_lines_content(&self) -> Vec<String> {
         self.tree.get_lines_content()
     }
 
+    /// Lines `start..end` (1-based, `end` excluded, without EOL), clamped to the document.
+    /// Reads only the pieces holding them, so fetching a screenful costs the same anywhere in
+    /// a large document.
+    pub fn get_lines_range(&self, start: usize, end: usize) -> Vec<String> {
+        self.tree.line_range(start, end)
+    }
+
+    /// All lines (without EOL) as a shared handle, built once and reused until the next edit.
+    pub fn snapshot_lines(&self) -> Rc<Vec<String>> {
+        self.lines_snapshot
+            .borrow_mut()
+            .get_or_insert_with(|| Rc::new(self.tree.get_lines_content()))
+            .clone()
+    }
+
+    /// Lazily iterate `(line_number, content)` pairs, 1-based, without EOL.
+    pub fn enumerated_lines(&self) -> impl Iterator<Item = (usize, String)> + '_ {
+        self.tree.lines().enumerate().map(|(i, line)| (i + 1, line))
+    }
+
+    /// Lines around the line containing byte `offset`: up to `before` lines above and `after`
+    /// below, clamped to the document. Returns the lines and the index of the target line in them.
+    pub fn context_around(
+        &self,
+        offset: usize,
+        before: usize,
+        after: usize,
+    ) -> (Vec<String>, usize) {
+        let target = self.get_position_at(offset.min(self.get_length())).line();
+        let first = target.saturating_sub(before).max(1);
+        let last = target.saturating_add(after).min(self.get_line_count());
+        let lines = (first..=last)
+            .map(|line_number| self.get_line_content(line_number))
+            .collect();
+        (lines, target - first)
+    }
+
+    /// Byte offset of the bracket matching the one at `offset`, if that byte is one of `()[]{}`.
+    /// Scans the pieces in place from `offset`, so only the text up to the partner is read,
+    /// and no further than a MiB: a partner beyond that counts as missing.
+    pub fn matching_bracket(&self, offset: usize) -> Option<usize> {
+        let bracket = self.tree.byte_at(offset)?;
+        let (partner, forward) = match bracket {
+            b'(' => (b')', true),
+            b'[' => (b']', true),
+            b'{' => (b'}', true),
+            b')' => (b'(', false),
+            b']' => (b'[', false),
+            b'}' => (b'{', false),
+            _ => return None,
+        };
+
+        // Brackets are ASCII, so scanning bytes never lands inside a multi-byte char
+        let mut depth = 0usize;
+        self.tree
+            .find_byte(offset, !forward, BRACKET_SCAN_LIMIT, |byte| {
+                if byte == bracket {
+                    depth += 1;
+                } else if byte == partner {
+                    depth -= 1;
+                    return depth == 0;
+                }
+                false
+            })
+    }
+
+    /// Byte range of the word (letters, digits and `_`) at byte `offset`, or ending there
+    /// when the caret sits just after it. Words never span lines.
+    pub fn word_range_at(&self, offset: usize) -> Option<Range<usize>> {
+        let (line_start, _, word) = self.word_in_line(offset)?;
+        Some(line_start + word.start..line_start + word.end)
+    }
+
+    /// The word at byte `offset`, as found by [`TextBuffer::word_range_at`].
+    pub fn word_at(&self, offset: usize) -> Option<String> {
+        let (_, line, word) = self.word_in_line(offset)?;
+        Some(line[word].to_string())
+    }
+
+    // Offset of the line holding `offset`, its content and the word's byte range in it
+    fn word_in_line(&self, offset: usize) -> Option<(usize, String, Range<usize>)> {
+        let offset = offset.min(self.get_length());
+        let position = self.get_position_at(offset);
+        let line = self.get_line_content(position.line());
+        let column = floor_char_boundary(&line, position.column() - 1);
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+        let start = line[..column]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| is_word(c))
+            .last()
+            .map_or(column, |(i, _)| i);
+        let end = line[column..]
+            .char_indices()
+            .find(|&(_, c)| !is_word(c))
+            .map_or(line.len(), |(i, _)| column + i);
+        if start == end {
+            return None;
+        }
+        Some((offset - (position.column() - 1), line, start..end))
+    }
+
+    /// Offset where Ctrl+Right lands from `offset`: past any whitespace, then to the end of
+    /// the next word or punctuation run (Unicode word boundaries). From the end of a line it
+    /// moves to the start of the next one.
+    pub fn next_word_boundary(&self, offset: usize) -> usize {
+        let offset = offset.min(self.get_length());
+        let position = self.get_position_at(offset);
+        let line = self.get_line_content(position.line());
+        let column = position.column() - 1;
+        if column >= line.len() {
+            return match position.line() < self.get_line_count() {
+                true => self.get_offset_at(position.line() + 1, 1),
+                false => offset,
+            };
+        }
+        offset - column + next_boundary_in_line(&line, floor_char_boundary(&line, column))
+    }
+
+    /// Offset where Ctrl+Left lands from `offset`: back over any whitespace, then to the
+    /// start of the word or punctuation run before it. From the start of a line it moves to
+    /// the end of the previous one.
+    pub fn prev_word_boundary(&self, offset: usize) -> usize {
+        let offset = offset.min(self.get_length());
+        let position = self.get_position_at(offset);
+        let column = position.column() - 1;
+        if column == 0 {
+            return match position.line() > 1 {
+                true => {
+                    let prev = position.line() - 1;
+                    self.get_offset_at(prev, 1) + self.get_line_length(prev)
+                }
+                false => offset,
+            };
+        }
+        let line = self.get_line_content(position.line());
+        offset - column + prev_boundary_in_line(&line, floor_char_boundary(&line, column))
+    }
+
     /// Get the byte length (without EOL) of a line (1-based).
     pub fn get_line_length(&self, line_number: usize) -> usize {
         self.tree.get_line_length(line_number)
@@ -78,6 +694,36 @@ impl TextBuffer {
         self.tree.get_position_at(offset)
     }
 
+    /// 1-based (line, column) to 0-based byte offset, with `column` counted per `mode`.
+    pub fn get_offset_at_column(
+        &self,
+        line_number: usize,
+        column: usize,
+        mode: ColumnMode,
+    ) -> usize {
+        let byte = match mode {
+            ColumnMode::Bytes => column.saturating_sub(1),
+            ColumnMode::TabAware { .. } => mode.byte_index(
+                &self.get_line_content(line_number),
+                column.saturating_sub(1),
+            ),
+        };
+        self.get_offset_at(line_number, byte + 1)
+    }
+
+    /// 0-based byte offset to 1-based position, with the column counted per `mode`.
+    pub fn get_position_at_column(&self, offset: usize, mode: ColumnMode) -> Position {
+        let position = self.get_position_at(offset);
+        match mode {
+            ColumnMode::Bytes => position,
+            ColumnMode::TabAware { .. } => {
+                let line = self.get_line_content(position.line());
+                let column = mode.column(&line, position.column() - 1);
+                Position::new(position.line(), column + 1)
+            }
+        }
+    }
+
     /// UI-friendly: max column on a line (1-based).
     pub fn get_line_max_column(&self, line_number: usize) -> usize {
         self.get_line_length(line_number) + 1
@@ -96,3 +742,856 @@ impl FromStr for TextBuffer {
         Ok(Self::from_chunks(vec![chunk]))
     }
 }
+
+// Byte `column` of `line`, moved back to the start of the char it falls inside
+fn floor_char_boundary(line: &str, column: usize) -> usize {
+    let mut column = column.min(line.len());
+    while !line.is_char_boundary(column) {
+        column -= 1;
+    }
+    column
+}
+
+// End of the first segment after byte `column` of `line` that is not whitespace, or the end
+// of the line
+fn next_boundary_in_line(line: &str, column: usize) -> usize {
+    line.split_word_bound_indices()
+        .map(|(start, segment)| (start, start + segment.len(), segment))
+        .filter(|&(_, end, _)| end > column)
+        .find(|(_, _, segment)| !segment.trim().is_empty())
+        .map_or(line.len(), |(_, end, _)| end)
+}
+
+// Start of the last segment before byte `column` of `line` that is not whitespace, or 0
+fn prev_boundary_in_line(line: &str, column: usize) -> usize {
+    line.split_word_bound_indices()
+        .rev()
+        .filter(|&(start, _)| start < column)
+        .find(|(_, segment)| !segment.trim().is_empty())
+        .map_or(0, |(start, _)| start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delete_at_joins_lines_and_takes_whole_graphemes() {
+        let mut buffer = TextBuffer::from_str("one\r\ntwo\nthree").unwrap();
+        buffer.delete_at(1, 4, 2);
+        assert_eq!(buffer.get_text(), "onetwo\nthree");
+        assert_eq!(buffer.get_line_count(), 2);
+        buffer.delete_at(1, 7, 1);
+        assert_eq!(buffer.get_text(), "onetwothree");
+        assert_eq!(buffer.get_line_count(), 1);
+
+        // "é" as one code point and as e + combining acute, then a four-byte emoji
+        let mut buffer = TextBuffer::from_str("\u{e9}e\u{301}\u{1f600}!").unwrap();
+        buffer.delete_at(1, 1, '\u{e9}'.len_utf8());
+        assert_eq!(buffer.get_text(), "e\u{301}\u{1f600}!");
+        buffer.delete_at(1, 1, "e\u{301}".len());
+        assert_eq!(buffer.get_text(), "\u{1f600}!");
+        buffer.delete_at(1, 1, 4);
+        assert_eq!(buffer.get_text(), "!");
+    }
+
+    #[test]
+    fn word_boundaries_skip_whitespace_and_stop_at_punctuation() {
+        let line = "let x  = foo(bar);";
+        let nexts: Vec<usize> = std::iter::successors(Some(0), |&c| {
+            (c < line.len()).then(|| next_boundary_in_line(line, c))
+        })
+        .collect();
+        assert_eq!(nexts, vec![0, 3, 5, 8, 12, 13, 16, 17, 18]);
+
+        let prevs: Vec<usize> = std::iter::successors(Some(line.len()), |&c| {
+            (c > 0).then(|| prev_boundary_in_line(line, c))
+        })
+        .collect();
+        assert_eq!(prevs, vec![18, 17, 16, 13, 12, 9, 7, 4, 0]);
+
+        // Inside a word: to its ends
+        assert_eq!(next_boundary_in_line(line, 10), 12);
+        assert_eq!(prev_boundary_in_line(line, 10), 9);
+        // Trailing whitespace runs to the line end
+        assert_eq!(next_boundary_in_line("end   ", 3), 6);
+        assert_eq!(prev_boundary_in_line("   start", 3), 0);
+    }
+
+    #[test]
+    fn word_boundaries_step_through_cjk_and_cross_lines() {
+        // Each ideograph is a word of its own
+        let line = "日本語 text";
+        assert_eq!(next_boundary_in_line(line, 0), 3);
+        assert_eq!(next_boundary_in_line(line, 9), 14);
+        assert_eq!(prev_boundary_in_line(line, 9), 6);
+
+        let buffer = TextBuffer::from_str("one two\r\n  three").unwrap();
+        assert_eq!(buffer.next_word_boundary(4), 7);
+        assert_eq!(buffer.next_word_boundary(7), 9);
+        assert_eq!(buffer.next_word_boundary(9), 16);
+        assert_eq!(buffer.next_word_boundary(16), 16);
+        // Back over the indentation to the line start first
+        assert_eq!(buffer.prev_word_boundary(11), 9);
+        assert_eq!(buffer.prev_word_boundary(9), 7);
+        assert_eq!(buffer.prev_word_boundary(7), 4);
+        assert_eq!(buffer.prev_word_boundary(0), 0);
+    }
+
+    #[test]
+    fn enumerated_lines_are_one_based() {
+        let buffer = TextBuffer::from_str("alpha\r\nbeta\ngamma\n").unwrap();
+        let expected: Vec<(usize, String)> = buffer
+            .get_lines_content()
+            .into_iter()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line))
+            .collect();
+
+        let lines: Vec<(usize, String)> = buffer.enumerated_lines().collect();
+        assert_eq!(lines, expected);
+        assert_eq!(lines.first(), Some(&(1, "alpha".to_string())));
+        assert_eq!(lines.last(), Some(&(4, String::new())));
+    }
+
+    #[test]
+    fn context_around_clamps_at_document_edges() {
+        let text: String = (1..=9).map(|i| format!("line {i}\n")).collect();
+        let buffer = TextBuffer::from_str(text.trim_end()).unwrap();
+        let lines = |range: std::ops::RangeInclusive<usize>| -> Vec<String> {
+            range.map(|i| format!("line {i}")).collect()
+        };
+
+        // Near the top: only one line before line 2
+        let offset = buffer.get_offset_at(2, 3);
+        assert_eq!(buffer.context_around(offset, 2, 2), (lines(1..=4), 1));
+
+        // Middle: full context
+        let offset = buffer.get_offset_at(5, 1);
+        assert_eq!(buffer.context_around(offset, 2, 2), (lines(3..=7), 2));
+
+        // Bottom: nothing after the last line
+        let offset = buffer.get_length();
+        assert_eq!(buffer.context_around(offset, 2, 2), (lines(7..=9), 2));
+    }
+
+    #[test]
+    fn column_modes_round_trip_through_offsets() {
+        let buffer = TextBuffer::from_str("x\n\tif a {\n\t\tb\n").unwrap();
+        let tabs = ColumnMode::TabAware { tab_width: 4 };
+
+        // Byte columns are unaffected by the mode parameter
+        assert_eq!(
+            buffer.get_offset_at_column(3, 3, ColumnMode::Bytes),
+            buffer.get_offset_at(3, 3)
+        );
+        assert_eq!(buffer.get_offset_at_column(3, 3, ColumnMode::Bytes), 12);
+
+        // Cell 9 (1-based) on line 3 is 'b', after two tabs
+        let offset = buffer.get_offset_at_column(3, 9, tabs);
+        assert_eq!(&buffer.get_text()[offset..offset + 1], "b");
+        assert_eq!(
+            buffer.get_position_at_column(offset, tabs),
+            Position::new(3, 9)
+        );
+        assert_eq!(
+            buffer.get_position_at_column(offset, ColumnMode::Bytes),
+            Position::new(3, 3)
+        );
+
+        // Cell 5 on line 2 is the 'i' of "if"
+        let offset = buffer.get_offset_at_column(2, 5, tabs);
+        assert_eq!(&buffer.get_text()[offset..offset + 2], "if");
+    }
+
+    #[test]
+    fn snapshot_lines_is_shared_until_an_edit() {
+        let mut buffer = TextBuffer::from_str("one\ntwo").unwrap();
+        let first = buffer.snapshot_lines();
+        let second = buffer.snapshot_lines();
+        assert!(Rc::ptr_eq(&first, &second));
+        assert_eq!(*first, buffer.get_lines_content());
+
+        buffer.insert(3, "!");
+        let edited = buffer.snapshot_lines();
+        assert!(!Rc::ptr_eq(&first, &edited));
+        assert_eq!(*edited, vec!["one!".to_string(), "two".to_string()]);
+        assert_eq!(*first, vec!["one".to_string(), "two".to_string()]);
+
+        buffer.delete(0, 1);
+        assert_eq!(buffer.snapshot_lines()[0], "ne!");
+    }
+
+    #[test]
+    fn detect_eol_joins_a_crlf_split_across_pieces() {
+        let mut buffer = TextBuffer::from_str("a\r").unwrap();
+        buffer.insert(2, "\nb\n");
+        buffer.insert(0, "x\r\n");
+        assert_eq!(buffer.detect_eol(), Eol::CrLf);
+    }
+
+    #[test]
+    fn cr_only_document_round_trips() {
+        let original = "# Title\rfirst line\r\rlast line\r".as_bytes();
+        let mut buffer = crate::TextBufferBuilder::from_reader(original).unwrap();
+
+        assert_eq!(buffer.detect_eol(), Eol::Cr);
+        assert_eq!(buffer.get_line_count(), 5);
+        assert_eq!(
+            buffer.get_lines_content(),
+            vec!["# Title", "first line", "", "last line", ""]
+        );
+        assert_eq!(buffer.get_line_content(4), "last line");
+
+        // Edit and revert, then the preserved bytes are the original ones
+        let offset = buffer.get_offset_at(3, 1);
+        buffer.insert(offset, "middle\r");
+        assert_eq!(buffer.get_line_content(3), "middle");
+        assert_eq!(buffer.get_line_count(), 6);
+        buffer.delete(offset, "middle\r".len());
+
+        let mut preserved = Vec::new();
+        buffer
+            .save_to_writer(&mut preserved, EolMode::Preserve)
+            .unwrap();
+        assert_eq!(preserved, original);
+
+        let mut normalized = Vec::new();
+        buffer
+            .save_to_writer(&mut normalized, EolMode::Normalize(Eol::Lf))
+            .unwrap();
+        assert_eq!(normalized, b"# Title\nfirst line\n\nlast line\n");
+
+        let mut crlf = Vec::new();
+        buffer
+            .save_to_writer(&mut crlf, EolMode::Normalize(Eol::CrLf))
+            .unwrap();
+        assert_eq!(crlf, b"# Title\r\nfirst line\r\n\r\nlast line\r\n");
+    }
+
+    #[test]
+    fn write_to_matches_get_text() {
+        let mut buffer = TextBuffer::from_str("alpha\r\nbeta\n").unwrap();
+        buffer.insert(5, " one");
+        buffer.insert(0, "ünïcode ");
+        buffer.delete(3, 4);
+
+        let mut out = Vec::new();
+        buffer.write_to(&mut out).unwrap();
+        assert_eq!(out, buffer.get_text().into_bytes());
+
+        let path =
+            std::env::temp_dir().join(format!("text_buffer_write_to_{}", std::process::id()));
+        let saved = buffer.save_to_path(&path, EolMode::Normalize(Eol::Lf));
+        let written = std::fs::read(&path);
+        std::fs::remove_file(&path).unwrap();
+        saved.unwrap();
+        assert_eq!(
+            written.unwrap(),
+            buffer.get_text().replace("\r\n", "\n").into_bytes()
+        );
+    }
+
+    #[test]
+    fn normalize_keeps_missing_final_break() {
+        let buffer = TextBuffer::from_str("a\r\nb\rc").unwrap();
+        let mut out = Vec::new();
+        buffer
+            .save_to_writer(&mut out, EolMode::Normalize(Eol::Lf))
+            .unwrap();
+        assert_eq!(out, b"a\nb\nc");
+    }
+
+    #[test]
+    fn converting_line_endings_round_trips() {
+        let original = "# Title\n\nbody\nlast";
+        let mut buffer = TextBuffer::from_str(original).unwrap();
+
+        buffer.convert_line_endings(Eol::CrLf);
+        assert_eq!(buffer.get_text(), "# Title\r\n\r\nbody\r\nlast");
+        assert_eq!(buffer.get_line_count(), 4);
+        buffer.convert_line_endings(Eol::Cr);
+        assert_eq!(buffer.get_text(), "# Title\r\rbody\rlast");
+        assert_eq!(buffer.get_line_count(), 4);
+        buffer.convert_line_endings(Eol::Lf);
+        assert_eq!(buffer.get_text(), original);
+
+        // Mixed breaks all become one kind; a trailing break stays
+        let mut buffer = TextBuffer::from_str("a\r\nb\rc\n").unwrap();
+        buffer.convert_line_endings(Eol::Lf);
+        assert_eq!(buffer.get_text(), "a\nb\nc\n");
+        assert_eq!(buffer.undo().map(|p| p.offset), Some(7));
+        assert_eq!(buffer.get_text(), "a\r\nb\rc\n");
+
+        // Nothing to convert: no edit to undo
+        let mut buffer = TextBuffer::from_str("").unwrap();
+        buffer.convert_line_endings(Eol::CrLf);
+        assert_eq!(buffer.get_text(), "");
+        assert_eq!(buffer.undo(), None);
+    }
+
+    #[test]
+    fn set_text_matches_a_fresh_buffer() {
+        let mut buffer = TextBuffer::from_str("old\ncontent that is longer").unwrap();
+        buffer.insert(3, " edited");
+        let stale = buffer.snapshot_lines();
+
+        let text = "fn main() {\r\n    body();\r\n}\n";
+        buffer.set_text(text);
+        let fresh = TextBuffer::from_str(text).unwrap();
+
+        assert_eq!(buffer.get_text(), fresh.get_text());
+        assert_eq!(buffer.get_lines_content(), fresh.get_lines_content());
+        assert_eq!(buffer.get_line_count(), 4);
+        assert_eq!(buffer.get_length(), text.len());
+        assert_eq!(buffer.get_offset_at(2, 5), fresh.get_offset_at(2, 5));
+        assert!(!Rc::ptr_eq(&stale, &buffer.snapshot_lines()));
+        assert_eq!(buffer.tree.verify_invariants(), Ok(()));
+
+        buffer.set_text("");
+        assert_eq!(buffer.get_line_count(), 1);
+        assert_eq!(buffer.get_length(), 0);
+        assert_eq!(buffer.tree.verify_invariants(), Ok(()));
+    }
+
+    // Random document mixing line endings, multi-byte chars and an optional final break
+    fn random_document(seed: u64, len: usize) -> String {
+        const PIECES: [&str; 10] = [
+            "\n", "\r\n", "\r", "a", "word ", "é", "€", "𝄞", "中文", "\t",
+        ];
+        let mut state = seed;
+        let mut text = String::new();
+        while text.len() < len {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            text.push_str(PIECES[(state >> 33) as usize % PIECES.len()]);
+        }
+        if seed.is_multiple_of(2) {
+            text.truncate(text.trim_end_matches(['\r', '\n']).len());
+        }
+        text
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        // Mostly small documents, plus some spanning several read chunks so that line
+        // breaks and multi-byte chars land on chunk boundaries
+        for seed in 0..200u64 {
+            let len = if seed.is_multiple_of(20) {
+                200_000
+            } else {
+                1 + seed as usize * 7
+            };
+            let text = random_document(seed, len);
+            let original = TextBuffer::from_str(&text).unwrap();
+
+            let mut saved = Vec::new();
+            original
+                .save_to_writer(&mut saved, EolMode::Preserve)
+                .unwrap();
+            let reloaded = crate::TextBufferBuilder::from_reader(saved.as_slice()).unwrap();
+
+            assert_eq!(reloaded.get_text(), original.get_text(), "seed {seed}");
+            assert_eq!(
+                reloaded.get_lines_content(),
+                original.get_lines_content(),
+                "seed {seed}"
+            );
+            assert_eq!(
+                reloaded.get_line_count(),
+                original.get_line_count(),
+                "seed {seed}"
+            );
+
+            // Normalizing changes the breaks but never the lines
+            let mut normalized = Vec::new();
+            original
+                .save_to_writer(&mut normalized, EolMode::Normalize(Eol::CrLf))
+                .unwrap();
+            let reloaded = crate::TextBufferBuilder::from_reader(normalized.as_slice()).unwrap();
+            assert_eq!(
+                reloaded.get_lines_content(),
+                original.get_lines_content(),
+                "seed {seed}"
+            );
+        }
+    }
+
+    #[test]
+    fn typing_replaces_a_multi_line_selection() {
+        let mut buffer = TextBuffer::from_str("alpha\nbeta\ngamma").unwrap();
+        // Select "pha\nbeta\nga" with the head before the anchor
+        let caret = buffer.type_over_selection(13, 2, "X");
+        assert_eq!(buffer.get_text(), "alXmma");
+        assert_eq!(caret, 3);
+        assert_eq!(buffer.get_line_count(), 1);
+    }
+
+    #[test]
+    fn paste_and_enter_replace_a_selection() {
+        let mut buffer = TextBuffer::from_str("one two three").unwrap();
+        let caret = buffer.type_over_selection(4, 7, "2\nand 2½");
+        assert_eq!(buffer.get_text(), "one 2\nand 2½ three");
+        assert_eq!(caret, 4 + "2\nand 2½".len());
+        assert_eq!(buffer.get_position_at(caret), Position::new(2, 8));
+
+        let caret = buffer.type_over_selection(3, 4, "\n");
+        assert_eq!(buffer.get_lines_content(), vec!["one", "2", "and 2½ three"]);
+        assert_eq!(buffer.get_position_at(caret), Position::new(2, 1));
+
+        // Empty selection inserts at the caret
+        let caret = buffer.type_over_selection(0, 0, ">");
+        assert_eq!(buffer.get_line_content(1), ">one");
+        assert_eq!(caret, 1);
+    }
+
+    #[test]
+    fn duplicated_lines_land_below_with_a_line_break() {
+        let mut buffer = TextBuffer::from_str("one\ntwo\nthree").unwrap();
+        buffer.duplicate_lines(1..3, Eol::Lf);
+        assert_eq!(buffer.get_text(), "one\ntwo\none\ntwo\nthree");
+
+        // The last line gets a break before its copy, and none after it
+        buffer.duplicate_lines(5..6, Eol::CrLf);
+        assert_eq!(buffer.get_text(), "one\ntwo\none\ntwo\nthree\r\nthree");
+        buffer.undo();
+        assert_eq!(buffer.get_text(), "one\ntwo\none\ntwo\nthree");
+
+        buffer.duplicate_lines(4..4, Eol::Lf);
+        buffer.duplicate_lines(9..10, Eol::Lf);
+        assert_eq!(buffer.get_line_count(), 5);
+    }
+
+    #[test]
+    fn moved_lines_swap_with_their_neighbor() {
+        let mut buffer = TextBuffer::from_str("a\r\nb\nc").unwrap();
+        assert!(buffer.move_lines(3..4, false));
+        assert_eq!(buffer.get_text(), "a\r\nc\nb");
+        assert!(buffer.move_lines(1..3, true));
+        assert_eq!(buffer.get_text(), "b\r\na\nc");
+        buffer.undo();
+        assert_eq!(buffer.get_text(), "a\r\nc\nb");
+
+        // Nothing above the first line or below the last
+        assert!(!buffer.move_lines(1..2, false));
+        assert!(!buffer.move_lines(2..4, true));
+        assert_eq!(buffer.get_text(), "a\r\nc\nb");
+    }
+
+    #[test]
+    fn joined_lines_meet_at_one_space() {
+        let mut buffer = TextBuffer::from_str("one  \n\t two\nthree\n\nfour").unwrap();
+        assert_eq!(buffer.join_lines(1..2), Some(3));
+        assert_eq!(buffer.get_text(), "one two\nthree\n\nfour");
+
+        // Empty lines add no space
+        assert_eq!(buffer.join_lines(2..5), Some(13));
+        assert_eq!(buffer.get_text(), "one two\nthreefour");
+        buffer.undo();
+        assert_eq!(buffer.get_text(), "one two\nthree\n\nfour");
+
+        // Nothing follows the last line
+        assert_eq!(buffer.join_lines(4..5), None);
+        assert_eq!(buffer.get_text(), "one two\nthree\n\nfour");
+    }
+
+    #[test]
+    fn word_at_finds_the_word_around_an_offset() {
+        let buffer = TextBuffer::from_str("let ok = true;\r\nnaïve_2 = x").unwrap();
+        assert_eq!(buffer.word_range_at(9), Some(9..13));
+        assert_eq!(buffer.word_at(11).as_deref(), Some("true"));
+        // Just after a word still finds it
+        assert_eq!(buffer.word_at(13).as_deref(), Some("true"));
+        assert_eq!(buffer.word_at(7), None);
+        assert_eq!(buffer.word_at(16).as_deref(), Some("naïve_2"));
+        assert_eq!(buffer.word_range_at(28), Some(27..28));
+    }
+
+    #[test]
+    fn word_helpers_take_offsets_inside_a_char() {
+        let buffer = TextBuffer::from_str("naïve café").unwrap();
+        // Offset 3 is the second byte of "ï"
+        assert_eq!(buffer.word_range_at(3), Some(0..6));
+        assert_eq!(buffer.word_at(3).as_deref(), Some("naïve"));
+        assert_eq!(buffer.next_word_boundary(3), 6);
+        assert_eq!(buffer.prev_word_boundary(3), 0);
+        // Inside the last char of the document
+        assert_eq!(buffer.word_at(11).as_deref(), Some("café"));
+
+        let buffer = TextBuffer::from_str("é").unwrap();
+        assert_eq!(buffer.word_at(1).as_deref(), Some("é"));
+    }
+
+    #[test]
+    fn incremental_stats_match_a_full_recount() {
+        let mut buffer = TextBuffer::from_str("hello wörld\r\nline two").unwrap();
+        let mut stats = buffer.stats();
+        buffer.record_changes();
+
+        let mut check = |buffer: &mut TextBuffer| {
+            for change in buffer.take_changes() {
+                stats.apply(&change);
+            }
+            assert_eq!(stats, buffer.stats(), "{:?}", buffer.get_text());
+        };
+
+        buffer.insert(5, "X"); // extends "hello"
+        check(&mut buffer);
+        buffer.delete(6, 1); // removes the space, joining two words
+        check(&mut buffer);
+        buffer.insert(13, "\n"); // between CR and LF: one break becomes two
+        check(&mut buffer);
+        buffer.delete(13, 1); // and back
+        check(&mut buffer);
+        buffer.replace(0..6, "a b\r"); // splits a word with a lone CR
+        check(&mut buffer);
+        buffer.delete(2, buffer.get_length() - 4);
+        check(&mut buffer);
+        buffer.set_text("fresh\ttext\n");
+        check(&mut buffer);
+        buffer.insert(buffer.get_length(), "ö");
+        check(&mut buffer);
+
+        // Joins that only hold with more than one character of context
+        buffer.set_text("can t, 3 4");
+        check(&mut buffer);
+        buffer.replace(3..4, "'"); // "can't" is one word
+        check(&mut buffer);
+        buffer.replace(8..9, "."); // and so is "3.4"
+        check(&mut buffer);
+        buffer.insert(5, "漢字"); // each ideograph on its own
+        check(&mut buffer);
+    }
+
+    #[test]
+    fn change_callbacks_see_every_edit() {
+        let mut buffer = TextBuffer::from_str("one\r\ntwo").unwrap();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let seen = Rc::clone(&events);
+        buffer.on_change(Box::new(move |change| {
+            seen.borrow_mut().push((
+                change.offset,
+                change.removed_len(),
+                change.inserted_len(),
+                change.line_delta(),
+            ))
+        }));
+
+        buffer.insert(3, "!\n");
+        buffer.delete(0, 3);
+        buffer.replace(3..7, "2\n3\n");
+        buffer.insert(1, "\r"); // before the LF: still one line break
+        buffer.insert(2, "x"); // between CR and LF: one line break becomes two
+        buffer.undo();
+        buffer.set_text("");
+        assert_eq!(
+            *events.borrow(),
+            [
+                (3, 0, 2, 1),
+                (0, 3, 0, 0),
+                (3, 4, 4, 2),
+                (1, 0, 1, 0),
+                (2, 0, 1, 1),
+                (2, 1, 0, -1),
+                (0, 8, 0, -4),
+            ]
+        );
+    }
+
+    #[test]
+    fn snapshots_and_clones_keep_their_text() {
+        let mut buffer = TextBuffer::from_str("first\nsecond").unwrap();
+        buffer.insert(5, " line");
+        let snapshot = buffer.snapshot();
+        let mut copy = buffer.clone();
+
+        buffer.replace(0..5, "1st");
+        buffer.insert(buffer.get_length(), "\nthird");
+        assert_eq!(snapshot.get_text(), "first line\nsecond");
+        assert_eq!(snapshot.get_line_count(), 2);
+        assert_eq!(snapshot.get_line_content(2), "second");
+
+        copy.delete(0, 6);
+        assert_eq!(copy.get_text(), "line\nsecond");
+        assert_eq!(buffer.get_text(), "1st line\nsecond\nthird");
+
+        // The copy has the history up to the clone
+        copy.undo();
+        assert_eq!(copy.content_hash(), snapshot.content_hash());
+        copy.undo();
+        assert_eq!(copy.get_text(), "first\nsecond");
+    }
+
+    #[test]
+    fn batch_matches_edits_made_back_to_front() {
+        let text = "alpha beta\ngamma delta\nepsilon";
+        let mut manual = TextBuffer::from_str(text).unwrap();
+        manual.replace(23..30, "E");
+        manual.replace(17..22, "δ\n");
+        manual.replace(0..5, "");
+
+        // In any order, with offsets into the original text
+        let mut batched = TextBuffer::from_str(text).unwrap();
+        batched.batch(|batch| {
+            batch.replace(17..22, "δ\n");
+            batch.delete(0..5);
+            batch.replace(23..30, "E");
+        });
+        assert_eq!(batched.get_text(), manual.get_text());
+        assert_eq!(batched.get_text(), " beta\ngamma δ\n\nE");
+        assert_eq!(batched.get_line_count(), 4);
+
+        // One undo step, leaving the caret after the last text it restores
+        assert_eq!(batched.undo().map(|pos| pos.offset), Some(30));
+        assert_eq!(batched.get_text(), text);
+        assert!(batched.undo().is_none());
+        batched.redo();
+        assert_eq!(batched.get_text(), manual.get_text());
+
+        // Recording changes applies the edits one by one, each with its own event
+        let mut watched = TextBuffer::from_str(text).unwrap();
+        watched.record_changes();
+        watched.batch(|batch| {
+            batch.delete(0..5);
+            batch.replace(23..30, "E");
+        });
+        assert_eq!(watched.get_text(), " beta\ngamma delta\nE");
+        let offsets: Vec<_> = watched.take_changes().iter().map(|c| c.offset).collect();
+        assert_eq!(offsets, [23, 0]);
+    }
+
+    #[test]
+    fn batch_keeps_insert_order_and_drops_overlaps() {
+        let mut buffer = TextBuffer::from_str("abc").unwrap();
+        buffer.batch(|batch| {
+            batch.insert(1, "1");
+            batch.insert(1, "2");
+            batch.replace(2..3, "X");
+            batch.delete(1..3); // overlaps the replace after it
+            batch.insert(99, "!");
+        });
+        assert_eq!(buffer.get_text(), "a12bX!");
+
+        buffer.batch(|_| {});
+        buffer.undo();
+        assert_eq!(buffer.get_text(), "abc");
+    }
+
+    #[test]
+    fn stats_count_unicode_words() {
+        let text = "# Title: hello, world!\r\n\n- can't stop -- 3.5 times\rnaïve 漢字かな\n";
+        let buffer = TextBuffer::from_str(text).unwrap();
+        assert_eq!(buffer.stats(), TextStats::of(text));
+        assert_eq!(
+            buffer.stats(),
+            TextStats {
+                lines: 5,
+                words: 12,
+                chars: text.chars().count(),
+                bytes: text.len(),
+            }
+        );
+        assert_eq!(TextBuffer::from_str("").unwrap().stats().lines, 1);
+    }
+
+    #[test]
+    fn undo_to_empty_and_redo_to_the_end() {
+        let mut buffer = TextBuffer::from_str("").unwrap();
+        let mut states = vec![buffer.get_text()];
+        let mut edit = |buffer: &mut TextBuffer, f: &dyn Fn(&mut TextBuffer)| {
+            f(buffer);
+            states.push(buffer.get_text());
+        };
+
+        edit(&mut buffer, &|b| b.insert(0, "hello\nworld"));
+        edit(&mut buffer, &|b| b.delete(2, 5));
+        edit(&mut buffer, &|b| b.insert(2, "ñ\r\n"));
+        edit(&mut buffer, &|b| b.replace(0..4, "X"));
+        edit(&mut buffer, &|b| b.set_text("fresh"));
+        edit(&mut buffer, &|b| b.delete(0, 2));
+        assert_eq!(buffer.get_text(), "esh");
+
+        for expected in states.iter().rev().skip(1) {
+            assert!(buffer.undo().is_some());
+            assert_eq!(&buffer.get_text(), expected);
+        }
+        assert!(buffer.undo().is_none());
+        assert_eq!(buffer.get_line_count(), 1);
+
+        for expected in states.iter().skip(1) {
+            assert!(buffer.redo().is_some());
+            assert_eq!(&buffer.get_text(), expected);
+        }
+        assert!(buffer.redo().is_none());
+    }
+
+    #[test]
+    fn undo_and_redo_report_the_caret_position() {
+        let mut buffer = TextBuffer::from_str("one\ntwo").unwrap();
+        let at = |offset: usize, line: usize, column: usize| {
+            Some(EditPosition {
+                offset,
+                position: Position::new(line, column),
+            })
+        };
+
+        // Insert: undo leaves the caret where the text was, redo after it
+        buffer.insert(5, "XY\nZ");
+        assert_eq!(buffer.undo(), at(5, 2, 2));
+        assert_eq!(buffer.redo(), at(9, 3, 2));
+
+        // Delete: undo puts the caret after the restored text, redo where it was removed
+        buffer.delete(1, 3);
+        assert_eq!(buffer.get_text(), "otXY\nZwo");
+        assert_eq!(buffer.undo(), at(4, 2, 1));
+        assert_eq!(buffer.redo(), at(1, 1, 2));
+
+        // Replace: after the old text on undo, after the new text on redo
+        buffer.replace(0..2, "abc");
+        assert_eq!(buffer.undo(), at(2, 1, 3));
+        assert_eq!(buffer.redo(), at(3, 1, 4));
+
+        // A group: undo ends at its first edit, redo at its last
+        buffer.begin_group();
+        buffer.insert(0, "a");
+        buffer.insert(buffer.get_length(), "z");
+        buffer.end_group();
+        assert_eq!(buffer.undo(), at(0, 1, 1));
+        assert_eq!(buffer.redo(), at(buffer.get_length(), 2, 5));
+    }
+
+    #[test]
+    fn versions_follow_undo_and_redo() {
+        let mut buffer = TextBuffer::from_str("abc").unwrap();
+        let saved = buffer.version();
+        buffer.insert(3, "d");
+        let edited = buffer.version();
+        assert_ne!(edited, saved);
+
+        buffer.undo();
+        assert_eq!(buffer.version(), saved);
+        buffer.redo();
+        assert_eq!(buffer.version(), edited);
+
+        // Joining a group still makes a new version
+        buffer.begin_group();
+        buffer.insert(4, "e");
+        let typed = buffer.version();
+        buffer.insert(5, "f");
+        buffer.end_group();
+        assert_ne!(buffer.version(), typed);
+
+        // Text put back by hand is a new version too
+        buffer.delete(3, 3);
+        assert_eq!(buffer.get_text(), "abc");
+        assert_ne!(buffer.version(), saved);
+
+        buffer.clear_history();
+        let cleared = buffer.version();
+        buffer.insert(0, "x");
+        buffer.undo();
+        assert_eq!(buffer.version(), cleared);
+    }
+
+    #[test]
+    fn grouped_typing_undoes_as_one_step() {
+        let mut buffer = TextBuffer::from_str("ab").unwrap();
+        buffer.begin_group();
+        for (i, c) in ["x", "y", "z"].iter().enumerate() {
+            buffer.insert(1 + i, c);
+        }
+        buffer.delete(3, 1);
+        buffer.end_group();
+        buffer.insert(0, "!");
+        assert_eq!(buffer.get_text(), "!axyb");
+
+        assert!(buffer.undo().is_some());
+        assert_eq!(buffer.get_text(), "axyb");
+        assert!(buffer.undo().is_some());
+        assert_eq!(buffer.get_text(), "ab");
+        assert!(buffer.redo().is_some());
+        assert_eq!(buffer.get_text(), "axyb");
+
+        // A new edit drops the redo step
+        buffer.insert(0, "?");
+        assert!(buffer.redo().is_none());
+        assert_eq!(buffer.get_text(), "?axyb");
+    }
+
+    #[test]
+    fn matching_bracket_respects_nesting() {
+        let buffer = TextBuffer::from_str("f(a[0], {b: (c)})\n)").unwrap();
+        assert_eq!(buffer.matching_bracket(1), Some(16));
+        assert_eq!(buffer.matching_bracket(16), Some(1));
+        assert_eq!(buffer.matching_bracket(3), Some(5));
+        assert_eq!(buffer.matching_bracket(8), Some(15));
+        assert_eq!(buffer.matching_bracket(12), Some(14));
+
+        // Not a bracket, unmatched, and out of range
+        assert_eq!(buffer.matching_bracket(0), None);
+        assert_eq!(buffer.matching_bracket(18), None);
+        assert_eq!(buffer.matching_bracket(100), None);
+    }
+
+    #[test]
+    fn matching_bracket_across_edited_pieces() {
+        let mut buffer = TextBuffer::from_str("{ é }").unwrap();
+        buffer.insert(2, "[(x)");
+        buffer.insert(buffer.get_length() - 2, "] ");
+        buffer.insert(0, "({");
+        buffer.insert(buffer.get_length(), "})");
+        let text = buffer.get_text();
+        assert_eq!(text, "({{ [(x)é]  }})");
+
+        let expected = [(0, 15), (1, 14), (2, 13), (4, 10), (5, 7)];
+        for (open, close) in expected {
+            assert_eq!(buffer.matching_bracket(open), Some(close), "{open}");
+            assert_eq!(buffer.matching_bracket(close), Some(open), "{close}");
+        }
+
+        // Unmatched once its partner is gone
+        buffer.delete(15, 1);
+        assert_eq!(buffer.matching_bracket(0), None);
+        assert_eq!(buffer.matching_bracket(1), Some(14));
+    }
+
+    #[test]
+    fn matching_bracket_gives_up_past_the_scan_limit() {
+        let near = format!("({})", "x".repeat(1000));
+        let buffer = TextBuffer::from_str(&near).unwrap();
+        assert_eq!(buffer.matching_bracket(0), Some(1001));
+
+        let far = format!("({})", "x".repeat(BRACKET_SCAN_LIMIT));
+        let buffer = TextBuffer::from_str(&far).unwrap();
+        assert_eq!(buffer.matching_bracket(0), None);
+        assert_eq!(buffer.matching_bracket(BRACKET_SCAN_LIMIT + 1), None);
+    }
+
+    #[test]
+    fn lines_range_of_a_large_document() {
+        let text: String = (0..100_000).map(|i| format!("line {i}\r\n")).collect();
+        let mut buffer = TextBuffer::from_str(&text).unwrap();
+        buffer.insert(buffer.get_offset_at(50_001, 1), "edited ");
+
+        assert_eq!(
+            buffer.get_lines_range(50_000, 50_003),
+            vec!["line 49999", "edited line 50000", "line 50001"]
+        );
+        assert_eq!(
+            buffer.get_lines_range(99_999, 200_000),
+            vec!["line 99998", "line 99999", ""]
+        );
+        assert!(buffer.get_lines_range(100_002, 100_010).is_empty());
+        assert!(buffer.get_lines_range(0, 1).is_empty());
+    }
+
+    #[test]
+    fn enumerated_lines_of_empty_buffer() {
+        let buffer = TextBuffer::from_str("").unwrap();
+        assert_eq!(
+            buffer.enumerated_lines().collect::<Vec<_>>(),
+            vec![(1, String::new())]
+        );
+    }
+}
@@ -1,4 +1,6 @@
 use crate::buffer::TextBuffer;
+use crate::encoding::FileEncoding;
+use encoding_rs::{Encoding, UTF_8};
 use piece_tree::StringBuffer;
 use std::{
     fs::File,
@@ -6,14 +8,30 @@ use std::{
     path::Path,
 };
 
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
 #[derive(Default, Debug)]
 pub struct TextBufferBuilder {
     chunks: Vec<StringBuffer>,
+    capacity: usize,
 }
 
 impl TextBufferBuilder {
     pub fn new() -> Self {
-        Self { chunks: Vec::new() }
+        Self {
+            chunks: Vec::new(),
+            capacity: 0,
+        }
+    }
+
+    /// Builder for a document of roughly `bytes` bytes, e.g. a file of known size.
+    /// Reserves room for the chunks a file of that size is read in, and forwards the
+    /// hint so the piece tree reserves its buffers up front.
+    pub fn with_capacity(bytes: usize) -> Self {
+        Self {
+            chunks: Vec::with_capacity(bytes.div_ceil(READ_CHUNK_SIZE)),
+            capacity: bytes,
+        }
     }
 
     /// Accept a chunk of text (may include multiple lines).
@@ -26,65 +44,392 @@ impl TextBufferBuilder {
 
     /// Finish building and return a `TextBuffer`.
     pub fn finish(mut self) -> TextBuffer {
-        TextBuffer::from_chunks(std::mem::take(&mut self.chunks))
+        TextBuffer::from_chunks_with_capacity(std::mem::take(&mut self.chunks), self.capacity)
+    }
+
+    /// Read a whole document from `reader`, e.g. stdin or a pipe.
+    pub fn from_reader<R: Read>(reader: R) -> io::Result<TextBuffer> {
+        Self::new().read_from(reader)
     }
 
     pub fn load_from_path<P: AsRef<Path>>(path: P) -> io::Result<TextBuffer> {
-        let chunks = Self::read_chunks_from_path(path)?;
-        let mut builder = TextBufferBuilder::new();
-        for s in chunks {
-            builder.accept_chunk(&s);
-        }
-        Ok(builder.finish())
+        Ok(Self::load_from_path_with_encoding(path, None)?.0)
     }
 
-    pub fn read_chunks_from_path<P: AsRef<Path>>(path: P) -> io::Result<Vec<String>> {
+    /// Load a file, decoding it as its byte order mark says, else as `encoding`, else as
+    /// UTF-8. Returns the encoding used, to save the file back in it.
+    pub fn load_from_path_with_encoding<P: AsRef<Path>>(
+        path: P,
+        encoding: Option<&'static Encoding>,
+    ) -> io::Result<(TextBuffer, FileEncoding)> {
         let file = File::open(path)?;
-        let mut reader = BufReader::new(file);
+        let size = file.metadata()?.len() as usize;
+        let mut builder = Self::with_capacity(size);
+        let encoding = read_chunks(file, encoding, |chunk| builder.accept_chunk(chunk))?;
+        Ok((builder.finish(), encoding))
+    }
+
+    fn read_from<R: Read>(mut self, reader: R) -> io::Result<TextBuffer> {
+        read_chunks(reader, None, |chunk| self.accept_chunk(chunk))?;
+        Ok(self.finish())
+    }
 
+    pub fn read_chunks_from_path<P: AsRef<Path>>(path: P) -> io::Result<Vec<String>> {
+        Self::read_chunks_from_reader(File::open(path)?)
+    }
+
+    /// [`TextBufferBuilder::read_chunks_from_path`], decoding as
+    /// [`TextBufferBuilder::load_from_path_with_encoding`] does.
+    pub fn read_chunks_from_path_with_encoding<P: AsRef<Path>>(
+        path: P,
+        encoding: Option<&'static Encoding>,
+    ) -> io::Result<(Vec<String>, FileEncoding)> {
         let mut out: Vec<String> = Vec::new();
-        let mut buf = vec![0u8; 64 * 1024];
-        let mut carry: Vec<u8> = Vec::new();
+        let encoding = read_chunks(File::open(path)?, encoding, |chunk| {
+            out.push(chunk.to_string())
+        })?;
+        Ok((out, encoding))
+    }
 
-        loop {
-            let n = reader.read(&mut buf)?;
-            if n == 0 {
-                break;
-            }
+    /// Read `reader` into UTF-8 chunks, carrying codepoints split across reads into the next chunk.
+    pub fn read_chunks_from_reader<R: Read>(reader: R) -> io::Result<Vec<String>> {
+        let mut out: Vec<String> = Vec::new();
+        read_chunks(reader, None, |chunk| out.push(chunk.to_string()))?;
+        Ok(out)
+    }
+}
 
-            // Combine carry + new bytes
-            let mut combined = Vec::with_capacity(carry.len() + n);
-            combined.extend_from_slice(&carry);
-            combined.extend_from_slice(&buf[..n]);
+/// Sniff the encoding from the first bytes of `reader` (see [`FileEncoding::sniff`]), then
+/// decode it into UTF-8 chunks for `f`, dropping the byte order mark if there is one.
+fn read_chunks<R: Read>(
+    mut reader: R,
+    encoding: Option<&'static Encoding>,
+    f: impl FnMut(&str),
+) -> io::Result<FileEncoding> {
+    // Long enough for any byte order mark, even from a reader handing out single bytes
+    let mut head = [0u8; 3];
+    let mut len = 0;
+    while len < head.len() {
+        match reader.read(&mut head[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+
+    let file_encoding = FileEncoding::sniff(&head[..len], encoding);
+    let body = (&head[file_encoding.bom_bytes().len()..len]).chain(reader);
+    if file_encoding.encoding == UTF_8 {
+        read_utf8_chunks(body, f)?;
+    } else {
+        read_decoded_chunks(body, file_encoding.encoding, f)?;
+    }
+    Ok(file_encoding)
+}
 
-            // Find longest valid UTF-8 prefix
-            let valid_len = match std::str::from_utf8(&combined) {
-                Ok(_) => combined.len(),
-                Err(e) => e.valid_up_to(),
-            };
+/// Like [`read_utf8_chunks`] for any other encoding, decoded with `encoding_rs`. Malformed
+/// input becomes U+FFFD.
+fn read_decoded_chunks<R: Read>(
+    reader: R,
+    encoding: &'static Encoding,
+    mut f: impl FnMut(&str),
+) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut buf = vec![0u8; READ_CHUNK_SIZE];
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let mut carry_cr = false;
 
-            if valid_len > 0 {
-                let s = std::str::from_utf8(&combined[..valid_len]).expect("valid UTF-8 prefix");
-                out.push(s.to_string());
-            }
+    loop {
+        let n = reader.read(&mut buf)?;
+        let last = n == 0;
 
-            // Keep any partial codepoint for the next read
-            carry.clear();
-            if valid_len < combined.len() {
-                carry.extend_from_slice(&combined[valid_len..]);
-            }
+        let room = decoder
+            .max_utf8_buffer_length(n)
+            .expect("chunk small enough to decode");
+        let mut decoded = String::with_capacity(room + 1);
+        if carry_cr {
+            decoded.push('\r');
+        }
+        // With `room` to spare the decoder always takes the whole read
+        let _ = decoder.decode_to_string(&buf[..n], &mut decoded, last);
+
+        carry_cr = !last && decoded.ends_with('\r');
+        if carry_cr {
+            decoded.pop();
+        }
+        if !decoded.is_empty() {
+            f(&decoded);
+        }
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+/// Decode `reader` in `READ_CHUNK_SIZE` reads, handing each decoded chunk to `f`.
+/// A codepoint split across reads is carried into the next chunk, and so is a trailing
+/// '\r' so a CRLF never straddles two chunks; invalid sequences become U+FFFD, as with
+/// `String::from_utf8_lossy`.
+fn read_utf8_chunks<R: Read>(reader: R, mut f: impl FnMut(&str)) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut buf = vec![0u8; READ_CHUNK_SIZE];
+    let mut carry: Vec<u8> = Vec::new();
+
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
         }
 
-        if !carry.is_empty() {
-            match std::str::from_utf8(&carry) {
-                Ok(s) => out.push(s.to_string()),
-                Err(_) => {
-                    // lossy decode trailing broken sequence
-                    out.push(String::from_utf8_lossy(&carry).to_string());
+        // Combine carry + new bytes
+        let mut combined = std::mem::take(&mut carry);
+        combined.extend_from_slice(&buf[..n]);
+
+        let mut decoded = String::new();
+        let mut rest = combined.as_slice();
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(s) => {
+                    decoded.push_str(s);
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    decoded.push_str(std::str::from_utf8(valid).expect("valid UTF-8 prefix"));
+                    match e.error_len() {
+                        // Invalid sequence: replace it and keep decoding
+                        Some(len) => {
+                            decoded.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        // Partial codepoint: keep it for the next read
+                        None => {
+                            carry.extend_from_slice(after);
+                            break;
+                        }
+                    }
                 }
             }
         }
 
-        Ok(out)
+        if decoded.ends_with('\r') {
+            decoded.pop();
+            carry.insert(0, b'\r');
+        }
+        if !decoded.is_empty() {
+            f(&decoded);
+        }
+    }
+
+    if !carry.is_empty() {
+        // lossy decode trailing broken sequence
+        f(&String::from_utf8_lossy(&carry));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Reader handing out at most `step` bytes per call
+    struct Trickle<'a> {
+        data: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.step.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn from_reader_carries_split_codepoints() {
+        // Put a 4-byte char across the read chunk boundary
+        let mut text = "a".repeat(READ_CHUNK_SIZE - 2);
+        text.push_str("𝄞 naïve\r\nцвет\n");
+        text.push_str(&"é".repeat(1000));
+        let bytes = text.clone().into_bytes();
+
+        let from_cursor = TextBufferBuilder::from_reader(Cursor::new(bytes.clone())).unwrap();
+        assert_eq!(from_cursor.get_text(), text);
+
+        // Reads shorter than any codepoint split every multi-byte char
+        let short = "𝄞 naïve\r\nцвет\n".repeat(4);
+        let trickled = TextBufferBuilder::from_reader(Trickle {
+            data: short.as_bytes(),
+            step: 3,
+        })
+        .unwrap();
+        assert_eq!(trickled.get_text(), short);
+
+        let path =
+            std::env::temp_dir().join(format!("text_buffer_reader_{}.txt", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        let from_file = TextBufferBuilder::load_from_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        let from_file = from_file.unwrap();
+
+        assert_eq!(from_file.get_text(), from_cursor.get_text());
+        assert_eq!(
+            from_file.get_lines_content(),
+            from_cursor.get_lines_content()
+        );
+    }
+
+    // Both public loaders, fed the same bytes
+    fn load_both(bytes: &[u8]) -> (String, String) {
+        let chunks = TextBufferBuilder::read_chunks_from_reader(Cursor::new(bytes)).unwrap();
+        let buffer = TextBufferBuilder::from_reader(Cursor::new(bytes)).unwrap();
+        (chunks.concat(), buffer.get_text())
+    }
+
+    #[test]
+    fn loaders_agree_on_tricky_input() {
+        let expected_lossy = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+
+        // Empty input
+        assert_eq!(load_both(b""), (String::new(), String::new()));
+
+        // Multi-byte char split across reads
+        let mut split = vec![b'a'; READ_CHUNK_SIZE - 1];
+        split.extend_from_slice("€uro\n".as_bytes());
+        let (chunks, buffer) = load_both(&split);
+        assert_eq!(chunks, expected_lossy(&split));
+        assert_eq!(buffer, chunks);
+
+        // Truncated codepoint at the end of input
+        let mut truncated = b"tail ".to_vec();
+        truncated.extend_from_slice(&"€".as_bytes()[..2]);
+        let (chunks, buffer) = load_both(&truncated);
+        assert_eq!(chunks, expected_lossy(&truncated));
+        assert_eq!(buffer, chunks);
+
+        // Invalid bytes in the middle, including right at the read boundary
+        let mut invalid = vec![b'b'; READ_CHUNK_SIZE - 1];
+        invalid.extend_from_slice(b"\xff\xfe ok \xc3(\n");
+        let (chunks, buffer) = load_both(&invalid);
+        assert_eq!(chunks, expected_lossy(&invalid));
+        assert_eq!(buffer, chunks);
+    }
+
+    #[test]
+    fn crlf_is_never_split_across_chunks() {
+        let mut text = "a".repeat(READ_CHUNK_SIZE - 1);
+        text.push_str("\r\nb\r");
+        let chunks = TextBufferBuilder::read_chunks_from_reader(text.as_bytes()).unwrap();
+        assert!(
+            chunks
+                .iter()
+                .all(|chunk| !chunk.ends_with('\r') || chunk == "\r")
+        );
+        assert_eq!(chunks.concat(), text);
+
+        let buffer = TextBufferBuilder::from_reader(text.as_bytes()).unwrap();
+        assert_eq!(buffer.get_line_count(), 3);
+    }
+
+    // "héllo\r\n𝄞" as UTF-16LE, after a byte order mark
+    const UTF16LE_FIXTURE: &[u8] = b"\xFF\xFEh\0\xE9\0l\0l\0o\0\r\0\n\0\x34\xD8\x1E\xDD";
+
+    fn temp_file(name: &str, bytes: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("text_buffer_{name}_{}", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn utf16_with_bom_is_decoded() {
+        let path = temp_file("utf16le.txt", UTF16LE_FIXTURE);
+        let loaded = TextBufferBuilder::load_from_path_with_encoding(&path, None);
+        let chunks = TextBufferBuilder::read_chunks_from_path_with_encoding(&path, None);
+        std::fs::remove_file(&path).unwrap();
+
+        let (buffer, encoding) = loaded.unwrap();
+        assert_eq!(buffer.get_text(), "héllo\r\n𝄞");
+        assert_eq!(buffer.get_line_count(), 2);
+        assert_eq!((encoding.name(), encoding.bom), ("UTF-16LE", true));
+        assert_eq!(encoding.encode(&buffer.get_text()), UTF16LE_FIXTURE);
+
+        let (chunks, chunk_encoding) = chunks.unwrap();
+        assert_eq!(chunks.concat(), buffer.get_text());
+        assert_eq!(chunk_encoding, encoding);
+
+        // Code units split across reads, and a CRLF across the read boundary
+        let mut long = b"\xFE\xFF".to_vec();
+        let text = format!("{}\r\nнет", "a".repeat(READ_CHUNK_SIZE / 2 - 2));
+        long.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        let trickled = TextBufferBuilder::read_chunks_from_reader(Trickle {
+            data: &long,
+            step: 3,
+        })
+        .unwrap();
+        assert_eq!(trickled.concat(), text);
+        let chunks = TextBufferBuilder::read_chunks_from_reader(long.as_slice()).unwrap();
+        assert!(chunks.iter().all(|chunk| !chunk.ends_with('\r')));
+        assert_eq!(chunks.concat(), text);
+    }
+
+    #[test]
+    fn utf8_with_bom_and_a_fallback_encoding() {
+        let path = temp_file("utf8bom.txt", b"\xEF\xBB\xBFcaf\xC3\xA9\n");
+        let loaded =
+            TextBufferBuilder::load_from_path_with_encoding(&path, Some(encoding_rs::WINDOWS_1252));
+        std::fs::remove_file(&path).unwrap();
+        let (buffer, encoding) = loaded.unwrap();
+        // The mark names the encoding over the fallback, and is not part of the text
+        assert_eq!((encoding.encoding, encoding.bom), (UTF_8, true));
+        assert_eq!(buffer.get_text().chars().next(), Some('c'));
+        assert_eq!(buffer.get_text(), "café\n");
+        assert_eq!(
+            encoding.encode(&buffer.get_text()),
+            b"\xEF\xBB\xBFcaf\xC3\xA9\n"
+        );
+
+        // Also when the mark arrives a byte at a time, or is all there is
+        let trickled = TextBufferBuilder::from_reader(Trickle {
+            data: b"\xEF\xBB\xBF\xEF\xBB\xBFx",
+            step: 1,
+        })
+        .unwrap();
+        assert_eq!(trickled.get_text(), "\u{FEFF}x");
+        assert_eq!(load_both(b"\xEF\xBB\xBF"), (String::new(), String::new()));
+
+        let path = temp_file("latin1.txt", b"caf\xE9\n");
+        let loaded =
+            TextBufferBuilder::load_from_path_with_encoding(&path, Some(encoding_rs::WINDOWS_1252));
+        std::fs::remove_file(&path).unwrap();
+        let (buffer, encoding) = loaded.unwrap();
+        assert_eq!(buffer.get_text(), "café\n");
+        assert_eq!(encoding.name(), "windows-1252");
+    }
+
+    #[test]
+    fn with_capacity_matches_unhinted_build() {
+        let chunk = "line of text\n".repeat(READ_CHUNK_SIZE / 13);
+        let count = 8;
+
+        let mut plain = TextBufferBuilder::new();
+        let mut hinted = TextBufferBuilder::with_capacity(chunk.len() * count);
+        let reserved = hinted.chunks.capacity();
+        assert!(reserved >= count);
+
+        for _ in 0..count {
+            plain.accept_chunk(&chunk);
+            hinted.accept_chunk(&chunk);
+        }
+        // The hinted builder never had to grow its chunk list
+        assert_eq!(hinted.chunks.capacity(), reserved);
+
+        let plain = plain.finish();
+        let hinted = hinted.finish();
+        assert_eq!(hinted.get_text(), plain.get_text());
+        assert_eq!(hinted.get_line_count(), plain.get_line_count());
+        assert_eq!(hinted.get_length(), chunk.len() * count);
     }
 }
@@ -0,0 +1,231 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// How columns are counted when converting between columns and byte indices of a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColumnMode {
+    /// A column is a byte, as in `get_offset_at`/`get_position_at`.
+    #[default]
+    Bytes,
+    /// A column is a display cell: a grapheme takes one cell, a tab runs to the next
+    /// multiple of `tab_width`.
+    TabAware { tab_width: usize },
+}
+
+impl ColumnMode {
+    /// 0-based byte index in `line` for 0-based `column`. In tab-aware mode a column inside
+    /// a tab snaps to its nearer edge, and columns past the end clamp to the line length.
+    pub fn byte_index(self, line: &str, column: usize) -> usize {
+        match self {
+            ColumnMode::Bytes => column.min(line.len()),
+            ColumnMode::TabAware { tab_width } => {
+                for (byte, start, width) in cells(line, tab_width) {
+                    if column < start + width {
+                        let past_middle = column - start >= width.div_ceil(2);
+                        return if past_middle {
+                            byte + next_grapheme_len(&line[byte..])
+                        } else {
+                            byte
+                        };
+                    }
+                }
+                line.len()
+            }
+        }
+    }
+
+    /// 0-based column of 0-based byte index `byte` in `line`.
+    pub fn column(self, line: &str, byte: usize) -> usize {
+        match self {
+            ColumnMode::Bytes => byte.min(line.len()),
+            ColumnMode::TabAware { tab_width } => cells(line, tab_width)
+                .find(|&(start_byte, _, _)| start_byte >= byte)
+                .map_or_else(|| display_width(line, tab_width), |(_, start, _)| start),
+        }
+    }
+}
+
+/// Display width of `line` in cells, with tabs expanded to `tab_width` stops.
+pub fn display_width(line: &str, tab_width: usize) -> usize {
+    cells(line, tab_width)
+        .last()
+        .map_or(0, |(_, start, width)| start + width)
+}
+
+/// Spaces taking a caret after `before` (its line up to the caret) to the next tab stop,
+/// with stops every `tab_width` cells. A caret on a stop moves a full `tab_width`.
+pub fn spaces_to_tab_stop(before: &str, tab_width: usize) -> usize {
+    let tab_width = tab_width.max(1);
+    tab_width - display_width(before, tab_width) % tab_width
+}
+
+/// Bytes at the start of `line` that one dedent removes: a leading tab, or up to
+/// `tab_width` leading spaces.
+pub fn dedent_len(line: &str, tab_width: usize) -> usize {
+    if line.starts_with('\t') {
+        return 1;
+    }
+    line.bytes()
+        .take(tab_width.max(1))
+        .take_while(|&b| b == b' ')
+        .count()
+}
+
+/// Edits indenting each of `lines` by `amount` units, or outdenting them when it is
+/// negative, one per line as the bytes at its start to replace and the text to put there.
+/// Indenting puts `amount` copies of `unit` (a tab, or spaces) in front of every line that is
+/// not blank. Outdenting takes off [`dedent_len`] bytes per unit, as far as the indentation
+/// goes; a line without any is left alone.
+pub fn indent_edits<S: AsRef<str>>(
+    lines: &[S],
+    unit: &str,
+    amount: i32,
+    tab_width: usize,
+) -> Vec<(std::ops::Range<usize>, String)> {
+    lines
+        .iter()
+        .map(|line| {
+            let line = line.as_ref();
+            if amount >= 0 {
+                let indent = if line.trim().is_empty() {
+                    String::new()
+                } else {
+                    unit.repeat(amount as usize)
+                };
+                return (0..0, indent);
+            }
+            let mut removed = 0;
+            for _ in 0..amount.unsigned_abs() {
+                removed += dedent_len(&line[removed..], tab_width);
+            }
+            (0..removed, String::new())
+        })
+        .collect()
+}
+
+/// `line` with each tab replaced by the spaces up to its tab stop.
+pub fn expand_tabs(line: &str, tab_width: usize) -> String {
+    let mut out = String::with_capacity(line.len());
+    for (byte, _, width) in cells(line, tab_width) {
+        let grapheme = &line[byte..byte + next_grapheme_len(&line[byte..])];
+        if grapheme == "\t" {
+            out.extend(std::iter::repeat_n(' ', width));
+        } else {
+            out.push_str(grapheme);
+        }
+    }
+    out
+}
+
+// (byte index, first cell, width in cells) of each grapheme of `line`
+fn cells(line: &str, tab_width: usize) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+    let tab_width = tab_width.max(1);
+    line.grapheme_indices(true)
+        .scan(0, move |cell, (byte, grapheme)| {
+            let start = *cell;
+            let width = if grapheme == "\t" {
+                tab_width - start % tab_width
+            } else {
+                1
+            };
+            *cell += width;
+            Some((byte, start, width))
+        })
+}
+
+fn next_grapheme_len(s: &str) -> usize {
+    s.graphemes(true).next().map_or(0, str::len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABS: ColumnMode = ColumnMode::TabAware { tab_width: 4 };
+
+    #[test]
+    fn clicks_land_on_tab_expanded_characters() {
+        // Cells: a=0, tab=1..4, b=4, tab=5..8, é=8
+        let line = "a\tb\té";
+
+        // Byte columns count the tab as one column
+        assert_eq!(ColumnMode::Bytes.byte_index(line, 2), 2);
+        assert_eq!(ColumnMode::Bytes.column(line, 2), 2);
+
+        // Tab-aware: click on 'b' (cell 4) and on 'é' (cell 8)
+        assert_eq!(&line[TABS.byte_index(line, 4)..], "b\té");
+        assert_eq!(&line[TABS.byte_index(line, 8)..], "é");
+        // Inside a tab, snap to the nearer edge
+        assert_eq!(TABS.byte_index(line, 2), 1);
+        assert_eq!(TABS.byte_index(line, 3), 2);
+        // Past the end
+        assert_eq!(TABS.byte_index(line, 40), line.len());
+
+        assert_eq!(TABS.column(line, 2), 4);
+        assert_eq!(TABS.column(line, 4), 8);
+        assert_eq!(TABS.column(line, line.len()), 9);
+    }
+
+    #[test]
+    fn expands_tabs_to_stops() {
+        assert_eq!(expand_tabs("a\tbc\td", 4), "a   bc  d");
+        assert_eq!(display_width("a\tbc\td", 4), 9);
+        assert_eq!(display_width("", 4), 0);
+    }
+
+    #[test]
+    fn soft_tabs_fill_up_to_the_next_stop() {
+        assert_eq!(spaces_to_tab_stop("", 4), 4);
+        assert_eq!(spaces_to_tab_stop("ab", 4), 2);
+        assert_eq!(spaces_to_tab_stop("abcd", 4), 4);
+        assert_eq!(spaces_to_tab_stop("  - item", 2), 2);
+        assert_eq!(spaces_to_tab_stop("  - ite", 2), 1);
+        // Counted in cells: a tab already reaches a stop, é is one cell
+        assert_eq!(spaces_to_tab_stop("a	", 4), 4);
+        assert_eq!(spaces_to_tab_stop("	é", 4), 3);
+    }
+
+    #[test]
+    fn dedent_removes_one_unit_of_indentation() {
+        assert_eq!(dedent_len("		x", 4), 1);
+        assert_eq!(dedent_len("      x", 4), 4);
+        assert_eq!(dedent_len("  x", 4), 2);
+        assert_eq!(dedent_len(" 	x", 4), 1);
+        assert_eq!(dedent_len("x", 4), 0);
+    }
+
+    #[test]
+    fn indent_edits_shift_every_line_by_a_unit() {
+        let apply = |lines: &[&str], unit, amount| -> Vec<String> {
+            lines
+                .iter()
+                .zip(indent_edits(lines, unit, amount, 4))
+                .map(|(line, (range, text))| {
+                    let mut line = line.to_string();
+                    line.replace_range(range, &text);
+                    line
+                })
+                .collect()
+        };
+        let block = ["fn main() {", "    body();", "", "\t}"];
+
+        assert_eq!(
+            apply(&block, "    ", 1),
+            ["    fn main() {", "        body();", "", "    \t}"]
+        );
+        assert_eq!(
+            apply(&block, "\t", 2),
+            ["\t\tfn main() {", "\t\t    body();", "", "\t\t\t}"]
+        );
+        // Nothing to take off the first line
+        assert_eq!(
+            apply(&block, "    ", -1),
+            ["fn main() {", "body();", "", "}"]
+        );
+        assert_eq!(indent_edits(&block, "\t", -1, 4)[0], (0..0, String::new()));
+        // Two units, as far as the indentation goes
+        assert_eq!(
+            apply(&["  \tx", "      y", "\t\t\tz"], "\t", -2),
+            ["x", "y", "\tz"]
+        );
+    }
+}
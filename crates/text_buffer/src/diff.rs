@@ -0,0 +1,204 @@
+/// One run of a line diff, counted in lines. Applying the runs in order walks both the old
+/// and the new text from the top: `Equal` advances both, `Delete` only the old and `Insert`
+/// only the new.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffOp {
+    Equal(usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+// Past this many edits the middle of the diff is reported as one replaced block
+const MAX_EDIT_DISTANCE: usize = 1024;
+
+/// Line diff turning `old` into `new` (Myers' algorithm). Within a change, deletions come
+/// before insertions; consecutive runs of the same kind are merged.
+pub fn diff_lines<A: AsRef<str>, B: AsRef<str>>(old: &[A], new: &[B]) -> Vec<DiffOp> {
+    let old: Vec<&str> = old.iter().map(AsRef::as_ref).collect();
+    let new: Vec<&str> = new.iter().map(AsRef::as_ref).collect();
+
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut ops = Vec::new();
+    push(&mut ops, DiffOp::Equal(prefix));
+    middle(
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+        &mut ops,
+    );
+    push(&mut ops, DiffOp::Equal(suffix));
+    ops
+}
+
+fn push(ops: &mut Vec<DiffOp>, op: DiffOp) {
+    use DiffOp::*;
+    match (ops.last_mut(), op) {
+        (_, Equal(0) | Delete(0) | Insert(0)) => {}
+        (Some(Equal(n)), Equal(m))
+        | (Some(Delete(n)), Delete(m))
+        | (Some(Insert(n)), Insert(m)) => *n += m,
+        // Keep deletions ahead of insertions within a change
+        (Some(Insert(n)), Delete(m)) => {
+            let inserted = *n;
+            ops.pop();
+            push(ops, Delete(m));
+            push(ops, Insert(inserted));
+        }
+        _ => ops.push(op),
+    }
+}
+
+// Diff of the part between the common prefix and suffix
+fn middle(old: &[&str], new: &[&str], ops: &mut Vec<DiffOp>) {
+    let (n, m) = (old.len(), new.len());
+    if n == 0 || m == 0 {
+        push(ops, DiffOp::Delete(n));
+        push(ops, DiffOp::Insert(m));
+        return;
+    }
+
+    // Furthest x reached on each diagonal k = x - y, indexed by k + offset, saved per edit count
+    let max = (n + m).min(MAX_EDIT_DISTANCE);
+    let offset = max + 1;
+    let mut v = vec![0usize; 2 * max + 3];
+    let mut trace: Vec<Vec<usize>> = Vec::new();
+    let mut found = None;
+    'search: for d in 0..=max as isize {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let i = (k + offset as isize) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            while x < n && y < m && old[x] == new[y] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                found = Some(d);
+                break 'search;
+            }
+        }
+    }
+
+    let Some(d_end) = found else {
+        push(ops, DiffOp::Delete(n));
+        push(ops, DiffOp::Insert(m));
+        return;
+    };
+
+    // Walk the trace back from the end, collecting runs in reverse
+    let mut rev = Vec::new();
+    let (mut x, mut y) = (n as isize, m as isize);
+    for d in (0..=d_end).rev() {
+        let v = &trace[d as usize];
+        let k = x - y;
+        let i = (k + offset as isize) as usize;
+        let prev_k = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = if d == 0 {
+            0
+        } else {
+            v[(prev_k + offset as isize) as usize] as isize
+        };
+        let prev_y = prev_x - prev_k;
+        let start_x = if d == 0 {
+            0
+        } else if prev_k == k + 1 {
+            prev_x
+        } else {
+            prev_x + 1
+        };
+        rev.push(DiffOp::Equal((x - start_x) as usize));
+        if d > 0 {
+            rev.push(if prev_k == k + 1 {
+                DiffOp::Insert(1)
+            } else {
+                DiffOp::Delete(1)
+            });
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    for op in rev.into_iter().rev() {
+        push(ops, op);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DiffOp::*;
+
+    // Rebuild `new` from `old` and the ops, checking the run lengths fit
+    fn apply(old: &[&str], new: &[&str], ops: &[DiffOp]) -> Vec<String> {
+        let (mut i, mut j) = (0, 0);
+        let mut out = Vec::new();
+        for op in ops {
+            match *op {
+                Equal(n) => {
+                    assert_eq!(old[i..i + n], new[j..j + n]);
+                    out.extend(old[i..i + n].iter().map(|s| s.to_string()));
+                    i += n;
+                    j += n;
+                }
+                Delete(n) => i += n,
+                Insert(n) => {
+                    out.extend(new[j..j + n].iter().map(|s| s.to_string()));
+                    j += n;
+                }
+            }
+        }
+        assert_eq!((i, j), (old.len(), new.len()));
+        out
+    }
+
+    #[test]
+    fn reports_minimal_changes() {
+        let old = ["a", "b", "c", "d", "e"];
+        assert_eq!(diff_lines(&old, &old), vec![Equal(5)]);
+        assert_eq!(
+            diff_lines(&old, &["a", "x", "c", "d", "e", "f"]),
+            vec![Equal(1), Delete(1), Insert(1), Equal(3), Insert(1)]
+        );
+        assert_eq!(
+            diff_lines(&old, &["a", "e"]),
+            vec![Equal(1), Delete(3), Equal(1)]
+        );
+        assert_eq!(diff_lines::<&str, &str>(&[], &["a"]), vec![Insert(1)]);
+        assert_eq!(diff_lines(&["a"], &[] as &[&str]), vec![Delete(1)]);
+    }
+
+    #[test]
+    fn ops_rebuild_the_new_lines() {
+        let old = ["fn main() {", "    let x = 1;", "    x", "}", "", "// end"];
+        let new = [
+            "// start",
+            "fn main() {",
+            "    let y = 2;",
+            "    y",
+            "}",
+            "// end",
+        ];
+        let ops = diff_lines(&old, &new);
+        assert_eq!(apply(&old, &new, &ops), new);
+        let deleted: usize = ops
+            .iter()
+            .map(|op| if let Delete(n) = op { *n } else { 0 })
+            .sum();
+        assert_eq!(deleted, 3);
+    }
+}
@@ -0,0 +1,115 @@
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+
+/// How a file's bytes map to its text: the character encoding it was decoded with, and
+/// whether it started with a byte order mark. Saving encodes the text back the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileEncoding {
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+}
+
+impl Default for FileEncoding {
+    fn default() -> Self {
+        Self {
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+}
+
+impl FileEncoding {
+    /// Encoding of a file starting with `head`: the one its byte order mark names,
+    /// otherwise `fallback`, otherwise UTF-8.
+    pub fn sniff(head: &[u8], fallback: Option<&'static Encoding>) -> Self {
+        match Encoding::for_bom(head) {
+            Some((encoding, _)) => Self {
+                encoding,
+                bom: true,
+            },
+            None => Self {
+                encoding: fallback.unwrap_or(UTF_8),
+                bom: false,
+            },
+        }
+    }
+
+    /// Name shown for the encoding, e.g. "UTF-8" or "UTF-16LE".
+    pub fn name(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    /// The byte order mark this file starts with, empty without one.
+    pub fn bom_bytes(&self) -> &'static [u8] {
+        match (self.bom, self.encoding) {
+            (false, _) => b"",
+            (true, e) if e == UTF_8 => b"\xEF\xBB\xBF",
+            (true, e) if e == UTF_16LE => b"\xFF\xFE",
+            (true, e) if e == UTF_16BE => b"\xFE\xFF",
+            (true, _) => b"",
+        }
+    }
+
+    /// `text` as the bytes of a file in this encoding, byte order mark first. Characters
+    /// the encoding cannot represent are written as HTML numeric character references.
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.bom_bytes().len() + text.len());
+        bytes.extend_from_slice(self.bom_bytes());
+        // encoding_rs only decodes UTF-16; its encoder would write UTF-8
+        if self.encoding == UTF_16LE {
+            bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        } else if self.encoding == UTF_16BE {
+            bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        } else {
+            bytes.extend_from_slice(&self.encoding.encode(text).0);
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::WINDOWS_1252;
+
+    #[test]
+    fn sniffs_byte_order_marks_and_falls_back_without_one() {
+        let le = FileEncoding::sniff(b"\xFF\xFEa\0", None);
+        assert_eq!((le.encoding, le.bom), (UTF_16LE, true));
+        let be = FileEncoding::sniff(b"\xFE\xFF\0a", Some(WINDOWS_1252));
+        assert_eq!((be.encoding, be.bom), (UTF_16BE, true));
+
+        let utf8 = FileEncoding::sniff(b"\xEF\xBB\xBFa", Some(WINDOWS_1252));
+        assert_eq!((utf8.encoding, utf8.bom), (UTF_8, true));
+        assert_eq!(FileEncoding::sniff(b"abc", None), FileEncoding::default());
+        assert_eq!(
+            FileEncoding::sniff(b"caf\xE9", Some(WINDOWS_1252)).encoding,
+            WINDOWS_1252
+        );
+    }
+
+    #[test]
+    fn encodes_back_to_the_original_bytes() {
+        let le = FileEncoding {
+            encoding: UTF_16LE,
+            bom: true,
+        };
+        assert_eq!(le.encode("hé\n"), b"\xFF\xFEh\0\xE9\0\n\0");
+        let be = FileEncoding {
+            encoding: UTF_16BE,
+            bom: true,
+        };
+        assert_eq!(be.encode("𝄞"), b"\xFE\xFF\xD8\x34\xDD\x1E");
+
+        let latin1 = FileEncoding {
+            encoding: WINDOWS_1252,
+            bom: false,
+        };
+        assert_eq!(latin1.encode("café"), b"caf\xE9");
+        assert_eq!(FileEncoding::default().encode("café"), "café".as_bytes());
+        let utf8_bom = FileEncoding {
+            encoding: UTF_8,
+            bom: true,
+        };
+        assert_eq!(utf8_bom.encode("a"), b"\xEF\xBB\xBFa");
+    }
+}
@@ -0,0 +1,122 @@
+/// A line break sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Eol {
+    #[default]
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl Eol {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Eol::Lf => "\n",
+            Eol::CrLf => "\r\n",
+            Eol::Cr => "\r",
+        }
+    }
+}
+
+/// How line breaks are written when saving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EolMode {
+    /// Write every line break as it is in the buffer.
+    #[default]
+    Preserve,
+    /// Rewrite every line break as the given one.
+    Normalize(Eol),
+}
+
+/// The most frequent line break in `text`, `Eol::Lf` when there is none. Ties prefer
+/// LF, then CRLF.
+pub fn detect_eol(text: &str) -> Eol {
+    let mut counts = EolCounts::default();
+    counts.add(text);
+    counts.majority()
+}
+
+/// Line breaks counted over text handed in a chunk at a time. A CR ending one chunk is held
+/// back until the next shows whether it starts a CRLF.
+#[derive(Debug, Default)]
+pub(crate) struct EolCounts {
+    lf: usize,
+    crlf: usize,
+    cr: usize,
+    pending_cr: bool,
+}
+
+impl EolCounts {
+    pub(crate) fn add(&mut self, chunk: &str) {
+        let mut bytes = chunk.as_bytes();
+        if std::mem::take(&mut self.pending_cr) {
+            match bytes.first() {
+                Some(b'\n') => {
+                    self.crlf += 1;
+                    bytes = &bytes[1..];
+                }
+                Some(_) => self.cr += 1,
+                None => self.pending_cr = true,
+            }
+        }
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\r' if i + 1 == bytes.len() => self.pending_cr = true,
+                b'\r' if bytes[i + 1] == b'\n' => {
+                    self.crlf += 1;
+                    i += 1;
+                }
+                b'\r' => self.cr += 1,
+                b'\n' => self.lf += 1,
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    /// The most frequent line break, with a held-back CR counted as one. See [`detect_eol`].
+    pub(crate) fn majority(self) -> Eol {
+        let cr = self.cr + usize::from(self.pending_cr);
+        if cr > self.lf && cr > self.crlf {
+            Eol::Cr
+        } else if self.crlf > self.lf {
+            Eol::CrLf
+        } else {
+            Eol::Lf
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_majority_line_break() {
+        assert_eq!(detect_eol(""), Eol::Lf);
+        assert_eq!(detect_eol("no breaks"), Eol::Lf);
+        assert_eq!(detect_eol("a\nb\n"), Eol::Lf);
+        assert_eq!(detect_eol("a\r\nb\r\n"), Eol::CrLf);
+        assert_eq!(detect_eol("a\rb\rc"), Eol::Cr);
+        // Mixed: the majority wins, CRLF is not counted as CR or LF
+        assert_eq!(detect_eol("a\rb\rc\r\nd\n"), Eol::Cr);
+        assert_eq!(detect_eol("a\r\nb\r\nc\r"), Eol::CrLf);
+        assert_eq!(detect_eol("a\r\nb\n"), Eol::Lf);
+    }
+
+    #[test]
+    fn counts_a_crlf_split_across_chunks_once() {
+        let mut counts = EolCounts::default();
+        for chunk in ["a\r", "", "\nb\r", "\nc\n"] {
+            counts.add(chunk);
+        }
+        assert_eq!((counts.lf, counts.crlf, counts.cr), (1, 2, 0));
+        assert_eq!(counts.majority(), Eol::CrLf);
+
+        let mut counts = EolCounts::default();
+        for chunk in ["a\r", "b\r"] {
+            counts.add(chunk);
+        }
+        assert_eq!(counts.majority(), Eol::Cr);
+    }
+}
@@ -0,0 +1,108 @@
+use piece_tree::BufferCursor;
+
+/// Where the caret belongs after an undo or redo: just past the text it put back, or where
+/// text was taken out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EditPosition {
+    pub offset: usize,
+    /// 1-based line and byte column of `offset`.
+    pub position: BufferCursor,
+}
+
+/// One reversible edit: `removed` replaced by `inserted` at byte `offset`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Edit {
+    pub offset: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+/// Edits undone and redone as one step, with the version of the text once they are made.
+#[derive(Debug, Clone)]
+struct Group {
+    version: u64,
+    edits: Vec<Edit>,
+}
+
+/// Undo and redo stacks of edit groups. A group is undone and redone as one step; edits made
+/// between `begin_group` and the matching `end_group` share one.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct History {
+    undo: Vec<Group>,
+    redo: Vec<Group>,
+    // Version of the text with nothing left to undo
+    base: u64,
+    // Last version handed out
+    latest: u64,
+    // Open `begin_group` calls
+    depth: usize,
+    // The newest undo group takes further edits
+    joining: bool,
+}
+
+impl History {
+    /// Log a new edit. Anything undone before it can no longer be redone.
+    pub fn record(&mut self, edit: Edit) {
+        self.redo.clear();
+        self.latest += 1;
+        match self.undo.last_mut() {
+            Some(group) if self.joining => {
+                group.edits.push(edit);
+                group.version = self.latest;
+            }
+            _ => {
+                self.undo.push(Group {
+                    version: self.latest,
+                    edits: vec![edit],
+                });
+                self.joining = self.depth > 0;
+            }
+        }
+    }
+
+    /// Changes with every edit and goes back to an earlier value on undo or redo, so equal
+    /// versions mean equal text.
+    pub fn version(&self) -> u64 {
+        self.undo.last().map_or(self.base, |group| group.version)
+    }
+
+    pub fn begin_group(&mut self) {
+        if self.depth == 0 {
+            self.joining = false;
+        }
+        self.depth += 1;
+    }
+
+    pub fn end_group(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth == 0 {
+            self.joining = false;
+        }
+    }
+
+    /// The newest group to undo, moved to the redo stack. Edits are in the order they were
+    /// made.
+    pub fn undo(&mut self) -> Option<&[Edit]> {
+        self.joining = false;
+        let group = self.undo.pop()?;
+        self.redo.push(group);
+        self.redo.last().map(|group| group.edits.as_slice())
+    }
+
+    /// The newest undone group, moved back to the undo stack.
+    pub fn redo(&mut self) -> Option<&[Edit]> {
+        self.joining = false;
+        let group = self.redo.pop()?;
+        self.undo.push(group);
+        self.undo.last().map(|group| group.edits.as_slice())
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self {
+            depth: self.depth,
+            base: self.version(),
+            latest: self.latest,
+            ..Self::default()
+        };
+    }
+}
@@ -0,0 +1,242 @@
+/// Leading spaces and tabs of `line`.
+pub fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// Markdown list marker starting `text`, with the space after it: `- `, `* `, `+ `, or a
+/// number and `. `.
+pub fn list_marker(text: &str) -> Option<&str> {
+    if text.starts_with("- ") || text.starts_with("* ") || text.starts_with("+ ") {
+        return Some(&text[..2]);
+    }
+    let digits = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    (digits > 0 && text[digits..].starts_with(". ")).then(|| &text[..digits + 2])
+}
+
+/// Marker of the item after one marked with `marker`: the same bullet, or the next number.
+pub fn next_list_marker(marker: &str) -> String {
+    match marker
+        .strip_suffix(". ")
+        .and_then(|n| n.parse::<u64>().ok())
+    {
+        Some(n) => format!("{}. ", n + 1),
+        None => marker.to_string(),
+    }
+}
+
+/// What Enter does at the end of `before`, the line up to the caret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineBreak {
+    /// Break the line and start the new one with this text: the indentation of `before`,
+    /// followed by the next list marker when `before` is a list item.
+    Continue(String),
+    /// `before` is a list item with nothing in it: take off the marker, the bytes `range`
+    /// of the line, and do not break the line.
+    EndList(std::ops::Range<usize>),
+}
+
+/// How Enter continues `line` with the caret `caret` bytes in. `lists` continues Markdown
+/// lists; otherwise only the indentation carries over.
+pub fn line_break(line: &str, caret: usize, lists: bool) -> LineBreak {
+    let before = &line[..caret];
+    let indent = leading_whitespace(before);
+    let item = &before[indent.len()..];
+    match list_marker(item).filter(|_| lists) {
+        Some(marker) if line[indent.len()..].trim_end() == marker.trim_end() => {
+            LineBreak::EndList(indent.len()..line.len())
+        }
+        Some(marker) => LineBreak::Continue(format!("{indent}{}", next_list_marker(marker))),
+        None => LineBreak::Continue(indent.to_string()),
+    }
+}
+
+/// Markdown prefix put in front of lines by [`toggle_prefix`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinePrefix {
+    /// `- `
+    Bullet,
+    /// `1. `, `2. `, ... numbered down the lines.
+    Numbered,
+    /// This many `#` and a space; 0 takes headings off.
+    Heading(usize),
+}
+
+/// Edits giving each of `lines` `prefix`, one per line as the bytes of the line to replace
+/// and the text to put there: a list marker replaces any marker after the indentation,
+/// heading hashes replace any at the start. If every line already has the prefix, it is
+/// taken off instead. Blank lines among several are left as they are, and so are lines that
+/// keep their prefix; their edits are empty.
+pub fn toggle_prefix<S: AsRef<str>>(
+    lines: &[S],
+    prefix: LinePrefix,
+) -> Vec<(std::ops::Range<usize>, String)> {
+    let counted = |line: &str| lines.len() == 1 || !line.trim().is_empty();
+    // The bytes holding the current prefix of each line, and whether it is `prefix`
+    let current: Vec<_> = lines
+        .iter()
+        .map(|line| {
+            let line = line.as_ref();
+            match prefix {
+                LinePrefix::Heading(level) => match heading_level(line) {
+                    Some(n) => (0..(n + 1).min(line.len()), n == level),
+                    None => (0..0, false),
+                },
+                LinePrefix::Bullet | LinePrefix::Numbered => {
+                    let indent = leading_whitespace(line).len();
+                    let marker = list_marker(&line[indent..]).unwrap_or("");
+                    let numbered = marker.ends_with(". ");
+                    let same = !marker.is_empty() && numbered == (prefix == LinePrefix::Numbered);
+                    (indent..indent + marker.len(), same)
+                }
+            }
+        })
+        .collect();
+    let all = lines
+        .iter()
+        .zip(&current)
+        .filter(|(line, _)| counted(line.as_ref()))
+        .all(|(_, (_, same))| *same);
+
+    let mut number = 0;
+    lines
+        .iter()
+        .zip(current)
+        .map(|(line, (range, same))| {
+            let keep = (range.start..range.start, String::new());
+            if !counted(line.as_ref()) {
+                return keep;
+            }
+            if all {
+                return (range, String::new());
+            }
+            number += 1;
+            match prefix {
+                // Other bullets stay as they are
+                LinePrefix::Bullet if same => keep,
+                LinePrefix::Bullet => (range, String::from("- ")),
+                LinePrefix::Numbered => (range, format!("{number}. ")),
+                LinePrefix::Heading(_) if same => keep,
+                LinePrefix::Heading(0) => (range, String::new()),
+                LinePrefix::Heading(level) => (range, format!("{} ", "#".repeat(level))),
+            }
+        })
+        .collect()
+}
+
+// Level of a heading line: one to six `#` at the start, then a space or the end
+fn heading_level(line: &str) -> Option<usize> {
+    let level = line.len() - line.trim_start_matches('#').len();
+    let rest = &line[level..];
+    ((1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' '))).then_some(level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_lines_copy_the_indentation() {
+        let text = |line: &str, caret| match line_break(line, caret, false) {
+            LineBreak::Continue(text) => text,
+            other => panic!("{other:?}"),
+        };
+        assert_eq!(text("    let x = 1;", 14), "    ");
+        assert_eq!(text("\t\t  body", 8), "\t\t  ");
+        assert_eq!(text("no indent", 9), "");
+        // Only the indentation before the caret
+        assert_eq!(text("      x", 2), "  ");
+        // Lists are left alone without `lists`
+        assert_eq!(text("  - item", 8), "  ");
+    }
+
+    #[test]
+    fn markdown_lists_continue_or_end() {
+        assert_eq!(
+            line_break("  - item", 8, true),
+            LineBreak::Continue("  - ".to_string())
+        );
+        assert_eq!(
+            line_break("* a", 3, true),
+            LineBreak::Continue("* ".to_string())
+        );
+        assert_eq!(
+            line_break("9. ninth", 8, true),
+            LineBreak::Continue("10. ".to_string())
+        );
+        // An empty item ends the list
+        assert_eq!(line_break("  - ", 4, true), LineBreak::EndList(2..4));
+        assert_eq!(line_break("1. ", 3, true), LineBreak::EndList(0..3));
+        // Not markers
+        assert_eq!(
+            line_break("-item", 5, true),
+            LineBreak::Continue(String::new())
+        );
+        assert_eq!(
+            line_break("1.5 apples", 10, true),
+            LineBreak::Continue(String::new())
+        );
+    }
+
+    #[test]
+    fn list_prefixes_toggle_on_every_line() {
+        let apply = |lines: &[&str], prefix| -> Vec<String> {
+            lines
+                .iter()
+                .zip(toggle_prefix(lines, prefix))
+                .map(|(line, (range, text))| {
+                    let mut line = line.to_string();
+                    line.replace_range(range, &text);
+                    line
+                })
+                .collect()
+        };
+
+        let lines = ["one", "", "  * two", "3. three"];
+        let bullets = apply(&lines, LinePrefix::Bullet);
+        assert_eq!(bullets, ["- one", "", "  * two", "- three"]);
+        assert_eq!(
+            apply(
+                &bullets.iter().map(String::as_str).collect::<Vec<_>>(),
+                LinePrefix::Bullet
+            ),
+            ["one", "", "  two", "three"]
+        );
+
+        let numbered = apply(&lines, LinePrefix::Numbered);
+        assert_eq!(numbered, ["1. one", "", "  2. two", "3. three"]);
+        let numbered: Vec<_> = numbered.iter().map(String::as_str).collect();
+        assert_eq!(
+            apply(&numbered, LinePrefix::Numbered),
+            ["one", "", "  two", "three"]
+        );
+
+        // A single blank line still gets a marker
+        assert_eq!(apply(&[""], LinePrefix::Bullet), ["- "]);
+    }
+
+    #[test]
+    fn heading_levels_replace_and_toggle() {
+        let edits = toggle_prefix(&["Title", "## Sub", "#tag"], LinePrefix::Heading(2));
+        assert_eq!(
+            edits,
+            [
+                (0..0, String::from("## ")),
+                (0..0, String::new()),
+                (0..0, String::from("## ")),
+            ]
+        );
+        assert_eq!(
+            toggle_prefix(&["### Deep"], LinePrefix::Heading(1)),
+            [(0..4, String::from("# "))]
+        );
+        // The same level again takes it off; level 0 takes any off
+        assert_eq!(
+            toggle_prefix(&["## Sub", "##"], LinePrefix::Heading(2)),
+            [(0..3, String::new()), (0..2, String::new())]
+        );
+        assert_eq!(
+            toggle_prefix(&["# A", "B"], LinePrefix::Heading(0)),
+            [(0..2, String::new()), (0..0, String::new())]
+        );
+    }
+}
@@ -1,5 +1,29 @@
+mod batch;
 mod buffer;
 mod buffer_builder;
+pub mod column;
+mod diff;
+mod encoding;
+mod eol;
+mod history;
+mod indent;
+mod save;
+mod snapshot;
+mod stats;
+mod toggle;
 
-pub use crate::buffer::TextBuffer;
+pub use crate::batch::BatchEdit;
+pub use crate::buffer::{ChangeCallback, TextBuffer};
 pub use crate::buffer_builder::TextBufferBuilder;
+pub use crate::column::ColumnMode;
+pub use crate::diff::{DiffOp, diff_lines};
+pub use crate::encoding::FileEncoding;
+pub use crate::eol::{Eol, EolMode, detect_eol};
+pub use crate::history::EditPosition;
+pub use crate::indent::{LineBreak, LinePrefix, leading_whitespace, line_break, toggle_prefix};
+pub use crate::save::{save_atomic, save_atomic_with};
+pub use crate::snapshot::Snapshot;
+pub use crate::stats::{ChangeEvent, TextStats};
+pub use crate::toggle::{step_word, toggle_word};
+pub use encoding_rs::{self, Encoding};
+pub use piece_tree::SearchOptions;
@@ -0,0 +1,162 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Write `contents` to `path` without ever leaving it half written: the bytes go to a
+/// hidden temp file next to it, which is synced and then renamed over `path`. An existing
+/// file's permissions carry over. If anything fails before the rename, `path` is untouched.
+pub fn save_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> io::Result<()> {
+    save_atomic_with(path, |writer| writer.write_all(contents))
+}
+
+/// [`save_atomic`] with the contents written by `write`, straight into the temp file's
+/// buffered writer, e.g. to stream a large document instead of copying it first.
+pub fn save_atomic_with<P, F>(path: P, write: F) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let path = path.as_ref();
+    save_via(&temp_path(path)?, path, write)
+}
+
+// `.<name>.tmp` in the same directory, so the rename stays on one file system
+fn temp_path(path: &Path) -> io::Result<PathBuf> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| io::Error::other("No parent directory"))?;
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::other("No file name"))?;
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(".tmp");
+    Ok(dir.join(tmp_name))
+}
+
+// `save_atomic_with` through the temp file `tmp_path`
+fn save_via(
+    tmp_path: &Path,
+    dest: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    if let Err(e) = write_synced(tmp_path, dest, write) {
+        let _ = fs::remove_file(tmp_path);
+        return Err(e);
+    }
+
+    match fs::rename(tmp_path, dest) {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            // Fallback: remove target and retry
+            fs::remove_file(dest)?;
+            fs::rename(tmp_path, dest)?;
+        }
+        Err(e) => {
+            let _ = fs::remove_file(tmp_path);
+            return Err(e);
+        }
+    }
+
+    // Sync directory on Unix for crash consistency
+    #[cfg(target_family = "unix")]
+    if let Some(dir) = dest.parent()
+        && let Ok(dir_fd) = fs::File::open(dir)
+    {
+        let _ = dir_fd.sync_all();
+    }
+
+    Ok(())
+}
+
+// Create or truncate `tmp_path`, have `write` fill it, sync it to disk, and give it `dest`'s
+// permissions
+fn write_synced(
+    tmp_path: &Path,
+    dest: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let tmp_file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(tmp_path)?;
+
+    let mut writer = BufWriter::new(tmp_file);
+    write(&mut writer)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+
+    if let Ok(meta) = fs::metadata(dest) {
+        fs::set_permissions(tmp_path, meta.permissions())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("text_buffer_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn replaces_the_file_and_leaves_no_temp_behind() {
+        let dir = scratch_dir("save_ok");
+        let path = dir.join("notes.md");
+        fs::write(&path, "old").unwrap();
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        save_atomic(&path, b"new contents").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new contents");
+        assert!(!temp_path(&path).unwrap().exists());
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+        }
+
+        // A new file is created too
+        save_atomic(dir.join("fresh.md"), b"fresh").unwrap();
+        assert_eq!(fs::read(dir.join("fresh.md")).unwrap(), b"fresh");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn target_is_untouched_when_the_temp_file_cannot_be_written() {
+        let dir = scratch_dir("save_fail");
+        let path = dir.join("notes.md");
+        fs::write(&path, "original").unwrap();
+
+        // A temp file in a directory that does not exist
+        let missing = dir.join("missing").join(".notes.md.tmp");
+        assert!(save_via(&missing, &path, |w| w.write_all(b"lost")).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"original");
+
+        // A directory where the temp file should go
+        fs::create_dir(temp_path(&path).unwrap()).unwrap();
+        assert!(save_atomic(&path, b"lost").is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"original");
+        fs::remove_dir(temp_path(&path).unwrap()).unwrap();
+
+        // The writer failing partway
+        let failed = save_atomic_with(&path, |w| {
+            w.write_all(b"half")?;
+            Err(io::Error::other("disk full"))
+        });
+        assert!(failed.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"original");
+        assert!(!temp_path(&path).unwrap().exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+use piece_tree::PieceTree;
+use std::io::{self, Write};
+
+/// Read-only copy of a [`crate::TextBuffer`]'s text at one moment, taken with
+/// [`crate::TextBuffer::snapshot`], e.g. to diff against or to save in the background. Later
+/// edits to the buffer don't show up in it. Taking one copies the piece structure, not the
+/// text.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    tree: PieceTree,
+}
+
+impl Snapshot {
+    pub(crate) fn new(tree: PieceTree) -> Self {
+        Self { tree }
+    }
+
+    /// The complete text.
+    pub fn get_text(&self) -> String {
+        self.tree.get_text()
+    }
+
+    /// Text of the bytes `start..end`, `end` clamped to the length.
+    pub fn get_text_in_range(&self, start: usize, end: usize) -> String {
+        self.tree.get_text_in_range(start, end)
+    }
+
+    /// Length in bytes.
+    pub fn get_length(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Number of lines; an empty text has one.
+    pub fn get_line_count(&self) -> usize {
+        self.tree.line_count()
+    }
+
+    /// A 1-based line without its line break.
+    pub fn get_line_content(&self, line_number: usize) -> String {
+        self.tree.get_line_content(line_number)
+    }
+
+    /// Hash of the text, equal to the buffer's [`crate::TextBuffer::content_hash`] when
+    /// their texts match.
+    pub fn content_hash(&self) -> u64 {
+        self.tree.content_hash()
+    }
+
+    /// Write the text to `writer` a piece at a time.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.tree.write_to(writer)
+    }
+}
@@ -0,0 +1,178 @@
+use std::io::{self, Write};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Document counts for the statistics panel and status bar. `lines` is the number of line
+/// breaks (LF, CRLF or a lone CR) plus one, and words are the Unicode words of the text
+/// (UAX #29), so punctuation is not a word, "can't" and "3.5" are one, and every CJK
+/// ideograph counts on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TextStats {
+    pub lines: usize,
+    pub words: usize,
+    pub chars: usize,
+    pub bytes: usize,
+}
+
+/// One edit to the document: `removed` replaced by `inserted` at byte `offset`, with the
+/// text right before and after the edited range, which decides whether words and line
+/// breaks at its edges join or split. `before` starts at the nearest whitespace character
+/// and `after` ends with one, unless that is too far away to be worth reading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    pub offset: usize,
+    pub removed: String,
+    pub inserted: String,
+    pub before: String,
+    pub after: String,
+}
+
+impl ChangeEvent {
+    /// Bytes taken out of the document.
+    pub fn removed_len(&self) -> usize {
+        self.removed.len()
+    }
+
+    /// Bytes put into the document.
+    pub fn inserted_len(&self) -> usize {
+        self.inserted.len()
+    }
+
+    /// Lines the edit added, negative when it removed some. A CR and LF it joins into one
+    /// line break, or splits apart, count too.
+    pub fn line_delta(&self) -> isize {
+        let with_edges = |middle: &str| {
+            let mut text = String::with_capacity(middle.len() + 2);
+            text.extend(self.before.chars().next_back());
+            text.push_str(middle);
+            text.extend(self.after.chars().next());
+            line_breaks(&text) as isize
+        };
+        with_edges(&self.inserted) - with_edges(&self.removed)
+    }
+}
+
+impl TextStats {
+    /// Count everything in `text`.
+    pub fn of(text: &str) -> Self {
+        Self {
+            lines: line_breaks(text) + 1,
+            words: words(text),
+            chars: text.chars().count(),
+            bytes: text.len(),
+        }
+    }
+
+    /// Update the counts for `change`, looking only at the changed text and its neighbours.
+    pub fn apply(&mut self, change: &ChangeEvent) {
+        let around = |middle: &str| {
+            let mut text = String::with_capacity(change.before.len() + middle.len() + 8);
+            text.push_str(&change.before);
+            text.push_str(middle);
+            text.push_str(&change.after);
+            text
+        };
+        // The neighbours count the same way on both sides, so the difference is the change
+        let old = around(&change.removed);
+        let new = around(&change.inserted);
+
+        self.lines = self.lines + line_breaks(&new) - line_breaks(&old);
+        self.words = self.words + words(&new) - words(&old);
+        self.chars = self.chars + change.inserted.chars().count() - change.removed.chars().count();
+        self.bytes = self.bytes + change.inserted.len() - change.removed.len();
+    }
+}
+
+/// Counts a document written to it in pieces, e.g. by [`crate::TextBuffer::write_to`],
+/// holding on to no more than the current line.
+#[derive(Debug, Default)]
+pub(crate) struct StatsWriter {
+    stats: TextStats,
+    // Written bytes after the last complete line break
+    pending: Vec<u8>,
+}
+
+impl StatsWriter {
+    /// The counts of everything written.
+    pub(crate) fn finish(mut self) -> TextStats {
+        self.count(self.pending.len());
+        self.stats.lines += 1;
+        self.stats
+    }
+
+    // Count the first `len` pending bytes, which end at a line break or the end of the text
+    fn count(&mut self, len: usize) {
+        let text = String::from_utf8_lossy(&self.pending[..len]);
+        self.stats.lines += line_breaks(&text);
+        self.stats.words += words(&text);
+        self.stats.chars += text.chars().count();
+        self.stats.bytes += len;
+        self.pending.drain(..len);
+    }
+}
+
+impl Write for StatsWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        // Words never span a line break, but a CR at the end may still get its LF
+        let last = self.pending.len().saturating_sub(1);
+        let cut = self
+            .pending
+            .iter()
+            .enumerate()
+            .rposition(|(i, &b)| b == b'\n' || (b == b'\r' && i < last));
+        if let Some(i) = cut {
+            self.count(i + 1);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn words(text: &str) -> usize {
+    text.unicode_words().count()
+}
+
+fn line_breaks(text: &str) -> usize {
+    text.matches('\n').count() + text.matches('\r').count() - text.matches("\r\n").count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_lines_words_chars_and_bytes() {
+        assert_eq!(
+            TextStats::of("héllo wörld\r\n\tsecond line\rthird"),
+            TextStats {
+                lines: 3,
+                words: 5,
+                chars: 31,
+                bytes: 33,
+            }
+        );
+        assert_eq!(TextStats::of("Hi, there... (ok?) — 3.5% can't").words, 5);
+        assert_eq!(TextStats::of("中文 文本").words, 4);
+        assert_eq!(
+            TextStats::of(""),
+            TextStats {
+                lines: 1,
+                ..TextStats::default()
+            }
+        );
+    }
+
+    #[test]
+    fn writer_counts_like_the_whole_text_however_it_is_split() {
+        let text = "one, two\r\n漢字 can't\rx 3.5\n\n";
+        for at in 0..=text.len() {
+            let mut writer = StatsWriter::default();
+            writer.write_all(&text.as_bytes()[..at]).unwrap();
+            writer.write_all(&text.as_bytes()[at..]).unwrap();
+            assert_eq!(writer.finish(), TextStats::of(text), "split at {at}");
+        }
+    }
+}
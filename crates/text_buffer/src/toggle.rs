@@ -0,0 +1,99 @@
+// Words that toggle to each other, in lowercase
+const OPPOSITES: &[(&str, &str)] = &[
+    ("true", "false"),
+    ("yes", "no"),
+    ("on", "off"),
+    ("enable", "disable"),
+    ("enabled", "disabled"),
+    ("show", "hide"),
+    ("open", "close"),
+    ("left", "right"),
+    ("top", "bottom"),
+    ("min", "max"),
+    ("first", "last"),
+];
+
+/// Replacement for `word` when toggled: its opposite (`true` → `false`, `On` → `Off`), or
+/// with a trailing integer incremented (`v9` → `v10`). `None` when there is nothing to toggle.
+pub fn toggle_word(word: &str) -> Option<String> {
+    step_word(word, 1)
+}
+
+/// Like [`toggle_word`], but adds `step` to a trailing integer. Opposites toggle either way.
+/// `None` as well when the integer would leave the range of `u64`.
+pub fn step_word(word: &str, step: i64) -> Option<String> {
+    opposite(word).or_else(|| step_number(word, step))
+}
+
+// Opposite keeping the case pattern: lower, UPPER or Capitalized
+fn opposite(word: &str) -> Option<String> {
+    let lower = word.to_lowercase();
+    let other = OPPOSITES.iter().find_map(|&(a, b)| {
+        if a == lower {
+            Some(b)
+        } else if b == lower {
+            Some(a)
+        } else {
+            None
+        }
+    })?;
+
+    let mut chars = word.chars();
+    let first = chars.next()?;
+    if word == lower {
+        Some(other.to_string())
+    } else if word == word.to_uppercase() {
+        Some(other.to_uppercase())
+    } else if first.is_uppercase() && chars.as_str() == chars.as_str().to_lowercase() {
+        let mut chars = other.chars();
+        let first = chars.next()?.to_uppercase();
+        Some(first.chain(chars).collect())
+    } else {
+        None
+    }
+}
+
+// Add `step` to the ASCII digits ending `word`, keeping zero padding (`007` → `008`)
+fn step_number(word: &str, step: i64) -> Option<String> {
+    let digits = word.len() - word.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 {
+        return None;
+    }
+    let (prefix, number) = word.split_at(word.len() - digits);
+    let value = number.parse::<u64>().ok()?.checked_add_signed(step)?;
+    let width = if number.starts_with('0') { digits } else { 0 };
+    Some(format!("{prefix}{value:0width$}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggles_opposites_keeping_case() {
+        assert_eq!(toggle_word("true").as_deref(), Some("false"));
+        assert_eq!(toggle_word("false").as_deref(), Some("true"));
+        assert_eq!(toggle_word("On").as_deref(), Some("Off"));
+        assert_eq!(toggle_word("YES").as_deref(), Some("NO"));
+        assert_eq!(toggle_word("enabled").as_deref(), Some("disabled"));
+        assert_eq!(step_word("Disabled", -1).as_deref(), Some("Enabled"));
+        assert_eq!(toggle_word("tRUE"), None);
+    }
+
+    #[test]
+    fn steps_trailing_integers() {
+        assert_eq!(toggle_word("41").as_deref(), Some("42"));
+        assert_eq!(toggle_word("v9").as_deref(), Some("v10"));
+        assert_eq!(toggle_word("item007").as_deref(), Some("item008"));
+        assert_eq!(step_word("10", -1).as_deref(), Some("9"));
+        assert_eq!(step_word("0", -1), None);
+        assert_eq!(toggle_word(&u64::MAX.to_string()), None);
+    }
+
+    #[test]
+    fn other_words_do_not_toggle() {
+        assert_eq!(toggle_word("banana"), None);
+        assert_eq!(toggle_word("2b_or_not"), None);
+        assert_eq!(toggle_word(""), None);
+    }
+}